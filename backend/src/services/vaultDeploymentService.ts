@@ -276,7 +276,13 @@ export async function buildDeploymentTransaction(
     ]);
 
     // Build transaction to call create_vault on factory
-    const operation = factoryContract.call('create_vault', vaultConfigStruct);
+    // The deploying wallet is the caller, so the factory must have been opened
+    // with set_permissionless (deploy-contracts.ps1 does this)
+    const operation = factoryContract.call(
+      'create_vault',
+      StellarSdk.Address.fromString(sourceAddress).toScVal(),
+      vaultConfigStruct
+    );
 
     // Determine network passphrase
     const buildNetworkPassphrase = servers.network === 'futurenet' 
@@ -680,7 +686,13 @@ export async function deployVault(
     ]);
 
     // Build transaction to call create_vault on factory
-    const operation = factoryContract.call('create_vault', vaultConfigStruct);
+    // The deploying wallet is the caller, so the factory must have been opened
+    // with set_permissionless (deploy-contracts.ps1 does this)
+    const operation = factoryContract.call(
+      'create_vault',
+      StellarSdk.Address.fromString(sourceKeypair.publicKey()).toScVal(),
+      vaultConfigStruct
+    );
 
     let transaction = new StellarSdk.TransactionBuilder(sourceAccount, {
       fee: StellarSdk.BASE_FEE,
@@ -0,0 +1,127 @@
+// Yield source for the vault's idle base-asset buffer
+// A same-asset pool with instant 1:1-or-better redemption (e.g. a lending market
+// supply position). Idle base asset above a small hot threshold is parked here
+// and pulled back on demand when a withdrawal needs it.
+use soroban_sdk::{contractclient, symbol_short, Address, Env, Symbol};
+use crate::errors::VaultError;
+
+const BUFFER_POOL: Symbol = symbol_short!("BUF_POOL");
+const BUFFER_HOT: Symbol = symbol_short!("BUF_HOT");
+
+/// Instant-redeem pool interface
+#[contractclient(name = "BufferPoolClient")]
+pub trait BufferPoolInterface {
+    /// Book tokens already transferred to the pool by `sender`
+    /// Returns the amount credited
+    fn supply(env: Env, sender: Address, amount: i128) -> i128;
+
+    /// Redeem `amount` of the underlying back to `sender`
+    /// Returns the amount sent
+    fn redeem(env: Env, sender: Address, amount: i128) -> i128;
+
+    /// Underlying redeemable by `user`, including accrued yield
+    fn balance_of(env: Env, user: Address) -> i128;
+}
+
+/// Configured buffer pool and hot threshold, if any
+pub fn get_buffer_config(env: &Env) -> Option<(Address, i128)> {
+    let pool: Option<Address> = env.storage().instance().get(&BUFFER_POOL);
+    pool.map(|pool| {
+        let hot: i128 = env.storage().instance().get(&BUFFER_HOT).unwrap_or(0);
+        (pool, hot)
+    })
+}
+
+/// Store (or clear) the buffer pool and hot threshold
+pub fn set_buffer_config(env: &Env, pool: Option<Address>, hot_threshold: i128) {
+    match pool {
+        Some(pool) => {
+            env.storage().instance().set(&BUFFER_POOL, &pool);
+            env.storage().instance().set(&BUFFER_HOT, &hot_threshold);
+        }
+        None => {
+            env.storage().instance().remove(&BUFFER_POOL);
+            env.storage().instance().remove(&BUFFER_HOT);
+        }
+    }
+}
+
+/// Underlying currently parked in the buffer pool
+pub fn parked_balance(env: &Env) -> i128 {
+    match get_buffer_config(env) {
+        Some((pool, _)) => {
+            BufferPoolClient::new(env, &pool).balance_of(&env.current_contract_address())
+        }
+        None => 0,
+    }
+}
+
+/// Park base asset above the hot threshold in the buffer pool
+/// Returns the amount parked (0 when no pool is configured)
+pub fn park_idle(env: &Env, base_token: &Address) -> Result<i128, VaultError> {
+    let (pool, hot_threshold) = match get_buffer_config(env) {
+        Some(cfg) => cfg,
+        None => return Ok(0),
+    };
+
+    let balance = crate::token_client::get_vault_balance(env, base_token);
+    let excess = balance - hot_threshold;
+    if excess <= 0 {
+        return Ok(0);
+    }
+
+    let vault_address = env.current_contract_address();
+    crate::token_client::transfer_tokens(env, base_token, &vault_address, &pool, excess)?;
+
+    let credited = BufferPoolClient::new(env, &pool).supply(&vault_address, &excess);
+    if credited <= 0 {
+        return Err(VaultError::InvalidAmount);
+    }
+
+    env.events().publish((symbol_short!("buf_park"),), excess);
+
+    Ok(excess)
+}
+
+/// Make sure the vault holds at least `amount` of the base asset, redeeming
+/// the shortfall from the buffer pool if needed
+/// Returns the amount redeemed. Anything the buffer can't cover is left to the caller.
+pub fn ensure_liquid(env: &Env, base_token: &Address, amount: i128) -> Result<i128, VaultError> {
+    let balance = crate::token_client::get_vault_balance(env, base_token);
+    if balance >= amount {
+        return Ok(0);
+    }
+
+    let shortfall = amount - balance;
+    let parked = parked_balance(env);
+    let to_redeem = shortfall.min(parked);
+    if to_redeem <= 0 {
+        return Ok(0);
+    }
+
+    redeem(env, to_redeem)
+}
+
+/// Redeem everything parked in the buffer pool
+pub fn redeem_all(env: &Env) -> Result<i128, VaultError> {
+    let parked = parked_balance(env);
+    if parked <= 0 {
+        return Ok(0);
+    }
+
+    redeem(env, parked)
+}
+
+fn redeem(env: &Env, amount: i128) -> Result<i128, VaultError> {
+    let (pool, _) = get_buffer_config(env).ok_or(VaultError::InvalidConfiguration)?;
+
+    let received = BufferPoolClient::new(env, &pool)
+        .redeem(&env.current_contract_address(), &amount);
+    if received < amount {
+        return Err(VaultError::InsufficientLiquidity);
+    }
+
+    env.events().publish((symbol_short!("buf_redm"),), received);
+
+    Ok(received)
+}
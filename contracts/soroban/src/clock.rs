@@ -0,0 +1,16 @@
+// Time access for the vault
+// Mutating paths always use ledger time via `now`. Read-only evaluation entry
+// points may take a caller-supplied `as_of` so keepers and frontends can ask
+// "would this fire at T" without advancing the ledger.
+use soroban_sdk::Env;
+
+/// Current ledger timestamp
+pub fn now(env: &Env) -> u64 {
+    env.ledger().timestamp()
+}
+
+/// Timestamp for a read-only evaluation: `as_of` if given, ledger time otherwise
+/// Never use this on a path that writes state.
+pub fn view_time(env: &Env, as_of: Option<u64>) -> u64 {
+    as_of.unwrap_or_else(|| now(env))
+}
@@ -1,28 +1,54 @@
 // Rule evaluation engine
-use soroban_sdk::{Env, symbol_short, Symbol, Vec};
-use crate::types::RebalanceRule;
+use soroban_sdk::{Env, Map, symbol_short, Symbol, Vec};
+use syft_errors::bounded_iter;
+use crate::types::{RebalanceRule, SkipReason, MAX_RULES};
 
-const STATE: Symbol = symbol_short!("STATE");
+const RULE_SEQUENCES: Symbol = symbol_short!("RULE_SEQ");
+
+/// Shortest interval, in ledgers, a "ledger_interval" rule may use
+pub const MIN_LEDGER_INTERVAL: i128 = 10;
 
 /// Evaluate all rebalancing rules and return true if any should trigger
+/// A rule set over MAX_RULES never triggers.
 pub fn evaluate_rules(env: &Env, rules: &Vec<RebalanceRule>) -> bool {
-    for i in 0..rules.len() {
-        if let Some(rule) = rules.get(i) {
-            if evaluate_single_rule(env, &rule) {
-                return true;
-            }
+    let now = crate::clock::now(env);
+    let Ok(rules) = bounded_iter(rules, MAX_RULES, ()) else {
+        return false;
+    };
+    for (i, rule) in rules.enumerate() {
+        if evaluate_single_rule(env, i as u32, &rule, now) {
+            return true;
         }
     }
     false
 }
 
-/// Evaluate a single rule based on its condition type
-fn evaluate_single_rule(env: &Env, rule: &RebalanceRule) -> bool {
+/// Evaluate every rule at time `now`, one result per rule in config order
+/// Read-only: callers may pass a hypothetical timestamp. Empty over MAX_RULES.
+pub fn evaluate_rules_at(env: &Env, rules: &Vec<RebalanceRule>, now: u64) -> Vec<bool> {
+    let mut results = Vec::new(env);
+    let Ok(rules) = bounded_iter(rules, MAX_RULES, ()) else {
+        return results;
+    };
+    for (i, rule) in rules.enumerate() {
+        results.push_back(evaluate_single_rule(env, i as u32, &rule, now));
+    }
+    results
+}
+
+/// Evaluate a single rule based on its condition type, as of `now`
+/// `index` is the rule's position in the config, used for per-rule tracking.
+fn evaluate_single_rule(env: &Env, index: u32, rule: &RebalanceRule, now: u64) -> bool {
     use soroban_sdk::String;
     
     // Time-based condition: Check if enough time has passed since last rebalance
     if rule.condition_type == String::from_str(env, "time") {
-        return evaluate_time_condition(env, rule);
+        return evaluate_time_condition(env, rule, now);
+    }
+    
+    // Ledger cadence: Check if enough ledgers have closed since this rule last ran
+    if rule.condition_type == String::from_str(env, "ledger_interval") {
+        return evaluate_ledger_interval_condition(env, index, rule);
     }
     
     // APY threshold condition: Check if APY meets threshold
@@ -44,22 +70,77 @@ fn evaluate_single_rule(env: &Env, rule: &RebalanceRule) -> bool {
 }
 
 /// Evaluate time-based rebalancing condition
-fn evaluate_time_condition(env: &Env, rule: &RebalanceRule) -> bool {
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+fn evaluate_time_condition(env: &Env, rule: &RebalanceRule, now: u64) -> bool {
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .unwrap_or(crate::types::VaultState {
             total_shares: 0,
             total_value: 0,
             last_rebalance: 0,
         });
     
-    let current_time = env.ledger().timestamp();
-    let time_elapsed = current_time.saturating_sub(state.last_rebalance);
+    let time_elapsed = now.saturating_sub(state.last_rebalance);
     
     // threshold is in seconds
     time_elapsed >= rule.threshold as u64
 }
 
+/// Evaluate ledger-interval condition
+fn evaluate_ledger_interval_condition(env: &Env, index: u32, rule: &RebalanceRule) -> bool {
+    let last = rule_sequences(env).get(index).unwrap_or(0);
+    let elapsed = env.ledger().sequence().saturating_sub(last);
+    
+    // threshold is in ledgers
+    elapsed as i128 >= rule.threshold
+}
+
+fn rule_sequences(env: &Env) -> Map<u32, u32> {
+    env.storage().instance()
+        .get(&RULE_SEQUENCES)
+        .unwrap_or(Map::new(env))
+}
+
+/// Check rule parameters that can't be fixed after initialization
+pub fn validate_rules(env: &Env, rules: &Vec<RebalanceRule>) -> bool {
+    use soroban_sdk::String;
+
+    let ledger_interval = String::from_str(env, "ledger_interval");
+    rules.iter().all(|rule| {
+        rule.condition_type != ledger_interval || rule.threshold >= MIN_LEDGER_INTERVAL
+    })
+}
+
+/// Restart the cadence of "ledger_interval" rules from the current ledger
+/// Called with the action that just ran, or None (on initialize) for every rule.
+pub fn record_ledger_rules(env: &Env, action: Option<&str>) {
+    use soroban_sdk::String;
+
+    let cfg: crate::types::VaultConfig = match crate::migrations::read_config(env) {
+        Some(cfg) => cfg,
+        None => return,
+    };
+
+    let action = action.map(|action| String::from_str(env, action));
+    let ledger_interval = String::from_str(env, "ledger_interval");
+    let sequence = env.ledger().sequence();
+    let mut sequences = rule_sequences(env);
+    let mut changed = false;
+
+    let Ok(rules) = bounded_iter(&cfg.rules, MAX_RULES, ()) else {
+        return;
+    };
+    for (i, rule) in rules.enumerate() {
+        let matches_action = action.as_ref().is_none_or(|action| rule.action == *action);
+        if rule.condition_type == ledger_interval && matches_action {
+            sequences.set(i as u32, sequence);
+            changed = true;
+        }
+    }
+
+    if changed {
+        env.storage().instance().set(&RULE_SEQUENCES, &sequences);
+    }
+}
+
 /// Evaluate APY threshold condition
 fn evaluate_apy_condition(_env: &Env, rule: &RebalanceRule) -> bool {
     // In MVP, we'll use a simplified APY calculation
@@ -75,8 +156,7 @@ fn evaluate_allocation_condition(env: &Env, _rule: &RebalanceRule) -> bool {
     // Check if current allocation drifted from target
     // In MVP, simplified logic - will be enhanced with real asset balance tracking
     
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .unwrap_or(crate::types::VaultState {
             total_shares: 0,
             total_value: 0,
@@ -97,71 +177,42 @@ fn evaluate_price_condition(_env: &Env, rule: &RebalanceRule) -> bool {
     rule.threshold > 0
 }
 
-/// Check if any rule should trigger rebalancing
-pub fn should_rebalance(env: &Env) -> bool {
-    let config: Result<crate::types::VaultConfig, crate::errors::VaultError> = 
-        env.storage().instance().get(&symbol_short!("CONFIG"))
-        .ok_or(crate::errors::VaultError::NotInitialized);
-    
-    match config {
-        Ok(cfg) => {
-            // Only check rebalance-type rules
-            for i in 0..cfg.rules.len() {
-                if let Some(rule) = cfg.rules.get(i) {
-                    use soroban_sdk::String;
-                    if rule.action == String::from_str(env, "rebalance") && evaluate_single_rule(env, &rule) {
-                        return true;
-                    }
-                }
-            }
-            false
-        },
-        Err(_) => false,
-    }
-}
+/// Explain why no rule with the given action fires right now
+/// Returns None if at least one matching rule evaluates true.
+/// A failing "time" or "ledger_interval" rule is reported as a cooldown, anything
+/// else as rules-not-met.
+pub fn skip_reason(env: &Env, action: &str) -> Option<SkipReason> {
+    use soroban_sdk::String;
 
-/// Check if any rule should trigger staking
-pub fn should_stake(env: &Env) -> bool {
-    let config: Result<crate::types::VaultConfig, crate::errors::VaultError> = 
-        env.storage().instance().get(&symbol_short!("CONFIG"))
-        .ok_or(crate::errors::VaultError::NotInitialized);
-    
-    match config {
-        Ok(cfg) => {
-            // Only check stake-type rules
-            for i in 0..cfg.rules.len() {
-                if let Some(rule) = cfg.rules.get(i) {
-                    use soroban_sdk::String;
-                    if rule.action == String::from_str(env, "stake") && evaluate_single_rule(env, &rule) {
-                        return true;
-                    }
-                }
-            }
-            false
-        },
-        Err(_) => false,
+    let cfg: crate::types::VaultConfig = match crate::migrations::read_config(env) {
+        Some(cfg) => cfg,
+        None => return Some(SkipReason::RulesNotMet),
+    };
+
+    let action = String::from_str(env, action);
+    let time = String::from_str(env, "time");
+    let ledger_interval = String::from_str(env, "ledger_interval");
+    let mut cooling_down = false;
+    let now = crate::clock::now(env);
+
+    let Ok(rules) = bounded_iter(&cfg.rules, MAX_RULES, ()) else {
+        return Some(SkipReason::RulesNotMet);
+    };
+    for (i, rule) in rules.enumerate() {
+        if rule.action != action {
+            continue;
+        }
+        if evaluate_single_rule(env, i as u32, &rule, now) {
+            return None;
+        }
+        if rule.condition_type == time || rule.condition_type == ledger_interval {
+            cooling_down = true;
+        }
     }
-}
 
-/// Check if any rule should trigger liquidity provision
-pub fn should_provide_liquidity(env: &Env) -> bool {
-    let config: Result<crate::types::VaultConfig, crate::errors::VaultError> = 
-        env.storage().instance().get(&symbol_short!("CONFIG"))
-        .ok_or(crate::errors::VaultError::NotInitialized);
-    
-    match config {
-        Ok(cfg) => {
-            // Only check liquidity-type rules
-            for i in 0..cfg.rules.len() {
-                if let Some(rule) = cfg.rules.get(i) {
-                    use soroban_sdk::String;
-                    if rule.action == String::from_str(env, "liquidity") && evaluate_single_rule(env, &rule) {
-                        return true;
-                    }
-                }
-            }
-            false
-        },
-        Err(_) => false,
+    if cooling_down {
+        Some(SkipReason::Cooldown)
+    } else {
+        Some(SkipReason::RulesNotMet)
     }
 }
@@ -1,22 +1,110 @@
 // Event emissions for vault actions
-use soroban_sdk::{symbol_short, Address, Env, Symbol, String};
+use soroban_sdk::{symbol_short, Address, Env, IntoVal, Symbol, String, Topics, Val};
+use crate::types::{SkipReason, WithdrawReceipt};
 
 const DEPOSIT: Symbol = symbol_short!("deposit");
 const WITHDRAW: Symbol = symbol_short!("withdraw");
 const REBALANCE: Symbol = symbol_short!("rebalance");
+const LOG_LEVEL: Symbol = symbol_short!("LOG_LVL");
+const EVENT_FLAGS: Symbol = symbol_short!("EVT_FLAGS");
+
+/// Event categories, combined as a bitmask for set_event_flags
+/// Events outside these categories (fees, profit sharing, admin) are always emitted.
+pub const EVENTS_DEPOSITS: u32 = 1 << 0;
+pub const EVENTS_WITHDRAWALS: u32 = 1 << 1;
+pub const EVENTS_REBALANCES: u32 = 1 << 2;  // Rebalance, stake, unstake and liquidity runs
+pub const EVENTS_SWAPS: u32 = 1 << 3;
+pub const EVENTS_DEBUG: u32 = 1 << 4;
+pub const EVENTS_TRANSFERS: u32 = 1 << 5;  // Shares credited to someone other than the payer
+pub const EVENTS_ALL: u32 = EVENTS_DEPOSITS
+    | EVENTS_WITHDRAWALS
+    | EVENTS_REBALANCES
+    | EVENTS_SWAPS
+    | EVENTS_DEBUG
+    | EVENTS_TRANSFERS;
+
+/// Log levels for diagnostic events; core events are always emitted
+pub const LOG_QUIET: u32 = 0;
+pub const LOG_INFO: u32 = 1;
+pub const LOG_DEBUG: u32 = 2;
+
+/// Current diagnostic log level (LOG_INFO when unset)
+pub fn log_level(env: &Env) -> u32 {
+    env.storage().instance().get(&LOG_LEVEL).unwrap_or(LOG_INFO)
+}
+
+/// Enabled event categories (EVENTS_ALL when unset)
+pub fn event_flags(env: &Env) -> u32 {
+    env.storage().instance().get(&EVENT_FLAGS).unwrap_or(EVENTS_ALL)
+}
+
+/// Publish an event in `category`; dropped when the category is disabled
+pub fn publish<T, D>(env: &Env, category: u32, topics: T, data: D)
+where
+    T: Topics,
+    D: IntoVal<Env, Val>,
+{
+    if event_flags(env) & category != 0 {
+        env.events().publish(topics, data);
+    }
+}
 
 pub fn emit_deposit(env: &Env, user: &Address, amount: i128, shares: i128) {
-    env.events().publish((DEPOSIT, user), (amount, shares));
+    publish(env, EVENTS_DEPOSITS, (DEPOSIT, user), (amount, shares));
+}
+
+/// Emitted when shares paid for by `from` end up owned by `to`, so indexers
+/// can follow ownership separately from the deposit itself
+pub fn emit_share_transfer(env: &Env, from: &Address, to: &Address, shares: i128) {
+    publish(env, EVENTS_TRANSFERS, (Symbol::new(env, "share_transfer"), from, to), shares);
 }
 
-pub fn emit_withdraw(env: &Env, user: &Address, shares: i128, amount: i128) {
-    env.events().publish((WITHDRAW, user), (shares, amount));
+pub fn emit_withdraw(env: &Env, user: &Address, receipt: &WithdrawReceipt) {
+    publish(
+        env,
+        EVENTS_WITHDRAWALS,
+        (WITHDRAW, user),
+        (
+            receipt.shares_burned,
+            receipt.gross_amount,
+            receipt.fees.clone(),
+            receipt.net_amount,
+            receipt.payout_token.clone(),
+        ),
+    );
 }
 
 pub fn emit_rebalance(env: &Env, timestamp: u64) {
-    env.events().publish((REBALANCE,), timestamp);
+    publish(env, EVENTS_REBALANCES, (REBALANCE,), timestamp);
+}
+
+/// Emitted when force_rebalance finds no rule with action "rebalance"
+pub fn emit_no_rebalance_rule(env: &Env) {
+    publish(env, EVENTS_REBALANCES, (Symbol::new(env, "no_rebalance_rule"),), crate::clock::now(env));
 }
 
 pub fn emit_vault_event(env: &Env, event_type: String, amount: i128) {
-    env.events().publish((event_type,), amount);
+    publish(env, EVENTS_REBALANCES, (event_type,), amount);
+}
+
+/// Emitted when a trigger call returns without executing (action = rebalance/stake/liquidity)
+pub fn emit_trigger_skip(env: &Env, action: Symbol, reason: SkipReason) {
+    publish(env, EVENTS_REBALANCES, (Symbol::new(env, "trigger_skip"), action), reason);
+}
+
+/// Emitted when an asset is found above its allocation cap
+pub fn emit_allocation_breach(env: &Env, asset: &Address, current_bps: i128, cap_bps: i128) {
+    env.events().publish(
+        (Symbol::new(env, "allocation_breach"), asset.clone()),
+        (current_bps, cap_bps),
+    );
+}
+
+/// Emitted when a deposit is already in the base asset and no swap is attempted
+/// Info level, so it can be told apart from a swap that was tried and failed
+pub fn emit_no_swap_needed(env: &Env, token: &Address, amount: i128) {
+    if log_level(env) == LOG_QUIET {
+        return;
+    }
+    publish(env, EVENTS_SWAPS, (Symbol::new(env, "no_swap_needed"), token.clone()), amount);
 }
@@ -0,0 +1,219 @@
+// Batched exits for large withdrawals
+// Withdrawals at or above the owner's threshold are queued instead of paid.
+// Everything queued in one ledger is settled together on a later ledger with
+// one combined unwind, and the slippage it realizes is shared pro-rata, so a
+// second whale exiting in the same ledger doesn't pay for the first one's
+// price impact. Settled amounts are then claimable, and are held aside from
+// the vault's own balance until they are.
+use soroban_sdk::{symbol_short, Address, Env, Map, Symbol};
+use syft_errors::bounded_iter;
+use crate::errors::VaultError;
+use crate::types::{VaultConfig, VaultState, MAX_ASSETS, MAX_EXIT_BATCH};
+
+const POSITION: Symbol = symbol_short!("POSITION");
+const MAX_REBALANCE_LOSS: Symbol = symbol_short!("MAX_LOSS");
+const EXIT_MIN: Symbol = symbol_short!("EXIT_MIN");
+const EXIT_BATCH: Symbol = symbol_short!("EXIT_BAT");
+const EXIT_CLAIM: Symbol = symbol_short!("EXIT_CLM");
+const EXIT_OWED: Symbol = symbol_short!("EXIT_OWED");
+
+/// Smallest withdrawal, in base asset value, that is batched (None when batching is off)
+pub fn batch_threshold(env: &Env) -> Option<i128> {
+    env.storage().instance().get(&EXIT_MIN)
+}
+
+/// Turn batching on at `min_amount`, or off
+pub fn set_batch_threshold(env: &Env, min_amount: Option<i128>) {
+    match min_amount {
+        Some(min_amount) => env.storage().instance().set(&EXIT_MIN, &min_amount),
+        None => env.storage().instance().remove(&EXIT_MIN),
+    }
+}
+
+/// Open batch: the ledger it was opened in and the shares queued per user
+pub fn pending_batch(env: &Env) -> Option<(u32, Map<Address, i128>)> {
+    env.storage().instance().get(&EXIT_BATCH)
+}
+
+/// Shares `user` has queued in the open batch
+pub fn pending_shares(env: &Env, user: &Address) -> i128 {
+    pending_batch(env)
+        .and_then(|(_, requests)| requests.get(user.clone()))
+        .unwrap_or(0)
+}
+
+/// Queue `shares` (already taken out of the user's position) for the open batch
+/// Opens a batch in the current ledger if there isn't one. Returns the user's
+/// queued total.
+pub fn queue(env: &Env, user: &Address, shares: i128) -> Result<i128, VaultError> {
+    let (ledger, mut requests) = pending_batch(env)
+        .unwrap_or((env.ledger().sequence(), Map::new(env)));
+
+    let queued = requests.get(user.clone()).unwrap_or(0);
+    if queued == 0 && requests.len() >= MAX_EXIT_BATCH {
+        return Err(VaultError::TooManyItems);
+    }
+    let queued = queued.checked_add(shares).ok_or(VaultError::InvalidAmount)?;
+    requests.set(user.clone(), queued);
+    env.storage().instance().set(&EXIT_BATCH, &(ledger, requests));
+
+    Ok(queued)
+}
+
+/// Settle the open batch if its ledger has closed
+/// Returns the base asset realized for it (0 when nothing was due).
+pub fn settle_due(env: &Env) -> Result<i128, VaultError> {
+    match pending_batch(env) {
+        Some((ledger, requests)) if ledger < env.ledger().sequence() => settle(env, &requests),
+        _ => Ok(0),
+    }
+}
+
+/// Settled base asset waiting to be claimed by `user`
+pub fn claimable(env: &Env, user: &Address) -> i128 {
+    env.storage().instance().get(&(EXIT_CLAIM, user.clone())).unwrap_or(0)
+}
+
+/// Remove and return `user`'s claimable amount
+pub fn take_claim(env: &Env, user: &Address) -> Result<i128, VaultError> {
+    let amount = claimable(env, user);
+    env.storage().instance().remove(&(EXIT_CLAIM, user.clone()));
+    if let Some((token, owed)) = owed_total(env) {
+        let owed = owed.checked_sub(amount).ok_or(VaultError::InvalidAmount)?;
+        env.storage().instance().set(&EXIT_OWED, &(token, owed));
+    }
+    Ok(amount)
+}
+
+/// Amount of `token` the vault holds for settled but unclaimed exits
+pub fn reserved(env: &Env, token: &Address) -> i128 {
+    match owed_total(env) {
+        Some((owed_token, owed)) if owed_token == *token => owed,
+        _ => 0,
+    }
+}
+
+fn owed_total(env: &Env) -> Option<(Address, i128)> {
+    env.storage().instance().get(&EXIT_OWED)
+}
+
+// Burn the batch's shares for one combined unwind and credit each participant
+// the same realized value per share, less their own performance fee
+fn settle(env: &Env, requests: &Map<Address, i128>) -> Result<i128, VaultError> {
+    let config: VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    let mut state: VaultState = crate::migrations::read_state(env)
+        .ok_or(VaultError::NotInitialized)?;
+    let base_token = crate::token_client::base_asset(env, &config.assets)?;
+
+    let users = requests.keys();
+    let mut batch_shares: i128 = 0;
+    for user in bounded_iter(&users, MAX_EXIT_BATCH, VaultError::TooManyItems)? {
+        batch_shares = batch_shares.checked_add(requests.get(user).unwrap_or(0))
+            .ok_or(VaultError::InvalidAmount)?;
+    }
+    if batch_shares <= 0 || state.total_shares < batch_shares {
+        return Err(VaultError::InvalidAmount);
+    }
+
+    let gross_total = batch_shares.checked_mul(state.total_value)
+        .and_then(|v| v.checked_div(state.total_shares))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    crate::buffer_pool::ensure_liquid(env, &base_token, gross_total)?;
+    let loss = unwind(env, &config, &base_token, gross_total)?;
+    let realized_total = gross_total.checked_sub(loss)
+        .ok_or(VaultError::InvalidAmount)?;
+    if crate::token_client::get_vault_balance(env, &base_token) < realized_total {
+        return Err(VaultError::InsufficientLiquidity);
+    }
+
+    for user in bounded_iter(&users, MAX_EXIT_BATCH, VaultError::TooManyItems)? {
+        let shares = requests.get(user.clone()).unwrap_or(0);
+        let realized = shares.checked_mul(realized_total)
+            .and_then(|v| v.checked_div(batch_shares))
+            .ok_or(VaultError::InvalidAmount)?;
+        let perf_fee = crate::fees::performance_fee(env, &user, shares, realized)?;
+        if perf_fee > 0 {
+            crate::token_client::transfer_from_vault(env, &base_token, &config.owner, perf_fee)?;
+        }
+
+        let net = realized.checked_sub(perf_fee).ok_or(VaultError::InvalidAmount)?;
+        let owed = claimable(env, &user).checked_add(net).ok_or(VaultError::InvalidAmount)?;
+        env.storage().instance().set(&(EXIT_CLAIM, user.clone()), &owed);
+        let total_owed = reserved(env, &base_token).checked_add(net).ok_or(VaultError::InvalidAmount)?;
+        env.storage().instance().set(&EXIT_OWED, &(base_token.clone(), total_owed));
+
+        // The entry price was kept for the fee above; drop it once nothing is left
+        if !env.storage().instance().has(&(POSITION, user.clone())) {
+            crate::fees::clear_entry(env, &user);
+        }
+
+        env.events().publish(
+            (Symbol::new(env, "exit_settled"), user),
+            (shares, realized, perf_fee),
+        );
+    }
+
+    state.total_shares = state.total_shares.checked_sub(batch_shares)
+        .ok_or(VaultError::InvalidAmount)?;
+    state.total_value = state.total_value.checked_sub(gross_total)
+        .ok_or(VaultError::InvalidAmount)?;
+    crate::migrations::write_state(env, &state);
+    env.storage().instance().remove(&EXIT_BATCH);
+    crate::flows::record_withdrawal(env, realized_total);
+    crate::stats::report(env, state.total_value);
+
+    env.events().publish(
+        (Symbol::new(env, "exit_batch"),),
+        (batch_shares, gross_total, realized_total),
+    );
+
+    Ok(realized_total)
+}
+
+// Sell other assets for the base asset until the vault holds `target` less
+// whatever the sales lost. Assets are valued 1:1 in raw units, the basis
+// total_value is kept in, so the loss is what was sold minus what came back.
+fn unwind(env: &Env, config: &VaultConfig, base_token: &Address, target: i128) -> Result<i128, VaultError> {
+    let max_loss_bps: Option<i128> = env.storage().instance().get(&MAX_REBALANCE_LOSS).unwrap_or(None);
+    let mut loss: i128 = 0;
+
+    for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+        if asset == *base_token {
+            continue;
+        }
+
+        let liquid = crate::token_client::get_vault_balance(env, base_token);
+        let shortfall = target - loss - liquid;
+        if shortfall <= 0 {
+            break;
+        }
+
+        let amount_in = shortfall.min(crate::token_client::get_vault_balance(env, &asset));
+        if amount_in <= 0 {
+            continue;
+        }
+
+        let router = config.router_address.clone().ok_or(VaultError::RouterNotSet)?;
+        let min_amount_out = match max_loss_bps {
+            Some(bps) => amount_in * (10000 - bps) / 10000,
+            None => 0,
+        };
+        crate::token_client::approve_router(env, &asset, &router, amount_in)?;
+        let amount_out = crate::swap_router::swap_via_router(
+            env,
+            &router,
+            &asset,
+            base_token,
+            amount_in,
+            min_amount_out,
+        )?;
+
+        loss = loss.checked_add(amount_in - amount_out)
+            .ok_or(VaultError::InvalidAmount)?;
+    }
+
+    Ok(loss)
+}
+
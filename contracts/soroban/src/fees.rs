@@ -0,0 +1,187 @@
+// Performance fee accounting
+// The performance fee is charged per user at withdrawal time, on the gain
+// between that user's own entry share price and the current share price.
+// A user who enters after a drawdown only pays on gains above their entry,
+// and a user who entered before it doesn't escape fees on the recovery.
+use soroban_sdk::{symbol_short, Address, Env, Symbol};
+use crate::errors::VaultError;
+use crate::types::VaultState;
+
+const ENTRY_PRICE: Symbol = symbol_short!("ENTRY_PX");
+const PERF_FEE: Symbol = symbol_short!("PERF_FEE");
+const PERF_FEE_RECIPIENT: Symbol = symbol_short!("PERF_RCPT");
+const SHARE_SCALE: Symbol = symbol_short!("SHR_SCALE");
+const SWAP_FEES: Symbol = symbol_short!("SWAP_FEES");
+
+/// Share prices are fixed point with 7 decimals (1_0000000 = 1.0)
+pub const PRICE_SCALE: i128 = 1_0000000;
+
+/// Decimals of vault shares, whatever the base asset uses
+/// Matches Stellar's 7-decimal assets. A base asset with fewer decimals (USDC
+/// with 6) is scaled up so shares keep full precision; one with more keeps its own.
+pub const SHARE_DECIMALS: u32 = 7;
+
+/// DEX fee taken from each swap's input, per thousand (Soroswap's 0.3%)
+pub const SWAP_FEE_PER_MILLE: i128 = 3;
+
+/// Highest performance fee the owner may set (100%)
+pub const MAX_PERFORMANCE_FEE_BPS: i128 = 10000;
+
+/// Share units minted per base asset unit at a share price of 1.0
+/// Fixed when a deposit lands in an empty vault; 1 for vaults that predate it.
+pub fn share_scale(env: &Env) -> i128 {
+    env.storage().instance().get(&SHARE_SCALE).unwrap_or(1)
+}
+
+/// Fix the share scale for a base asset with `base_decimals` and return it
+/// Only valid while the vault has no shares outstanding.
+pub fn set_share_scale(env: &Env, base_decimals: u32) -> i128 {
+    let scale = 10i128.pow(SHARE_DECIMALS.saturating_sub(base_decimals));
+    env.storage().instance().set(&SHARE_SCALE, &scale);
+    scale
+}
+
+/// PRICE_SCALE times the share scale: divides share-space amounts back to base units
+pub fn price_divisor(env: &Env) -> Result<i128, VaultError> {
+    PRICE_SCALE.checked_mul(share_scale(env))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Current value of one share, scaled by PRICE_SCALE
+/// Measured against the share scale, so an empty vault prices shares at 1.0
+/// whatever the base asset's decimals, matching the first deposit.
+pub fn share_price(env: &Env, state: &VaultState) -> Result<i128, VaultError> {
+    if state.total_shares == 0 {
+        return Ok(PRICE_SCALE);
+    }
+
+    state.total_value
+        .checked_mul(price_divisor(env)?)
+        .and_then(|v| v.checked_div(state.total_shares))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Performance fee rate in basis points (0 when unset)
+pub fn get_performance_fee_bps(env: &Env) -> i128 {
+    env.storage().instance().get(&PERF_FEE).unwrap_or(0)
+}
+
+/// Store the performance fee rate in basis points
+pub fn set_performance_fee_bps(env: &Env, fee_bps: i128) -> Result<(), VaultError> {
+    if !(0..=MAX_PERFORMANCE_FEE_BPS).contains(&fee_bps) {
+        return Err(VaultError::InvalidConfiguration);
+    }
+
+    env.storage().instance().set(&PERF_FEE, &fee_bps);
+    Ok(())
+}
+
+/// Address performance fees are minted to as shares, if set
+/// When unset the fee is paid to the owner in the base asset instead.
+pub fn get_performance_fee_recipient(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&PERF_FEE_RECIPIENT)
+}
+
+/// Configure (or clear) the performance fee recipient
+pub fn set_performance_fee_recipient(env: &Env, recipient: Option<Address>) {
+    match recipient {
+        Some(recipient) => env.storage().instance().set(&PERF_FEE_RECIPIENT, &recipient),
+        None => env.storage().instance().remove(&PERF_FEE_RECIPIENT),
+    }
+}
+
+/// Shares of a `shares`-share withdrawal worth `fee` out of `gross_amount`
+/// Rounded down, so any remainder stays with the remaining holders.
+pub fn fee_shares(shares: i128, fee: i128, gross_amount: i128) -> Result<i128, VaultError> {
+    if fee <= 0 || gross_amount <= 0 {
+        return Ok(0);
+    }
+
+    shares.checked_mul(fee)
+        .and_then(|v| v.checked_div(gross_amount))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Entry share price recorded for a user (0 when they hold no shares)
+pub fn get_entry_price(env: &Env, user: &Address) -> i128 {
+    env.storage().instance().get(&(ENTRY_PRICE, user.clone())).unwrap_or(0)
+}
+
+/// Fold a deposit of `new_shares` at `price` into the user's entry price
+/// Top-ups move the entry price to the share-weighted average of both lots
+pub fn record_entry(
+    env: &Env,
+    user: &Address,
+    old_shares: i128,
+    new_shares: i128,
+    price: i128,
+) -> Result<(), VaultError> {
+    let total_shares = old_shares.checked_add(new_shares)
+        .ok_or(VaultError::InvalidAmount)?;
+    if total_shares <= 0 {
+        return Ok(());
+    }
+
+    let old_cost = get_entry_price(env, user)
+        .checked_mul(old_shares)
+        .ok_or(VaultError::InvalidAmount)?;
+    let new_cost = price
+        .checked_mul(new_shares)
+        .ok_or(VaultError::InvalidAmount)?;
+
+    let entry_price = old_cost
+        .checked_add(new_cost)
+        .and_then(|v| v.checked_div(total_shares))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    env.storage().instance().set(&(ENTRY_PRICE, user.clone()), &entry_price);
+    Ok(())
+}
+
+/// Forget the entry price of a user who has fully exited
+pub fn clear_entry(env: &Env, user: &Address) {
+    env.storage().instance().remove(&(ENTRY_PRICE, user.clone()));
+}
+
+/// Performance fee owed by `user` for withdrawing `shares` worth `gross_amount`
+/// Only the gain above the user's entry price is charged; losses charge nothing.
+pub fn performance_fee(
+    env: &Env,
+    user: &Address,
+    shares: i128,
+    gross_amount: i128,
+) -> Result<i128, VaultError> {
+    let fee_bps = get_performance_fee_bps(env);
+    if fee_bps == 0 {
+        return Ok(0);
+    }
+
+    // Cost basis of the shares being burned at the user's entry price
+    let cost_basis = get_entry_price(env, user)
+        .checked_mul(shares)
+        .and_then(|v| v.checked_div(price_divisor(env).ok()?))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    let gain = gross_amount.checked_sub(cost_basis)
+        .ok_or(VaultError::InvalidAmount)?;
+    if gain <= 0 {
+        return Ok(0);
+    }
+
+    gain.checked_mul(fee_bps)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Estimated DEX fees paid over the vault's lifetime
+/// Raw units of each swap's input token, summed on the same basis as total_value.
+pub fn total_swap_fees_paid(env: &Env) -> i128 {
+    env.storage().instance().get(&SWAP_FEES).unwrap_or(0)
+}
+
+/// Add the fee on a swap of `amount_in` to the lifetime total
+pub fn record_swap_fee(env: &Env, amount_in: i128) {
+    let fee = amount_in.saturating_mul(SWAP_FEE_PER_MILLE) / 1000;
+    let total = total_swap_fees_paid(env).saturating_add(fee);
+    env.storage().instance().set(&SWAP_FEES, &total);
+}
@@ -0,0 +1,24 @@
+// Lifetime deposit and withdrawal totals
+// Gross base asset paid in and paid out over the vault's life, in raw units
+// like total_value. Together with total_value they give the vault's net PnL:
+// total_value + withdrawn - deposited.
+use soroban_sdk::{symbol_short, Env, Symbol};
+
+const FLOWS: Symbol = symbol_short!("FLOWS");
+
+/// (deposited, withdrawn) so far
+pub fn flow_stats(env: &Env) -> (i128, i128) {
+    env.storage().instance().get(&FLOWS).unwrap_or((0, 0))
+}
+
+/// Add a deposit credited at `amount` of base asset
+pub fn record_deposit(env: &Env, amount: i128) {
+    let (deposited, withdrawn) = flow_stats(env);
+    env.storage().instance().set(&FLOWS, &(deposited.saturating_add(amount), withdrawn));
+}
+
+/// Add a withdrawal paying out `amount` of base asset, fees included
+pub fn record_withdrawal(env: &Env, amount: i128) {
+    let (deposited, withdrawn) = flow_stats(env);
+    env.storage().instance().set(&FLOWS, &(deposited, withdrawn.saturating_add(amount)));
+}
@@ -0,0 +1,64 @@
+// Keeper liveness
+// Every trigger call is a heartbeat. If keepers go quiet for longer than the
+// configured silence, deposits are flagged (and optionally refused) so money
+// doesn't keep flowing into a strategy nobody is running.
+use soroban_sdk::{symbol_short, Env, Symbol};
+use crate::errors::VaultError;
+
+const HEARTBEAT: Symbol = symbol_short!("KPR_BEAT");
+const MAX_SILENCE: Symbol = symbol_short!("KPR_SIL");
+const BLOCK_WHEN_STALE: Symbol = symbol_short!("KPR_BLK");
+
+/// Record keeper activity now
+pub fn heartbeat(env: &Env) {
+    env.storage().instance().set(&HEARTBEAT, &crate::clock::now(env));
+}
+
+/// Timestamp of the last keeper activity (0 if none was ever recorded)
+pub fn last_heartbeat(env: &Env) -> u64 {
+    env.storage().instance().get(&HEARTBEAT).unwrap_or(0)
+}
+
+/// Configured silence limit in seconds and whether stale vaults refuse deposits
+pub fn silence_config(env: &Env) -> (Option<u64>, bool) {
+    (
+        env.storage().instance().get(&MAX_SILENCE),
+        env.storage().instance().get(&BLOCK_WHEN_STALE).unwrap_or(false),
+    )
+}
+
+/// Configure (or with None, disable) the silence limit
+/// Restarts the clock, so enabling it never makes the vault stale at once.
+pub fn set_silence_config(env: &Env, max_silence_secs: Option<u64>, block_deposits: bool) {
+    match max_silence_secs {
+        Some(secs) => env.storage().instance().set(&MAX_SILENCE, &secs),
+        None => env.storage().instance().remove(&MAX_SILENCE),
+    }
+    env.storage().instance().set(&BLOCK_WHEN_STALE, &block_deposits);
+    heartbeat(env);
+}
+
+/// Whether keepers have been silent past the limit
+pub fn is_stale(env: &Env) -> bool {
+    match silence_config(env).0 {
+        Some(max_silence) => crate::clock::now(env).saturating_sub(last_heartbeat(env)) > max_silence,
+        None => false,
+    }
+}
+
+/// Called on deposit: warn when keepers are stale, and refuse if configured to
+pub fn check_deposit(env: &Env) -> Result<(), VaultError> {
+    if !is_stale(env) {
+        return Ok(());
+    }
+
+    env.events().publish(
+        (Symbol::new(env, "keeper_stale"),),
+        (last_heartbeat(env), crate::clock::now(env)),
+    );
+
+    if silence_config(env).1 {
+        return Err(VaultError::KeeperStale);
+    }
+    Ok(())
+}
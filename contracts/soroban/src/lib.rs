@@ -4,6 +4,7 @@
 mod types;
 mod vault;
 mod engine;
+mod clock;  // Ledger time, overridable for read-only evaluation
 mod rebalance;
 mod events;
 mod errors;
@@ -13,6 +14,20 @@ mod soroswap_router;
 mod pool_client;  // Direct pool interaction
 mod staking_client;  // Liquid staking integration
 mod liquidity_router;  // Liquidity provision
+mod buffer_pool;  // Yield on the idle withdrawal buffer
+mod fees;  // Per-user performance fee accounting
+mod profit_share;  // Enforced profit split with NFT holders
+mod op_ids;  // Replay protection for keeper calls
+mod migrations;  // Storage layout versions and upgrades
+mod keeper;  // Keeper heartbeat and stale-strategy guard
+mod pricing;  // Oracle and spot prices with fallback
+mod valuation;  // One valuation path for every cross-asset comparison
+mod exit_batch;  // Same-ledger batching of large withdrawals
+mod network;  // Network tag and factory integration denylist
+mod stats;  // TVL and holder count reported to the factory
+mod flows;  // Lifetime deposit and withdrawal totals
+mod pause;  // Emergency stop and proportional-exit emergency mode
+mod wind_down;  // Closing a vault at a frozen redemption price
 // mod factory;  // Factory should be a separate contract
 // mod vault_nft;  // VaultNFT should be a separate contract
 // mod nft_types;
@@ -23,3 +38,10 @@ pub use vault::*;
 // Export types and errors for external use
 pub use types::*;
 pub use errors::*;
+
+// Scenario builders and mock integrations; never part of the release wasm
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+
+#[cfg(test)]
+mod test;
@@ -43,6 +43,15 @@ pub trait LiquidityRouterInterface {
     ) -> i128;
 }
 
+/// Amounts offered to a pool, with its current reserves in the same token order
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidityAmounts {
+    pub amount_a: i128,
+    pub amount_b: i128,
+    pub reserve_a: i128,
+    pub reserve_b: i128,
+}
+
 /// Add liquidity to a Soroswap pool
 /// This adds both tokens to the pool and receives LP tokens
 pub fn add_liquidity_to_pool(
@@ -50,40 +59,31 @@ pub fn add_liquidity_to_pool(
     router_address: &Address,
     token_a: &Address,
     token_b: &Address,
-    amount_a: i128,
-    amount_b: i128,
-    slippage_percent: i128, // e.g., 5 for 5% slippage
+    amounts: &LiquidityAmounts,
+    slippage_percent: Option<i128>,  // Flat override, e.g. Some(5) for 5%; None derives from reserves
 ) -> Result<(i128, i128, i128), crate::errors::VaultError> {
     use crate::errors::VaultError;
     
+    let (amount_a, amount_b) = (amounts.amount_a, amounts.amount_b);
     if amount_a <= 0 || amount_b <= 0 {
         return Err(VaultError::InvalidAmount);
     }
-    
-    if slippage_percent < 0 || slippage_percent > 100 {
-        return Err(VaultError::InvalidConfiguration);
-    }
 
     let router_client = LiquidityRouterClient::new(env, router_address);
     let vault_address = env.current_contract_address();
     
-    // Calculate minimum amounts based on slippage tolerance
-    let amount_a_min = amount_a
-        .checked_mul(100 - slippage_percent)
-        .and_then(|v| v.checked_div(100))
-        .ok_or(VaultError::InvalidAmount)?;
-    
-    let amount_b_min = amount_b
-        .checked_mul(100 - slippage_percent)
-        .and_then(|v| v.checked_div(100))
-        .ok_or(VaultError::InvalidAmount)?;
+    // Calculate minimum amounts from the flat override or the pool's depth
+    let (amount_a_min, amount_b_min) = match slippage_percent {
+        Some(percent) => flat_min_amounts(amount_a, amount_b, percent)?,
+        None => derive_min_amounts(amount_a, amount_b, amounts.reserve_a, amounts.reserve_b)?,
+    };
     
     // Approve router to spend our tokens
     crate::token_client::approve_router(env, token_a, router_address, amount_a)?;
     crate::token_client::approve_router(env, token_b, router_address, amount_b)?;
     
     // Set deadline to 1 hour from now
-    let deadline = env.ledger().timestamp() + 3600;
+    let deadline = crate::clock::now(env) + 3600;
     
     // Add liquidity through router
     let (lp_tokens, actual_a, actual_b) = router_client.add_liquidity(
@@ -104,6 +104,75 @@ pub fn add_liquidity_to_pool(
     Ok((lp_tokens, actual_a, actual_b))
 }
 
+/// Base tolerance applied even to very deep pools (0.5%)
+pub const BASE_LP_SLIPPAGE_BPS: i128 = 50;
+/// Upper bound for the derived tolerance (20%)
+pub const MAX_LP_SLIPPAGE_BPS: i128 = 2000;
+
+/// Minimum amounts for a flat slippage percentage
+pub fn flat_min_amounts(
+    amount_a: i128,
+    amount_b: i128,
+    slippage_percent: i128,
+) -> Result<(i128, i128), crate::errors::VaultError> {
+    use crate::errors::VaultError;
+
+    if slippage_percent < 0 || slippage_percent > 100 {
+        return Err(VaultError::InvalidConfiguration);
+    }
+
+    let amount_a_min = amount_a
+        .checked_mul(100 - slippage_percent)
+        .and_then(|v| v.checked_div(100))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    let amount_b_min = amount_b
+        .checked_mul(100 - slippage_percent)
+        .and_then(|v| v.checked_div(100))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    Ok((amount_a_min, amount_b_min))
+}
+
+/// Minimum amounts derived from the pool's depth
+/// The tolerance grows with our share of the pool after the deposit:
+/// tolerance_bps = 50 + amount * 10000 / (reserve + amount), capped at 20%.
+/// The larger of the two sides' impacts is applied to both amounts.
+/// An empty pool gets the base tolerance since we set the price ourselves.
+pub fn derive_min_amounts(
+    amount_a: i128,
+    amount_b: i128,
+    reserve_a: i128,
+    reserve_b: i128,
+) -> Result<(i128, i128), crate::errors::VaultError> {
+    use crate::errors::VaultError;
+
+    let impact = |amount: i128, reserve: i128| -> Result<i128, VaultError> {
+        if reserve <= 0 {
+            return Ok(0);
+        }
+        amount
+            .checked_mul(10000)
+            .and_then(|v| v.checked_div(reserve.checked_add(amount)?))
+            .ok_or(VaultError::InvalidAmount)
+    };
+
+    let impact_bps = impact(amount_a, reserve_a)?.max(impact(amount_b, reserve_b)?);
+    let tolerance_bps = (BASE_LP_SLIPPAGE_BPS + impact_bps).min(MAX_LP_SLIPPAGE_BPS);
+
+    let amount_a_min = amount_a
+        .checked_mul(10000 - tolerance_bps)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    let amount_b_min = amount_b
+        .checked_mul(10000 - tolerance_bps)
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    Ok((amount_a_min, amount_b_min))
+}
+
 /// Remove liquidity from a Soroswap pool
 /// This burns LP tokens and receives both tokens back
 pub fn remove_liquidity_from_pool(
@@ -133,7 +202,7 @@ pub fn remove_liquidity_from_pool(
     let amount_b_min = 0;
     
     // Set deadline to 1 hour from now
-    let deadline = env.ledger().timestamp() + 3600;
+    let deadline = crate::clock::now(env) + 3600;
     
     // Remove liquidity through router
     let (amount_a, amount_b) = router_client.remove_liquidity(
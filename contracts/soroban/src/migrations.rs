@@ -0,0 +1,141 @@
+// Storage layout versioning
+// Every change to what the vault keeps in storage bumps STORAGE_VERSION and
+// adds a step here that rewrites a vault from the previous layout. Vaults
+// created before versioning carry no version entry and count as version 1.
+//
+// The config, state and positions are stored wrapped in StoredConfig,
+// StoredState and StoredPosition, tagged with the layout of the struct inside,
+// and only read and written through the helpers below. Changing one of those
+// structs means keeping the old layout as a legacy struct under its variant,
+// adding a variant for the new one, and upgrading old variants as they are
+// read, so a vault upgraded to new code keeps working before it is migrated.
+// Entries written before the wrappers are the V1 layout, unwrapped.
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Symbol, TryFromVal, Val};
+use crate::errors::VaultError;
+use crate::types::{UserPosition, VaultConfig, VaultState};
+
+const CONFIG: Symbol = symbol_short!("CONFIG");
+const STATE: Symbol = symbol_short!("STATE");
+const POSITION: Symbol = symbol_short!("POSITION");
+const STORAGE_VERSION_KEY: Symbol = symbol_short!("STOR_VER");
+
+/// Layout written by this code
+/// 1: original layout
+/// 2: base asset pinned under BASE_AST
+/// 3: config and state wrapped in StoredConfig and StoredState (positions
+///    are wrapped in StoredPosition as they are next written)
+pub const STORAGE_VERSION: u32 = 3;
+
+/// VaultConfig as kept in storage, by layout
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StoredConfig {
+    V1(VaultConfig),
+}
+
+/// VaultState as kept in storage, by layout
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StoredState {
+    V1(VaultState),
+}
+
+/// UserPosition as kept in storage, by layout
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StoredPosition {
+    V1(UserPosition),
+}
+
+/// The vault's config, upgraded to the current layout
+pub fn read_config(env: &Env) -> Option<VaultConfig> {
+    let stored: Val = env.storage().instance().get(&CONFIG)?;
+    match StoredConfig::try_from_val(env, &stored) {
+        Ok(StoredConfig::V1(config)) => Some(config),
+        Err(_) => VaultConfig::try_from_val(env, &stored).ok(),
+    }
+}
+
+/// Store the vault's config in the current layout
+pub fn write_config(env: &Env, config: &VaultConfig) {
+    env.storage().instance().set(&CONFIG, &StoredConfig::V1(config.clone()));
+}
+
+/// The vault's state, upgraded to the current layout
+pub fn read_state(env: &Env) -> Option<VaultState> {
+    let stored: Val = env.storage().instance().get(&STATE)?;
+    match StoredState::try_from_val(env, &stored) {
+        Ok(StoredState::V1(state)) => Some(state),
+        Err(_) => VaultState::try_from_val(env, &stored).ok(),
+    }
+}
+
+/// Store the vault's state in the current layout
+pub fn write_state(env: &Env, state: &VaultState) {
+    env.storage().instance().set(&STATE, &StoredState::V1(state.clone()));
+}
+
+/// `user`'s position, upgraded to the current layout
+pub fn read_position(env: &Env, user: &Address) -> Option<UserPosition> {
+    let stored: Val = env.storage().instance().get(&(POSITION, user.clone()))?;
+    match StoredPosition::try_from_val(env, &stored) {
+        Ok(StoredPosition::V1(position)) => Some(position),
+        Err(_) => UserPosition::try_from_val(env, &stored).ok(),
+    }
+}
+
+/// Store `user`'s position in the current layout
+pub fn write_position(env: &Env, user: &Address, position: &UserPosition) {
+    env.storage().instance().set(&(POSITION, user.clone()), &StoredPosition::V1(position.clone()));
+}
+
+/// Layout version of the data in storage (1 when unset)
+pub fn stored_version(env: &Env) -> u32 {
+    env.storage().instance().get(&STORAGE_VERSION_KEY).unwrap_or(1)
+}
+
+/// Mark storage as written in the current layout (on initialize)
+pub fn stamp_current(env: &Env) {
+    env.storage().instance().set(&STORAGE_VERSION_KEY, &STORAGE_VERSION);
+}
+
+/// Bring storage up to STORAGE_VERSION one step at a time
+/// Returns the version migrated from. Storage from a newer layout than this
+/// code understands is rejected rather than guessed at.
+pub fn migrate(env: &Env) -> Result<u32, VaultError> {
+    let from = stored_version(env);
+    if from > STORAGE_VERSION {
+        return Err(VaultError::InvalidConfiguration);
+    }
+
+    let mut version = from;
+    while version < STORAGE_VERSION {
+        match version {
+            1 => migrate_v1_to_v2(env)?,
+            2 => migrate_v2_to_v3(env)?,
+            _ => return Err(VaultError::InvalidConfiguration),
+        }
+        version += 1;
+    }
+
+    stamp_current(env);
+    Ok(from)
+}
+
+// v1 vaults treat whatever asset comes first as the base asset; pin it
+fn migrate_v1_to_v2(env: &Env) -> Result<(), VaultError> {
+    let config = read_config(env).ok_or(VaultError::NotInitialized)?;
+    let base_token = crate::token_client::base_asset(env, &config.assets)?;
+    crate::token_client::pin_base_asset(env, &base_token);
+    Ok(())
+}
+
+// Wrap the config and state; positions can't be listed, so they are wrapped
+// as they are next written and read either way until then
+fn migrate_v2_to_v3(env: &Env) -> Result<(), VaultError> {
+    let config = read_config(env).ok_or(VaultError::NotInitialized)?;
+    write_config(env, &config);
+    let state = read_state(env).ok_or(VaultError::NotInitialized)?;
+    write_state(env, &state);
+    Ok(())
+}
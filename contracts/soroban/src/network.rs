@@ -0,0 +1,65 @@
+// Network tag and integration denylist
+// The deploying factory stamps each vault with the network it serves. On a
+// mainnet vault, integrations the factory admin has denylisted (typically
+// testnet contracts) can't be configured.
+use soroban_sdk::{contractclient, symbol_short, Address, Env, Symbol, Vec};
+use crate::errors::VaultError;
+use crate::types::VaultConfig;
+
+const NETWORK: Symbol = symbol_short!("NETWORK");
+
+/// Network tag that turns the denylist into a hard check
+pub const MAINNET: Symbol = symbol_short!("mainnet");
+
+/// The parts of the stamping factory a vault calls into
+#[contractclient(name = "StampingFactoryClient")]
+pub trait StampingFactoryInterface {
+    /// Whether the factory admin has denylisted `address`
+    fn is_denied(env: Env, address: Address) -> bool;
+
+    /// Replace this vault's contribution to the protocol totals (see stats.rs)
+    fn report_stats(env: Env, vault: Address, tvl: i128, users: u32);
+}
+
+/// Factory that stamped this vault and the network it recorded, if any
+pub fn stamp(env: &Env) -> Option<(Address, Symbol)> {
+    env.storage().instance().get(&NETWORK)
+}
+
+/// Record the stamping factory and its network
+pub fn set_stamp(env: &Env, factory: &Address, network: &Symbol) {
+    env.storage().instance().set(&NETWORK, &(factory.clone(), network.clone()));
+}
+
+/// Whether the stamping factory denylists `address` (false for unstamped vaults)
+pub fn is_denied(env: &Env, address: &Address) -> bool {
+    match stamp(env) {
+        Some((factory, _)) => StampingFactoryClient::new(env, &factory).is_denied(address),
+        None => false,
+    }
+}
+
+/// Reject a denylisted integration on a mainnet vault
+pub fn check_integration(env: &Env, address: &Address) -> Result<(), VaultError> {
+    match stamp(env) {
+        Some((_, network)) if network == MAINNET && is_denied(env, address) => {
+            Err(VaultError::DeniedIntegration)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Every configured integration, tagged by kind as factory_set_integration names them
+pub fn integrations(env: &Env, config: &VaultConfig) -> Vec<(Symbol, Address)> {
+    let mut found = Vec::new(env);
+    if let Some(router) = &config.router_address {
+        found.push_back((symbol_short!("router"), router.clone()));
+    }
+    if let Some(staking) = &config.staking_pool_address {
+        found.push_back((symbol_short!("staking"), staking.clone()));
+    }
+    if let Some(factory) = &config.factory_address {
+        found.push_back((symbol_short!("factory"), factory.clone()));
+    }
+    found
+}
@@ -8,7 +8,7 @@ pub struct VaultNFT {
     pub vault_address: Address,
     pub ownership_percentage: i128,
     pub holder: Address,
-    pub metadata: String,
+    pub metadata: NFTMetadata,
 }
 
 #[contracttype]
@@ -0,0 +1,28 @@
+// Idempotent keeper operations
+// Keepers retry transactions that may already have landed. Calls that carry
+// an op id are remembered in a rolling window so a replay becomes a no-op.
+use soroban_sdk::{symbol_short, BytesN, Env, Symbol, Vec};
+
+const OP_IDS: Symbol = symbol_short!("OP_IDS");
+
+/// Number of most recent op ids remembered; older ids may be reused
+pub const OP_ID_WINDOW: u32 = 32;
+
+fn recent(env: &Env) -> Vec<BytesN<32>> {
+    env.storage().instance().get(&OP_IDS).unwrap_or(Vec::new(env))
+}
+
+/// Whether `op_id` is among the recently executed operations
+pub fn seen(env: &Env, op_id: &BytesN<32>) -> bool {
+    recent(env).contains(op_id)
+}
+
+/// Remember `op_id` as executed, evicting the oldest beyond the window
+pub fn remember(env: &Env, op_id: &BytesN<32>) {
+    let mut ids = recent(env);
+    ids.push_back(op_id.clone());
+    while ids.len() > OP_ID_WINDOW {
+        ids.pop_front();
+    }
+    env.storage().instance().set(&OP_IDS, &ids);
+}
@@ -0,0 +1,74 @@
+// Emergency stop and emergency mode
+// While paused nothing new goes into the vault or its strategy: deposits are
+// refused, and keeper triggers for rebalances, stakes and liquidity provision
+// are skipped with SkipReason::Paused. Withdrawals and unwinding positions
+// stay open, so users can always exit.
+// Emergency mode goes further for incidents where the DEX or pools can't be
+// trusted: on top of the pause, withdrawals that may swap or unwind are
+// refused too, and users exit with emergency_withdraw, a proportional in-kind
+// payout of what the vault holds. The guardian or owner can enter it; only
+// the owner can leave it.
+use soroban_sdk::{symbol_short, Address, Env, Symbol};
+use crate::errors::VaultError;
+
+const PAUSED: Symbol = symbol_short!("PAUSED");
+const EMERGENCY: Symbol = symbol_short!("EMERGENCY");
+const GUARDIAN: Symbol = symbol_short!("GUARDIAN");
+
+/// Whether the vault is paused
+pub fn paused(env: &Env) -> bool {
+    env.storage().instance().get(&PAUSED).unwrap_or(false)
+}
+
+/// Pause or unpause the vault
+pub fn set_paused(env: &Env, paused: bool) {
+    if paused {
+        env.storage().instance().set(&PAUSED, &true);
+    } else {
+        env.storage().instance().remove(&PAUSED);
+    }
+}
+
+/// Fail with EmergencyMode in emergency mode, or Paused while paused
+pub fn when_not_paused(env: &Env) -> Result<(), VaultError> {
+    when_not_emergency(env)?;
+    if paused(env) {
+        return Err(VaultError::Paused);
+    }
+    Ok(())
+}
+
+/// Whether the vault is in emergency mode
+pub fn emergency_mode(env: &Env) -> bool {
+    env.storage().instance().get(&EMERGENCY).unwrap_or(false)
+}
+
+/// Enter or leave emergency mode
+pub fn set_emergency_mode(env: &Env, active: bool) {
+    if active {
+        env.storage().instance().set(&EMERGENCY, &true);
+    } else {
+        env.storage().instance().remove(&EMERGENCY);
+    }
+}
+
+/// Fail with EmergencyMode while emergency mode is active
+pub fn when_not_emergency(env: &Env) -> Result<(), VaultError> {
+    if emergency_mode(env) {
+        return Err(VaultError::EmergencyMode);
+    }
+    Ok(())
+}
+
+/// Address allowed to enter emergency mode besides the owner, if any
+pub fn guardian(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&GUARDIAN)
+}
+
+/// Configure (or clear) the guardian
+pub fn set_guardian(env: &Env, guardian: Option<Address>) {
+    match guardian {
+        Some(guardian) => env.storage().instance().set(&GUARDIAN, &guardian),
+        None => env.storage().instance().remove(&GUARDIAN),
+    }
+}
@@ -25,6 +25,54 @@ pub trait LiquidityPoolInterface {
     
     /// Get token1 address
     fn token_1(env: Env) -> Address;
+    
+    /// Burn the LP tokens sent to the pool and pay the underlying out to `to`
+    /// Returns (amount0, amount1)
+    fn burn(env: Env, to: Address) -> (i128, i128);
+}
+
+/// Reserves of a pool oriented as (reserve_in, reserve_out) for a swap out of `token_in`
+/// Every caller orients through here, so a pool listing its tokens in either
+/// order is read the same way everywhere.
+pub fn orient_reserves(
+    env: &Env,
+    pool_address: &Address,
+    token_in: &Address,
+) -> Result<(i128, i128), crate::errors::VaultError> {
+    use crate::errors::VaultError;
+    
+    let pool_client = LiquidityPoolClient::new(env, pool_address);
+    let (reserve0, reserve1) = pool_client.try_get_reserves()
+        .ok()
+        .and_then(|r| r.ok())
+        .ok_or(VaultError::InvalidConfiguration)?;
+    
+    if is_token0(env, pool_address, token_in)? {
+        Ok((reserve0, reserve1))
+    } else {
+        Ok((reserve1, reserve0))
+    }
+}
+
+// Whether `token` is the pool's token0 (InvalidConfiguration if it's neither)
+fn is_token0(
+    env: &Env,
+    pool_address: &Address,
+    token: &Address,
+) -> Result<bool, crate::errors::VaultError> {
+    use crate::errors::VaultError;
+    
+    let pool_client = LiquidityPoolClient::new(env, pool_address);
+    let token0 = pool_client.try_token_0().ok().and_then(|r| r.ok());
+    let token1 = pool_client.try_token_1().ok().and_then(|r| r.ok());
+    
+    if token0.as_ref() == Some(token) {
+        Ok(true)
+    } else if token1.as_ref() == Some(token) {
+        Ok(false)
+    } else {
+        Err(VaultError::InvalidConfiguration)
+    }
 }
 
 /// Execute a direct swap through a liquidity pool
@@ -46,29 +94,12 @@ pub fn swap_via_pool(
     let pool_client = LiquidityPoolClient::new(env, pool_address);
     let vault_address = env.current_contract_address();
     
-    // Get pool token addresses to determine which is token0 and token1
-    let token0 = pool_client.token_0();
-    let token1 = pool_client.token_1();
-    
-    // Determine which token we're swapping from/to
-    let (is_token0_in, is_token0_out) = if from_token == &token0 {
-        (true, false)
-    } else if from_token == &token1 {
-        (false, true)
-    } else {
-        return Err(VaultError::InvalidConfiguration);
-    };
-    
-    // Get current reserves to calculate output
-    let (reserve0, reserve1) = pool_client.get_reserves();
+    // Determine which side of the pool we're swapping from
+    let is_token0_in = is_token0(env, pool_address, from_token)?;
     
     // Calculate output amount using constant product formula (x * y = k)
     // With 0.3% fee: amount_out = (amount_in * 997 * reserve_out) / (reserve_in * 1000 + amount_in * 997)
-    let (reserve_in, reserve_out) = if is_token0_in {
-        (reserve0, reserve1)
-    } else {
-        (reserve1, reserve0)
-    };
+    let (reserve_in, reserve_out) = orient_reserves(env, pool_address, from_token)?;
     
     let amount_in_with_fee = amount_in
         .checked_mul(997)
@@ -118,9 +149,49 @@ pub fn swap_via_pool(
         &vault_address,
     );
     
+    crate::fees::record_swap_fee(env, amount_in);
+    
     Ok(amount_out)
 }
 
+/// Remove liquidity by burning LP tokens at the pool, bypassing the router
+/// Soroswap pairs are their own LP token: the tokens are sent to the pair and
+/// burned there. Returns the amounts received as (token_a, token_b).
+pub fn remove_liquidity_via_pool(
+    env: &Env,
+    pool_address: &Address,
+    token_a: &Address,
+    lp_tokens: i128,
+) -> Result<(i128, i128), crate::errors::VaultError> {
+    use crate::errors::VaultError;
+    
+    if lp_tokens <= 0 {
+        return Err(VaultError::InvalidAmount);
+    }
+    
+    let pool_client = LiquidityPoolClient::new(env, pool_address);
+    let vault_address = env.current_contract_address();
+    
+    crate::token_client::transfer_tokens(
+        env,
+        pool_address,
+        &vault_address,
+        pool_address,
+        lp_tokens,
+    )?;
+    
+    let (amount0, amount1) = pool_client.burn(&vault_address);
+    if amount0 <= 0 || amount1 <= 0 {
+        return Err(VaultError::InvalidAmount);
+    }
+    
+    if is_token0(env, pool_address, token_a)? {
+        Ok((amount0, amount1))
+    } else {
+        Ok((amount1, amount0))
+    }
+}
+
 /// Calculate expected output for a swap without executing it
 /// This uses the same constant product formula as the actual swap
 pub fn calculate_swap_output(
@@ -136,30 +207,8 @@ pub fn calculate_swap_output(
         return Err(VaultError::InvalidAmount);
     }
 
-    let pool_client = LiquidityPoolClient::new(env, pool_address);
-    
-    // Get pool token addresses to determine which is token0 and token1
-    let token0 = pool_client.token_0();
-    let token1 = pool_client.token_1();
-    
-    // Determine which token we're swapping from
-    let is_token0_in = if from_token == &token0 {
-        true
-    } else if from_token == &token1 {
-        false
-    } else {
-        return Err(VaultError::InvalidConfiguration);
-    };
-    
-    // Get current reserves
-    let (reserve0, reserve1) = pool_client.get_reserves();
-    
     // Calculate output amount using constant product formula
-    let (reserve_in, reserve_out) = if is_token0_in {
-        (reserve0, reserve1)
-    } else {
-        (reserve1, reserve0)
-    };
+    let (reserve_in, reserve_out) = orient_reserves(env, pool_address, from_token)?;
     
     let amount_in_with_fee = amount_in
         .checked_mul(997)
@@ -194,31 +243,9 @@ pub fn calculate_swap_input(
         return Err(VaultError::InvalidAmount);
     }
 
-    let pool_client = LiquidityPoolClient::new(env, pool_address);
-    
-    // Get pool token addresses to determine which is token0 and token1
-    let token0 = pool_client.token_0();
-    let token1 = pool_client.token_1();
-    
-    // Determine which token we're swapping from
-    let is_token0_in = if from_token == &token0 {
-        true
-    } else if from_token == &token1 {
-        false
-    } else {
-        return Err(VaultError::InvalidConfiguration);
-    };
-    
-    // Get current reserves
-    let (reserve0, reserve1) = pool_client.get_reserves();
-    
     // Calculate input amount using constant product formula (solved for amount_in)
     // Formula: amount_in = (reserve_in * amount_out * 1000) / ((reserve_out - amount_out) * 997) + 1
-    let (reserve_in, reserve_out) = if is_token0_in {
-        (reserve0, reserve1)
-    } else {
-        (reserve1, reserve0)
-    };
+    let (reserve_in, reserve_out) = orient_reserves(env, pool_address, from_token)?;
     
     // Make sure we're not trying to drain the pool
     if amount_out_desired >= reserve_out {
@@ -260,10 +287,10 @@ pub fn get_pool_for_pair(
     }
     
     let factory_client = FactoryClient::new(env, factory_address);
-    let pool_address = factory_client.get_pair(&token_a.clone(), &token_b.clone());
     
-    // Verify pool exists (not zero address)
-    // In Soroban, we'd check if the address is valid
-    // For now, just return it
-    Ok(pool_address)
+    // The factory traps when it has no pair, so callers can fall back
+    match factory_client.try_get_pair(token_a, token_b) {
+        Ok(Ok(pool_address)) => Ok(pool_address),
+        _ => Err(VaultError::PoolNotFound),
+    }
 }
@@ -0,0 +1,194 @@
+// Asset prices for valuation
+// Prices come from a SEP-40 oracle (e.g. Reflector) or from Soroswap pool
+// reserves. Sources are tried in the configured priority order, so a stale
+// or missing oracle quote falls back to spot instead of stopping valuation.
+// In guarded mode a spot price is only trusted within a bound of the oracle,
+// or of the last price accepted, so one skewed pool can't swing it.
+use soroban_sdk::{contractclient, contracttype, symbol_short, token, Address, Env, Symbol, Vec, vec};
+use crate::errors::VaultError;
+use crate::fees::PRICE_SCALE;
+use crate::types::PriceSource;
+
+const ORACLE: Symbol = symbol_short!("ORACLE");
+const PRICE_SOURCES: Symbol = symbol_short!("PRICE_SRC");
+const PRICE_GUARD: Symbol = symbol_short!("PX_GUARD");
+const LAST_PRICE: Symbol = symbol_short!("LAST_PX");
+
+/// Asset identifier used by SEP-40 oracles
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OracleAsset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// A SEP-40 price quote, in the oracle's decimals
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// The parts of a SEP-40 price oracle used for valuation
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracleInterface {
+    /// Most recent quote for `asset`, if the oracle has one
+    fn lastprice(env: Env, asset: OracleAsset) -> Option<PriceData>;
+}
+
+/// Oracle address and the oldest quote age accepted from it, if configured
+pub fn oracle_config(env: &Env) -> Option<(Address, u64)> {
+    env.storage().instance().get(&ORACLE)
+}
+
+/// Configure (or clear) the oracle and its staleness limit in seconds
+pub fn set_oracle_config(env: &Env, oracle: Option<(Address, u64)>) {
+    match oracle {
+        Some(oracle) => env.storage().instance().set(&ORACLE, &oracle),
+        None => env.storage().instance().remove(&ORACLE),
+    }
+}
+
+/// Order price sources are tried in; oracle first, then spot, by default
+pub fn price_source_priority(env: &Env) -> Vec<PriceSource> {
+    env.storage().instance().get(&PRICE_SOURCES)
+        .unwrap_or(vec![env, PriceSource::Oracle, PriceSource::Spot])
+}
+
+/// Replace the price source order
+pub fn set_price_source_priority(env: &Env, sources: &Vec<PriceSource>) {
+    env.storage().instance().set(&PRICE_SOURCES, sources);
+}
+
+/// Largest deviation, in basis points, a spot price may have from its reference (None when unguarded)
+pub fn price_guard_bps(env: &Env) -> Option<i128> {
+    env.storage().instance().get(&PRICE_GUARD)
+}
+
+/// Turn guarded valuation on at `max_deviation_bps`, or off
+pub fn set_price_guard_bps(env: &Env, max_deviation_bps: Option<i128>) {
+    match max_deviation_bps {
+        Some(bps) => env.storage().instance().set(&PRICE_GUARD, &bps),
+        None => env.storage().instance().remove(&PRICE_GUARD),
+    }
+}
+
+/// Last price accepted for `token`, if any
+pub fn last_price(env: &Env, token: &Address) -> Option<i128> {
+    env.storage().instance().get(&(LAST_PRICE, token.clone()))
+}
+
+/// Price of `token` in base asset units per unit of `token`, scaled by PRICE_SCALE
+/// The base asset itself is always 1.0. Fails with PriceUnavailable only when
+/// every configured source fails.
+pub fn get_price(env: &Env, token: &Address) -> Result<i128, VaultError> {
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    let base = crate::token_client::base_asset(env, &config.assets)?;
+    if *token == base {
+        return Ok(PRICE_SCALE);
+    }
+
+    for source in price_source_priority(env).iter() {
+        let price = match source {
+            PriceSource::Oracle => oracle_price(env, token, &base),
+            PriceSource::Spot => spot_price(env, token, &base)
+                .map(|spot| guard_spot(env, token, &base, spot)),
+        };
+        if let Some(price) = price {
+            env.storage().instance().set(&(LAST_PRICE, token.clone()), &price);
+            return Ok(price);
+        }
+    }
+
+    Err(VaultError::PriceUnavailable)
+}
+
+/// Value of `amount` of `token` in base asset units at a fresh oracle price
+/// None without one. Never falls back to spot, so it can bound swaps through
+/// the very pool spot prices come from.
+pub fn oracle_value(env: &Env, token: &Address, amount: i128) -> Option<i128> {
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)?;
+    let base = crate::token_client::base_asset(env, &config.assets).ok()?;
+    let price = if *token == base {
+        PRICE_SCALE
+    } else {
+        oracle_price(env, token, &base)?
+    };
+    amount.checked_mul(price)?.checked_div(PRICE_SCALE)
+}
+
+// In guarded mode, replace a spot price that strays too far from its reference
+// The reference is a fresh oracle price, or else the last price accepted. A
+// rejected spot price gives way to the last accepted one (or the oracle's)
+// and fires a price_guard event. With no reference yet, spot is taken as is.
+fn guard_spot(env: &Env, token: &Address, base: &Address, spot: i128) -> i128 {
+    let Some(max_bps) = price_guard_bps(env) else {
+        return spot;
+    };
+    let last = last_price(env, token);
+    let Some(reference) = oracle_price(env, token, base).or(last) else {
+        return spot;
+    };
+
+    let within = (spot - reference).checked_abs()
+        .and_then(|diff| diff.checked_mul(10000))
+        .zip(reference.checked_mul(max_bps))
+        .is_some_and(|(diff, bound)| diff <= bound);
+    if within {
+        return spot;
+    }
+
+    let fallback = last.unwrap_or(reference);
+    env.events().publish(
+        (Symbol::new(env, "price_guard"), token.clone()),
+        (spot, reference, fallback),
+    );
+    fallback
+}
+
+// Oracle price of `token` in `base`, or None if either quote is missing or stale
+// Both quotes share the oracle's decimals, so only the tokens' decimals matter.
+fn oracle_price(env: &Env, token: &Address, base: &Address) -> Option<i128> {
+    let (oracle, max_age) = oracle_config(env)?;
+    let token_quote = fresh_quote(env, &oracle, token, max_age)?;
+    let base_quote = fresh_quote(env, &oracle, base, max_age)?;
+    if token_quote <= 0 || base_quote <= 0 {
+        return None;
+    }
+
+    let token_decimals = token::TokenClient::new(env, token).try_decimals().ok()?.ok()?;
+    let base_decimals = token::TokenClient::new(env, base).try_decimals().ok()?.ok()?;
+
+    // Whole-token ratio, converted to base units per token unit
+    let price = token_quote.checked_mul(PRICE_SCALE)?.checked_div(base_quote)?;
+    if base_decimals >= token_decimals {
+        price.checked_mul(10i128.checked_pow(base_decimals - token_decimals)?)
+    } else {
+        price.checked_div(10i128.checked_pow(token_decimals - base_decimals)?)
+    }
+}
+
+fn fresh_quote(env: &Env, oracle: &Address, asset: &Address, max_age: u64) -> Option<i128> {
+    let quote = PriceOracleClient::new(env, oracle)
+        .try_lastprice(&OracleAsset::Stellar(asset.clone()))
+        .ok()?
+        .ok()??;
+    if crate::clock::now(env).saturating_sub(quote.timestamp) > max_age {
+        return None;
+    }
+    Some(quote.price)
+}
+
+// Spot price of `token` in `base` from the Soroswap pair's reserves, if there is one
+fn spot_price(env: &Env, token: &Address, base: &Address) -> Option<i128> {
+    let factory = crate::swap_router::get_soroswap_factory_address_internal(env);
+    let pool = crate::pool_client::get_pool_for_pair(env, &factory, token, base).ok()?;
+    let (token_reserve, base_reserve) = crate::pool_client::orient_reserves(env, &pool, token).ok()?;
+    if token_reserve <= 0 {
+        return None;
+    }
+
+    base_reserve.checked_mul(PRICE_SCALE)?.checked_div(token_reserve)
+}
@@ -0,0 +1,88 @@
+// Profit sharing with NFT holders
+// The NFT contract records, per vault, the share of realized gains owed to its
+// holders. Gains are measured here as the rise in share price over the
+// high-water mark, so the owner can't understate them when distributing.
+use soroban_sdk::{contractclient, symbol_short, Address, Env, Map, Symbol};
+use crate::errors::{VaultError, VaultNFTError};
+use crate::fees::PRICE_SCALE;
+use crate::types::VaultState;
+
+const NFT_CONTRACT: Symbol = symbol_short!("NFT_CTR");
+const HIGH_WATER_MARK: Symbol = symbol_short!("HWM");
+
+/// Where a distributed profit came from, tagged on profit_distributed events
+pub const SOURCE_TRADING: Symbol = symbol_short!("trading");
+pub const SOURCE_STAKING: Symbol = symbol_short!("staking");
+pub const SOURCE_LP_FEES: Symbol = symbol_short!("lp_fees");
+
+/// The parts of the vault NFT contract used for profit sharing
+#[contractclient(name = "ProfitShareNFTClient")]
+pub trait ProfitShareNFTInterface {
+    /// Share of realized gains owed to holders, in basis points
+    fn get_profit_share(env: Env, vault_address: Address) -> i128;
+
+    /// Record `total_profit` of `token` (already transferred in) as owed to holders
+    fn distribute_profits(
+        env: Env,
+        caller: Address,
+        vault_address: Address,
+        total_profit: i128,
+        token: Address,
+    ) -> Result<Map<Address, i128>, VaultNFTError>;
+}
+
+/// NFT contract the vault distributes profits through, if configured
+pub fn get_nft_contract(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&NFT_CONTRACT)
+}
+
+/// Configure (or clear) the NFT contract
+pub fn set_nft_contract(env: &Env, nft_contract: Option<Address>) {
+    match nft_contract {
+        Some(nft_contract) => env.storage().instance().set(&NFT_CONTRACT, &nft_contract),
+        None => env.storage().instance().remove(&NFT_CONTRACT),
+    }
+}
+
+/// Share price gains are measured from, scaled by PRICE_SCALE
+/// Starts at 1.0, the price of the first deposit.
+pub fn high_water_mark(env: &Env) -> i128 {
+    env.storage().instance().get(&HIGH_WATER_MARK).unwrap_or(PRICE_SCALE)
+}
+
+/// Move the high-water mark to `price`
+pub fn set_high_water_mark(env: &Env, price: i128) {
+    env.storage().instance().set(&HIGH_WATER_MARK, &price);
+}
+
+/// Value gained above the high-water mark across all shares (0 when below it)
+pub fn realized_gain(env: &Env, state: &VaultState) -> Result<i128, VaultError> {
+    let price = crate::fees::share_price(env, state)?;
+    let delta = price - high_water_mark(env);
+    if delta <= 0 {
+        return Ok(0);
+    }
+
+    delta.checked_mul(state.total_shares)
+        .and_then(|v| v.checked_div(crate::fees::price_divisor(env).ok()?))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Profit share registered for this vault on the NFT contract (0 when none)
+pub fn profit_share_bps(env: &Env, nft_contract: &Address) -> i128 {
+    ProfitShareNFTClient::new(env, nft_contract)
+        .get_profit_share(&env.current_contract_address())
+}
+
+/// Least a distribution of `gain` may pay holders under `share_bps`, rounded up
+pub fn minimum_distribution(gain: i128, share_bps: i128) -> Result<i128, VaultError> {
+    gain.checked_mul(share_bps)
+        .and_then(|v| v.checked_add(9999))
+        .and_then(|v| v.checked_div(10000))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Whether `source` is one of the recognized profit sources
+pub fn is_known_source(source: &Symbol) -> bool {
+    *source == SOURCE_TRADING || *source == SOURCE_STAKING || *source == SOURCE_LP_FEES
+}
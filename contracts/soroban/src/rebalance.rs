@@ -1,25 +1,40 @@
 // Rebalancing execution logic
-use soroban_sdk::{Env, Address, symbol_short, Symbol, Vec, String};
+use soroban_sdk::{Env, Address, symbol_short, Map, Symbol, Vec, String};
+use syft_errors::bounded_iter;
 use crate::errors::VaultError;
+use crate::events::{EVENTS_REBALANCES, EVENTS_SWAPS};
+use crate::types::{MAX_ASSETS, MAX_RULES};
 
-const CONFIG: Symbol = symbol_short!("CONFIG");
-const STATE: Symbol = symbol_short!("STATE");
+const LP_SLIPPAGE: Symbol = symbol_short!("LP_SLIP");
+const MAX_REBALANCE_LOSS: Symbol = symbol_short!("MAX_LOSS");
+const ALLOCATION_CAPS: Symbol = symbol_short!("ALLOC_CAP");
+const REBALANCE_POSITIONS: Symbol = symbol_short!("REB_POS");
+const MIN_STAKE: Symbol = symbol_short!("MIN_STAKE");
+const AUTO_UNWIND: Symbol = symbol_short!("AUTO_UNW");
+const ALLOCATION_EPSILON: Symbol = symbol_short!("ALLOC_EPS");
+const MAX_STAKE_PER_POOL: Symbol = symbol_short!("MAX_STAKE");
+
+/// Slippage tolerated on each rebalance swap, in percent
+pub const REBALANCE_SWAP_SLIPPAGE_PCT: i128 = 5;
+
+/// How far target_allocation may sum from 100_0000 by default (0.01%)
+pub const DEFAULT_ALLOCATION_EPSILON: i128 = 100;
 
 /// Execute rebalancing of vault assets according to rules
 pub fn execute_rebalance(env: &Env) -> Result<(), VaultError> {
     use soroban_sdk::symbol_short;
     
     // Get vault configuration
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .ok_or(VaultError::NotInitialized)?;
     
     // Log rebalance start
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("reb_start"),),
         state.total_value
     );
@@ -30,28 +45,108 @@ pub fn execute_rebalance(env: &Env) -> Result<(), VaultError> {
     }
     
     // Execute rebalancing for each rule
-    for i in 0..config.rules.len() {
-        if let Some(rule) = config.rules.get(i) {
-            execute_rule_action(env, &rule, &config.assets, state.total_value)?;
-        }
+    for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+        execute_rule_action(env, &rule, &config.assets, state.total_value)?;
     }
     
     Ok(())
 }
 
+/// First rule in the config with action "rebalance", if any
+pub fn first_rebalance_rule(
+    env: &Env,
+    config: &crate::types::VaultConfig,
+) -> Option<crate::types::RebalanceRule> {
+    let rebalance = String::from_str(env, "rebalance");
+    config.rules.iter().find(|rule| rule.action == rebalance)
+}
+
+/// Tolerance around 100_0000 for the sum of a rule's target_allocation
+pub fn allocation_epsilon(env: &Env) -> i128 {
+    env.storage().instance()
+        .get(&ALLOCATION_EPSILON)
+        .unwrap_or(DEFAULT_ALLOCATION_EPSILON)
+}
+
+/// Sum of a rule's target_allocation
+fn allocation_total(rule: &crate::types::RebalanceRule) -> Result<i128, VaultError> {
+    let mut total_allocation: i128 = 0;
+    for alloc in bounded_iter(&rule.target_allocation, MAX_ASSETS, VaultError::TooManyItems)? {
+        total_allocation = total_allocation.checked_add(alloc)
+            .ok_or(VaultError::InvalidConfiguration)?;
+    }
+    Ok(total_allocation)
+}
+
+/// Target amount of each asset under `rule`, in the same order as the assets
+/// target_allocation uses 100_0000 = 100%; a sum slightly off 100% (within
+/// the allocation epsilon) is normalized so the targets still add up to the total
+pub fn target_amounts(
+    env: &Env,
+    rule: &crate::types::RebalanceRule,
+    total_value: i128,
+) -> Result<Vec<i128>, VaultError> {
+    let total_allocation = match allocation_total(rule)? {
+        0 => 100_0000,
+        total => total,
+    };
+    let mut targets: Vec<i128> = Vec::new(env);
+    for target_pct in bounded_iter(&rule.target_allocation, MAX_ASSETS, VaultError::TooManyItems)? {
+        let target_amount = total_value
+            .checked_mul(target_pct)
+            .and_then(|v| v.checked_div(total_allocation))
+            .ok_or(VaultError::InvalidAmount)?;
+        targets.push_back(target_amount);
+    }
+    Ok(targets)
+}
+
+/// Amount of `asset` the vault holds, counting staked and LP positions
+/// Staked tokens count toward the token that was staked; LP positions count
+/// the amounts originally provided toward each side of the pair.
+pub fn held_amount(env: &Env, asset: &Address) -> Result<i128, VaultError> {
+    let mut amount = crate::token_client::get_vault_balance(env, asset);
+
+    let stake_key = String::from_str(env, "stake_position");
+    if let Some(position) = env.storage().instance()
+        .get::<_, crate::types::StakingPosition>(&stake_key)
+    {
+        if position.original_token == *asset {
+            amount = amount.checked_add(position.staked_amount)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+    }
+
+    let lp_key = String::from_str(env, "lp_position");
+    if let Some(position) = env.storage().instance()
+        .get::<_, crate::types::LiquidityPosition>(&lp_key)
+    {
+        if position.token_a == *asset {
+            amount = amount.checked_add(position.amount_a_provided)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+        if position.token_b == *asset {
+            amount = amount.checked_add(position.amount_b_provided)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+    }
+
+    Ok(amount)
+}
+
 /// Execute only rebalance actions (excludes stake and liquidity)
 pub fn execute_rebalance_only(env: &Env) -> Result<(), VaultError> {
     use soroban_sdk::symbol_short;
     
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("reb_start"),),
         state.total_value
     );
@@ -61,11 +156,9 @@ pub fn execute_rebalance_only(env: &Env) -> Result<(), VaultError> {
     }
     
     // Execute only rebalance rules
-    for i in 0..config.rules.len() {
-        if let Some(rule) = config.rules.get(i) {
-            if rule.action == String::from_str(env, "rebalance") {
-                execute_rebalance_action(env, &rule, &config.assets, state.total_value)?;
-            }
+    for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+        if rule.action == String::from_str(env, "rebalance") {
+            execute_rebalance_action(env, &rule, &config.assets)?;
         }
     }
     
@@ -76,15 +169,15 @@ pub fn execute_rebalance_only(env: &Env) -> Result<(), VaultError> {
 pub fn execute_stake_only(env: &Env) -> Result<(), VaultError> {
     use soroban_sdk::symbol_short;
     
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("stk_start"),),
         state.total_value
     );
@@ -94,11 +187,40 @@ pub fn execute_stake_only(env: &Env) -> Result<(), VaultError> {
     }
     
     // Execute only stake rules
-    for i in 0..config.rules.len() {
-        if let Some(rule) = config.rules.get(i) {
-            if rule.action == String::from_str(env, "stake") {
-                execute_stake_action(env, &rule, &config.assets, state.total_value)?;
-            }
+    for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+        if rule.action == String::from_str(env, "stake") {
+            execute_stake_action(env, &rule, &config.assets, state.total_value)?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Execute only unstake actions (excludes rebalance, stake and liquidity)
+pub fn execute_unstake_only(env: &Env) -> Result<(), VaultError> {
+    use soroban_sdk::symbol_short;
+    
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
+        (symbol_short!("unstk_st"),),
+        state.total_value
+    );
+    
+    if state.total_value == 0 {
+        return Err(VaultError::InsufficientBalance);
+    }
+    
+    // Execute only unstake rules
+    for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+        if rule.action == String::from_str(env, "unstake") {
+            execute_unstake_action(env, &rule)?;
         }
     }
     
@@ -109,15 +231,15 @@ pub fn execute_stake_only(env: &Env) -> Result<(), VaultError> {
 pub fn execute_liquidity_only(env: &Env) -> Result<(), VaultError> {
     use soroban_sdk::symbol_short;
     
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    let state: crate::types::VaultState = env.storage().instance()
-        .get(&STATE)
+    let state: crate::types::VaultState = crate::migrations::read_state(env)
         .ok_or(VaultError::NotInitialized)?;
     
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("liq_start"),),
         state.total_value
     );
@@ -127,11 +249,9 @@ pub fn execute_liquidity_only(env: &Env) -> Result<(), VaultError> {
     }
     
     // Execute only liquidity rules
-    for i in 0..config.rules.len() {
-        if let Some(rule) = config.rules.get(i) {
-            if rule.action == String::from_str(env, "liquidity") {
-                execute_liquidity_action(env, &rule, &config.assets, state.total_value)?;
-            }
+    for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+        if rule.action == String::from_str(env, "liquidity") {
+            execute_liquidity_action(env, &rule, &config.assets, state.total_value)?;
         }
     }
     
@@ -148,14 +268,16 @@ fn execute_rule_action(
     use soroban_sdk::String;
     
     // Log the action we're executing
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("exec_act"),),
         rule.action.clone()
     );
     
     // Rebalance action: Adjust asset allocations to target percentages
     if rule.action == String::from_str(env, "rebalance") {
-        return execute_rebalance_action(env, rule, assets, total_value);
+        return execute_rebalance_action(env, rule, assets);
     }
     
     // Stake action: Move assets to staking
@@ -163,13 +285,20 @@ fn execute_rule_action(
         return execute_stake_action(env, rule, assets, total_value);
     }
     
+    // Unstake action: Pull part of the staking position back
+    if rule.action == String::from_str(env, "unstake") {
+        return execute_unstake_action(env, rule);
+    }
+    
     // Provide liquidity action: Add assets to AMM pools
     if rule.action == String::from_str(env, "liquidity") {
         return execute_liquidity_action(env, rule, assets, total_value);
     }
     
     // Log if no action matched
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("no_match"),),
         rule.action.clone()
     );
@@ -182,7 +311,6 @@ fn execute_rebalance_action(
     env: &Env,
     rule: &crate::types::RebalanceRule,
     assets: &Vec<Address>,
-    total_value: i128
 ) -> Result<(), VaultError> {
     // Validate target allocation matches number of assets
     if rule.target_allocation.len() != assets.len() {
@@ -190,44 +318,65 @@ fn execute_rebalance_action(
     }
     
     // Validate allocations sum to 100% (represented as 100_0000 for 2 decimal precision)
-    let mut total_allocation: i128 = 0;
-    for i in 0..rule.target_allocation.len() {
-        if let Some(alloc) = rule.target_allocation.get(i) {
-            total_allocation = total_allocation.checked_add(alloc)
-                .ok_or(VaultError::InvalidConfiguration)?;
-        }
-    }
+    let total_allocation = allocation_total(rule)?;
     
-    // Allow 100% allocation (100_0000 in our precision)
-    if total_allocation != 100_0000 && total_allocation != 0 {
+    // Allow 100% allocation (100_0000 in our precision), give or take the
+    // epsilon that rounding in off-chain tooling can introduce
+    if total_allocation != 0 && (total_allocation - 100_0000).abs() > allocation_epsilon(env) {
         return Err(VaultError::InvalidConfiguration);
     }
 
     // Get router address from config
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
     let router_address = config.router_address
         .ok_or(VaultError::InvalidConfiguration)?;
     
+    // Real holdings before any swap, for the whole-rebalance loss guard
+    let value_before = liquid_total(env, assets)?;
+    
     // Calculate current balances and target amounts
+    // Targets are sized against what the vault holds. By default that counts
+    // staked and LP positions toward their underlying asset, so value that is
+    // deployed isn't mistaken for a shortfall. With positions excluded, only
+    // the liquid balances are redistributed and positions are left out of both
+    // the current amounts and the total. Either way only liquid balance is sold.
+    let include_positions = rebalance_includes_positions(env);
     let mut current_balances: Vec<i128> = Vec::new(env);
-    let mut target_amounts: Vec<i128> = Vec::new(env);
+    let mut liquid_balances: Vec<i128> = Vec::new(env);
+    let mut total_value: i128 = 0;
+    
+    // Bounds every per-asset loop below, which all run over the same assets
+    for asset in bounded_iter(assets, MAX_ASSETS, VaultError::TooManyItems)? {
+        // Get current balance of this asset in vault
+        let liquid_balance = crate::token_client::get_vault_balance(env, &asset);
+        let current_balance = if include_positions {
+            held_amount(env, &asset)?
+        } else {
+            liquid_balance
+        };
+        total_value = total_value.checked_add(current_balance)
+            .ok_or(VaultError::InvalidAmount)?;
+        current_balances.push_back(current_balance);
+        liquid_balances.push_back(liquid_balance);
+    }
+    
+    let mut target_amounts = target_amounts(env, rule, total_value)?;
     
+    // No target may exceed its asset's concentration cap
+    let caps = get_allocation_caps(env);
     for i in 0..assets.len() {
-        if let (Some(asset), Some(target_pct)) = (assets.get(i), rule.target_allocation.get(i)) {
-            // Get current balance of this asset in vault
-            let current_balance = crate::token_client::get_vault_balance(env, &asset);
-            current_balances.push_back(current_balance);
-            
-            // Calculate target amount
-            let target_amount = total_value
-                .checked_mul(target_pct)
-                .and_then(|v| v.checked_div(100_0000))
-                .ok_or(VaultError::InvalidAmount)?;
-            
-            target_amounts.push_back(target_amount);
+        if let (Some(asset), Some(target)) = (assets.get(i), target_amounts.get(i)) {
+            if let Some(cap_bps) = caps.get(asset) {
+                let cap_amount = total_value
+                    .checked_mul(cap_bps)
+                    .and_then(|v| v.checked_div(10000))
+                    .ok_or(VaultError::InvalidAmount)?;
+                if target > cap_amount {
+                    target_amounts.set(i, cap_amount);
+                }
+            }
         }
     }
     
@@ -257,7 +406,9 @@ fn execute_rebalance_action(
     // Skip rebalancing if already at target allocation
     if !needs_rebalance {
         // Log that rebalance was skipped
-        env.events().publish(
+        crate::events::publish(
+            env,
+            EVENTS_REBALANCES,
             (symbol_short!("reb_skip"),),
             tolerance
         );
@@ -266,7 +417,9 @@ fn execute_rebalance_action(
     }
     
     // Log that we're proceeding with swaps
-    env.events().publish(
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
         (symbol_short!("reb_exec"),),
         true
     );
@@ -288,7 +441,9 @@ fn execute_rebalance_action(
             if diff > 0 {
                 // Need to buy more of this asset
                 // Log what we're trying to buy
-                env.events().publish(
+                crate::events::publish(
+                    env,
+                    EVENTS_SWAPS,
                     (symbol_short!("need_buy"),),
                     (asset.clone(), diff)
                 );
@@ -305,14 +460,18 @@ fn execute_rebalance_action(
                         target_amounts.get(j)
                     ) {
                         // Log what we're checking
-                        env.events().publish(
+                        crate::events::publish(
+                            env,
+                            EVENTS_SWAPS,
                             (symbol_short!("check_src"),),
                             (source_asset.clone(), source_current, source_target)
                         );
                         
                         if source_current > source_target {
                             // This asset has excess, use it as source
-                            let excess = source_current - source_target;
+                            // Staked or LP'd value can't be sold here, only the liquid part
+                            let excess = (source_current - source_target)
+                                .min(liquid_balances.get(j).unwrap_or(0));
                             
                             // Calculate how much of source asset we need to sell to get the target amount
                             // We want to buy 'diff' amount of target asset
@@ -332,7 +491,9 @@ fn execute_rebalance_action(
                             ) {
                                 Ok(addr) => addr,
                                 Err(e) => {
-                                    env.events().publish(
+                                    crate::events::publish(
+                                        env,
+                                        EVENTS_SWAPS,
                                         (symbol_short!("pool_err"),),
                                         symbol_short!("notfound")
                                     );
@@ -350,7 +511,9 @@ fn execute_rebalance_action(
                             ) {
                                 Ok(amt) => amt,
                                 Err(e) => {
-                                    env.events().publish(
+                                    crate::events::publish(
+                                        env,
+                                        EVENTS_SWAPS,
                                         (symbol_short!("calc_err"),),
                                         symbol_short!("failed")
                                     );
@@ -361,14 +524,18 @@ fn execute_rebalance_action(
                             // Make sure we don't swap more than our excess
                             let amount_to_swap = if amount_to_swap > excess { excess } else { amount_to_swap };
                             
-                            env.events().publish(
+                            crate::events::publish(
+                                env,
+                                EVENTS_SWAPS,
                                 (symbol_short!("calc_swap"),),
                                 (excess, amount_to_swap)
                             );
                             
                             // Skip if amount is negligible (less than 100 stroops)
                             if amount_to_swap < 100 {
-                                env.events().publish(
+                                crate::events::publish(
+                                    env,
+                                    EVENTS_SWAPS,
                                     (symbol_short!("skip_amt"),),
                                     amount_to_swap
                                 );
@@ -385,7 +552,9 @@ fn execute_rebalance_action(
                             ) {
                                 Ok(amt) => amt,
                                 Err(e) => {
-                                    env.events().publish(
+                                    crate::events::publish(
+                                        env,
+                                        EVENTS_SWAPS,
                                         (symbol_short!("out_err"),),
                                         symbol_short!("failed")
                                     );
@@ -393,16 +562,51 @@ fn execute_rebalance_action(
                                 }
                             };
                             
+                            // Keep the bought asset under its cap after the trade, not just
+                            // against the pre-trade total the target was sized on
+                            let (amount_to_swap, expected_output) = match caps.get(asset.clone()) {
+                                Some(cap_bps) => {
+                                    let mut held: i128 = 0;
+                                    for balance in current_balances.iter() {
+                                        held = held.checked_add(balance)
+                                            .ok_or(VaultError::InvalidAmount)?;
+                                    }
+                                    clamp_buy_to_cap(
+                                        env,
+                                        &pool_address,
+                                        (&source_asset, &asset),
+                                        (current, held),
+                                        cap_bps,
+                                        (amount_to_swap, expected_output),
+                                    )?
+                                }
+                                None => (amount_to_swap, expected_output),
+                            };
+                            
+                            if amount_to_swap < 100 || expected_output <= 0 {
+                                crate::events::publish(
+                                    env,
+                                    EVENTS_SWAPS,
+                                    (symbol_short!("skip_amt"),),
+                                    amount_to_swap
+                                );
+                                continue;
+                            }
+                            
                             // Calculate minimum output with 5% slippage tolerance
-                            let min_amount_out = (expected_output * 95) / 100;
+                            let min_amount_out = (expected_output * (100 - REBALANCE_SWAP_SLIPPAGE_PCT)) / 100;
                             
                             // Log swap attempt with expected and minimum outputs
-                            env.events().publish(
+                            crate::events::publish(
+                                env,
+                                EVENTS_SWAPS,
                                 (symbol_short!("swap_try"),),
                                 (source_asset.clone(), asset.clone(), amount_to_swap)
                             );
                             
-                            env.events().publish(
+                            crate::events::publish(
+                                env,
+                                EVENTS_SWAPS,
                                 (symbol_short!("swap_calc"),),
                                 (expected_output, min_amount_out)
                             );
@@ -415,7 +619,9 @@ fn execute_rebalance_action(
                                 amount_to_swap,
                             )?;
                             
-                            env.events().publish(
+                            crate::events::publish(
+                                env,
+                                EVENTS_SWAPS,
                                 (symbol_short!("approved"),),
                                 amount_to_swap
                             );
@@ -431,7 +637,9 @@ fn execute_rebalance_action(
                                 min_amount_out,
                             ) {
                                 Ok(amt) => {
-                                    env.events().publish(
+                                    crate::events::publish(
+                                        env,
+                                        EVENTS_SWAPS,
                                         (symbol_short!("swapped"),),
                                         amt
                                     );
@@ -439,7 +647,9 @@ fn execute_rebalance_action(
                                 },
                                 Err(e) => {
                                     // Log the error and propagate it
-                                    env.events().publish(
+                                    crate::events::publish(
+                                        env,
+                                        EVENTS_SWAPS,
                                         (symbol_short!("swap_err"),),
                                         symbol_short!("failed")
                                     );
@@ -450,6 +660,8 @@ fn execute_rebalance_action(
                             // Update balances after swap
                             current_balances.set(j, source_current - amount_to_swap);
                             current_balances.set(i, current + amount_out);
+                            liquid_balances.set(j, liquid_balances.get(j).unwrap_or(0) - amount_to_swap);
+                            liquid_balances.set(i, liquid_balances.get(i).unwrap_or(0) + amount_out);
                             
                             break;
                         }
@@ -459,9 +671,213 @@ fn execute_rebalance_action(
         }
     }
     
+    // Each swap passed its own slippage bound, but many small losses can add up.
+    // Re-read real balances and fail the whole rebalance (reverting every swap)
+    // if the vault lost more than the configured share of its value.
+    let max_loss_bps: Option<i128> = env.storage().instance()
+        .get(&MAX_REBALANCE_LOSS)
+        .unwrap_or(None);
+    if let Some(max_loss_bps) = max_loss_bps {
+        let value_after = liquid_total(env, assets)?;
+        let loss = value_before - value_after;
+        if loss > 0 {
+            let loss_bps = loss
+                .checked_mul(10000)
+                .and_then(|v| v.checked_div(value_before))
+                .ok_or(VaultError::InvalidAmount)?;
+            if loss_bps > max_loss_bps {
+                return Err(VaultError::RebalanceLossExceeded);
+            }
+        }
+    }
+    
     Ok(())
 }
 
+/// Whether rebalance sizing counts staked and LP positions (default true)
+pub fn rebalance_includes_positions(env: &Env) -> bool {
+    env.storage().instance()
+        .get(&REBALANCE_POSITIONS)
+        .unwrap_or(true)
+}
+
+/// Whether withdrawals unstake and pull liquidity to cover a liquid shortfall (default false)
+pub fn auto_unwind_on_withdraw(env: &Env) -> bool {
+    env.storage().instance()
+        .get(&AUTO_UNWIND)
+        .unwrap_or(false)
+}
+
+/// Free up base asset from positions until the vault holds `amount` of it liquid
+/// Unstakes first, then removes liquidity, each sized to what is still short
+/// (rounded up). The LP's other token stays in the vault. Returns the base
+/// asset freed; anything the positions can't cover is left to the caller.
+pub fn unwind_for_withdrawal(env: &Env, base_token: &Address, amount: i128) -> Result<i128, VaultError> {
+    let start = crate::token_client::get_vault_balance(env, base_token);
+    let shortfall = |env: &Env| amount - crate::token_client::get_vault_balance(env, base_token);
+    
+    let stake_key = String::from_str(env, "stake_position");
+    if let Some(position) = env.storage().instance().get::<_, crate::types::StakingPosition>(&stake_key) {
+        let short = shortfall(env);
+        if short > 0 && position.original_token == *base_token && position.staked_amount > 0 {
+            let st_amount = share_rounded_up(position.st_token_amount, short, position.staked_amount)?;
+            unstake(env, position, st_amount)?;
+        }
+    }
+    
+    let lp_key = String::from_str(env, "lp_position");
+    if let Some(position) = env.storage().instance().get::<_, crate::types::LiquidityPosition>(&lp_key) {
+        let short = shortfall(env);
+        let base_provided = if position.token_a == *base_token {
+            position.amount_a_provided
+        } else if position.token_b == *base_token {
+            position.amount_b_provided
+        } else {
+            0
+        };
+        if short > 0 && base_provided > 0 {
+            let lp_tokens = share_rounded_up(position.lp_tokens, short, base_provided)?;
+            recover_liquidity(env, lp_tokens)?;
+        }
+    }
+    
+    let freed = crate::token_client::get_vault_balance(env, base_token) - start;
+    if freed > 0 {
+        env.events().publish((Symbol::new(env, "withdraw_unwind"),), (amount, freed));
+    }
+    Ok(freed)
+}
+
+// `whole * part / of`, rounded up and capped at `whole`
+fn share_rounded_up(whole: i128, part: i128, of: i128) -> Result<i128, VaultError> {
+    let share = whole.checked_mul(part)
+        .and_then(|v| v.checked_add(of - 1))
+        .and_then(|v| v.checked_div(of))
+        .ok_or(VaultError::InvalidAmount)?;
+    Ok(share.min(whole))
+}
+
+/// Smallest amount a stake rule will stake (default 1, so empty stakes are skipped)
+pub fn min_stake_amount(env: &Env) -> i128 {
+    env.storage().instance()
+        .get(&MIN_STAKE)
+        .unwrap_or(1)
+}
+
+/// Cap on the staking pool's whole position, in basis points of total_value
+pub fn max_stake_per_pool_bps(env: &Env) -> Option<i128> {
+    env.storage().instance()
+        .get(&MAX_STAKE_PER_POOL)
+        .unwrap_or(None)
+}
+
+/// Per-asset concentration caps in basis points; assets without an entry are uncapped
+pub fn get_allocation_caps(env: &Env) -> Map<Address, i128> {
+    env.storage().instance()
+        .get(&ALLOCATION_CAPS)
+        .unwrap_or(Map::new(env))
+}
+
+/// Assets currently above their cap, as (asset, current_bps, cap_bps)
+/// Holdings include staked and LP positions and are valued with
+/// valuation::held_value, as in the allocation status view.
+pub fn allocation_breaches(
+    env: &Env,
+    assets: &Vec<Address>,
+) -> Result<Vec<(Address, i128, i128)>, VaultError> {
+    let caps = get_allocation_caps(env);
+    let mut breaches = Vec::new(env);
+    if caps.is_empty() {
+        return Ok(breaches);
+    }
+
+    let mut held: Vec<i128> = Vec::new(env);
+    let mut total: i128 = 0;
+    for asset in bounded_iter(assets, MAX_ASSETS, VaultError::TooManyItems)? {
+        let amount = crate::valuation::held_value(env, &asset)?;
+        total = total.checked_add(amount).ok_or(VaultError::InvalidAmount)?;
+        held.push_back(amount);
+    }
+    if total == 0 {
+        return Ok(breaches);
+    }
+
+    for i in 0..assets.len() {
+        let asset = assets.get(i).ok_or(VaultError::InvalidConfiguration)?;
+        if let Some(cap_bps) = caps.get(asset.clone()) {
+            let current_bps = held.get(i).unwrap_or(0)
+                .checked_mul(10000)
+                .and_then(|v| v.checked_div(total))
+                .ok_or(VaultError::InvalidAmount)?;
+            if current_bps > cap_bps {
+                breaches.push_back((asset, current_bps, cap_bps));
+            }
+        }
+    }
+
+    Ok(breaches)
+}
+
+/// Shrink a buy leg so the bought asset's share of holdings after the trade
+/// stays within `cap_bps`. `holdings` is (current amount of the bought asset,
+/// sum across all assets) on the planner's basis; `trade` is the planned (input, output).
+/// Returns the (possibly reduced) input and output.
+fn clamp_buy_to_cap(
+    env: &Env,
+    pool_address: &Address,
+    (source_asset, asset): (&Address, &Address),
+    (current, liquid): (i128, i128),
+    cap_bps: i128,
+    (amount_in, amount_out): (i128, i128),
+) -> Result<(i128, i128), VaultError> {
+    let post_total = liquid - amount_in + amount_out;
+    if (current + amount_out) * 10000 <= cap_bps * post_total {
+        return Ok((amount_in, amount_out));
+    }
+
+    // Largest output x with (current + x) <= cap * (liquid - in(x) + x), taking
+    // in(x) at this trade's in/out ratio. A smaller trade has less price impact,
+    // so the real post-trade share only comes out lower.
+    let headroom = cap_bps * liquid - 10000 * current;
+    if headroom <= 0 {
+        return Ok((0, 0));
+    }
+    let max_out = headroom
+        .checked_mul(amount_out)
+        .and_then(|v| v.checked_div(10000 * amount_out + cap_bps * (amount_in - amount_out)))
+        .ok_or(VaultError::InvalidAmount)?;
+    if max_out <= 0 {
+        return Ok((0, 0));
+    }
+
+    let clamped_in = crate::pool_client::calculate_swap_input(
+        env, pool_address, source_asset, asset, max_out,
+    )?;
+    let clamped_out = crate::pool_client::calculate_swap_output(
+        env, pool_address, source_asset, asset, clamped_in,
+    )?;
+
+    crate::events::publish(
+        env,
+        EVENTS_REBALANCES,
+        (symbol_short!("cap_clamp"),),
+        (asset.clone(), cap_bps, clamped_out)
+    );
+
+    Ok((clamped_in, clamped_out))
+}
+
+/// Sum of the vault's liquid balances across its assets, in raw token units
+/// (the same basis the rebalance planner sizes targets in)
+fn liquid_total(env: &Env, assets: &Vec<Address>) -> Result<i128, VaultError> {
+    let mut total: i128 = 0;
+    for asset in bounded_iter(assets, MAX_ASSETS, VaultError::TooManyItems)? {
+        total = total.checked_add(crate::token_client::get_vault_balance(env, &asset))
+            .ok_or(VaultError::InvalidAmount)?;
+    }
+    Ok(total)
+}
+
 /// Execute staking action
 fn execute_stake_action(
     env: &Env,
@@ -474,8 +890,21 @@ fn execute_stake_action(
         return Err(VaultError::InvalidConfiguration);
     }
     
+    // Read everything the stake depends on before calling out to the pool,
+    // so nothing it does during the call can change the decision
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    let staking_pool = config.staking_pool_address
+        .ok_or(VaultError::InvalidConfiguration)?;
+    let position_key = String::from_str(env, "stake_position");
+    let already_staked = env.storage().instance()
+        .get::<_, crate::types::StakingPosition>(&position_key)
+        .map(|position| position.staked_amount)
+        .unwrap_or(0);
+    let min_stake = min_stake_amount(env);
+    
     // Calculate staking amount based on threshold
-    let stake_amount = total_value
+    let mut stake_amount = total_value
         .checked_mul(rule.threshold)
         .and_then(|v| v.checked_div(100_0000))
         .ok_or(VaultError::InvalidAmount)?;
@@ -484,8 +913,42 @@ fn execute_stake_action(
         return Err(VaultError::InsufficientBalance);
     }
     
-    // Get the primary staking asset (typically native XLM or first asset)
-    let staking_token = assets.get(0).ok_or(VaultError::InvalidConfiguration)?;
+    // Clamp so the pool's whole position stays within the per-pool cap
+    if let Some(cap_bps) = max_stake_per_pool_bps(env) {
+        let cap = total_value
+            .checked_mul(cap_bps)
+            .and_then(|v| v.checked_div(10000))
+            .ok_or(VaultError::InvalidAmount)?;
+        let headroom = cap.checked_sub(already_staked)
+            .ok_or(VaultError::InvalidAmount)?
+            .max(0);
+        if stake_amount > headroom {
+            crate::events::publish(
+                env,
+                EVENTS_REBALANCES,
+                (Symbol::new(env, "stake_capped"),),
+                (stake_amount, headroom)
+            );
+            if headroom == 0 {
+                return Ok(());
+            }
+            stake_amount = headroom;
+        }
+    }
+    
+    // Dust stakes cost more in fees than they earn
+    if stake_amount < min_stake {
+        crate::events::publish(
+            env,
+            EVENTS_REBALANCES,
+            (Symbol::new(env, "stake_skip"),),
+            (stake_amount, min_stake)
+        );
+        return Ok(());
+    }
+    
+    // Stake the base asset (typically native XLM), wherever it sits in the list
+    let staking_token = crate::token_client::base_asset(env, assets)?;
     
     // Get current balance
     let balance = crate::token_client::get_vault_balance(env, &staking_token);
@@ -494,21 +957,16 @@ fn execute_stake_action(
         return Err(VaultError::InsufficientBalance);
     }
     
-    // Get staking pool address from config
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
-        .ok_or(VaultError::NotInitialized)?;
-    
-    let staking_pool = config.staking_pool_address
-        .ok_or(VaultError::InvalidConfiguration)?;
-    
     // Stake tokens through liquid staking pool
-    // This will deposit XLM and receive stXLM (or similar) in return
+    // This will deposit XLM and receive stXLM (or similar) in return, at
+    // close to the rate the pool quotes
+    let min_st_tokens = crate::staking_client::min_st_tokens(env, &staking_pool, stake_amount)?;
     let st_tokens_received = crate::staking_client::stake_tokens(
         env,
         &staking_pool,
         &staking_token,
         stake_amount,
+        min_st_tokens,
     )?;
     
     // Store staking position for tracking
@@ -517,12 +975,10 @@ fn execute_stake_action(
         original_token: staking_token.clone(),
         staked_amount: stake_amount,
         st_token_amount: st_tokens_received,
-        timestamp: env.ledger().timestamp(),
+        timestamp: crate::clock::now(env),
     };
     
     // Save position to storage
-    // Key: "stake_" + staking_pool address
-    let position_key = String::from_str(env, "stake_position");
     env.storage().instance().set(&position_key, &position);
     
     // Emit staking event
@@ -535,6 +991,91 @@ fn execute_stake_action(
     Ok(())
 }
 
+/// Execute unstake action
+/// The rule threshold is the share of the current staking position to
+/// unstake (100_0000 = all of it). Without a position there is nothing to do.
+fn execute_unstake_action(
+    env: &Env,
+    rule: &crate::types::RebalanceRule,
+) -> Result<(), VaultError> {
+    if rule.threshold <= 0 || rule.threshold > 100_0000 {
+        return Err(VaultError::InvalidConfiguration);
+    }
+    
+    let position_key = String::from_str(env, "stake_position");
+    let position: crate::types::StakingPosition = match env.storage().instance().get(&position_key) {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+    
+    // Size the unstake in staking tokens, rounding down
+    let st_amount = position.st_token_amount
+        .checked_mul(rule.threshold)
+        .and_then(|v| v.checked_div(100_0000))
+        .ok_or(VaultError::InvalidAmount)?;
+    
+    if st_amount <= 0 {
+        return Ok(());
+    }
+    
+    unstake(env, position, st_amount)?;
+    
+    Ok(())
+}
+
+// Unstake `st_amount` of the position and shrink its record to match
+// Returns the tokens received.
+fn unstake(
+    env: &Env,
+    mut position: crate::types::StakingPosition,
+    st_amount: i128,
+) -> Result<i128, VaultError> {
+    let position_key = String::from_str(env, "stake_position");
+    let tokens_received = crate::staking_client::unstake_tokens(
+        env,
+        &position.staking_pool,
+        st_amount,
+    )?;
+    
+    // Reduce the recorded stake by the same share of the original amount
+    let remaining_st = position.st_token_amount
+        .checked_sub(st_amount)
+        .ok_or(VaultError::InvalidAmount)?;
+    
+    if remaining_st == 0 {
+        env.storage().instance().remove(&position_key);
+    } else {
+        let released = position.staked_amount
+            .checked_mul(st_amount)
+            .and_then(|v| v.checked_div(position.st_token_amount))
+            .ok_or(VaultError::InvalidAmount)?;
+        position.staked_amount = position.staked_amount
+            .checked_sub(released)
+            .ok_or(VaultError::InvalidAmount)?;
+        position.st_token_amount = remaining_st;
+        env.storage().instance().set(&position_key, &position);
+    }
+    
+    // Emit unstaking event
+    crate::events::emit_vault_event(
+        env,
+        String::from_str(env, "tokens_unstaked"),
+        tokens_received,
+    );
+    
+    Ok(tokens_received)
+}
+
+/// Pair a liquidity action provides into: the base asset and the first
+/// other configured asset
+pub fn liquidity_pair(env: &Env, assets: &Vec<Address>) -> Result<(Address, Address), VaultError> {
+    let base = crate::token_client::base_asset(env, assets)?;
+    let other = assets.iter()
+        .find(|asset| *asset != base)
+        .ok_or(VaultError::InvalidConfiguration)?;
+    Ok((base, other))
+}
+
 /// Execute liquidity provision action
 fn execute_liquidity_action(
     env: &Env,
@@ -557,9 +1098,9 @@ fn execute_liquidity_action(
         return Err(VaultError::InsufficientBalance);
     }
     
-    // Get router and factory addresses from config
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&CONFIG)
+    // Read everything the provision depends on before calling out to the
+    // factory, pool or router, so nothing they do can change the decision
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
     let router_address = config.router_address
@@ -568,9 +1109,12 @@ fn execute_liquidity_action(
     let factory_address = config.factory_address
         .ok_or(VaultError::InvalidConfiguration)?;
     
-    // Use first two assets as liquidity pair
-    let token_a = assets.get(0).ok_or(VaultError::InvalidConfiguration)?;
-    let token_b = assets.get(1).ok_or(VaultError::InvalidConfiguration)?;
+    // Owner may pin a flat slippage percent, otherwise minimums scale with pool depth
+    let lp_slippage: Option<i128> = env.storage().instance()
+        .get(&LP_SLIPPAGE)
+        .unwrap_or(None);
+    
+    let (token_a, token_b) = liquidity_pair(env, assets)?;
     
     // Get current balances
     let balance_a = crate::token_client::get_vault_balance(env, &token_a);
@@ -584,18 +1128,9 @@ fn execute_liquidity_action(
         &token_b,
     )?;
     
-    // Get pool reserves to calculate optimal amounts
-    use crate::pool_client::LiquidityPoolClient;
-    let pool_client = LiquidityPoolClient::new(env, &pool_address);
-    let (reserve_a, reserve_b) = pool_client.get_reserves();
-    
-    // Determine if token_a is token0 or token1 in the pool
-    let pool_token0 = pool_client.token_0();
-    let (reserve_a_correct, reserve_b_correct) = if &pool_token0 == &token_a {
-        (reserve_a, reserve_b)
-    } else {
-        (reserve_b, reserve_a)
-    };
+    // Get pool reserves, oriented as (token_a, token_b), to calculate optimal amounts
+    let (reserve_a_correct, reserve_b_correct) =
+        crate::pool_client::orient_reserves(env, &pool_address, &token_a)?;
     
     // Calculate amounts to provide based on pool ratio
     // Start with half of liquidity_amount for each token
@@ -630,15 +1165,19 @@ fn execute_liquidity_action(
         return Err(VaultError::InsufficientBalance);
     }
     
-    // Add liquidity through router with 5% slippage tolerance
+    // Add liquidity through router
     let (lp_tokens, actual_a, actual_b) = crate::liquidity_router::add_liquidity_to_pool(
         env,
         &router_address,
         &token_a,
         &token_b,
-        amount_a,
-        amount_b,
-        5, // 5% slippage
+        &crate::liquidity_router::LiquidityAmounts {
+            amount_a,
+            amount_b,
+            reserve_a: reserve_a_correct,
+            reserve_b: reserve_b_correct,
+        },
+        lp_slippage,
     )?;
     
     // Store liquidity position for tracking
@@ -649,7 +1188,7 @@ fn execute_liquidity_action(
         lp_tokens,
         amount_a_provided: actual_a,
         amount_b_provided: actual_b,
-        timestamp: env.ledger().timestamp(),
+        timestamp: crate::clock::now(env),
     };
     
     // Save position to storage
@@ -667,6 +1206,148 @@ fn execute_liquidity_action(
     Ok(())
 }
 
+/// Withdraw `lp_tokens` of the vault's liquidity position
+/// Goes through the router when it works and straight to the pool when it
+/// doesn't, so a broken router can't strand the position. The position shrinks
+/// in proportion and is removed once empty. Returns (amount_a, amount_b).
+pub fn recover_liquidity(env: &Env, lp_tokens: i128) -> Result<(i128, i128), VaultError> {
+    let position_key = String::from_str(env, "lp_position");
+    let mut position: crate::types::LiquidityPosition = env.storage().instance()
+        .get(&position_key)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    if lp_tokens <= 0 || lp_tokens > position.lp_tokens {
+        return Err(VaultError::InvalidAmount);
+    }
+    
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    let via_router = config.router_address.and_then(|router_address| {
+        let deadline = crate::clock::now(env) + 3600;
+        match crate::liquidity_router::LiquidityRouterClient::new(env, &router_address)
+            .try_remove_liquidity(
+                &position.token_a,
+                &position.token_b,
+                &lp_tokens,
+                &0,
+                &0,
+                &env.current_contract_address(),
+                &deadline,
+            )
+        {
+            Ok(Ok((amount_a, amount_b))) if amount_a > 0 && amount_b > 0 => Some((amount_a, amount_b)),
+            _ => None,
+        }
+    });
+    
+    let (amount_a, amount_b) = match via_router {
+        Some(amounts) => amounts,
+        None => crate::pool_client::remove_liquidity_via_pool(
+            env,
+            &position.pool_address,
+            &position.token_a,
+            lp_tokens,
+        )?,
+    };
+    
+    let remaining = position.lp_tokens - lp_tokens;
+    if remaining == 0 {
+        env.storage().instance().remove(&position_key);
+    } else {
+        let keep = |provided: i128| -> Result<i128, VaultError> {
+            provided.checked_mul(remaining)
+                .and_then(|v| v.checked_div(position.lp_tokens))
+                .ok_or(VaultError::InvalidAmount)
+        };
+        position.amount_a_provided = keep(position.amount_a_provided)?;
+        position.amount_b_provided = keep(position.amount_b_provided)?;
+        position.lp_tokens = remaining;
+        env.storage().instance().set(&position_key, &position);
+    }
+    
+    env.events().publish(
+        (Symbol::new(env, "lp_recovered"), position.pool_address),
+        (lp_tokens, amount_a, amount_b, via_router.is_none()),
+    );
+    
+    Ok((amount_a, amount_b))
+}
+
+/// Unstake the whole staking position back into the vault
+/// The staked amount was already counted in total_value, so only the
+/// difference between what came back and what went in is booked.
+/// Returns the tokens received.
+pub fn withdraw_staking(env: &Env) -> Result<i128, VaultError> {
+    let position_key = String::from_str(env, "stake_position");
+    let position: crate::types::StakingPosition = env.storage().instance()
+        .get(&position_key)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    let staked_amount = position.staked_amount;
+    let st_token_amount = position.st_token_amount;
+    let staking_pool = position.staking_pool.clone();
+    let tokens_received = unstake(env, position, st_token_amount)?;
+    
+    let mut state: crate::types::VaultState = crate::migrations::read_state(env)
+        .ok_or(VaultError::NotInitialized)?;
+    state.total_value = tokens_received
+        .checked_sub(staked_amount)
+        .and_then(|gain| state.total_value.checked_add(gain))
+        .ok_or(VaultError::InvalidAmount)?;
+    crate::migrations::write_state(env, &state);
+    
+    env.events().publish(
+        (Symbol::new(env, "staking_withdrawn"), staking_pool),
+        (st_token_amount, staked_amount, tokens_received),
+    );
+    
+    Ok(tokens_received)
+}
+
+/// Remove the whole liquidity position through the router
+/// Like withdraw_staking, only the difference between what came back and what
+/// was provided is booked into total_value. Returns the amounts of token_a
+/// and token_b received.
+pub fn withdraw_liquidity(env: &Env, slippage_percent: i128) -> Result<(i128, i128), VaultError> {
+    let position_key = String::from_str(env, "lp_position");
+    let position: crate::types::LiquidityPosition = env.storage().instance()
+        .get(&position_key)
+        .ok_or(VaultError::NotInitialized)?;
+    
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
+        .ok_or(VaultError::NotInitialized)?;
+    let router_address = config.router_address
+        .ok_or(VaultError::RouterNotSet)?;
+    
+    let (amount_a, amount_b) = crate::liquidity_router::remove_liquidity_from_pool(
+        env,
+        &router_address,
+        &position.token_a,
+        &position.token_b,
+        position.lp_tokens,
+        slippage_percent,
+    )?;
+    env.storage().instance().remove(&position_key);
+    
+    let mut state: crate::types::VaultState = crate::migrations::read_state(env)
+        .ok_or(VaultError::NotInitialized)?;
+    state.total_value = amount_a
+        .checked_add(amount_b)
+        .and_then(|received| received.checked_sub(position.amount_a_provided))
+        .and_then(|gain| gain.checked_sub(position.amount_b_provided))
+        .and_then(|gain| state.total_value.checked_add(gain))
+        .ok_or(VaultError::InvalidAmount)?;
+    crate::migrations::write_state(env, &state);
+    
+    env.events().publish(
+        (Symbol::new(env, "lp_removed"), position.pool_address),
+        (position.lp_tokens, amount_a, amount_b),
+    );
+    
+    Ok((amount_a, amount_b))
+}
+
 /// Helper function to swap tokens using Stellar liquidity pools
 fn swap_tokens(
     env: &Env,
@@ -679,8 +1360,7 @@ fn swap_tokens(
     }
     
     // Get router address from vault config
-    let config: crate::types::VaultConfig = env.storage().instance()
-        .get(&symbol_short!("CONFIG"))
+    let config: crate::types::VaultConfig = crate::migrations::read_config(env)
         .ok_or(VaultError::NotInitialized)?;
     
     let router_address = config.router_address
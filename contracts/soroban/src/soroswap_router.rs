@@ -34,4 +34,12 @@ pub trait SoroswapRouterInterface {
         amount_in: i128,
         path: Vec<Address>,
     ) -> Vec<i128>;
+    
+    /// Pair the router swaps `token_a`/`token_b` through
+    /// Swaps pay the input from `to` straight into this pair.
+    fn router_pair_for(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+    ) -> Address;
 }
@@ -30,13 +30,40 @@ pub trait StakingPoolInterface {
     fn get_staked_balance(env: Env, user: Address) -> i128;
 }
 
+/// Shortfall against the pool's quoted rate a stake may mint, in basis points
+/// Covers rounding and deposit fees; anything worse is treated as a bad pool.
+pub const STAKE_TOLERANCE_BPS: i128 = 100;
+
+/// Fewest staking tokens a stake of `amount` should mint at the pool's quoted rate
+/// The quote less STAKE_TOLERANCE_BPS, for stake_tokens' `min_st_tokens`.
+pub fn min_st_tokens(
+    env: &Env,
+    pool_address: &Address,
+    amount: i128,
+) -> Result<i128, crate::errors::VaultError> {
+    use crate::errors::VaultError;
+    
+    let (base_amount, st_token_amount) = get_staking_rate(env, pool_address)?;
+    if base_amount <= 0 || st_token_amount <= 0 {
+        return Err(VaultError::InvalidConfiguration);
+    }
+    
+    amount.checked_mul(st_token_amount)
+        .and_then(|v| v.checked_div(base_amount))
+        .and_then(|v| v.checked_mul(10000 - STAKE_TOLERANCE_BPS))
+        .map(|v| v / 10000)
+        .ok_or(VaultError::InvalidAmount)
+}
+
 /// Stake tokens through a liquid staking pool
-/// This deposits the token and receives liquid staking tokens in return
+/// This deposits the token and receives liquid staking tokens in return.
+/// Fails with SlippageTooHigh if the pool mints fewer than `min_st_tokens`.
 pub fn stake_tokens(
     env: &Env,
     pool_address: &Address,
     token: &Address,
     amount: i128,
+    min_st_tokens: i128,
 ) -> Result<i128, crate::errors::VaultError> {
     use crate::errors::VaultError;
     
@@ -68,6 +95,10 @@ pub fn stake_tokens(
         return Err(VaultError::InvalidAmount);
     }
     
+    if st_tokens_received < min_st_tokens {
+        return Err(VaultError::SlippageTooHigh);
+    }
+    
     Ok(st_tokens_received)
 }
 
@@ -0,0 +1,41 @@
+// Vault stats reported to the stamping factory
+// The factory keeps protocol-wide TVL and user totals from what each vault
+// reports, so the vault sends its total_value and holder count whenever
+// deposits, withdrawals or a sync move them. Vaults the factory didn't
+// stamp have nowhere to report to.
+use soroban_sdk::{symbol_short, Env, Symbol};
+use crate::network::StampingFactoryClient;
+
+const HOLDERS: Symbol = symbol_short!("HOLDERS");
+
+/// Number of addresses holding shares
+/// Counted from when positions are stored and removed, so positions written
+/// before the count existed aren't in it.
+pub fn holders(env: &Env) -> u32 {
+    env.storage().instance().get(&HOLDERS).unwrap_or(0)
+}
+
+/// Count a newly stored position
+pub fn add_holder(env: &Env) {
+    env.storage().instance().set(&HOLDERS, &holders(env).saturating_add(1));
+}
+
+/// Count a removed position
+pub fn remove_holder(env: &Env) {
+    env.storage().instance().set(&HOLDERS, &holders(env).saturating_sub(1));
+}
+
+/// Report `tvl` and the holder count to the stamping factory, if any
+/// A refused report (the factory archived this vault, say) must not block
+/// the deposit or withdrawal behind it, so it is only flagged by an event.
+pub fn report(env: &Env, tvl: i128) {
+    let Some((factory, _)) = crate::network::stamp(env) else {
+        return;
+    };
+
+    let reported = StampingFactoryClient::new(env, &factory)
+        .try_report_stats(&env.current_contract_address(), &tvl, &holders(env));
+    if reported.is_err() {
+        env.events().publish((Symbol::new(env, "stats_refused"),), factory);
+    }
+}
@@ -1,7 +1,11 @@
 // Swap router interface for integrating with Soroswap/Phoenix DEX
-use soroban_sdk::{Address, Env, Vec, BytesN};
+use soroban_sdk::auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation};
+use soroban_sdk::{symbol_short, Address, Env, IntoVal, Symbol, Vec, BytesN};
 use crate::errors::VaultError;
 use crate::soroswap_router::SoroswapRouterClient;
+use crate::types::Venue;
+
+const VENUES: Symbol = symbol_short!("VENUES");
 
 /// Interface for Soroswap Aggregator Router
 /// Allows swapping tokens through multiple liquidity sources
@@ -18,7 +22,26 @@ pub trait SwapRouterInterface {
     ) -> u128;
 }
 
+/// Shortfall from the oracle value a token deposit's swap may have by default (1%)
+pub const DEFAULT_DEPOSIT_SLIPPAGE_BPS: i128 = 100;
+
+/// Venues swaps may use, in the order they are tried (empty: pool, then router)
+pub fn venue_preference(env: &Env) -> Vec<Venue> {
+    env.storage().instance().get(&VENUES).unwrap_or(Vec::new(env))
+}
+
+/// Replace the venue preference; an empty list restores the default order
+pub fn set_venue_preference(env: &Env, venues: &Vec<Venue>) {
+    if venues.is_empty() {
+        env.storage().instance().remove(&VENUES);
+    } else {
+        env.storage().instance().set(&VENUES, venues);
+    }
+}
+
 /// Execute token swap through Soroswap router
+/// With a venue preference set, venues are tried in that order and nothing
+/// outside the list is used; a venue without a pool for the pair is passed over.
 pub fn swap_via_router(
     env: &Env,
     router_address: &Address,
@@ -31,6 +54,44 @@ pub fn swap_via_router(
         return Err(VaultError::InvalidAmount);
     }
 
+    let venues = venue_preference(env);
+    if !venues.is_empty() {
+        for venue in venues.iter() {
+            match venue {
+                Venue::DirectPool => {
+                    let factory_address = get_soroswap_factory_address(env);
+                    if let Ok(pool_address) = crate::pool_client::get_pool_for_pair(
+                        env,
+                        &factory_address,
+                        from_token,
+                        to_token,
+                    ) {
+                        return crate::pool_client::swap_via_pool(
+                            env,
+                            &pool_address,
+                            from_token,
+                            to_token,
+                            amount_in,
+                            min_amount_out,
+                        );
+                    }
+                }
+                Venue::SoroswapRouter => {
+                    return swap_via_router_fallback(
+                        env,
+                        router_address,
+                        from_token,
+                        to_token,
+                        amount_in,
+                        min_amount_out,
+                    );
+                }
+                Venue::Phoenix => {}
+            }
+        }
+        return Err(VaultError::SwapFailed);
+    }
+
     // WORKAROUND: Instead of using the router which has auth issues,
     // we'll swap directly through the liquidity pool
     // This avoids the authorize_as_current_contract problem
@@ -53,7 +114,6 @@ pub fn swap_via_router(
         Ok(addr) => addr,
         Err(_) => {
             // If we can't find pool via factory, fall back to router
-            // but this will likely fail with auth error
             return swap_via_router_fallback(
                 env,
                 router_address,
@@ -76,7 +136,9 @@ pub fn swap_via_router(
     )
 }
 
-/// Fallback to router-based swap (may have auth issues)
+/// Swap through the Soroswap router
+/// The router transfers the input from the vault straight into the pair, a
+/// call the vault doesn't make itself, so that transfer is authorized up front.
 fn swap_via_router_fallback(
     env: &Env,
     router_address: &Address,
@@ -91,25 +153,29 @@ fn swap_via_router_fallback(
     path.push_back(to_token.clone());
     
     // Set deadline to 5 minutes from now
-    let deadline = env.ledger().timestamp() + 300;
+    let deadline = crate::clock::now(env) + 300;
     
     // Get vault address
     let vault_address = env.current_contract_address();
     
-    // Approve the router to spend from_token
-    crate::token_client::approve_router(
-        env,
-        from_token,
-        router_address,
-        amount_in,
-    )?;
+    let router_client = SoroswapRouterClient::new(env, router_address);
+    let pair_address = router_client.router_pair_for(from_token, to_token);
     
-    // CRITICAL: Authorize sub-contract calls
-    env.authorize_as_current_contract(soroban_sdk::vec![env]);
+    // The router's own require_auth on `to` is covered by the vault calling it
+    // directly; the token transfer it makes on to the pair is not
+    env.authorize_as_current_contract(soroban_sdk::vec![
+        env,
+        InvokerContractAuthEntry::Contract(SubContractInvocation {
+            context: ContractContext {
+                contract: from_token.clone(),
+                fn_name: Symbol::new(env, "transfer"),
+                args: (vault_address.clone(), pair_address, amount_in).into_val(env),
+            },
+            sub_invocations: Vec::new(env),
+        }),
+    ]);
     
     // Execute swap through Soroswap router
-    let router_client = SoroswapRouterClient::new(env, router_address);
-    
     // Call swap_exact_tokens_for_tokens
     let amounts = router_client.swap_exact_tokens_for_tokens(
         &amount_in,
@@ -128,6 +194,9 @@ fn swap_via_router_fallback(
         return Err(VaultError::SlippageTooHigh);
     }
 
+    // Router swaps go through the same pairs and pay the same fee
+    crate::fees::record_swap_fee(env, amount_in);
+
     Ok(amount_out)
 }
 
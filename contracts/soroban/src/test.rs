@@ -0,0 +1,2881 @@
+#![cfg(test)]
+
+extern crate std;
+
+use soroban_sdk::{
+    testutils::{Address as _, Events, Ledger},
+    token::TokenClient,
+    vec, Address, BytesN, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+
+use crate::errors::VaultError;
+use crate::testutils::buffer_pool::{MockBufferPool, MockBufferPoolClient};
+use crate::testutils::{config, create_oracle, create_pair, create_staking_pool, create_token, create_vault, rule, TestVault};
+use crate::types::{SkipReason, TriggerResult};
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Find the data of the last event whose first topic is `name`
+fn find_event(env: &Env, name: &str) -> Option<(Vec<Val>, Val)> {
+    let name = Symbol::new(env, name);
+    let mut found = None;
+    for (_, topics, data) in env.events().all().iter() {
+        if let Some(first) = topics.get(0) {
+            if Symbol::try_from_val(env, &first).ok() == Some(name.clone()) {
+                found = Some((topics.clone(), data));
+            }
+        }
+    }
+    found
+}
+
+/// Number of events so far whose first topic is `name`
+fn count_events(env: &Env, name: &str) -> usize {
+    let name = Symbol::new(env, name);
+    env.events().all().iter()
+        .filter(|(_, topics, _)| {
+            topics.get(0).and_then(|first| Symbol::try_from_val(env, &first).ok()) == Some(name.clone())
+        })
+        .count()
+}
+
+// ---------------------------------------------------------------------------
+// Trigger results
+// ---------------------------------------------------------------------------
+
+#[test]
+fn trigger_skips_empty_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let (token, _) = create_token(&env, &owner);
+    let rules = vec![&env, rule(&env, "allocation", 0, "rebalance", &[100_0000])];
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], rules));
+
+    assert_eq!(vault.trigger_rebalance(), TriggerResult::Skipped(SkipReason::EmptyVault));
+
+    let (topics, data) = find_event(&env, "trigger_skip").unwrap();
+    let action: Symbol = topics.get(1).unwrap().into_val(&env);
+    let reason: SkipReason = data.into_val(&env);
+    assert_eq!(action, Symbol::new(&env, "rebalance"));
+    assert_eq!(reason, SkipReason::EmptyVault);
+
+    // Clients built against the old () return can check what they talk to
+    assert_eq!(vault.get_contract_version(), crate::types::CONTRACT_VERSION);
+}
+
+#[test]
+fn trigger_skips_when_no_rule_matches() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let rules = vec![&env, rule(&env, "allocation", 0, "rebalance", &[100_0000])];
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], rules));
+
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    // Only a rebalance rule exists, so stake and liquidity have nothing to fire
+    assert_eq!(vault.trigger_stake(), TriggerResult::Skipped(SkipReason::RulesNotMet));
+    assert_eq!(vault.trigger_liquidity(), TriggerResult::Skipped(SkipReason::RulesNotMet));
+
+    let (topics, data) = find_event(&env, "trigger_skip").unwrap();
+    let action: Symbol = topics.get(1).unwrap().into_val(&env);
+    let reason: SkipReason = data.into_val(&env);
+    assert_eq!(action, Symbol::new(&env, "liquidity"));
+    assert_eq!(reason, SkipReason::RulesNotMet);
+}
+
+#[test]
+fn trigger_reports_cooldown_for_time_rules() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 3600, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+
+    assert_eq!(t.vault.trigger_stake(), TriggerResult::Skipped(SkipReason::Cooldown));
+
+    // Once the interval has elapsed the same call executes
+    t.advance_time(3600);
+    match t.vault.trigger_stake() {
+        TriggerResult::Executed(report) => {
+            assert_eq!(report.timestamp, 1_000 + 3600);
+            assert_eq!(report.total_value, 1000);
+        }
+        other => panic!("expected execution, got {:?}", other),
+    }
+    assert!(t.vault.has_staking_position());
+}
+
+#[test]
+fn trigger_rebalance_executes_when_rules_met() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("allocation", 0, "rebalance", &[100_0000])
+        .with_mock_dex()
+        .build();
+    t.deposit(500);
+
+    match t.vault.trigger_rebalance() {
+        TriggerResult::Executed(report) => assert_eq!(report.total_value, 500),
+        other => panic!("expected execution, got {:?}", other),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Asset weights
+// ---------------------------------------------------------------------------
+
+#[test]
+fn asset_weights_reports_target_and_current() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token_a, token_a_admin) = create_token(&env, &owner);
+    let (token_b, token_b_admin) = create_token(&env, &owner);
+
+    let rules = vec![
+        &env,
+        rule(&env, "time", 60, "stake", &[]),
+        rule(&env, "allocation", 0, "rebalance", &[60_0000, 40_0000]),
+        rule(&env, "allocation", 0, "rebalance", &[10_0000, 90_0000]),
+    ];
+    let assets = vec![&env, token_a.address.clone(), token_b.address.clone()];
+    let vault = create_vault(&env, &config(&env, &owner, assets, rules));
+
+    token_a_admin.mint(&user, &750);
+    vault.deposit(&user, &750);
+    // Drift: some of token B lands in the vault outside a rebalance
+    token_b_admin.mint(&vault.address, &250);
+
+    let weights = vault.asset_weights();
+    assert_eq!(weights.len(), 2);
+    // Targets come from the first rebalance rule
+    assert_eq!(weights.get(0).unwrap(), (token_a.address.clone(), 6000, 7500));
+    assert_eq!(weights.get(1).unwrap(), (token_b.address.clone(), 4000, 2500));
+}
+
+// ---------------------------------------------------------------------------
+// Withdraw receipts
+// ---------------------------------------------------------------------------
+
+#[test]
+fn withdraw_returns_receipt() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    let receipt = vault.withdraw(&user, &400);
+    assert_eq!(receipt.shares_burned, 400);
+    assert_eq!(receipt.gross_amount, 400);
+    assert_eq!(receipt.fees.len(), 0);
+    assert_eq!(receipt.net_amount, 400);
+    assert_eq!(receipt.payout_token, token.address);
+    assert_eq!(token.balance(&user), 400);
+
+    let (_, data) = find_event(&env, "withdraw").unwrap();
+    let payload: (i128, i128, Vec<(Symbol, i128)>, i128, Address) = data.into_val(&env);
+    assert_eq!(payload, (400, 400, Vec::new(&env), 400, token.address.clone()));
+
+    // Compatibility entry point pays the same and returns only the net amount
+    assert_eq!(vault.withdraw_net(&user, &600), 600);
+    assert_eq!(token.balance(&user), 1000);
+    assert_eq!(vault.get_position(&user).shares, 0);
+}
+
+// ---------------------------------------------------------------------------
+// force_rebalance without a rebalance rule
+// ---------------------------------------------------------------------------
+
+#[test]
+fn force_rebalance_without_rebalance_rule_is_reported() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 500);
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let pool = create_staking_pool(&env, &token.address);
+
+    let rules = vec![
+        &env,
+        rule(&env, "time", 0, "stake", &[]),
+        rule(&env, "time", 0, "liquidity", &[]),
+    ];
+    let mut cfg = config(&env, &owner, vec![&env, token.address.clone()], rules);
+    cfg.staking_pool_address = Some(pool);
+    let vault = create_vault(&env, &cfg);
+
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    env.ledger().with_mut(|l| l.timestamp = 900);
+    vault.force_rebalance();
+
+    assert!(find_event(&env, "no_rebalance_rule").is_some());
+    assert!(find_event(&env, "rebalance").is_none());
+    // Nothing ran and the timestamp was not bumped
+    assert_eq!(vault.get_state().last_rebalance, 500);
+    assert!(!vault.has_staking_position());
+}
+
+// ---------------------------------------------------------------------------
+// Factory co-admin for integration setters
+// ---------------------------------------------------------------------------
+
+#[test]
+fn factory_can_set_integrations_only_when_allowed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let factory = Address::generate(&env);
+    let router = Address::generate(&env);
+    let (token, _) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    // Not trusted yet
+    let res = vault.try_factory_set_integration(&factory, &Symbol::new(&env, "router"), &router);
+    assert_eq!(res, Err(Ok(VaultError::Unauthorized)));
+
+    // Only the owner may opt in
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        vault.try_set_allow_factory_admin(&stranger, &factory, &true),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    vault.set_allow_factory_admin(&owner, &factory, &true);
+    assert!(vault.get_allow_factory_admin());
+
+    vault.factory_set_integration(&factory, &Symbol::new(&env, "router"), &router);
+    assert_eq!(vault.get_config().router_address, Some(router.clone()));
+
+    // A different factory is still rejected, as is an unknown kind
+    let other_factory = Address::generate(&env);
+    assert_eq!(
+        vault.try_factory_set_integration(&other_factory, &Symbol::new(&env, "router"), &router),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_factory_set_integration(&factory, &Symbol::new(&env, "oracle"), &router),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    // Revoking stops further updates
+    vault.set_allow_factory_admin(&owner, &factory, &false);
+    let pool = Address::generate(&env);
+    assert_eq!(
+        vault.try_factory_set_integration(&factory, &Symbol::new(&env, "staking"), &pool),
+        Err(Ok(VaultError::Unauthorized))
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Liquidity slippage
+// ---------------------------------------------------------------------------
+
+#[test]
+fn lp_min_amounts_scale_with_pool_depth() {
+    use crate::liquidity_router::{derive_min_amounts, flat_min_amounts};
+
+    // Depositing 1_000 of each side
+    let deep = derive_min_amounts(1_000, 1_000, 10_000_000, 10_000_000).unwrap();
+    let shallow = derive_min_amounts(1_000, 1_000, 4_000, 4_000).unwrap();
+
+    // Deep pool: ~0.5% base tolerance plus a sliver of impact
+    assert_eq!(deep, (995, 995));
+    // Shallow pool: we become 20% of the pool, tolerance 0.5% + 20%, capped at 20%
+    assert_eq!(shallow, (800, 800));
+    assert!(deep.0 > shallow.0);
+
+    // The side with the larger impact drives the tolerance for both amounts
+    let lopsided = derive_min_amounts(1_000, 1_000, 10_000_000, 9_000).unwrap();
+    assert_eq!(lopsided, (895, 895));
+
+    // An empty pool only gets the base tolerance
+    assert_eq!(derive_min_amounts(1_000, 2_000, 0, 0).unwrap(), (995, 1990));
+
+    // The flat override ignores depth entirely
+    assert_eq!(flat_min_amounts(1_000, 1_000, 5).unwrap(), (950, 950));
+    assert!(flat_min_amounts(1_000, 1_000, 101).is_err());
+}
+
+#[test]
+fn lp_slippage_override_is_owner_only() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let (token, _) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    assert_eq!(vault.get_lp_slippage(), None);
+    vault.set_lp_slippage(&owner, &Some(3));
+    assert_eq!(vault.get_lp_slippage(), Some(3));
+
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        vault.try_set_lp_slippage(&stranger, &None),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    assert_eq!(
+        vault.try_set_lp_slippage(&owner, &Some(150)),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    vault.set_lp_slippage(&owner, &None);
+    assert_eq!(vault.get_lp_slippage(), None);
+}
+
+// ---------------------------------------------------------------------------
+// Buffer yield pool
+// ---------------------------------------------------------------------------
+
+#[test]
+fn idle_buffer_is_parked_and_redeemed_on_demand() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    let pool_id = env.register_contract(None, MockBufferPool);
+    let pool = MockBufferPoolClient::new(&env, &pool_id);
+    pool.init(&token.address);
+    vault.set_buffer_yield_pool(&owner, &Some(pool_id.clone()), &100);
+
+    // Deposit parks everything above the 100 hot threshold
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+    assert_eq!(token.balance(&vault.address), 100);
+    assert_eq!(vault.get_parked_buffer(), 900);
+
+    // Withdrawal larger than the hot balance redeems just the shortfall
+    let receipt = vault.withdraw(&user, &300);
+    assert_eq!(receipt.net_amount, 300);
+    assert_eq!(token.balance(&user), 300);
+    assert_eq!(token.balance(&vault.address), 0);
+    assert_eq!(vault.get_parked_buffer(), 700);
+
+    // Yield accrues in the pool; emergency unwind redeems all of it
+    token_admin.mint(&pool_id, &50);
+    pool.accrue(&vault.address, &50);
+    assert_eq!(vault.unwind_buffer(&owner), 750);
+    assert_eq!(token.balance(&vault.address), 750);
+    assert_eq!(vault.get_parked_buffer(), 0);
+
+    // Keeper can re-park idle funds
+    assert_eq!(vault.manage_buffer(), 650);
+    assert_eq!(vault.get_parked_buffer(), 650);
+
+    // Disabling the pool brings everything back
+    vault.set_buffer_yield_pool(&owner, &None, &0);
+    assert_eq!(token.balance(&vault.address), 750);
+    assert_eq!(vault.get_buffer_yield_pool(), None);
+}
+
+#[test]
+fn force_stake_runs_even_when_rules_are_not_met() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    // Time rule still in cooldown, so the trigger refuses to stake
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+
+    assert_eq!(t.vault.trigger_stake(), TriggerResult::Skipped(SkipReason::Cooldown));
+    assert!(!t.vault.has_staking_position());
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_force_stake(&stranger), Err(Ok(VaultError::Unauthorized)));
+
+    t.vault.force_stake(&t.owner);
+    assert!(t.vault.has_staking_position());
+    assert_eq!(t.vault.get_staking_position().staked_amount, 500);
+    assert_eq!(t.token(0).balance(t.staking_pool.as_ref().unwrap()), 500);
+}
+
+#[test]
+fn staking_pool_cannot_reenter_the_vault_mid_stake() {
+    use crate::testutils::staking_pool::MockStakingPoolClient;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    let pool = t.staking_pool.clone().unwrap();
+    let pool_client = MockStakingPoolClient::new(&env, &pool);
+    pool_client.set_reenter(&t.vault.address);
+    let value_before = t.vault.get_state().total_value;
+
+    // The pool's callback is refused, and the stake is booked exactly once
+    t.vault.force_stake(&t.owner);
+    assert!(!pool_client.reentered());
+    assert_eq!(t.vault.get_staking_position().staked_amount, 500);
+    assert_eq!(t.vault.get_staking_position().st_token_amount, 500);
+    assert_eq!(t.token(0).balance(&pool), 500);
+    assert_eq!(t.token(0).balance(&t.vault.address), 500);
+    assert_eq!(t.vault.get_state().total_value, value_before);
+}
+
+#[test]
+fn force_liquidity_is_owner_only() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let rules = vec![&env, rule(&env, "allocation", 0, "rebalance", &[100_0000])];
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], rules));
+
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    assert_eq!(vault.trigger_liquidity(), TriggerResult::Skipped(SkipReason::RulesNotMet));
+
+    let stranger = Address::generate(&env);
+    assert_eq!(vault.try_force_liquidity(&stranger), Err(Ok(VaultError::Unauthorized)));
+
+    // No liquidity rule configured, so forcing it is a no-op rather than an error
+    vault.force_liquidity(&owner);
+    assert!(!vault.has_liquidity_position());
+}
+
+#[test]
+fn performance_fee_is_charged_on_each_users_own_gain() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let early = Address::generate(&env);
+    let late = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    vault.set_performance_fee(&owner, &2000);
+    assert_eq!(
+        vault.try_set_performance_fee(&owner, &10001),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    token_admin.mint(&early, &1000);
+    token_admin.mint(&late, &1000);
+
+    // Early user enters at 1.0
+    vault.deposit(&early, &1000);
+
+    // Vault doubles, late user enters at 2.0
+    token_admin.mint(&vault.address, &1000);
+    assert_eq!(vault.sync_total_value(&owner), 2000);
+    assert_eq!(vault.deposit(&late, &1000), 500);
+
+    assert_eq!(vault.get_user_entry_price(&early), 1_0000000);
+    assert_eq!(vault.get_user_entry_price(&late), 2_0000000);
+
+    // Share price moves to 3.0
+    token_admin.mint(&vault.address, &1500);
+    assert_eq!(vault.sync_total_value(&owner), 4500);
+
+    // Same withdrawal, different fee: 20% of a 200 gain vs 20% of a 100 gain
+    let early_receipt = vault.withdraw(&early, &100);
+    let late_receipt = vault.withdraw(&late, &100);
+
+    assert_eq!(early_receipt.gross_amount, 300);
+    assert_eq!(late_receipt.gross_amount, 300);
+    assert_eq!(early_receipt.fees, vec![&env, (Symbol::new(&env, "perf_fee"), 40)]);
+    assert_eq!(late_receipt.fees, vec![&env, (Symbol::new(&env, "perf_fee"), 20)]);
+    assert_eq!(early_receipt.net_amount, 260);
+    assert_eq!(late_receipt.net_amount, 280);
+    assert_eq!(token.balance(&owner), 60);
+}
+
+#[test]
+fn performance_fee_is_minted_as_shares_to_the_fee_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    let user = t.deposit(1000);
+    let treasury = Address::generate(&env);
+
+    t.vault.set_performance_fee(&t.owner, &2000);
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        t.vault.try_set_performance_fee_recipient(&stranger, &Some(stranger.clone())),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    t.vault.set_performance_fee_recipient(&t.owner, &Some(treasury.clone()));
+    assert_eq!(t.vault.get_performance_fee_recipient(), Some(treasury.clone()));
+
+    // Price 1.0 -> 2.0
+    t.admins[0].mint(&t.vault.address, &1000);
+    t.vault.sync_total_value(&t.owner);
+
+    // 500 shares worth 1000 on a 500 gain: a 100 fee, kept in the vault as 50 shares
+    let receipt = t.vault.withdraw(&user, &500);
+    assert_eq!(receipt.fees, vec![&env, (Symbol::new(&env, "perf_fee"), 100)]);
+    assert_eq!(receipt.net_amount, 900);
+    assert_eq!(t.token(0).balance(&user), 900);
+    assert_eq!(t.token(0).balance(&t.owner), 0);
+
+    assert_eq!(t.vault.get_position(&treasury).shares, 50);
+    assert_eq!(t.vault.get_user_entry_price(&treasury), 2_0000000);
+    let state = t.vault.get_state();
+    assert_eq!((state.total_shares, state.total_value), (550, 1100));
+
+    // Cleared, the fee is paid in the base asset again
+    t.vault.set_performance_fee_recipient(&t.owner, &None);
+    t.vault.withdraw(&user, &100);
+    assert_eq!(t.token(0).balance(&t.owner), 20);
+    assert_eq!(t.vault.get_position(&treasury).shares, 50);
+}
+
+#[test]
+fn fee_config_reports_the_configured_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    assert_eq!(t.vault.fee_config(), (0, 0, 0, t.owner.clone()));
+
+    let treasury = Address::generate(&env);
+    t.vault.set_performance_fee(&t.owner, &1500);
+    assert_eq!(t.vault.fee_config(), (0, 1500, 0, t.owner.clone()));
+    t.vault.set_performance_fee_recipient(&t.owner, &Some(treasury.clone()));
+    assert_eq!(t.vault.fee_config(), (0, 1500, 0, treasury));
+}
+
+#[test]
+fn entry_price_averages_across_top_ups() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    token_admin.mint(&user, &2000);
+    vault.deposit(&user, &1000);
+
+    token_admin.mint(&vault.address, &1000);
+    vault.sync_total_value(&owner);
+
+    // 1000 shares at 1.0 plus 500 shares at 2.0
+    vault.deposit(&user, &1000);
+    assert_eq!(vault.get_user_entry_price(&user), 1_3333333);
+
+    vault.withdraw(&user, &1500);
+    assert_eq!(vault.get_user_entry_price(&user), 0);
+}
+
+#[test]
+fn six_decimal_base_asset_mints_seven_decimal_shares() {
+    use crate::testutils::decimal_token::{MockDecimalToken, MockDecimalTokenClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let usdc_id = env.register_contract(None, MockDecimalToken);
+    let usdc = MockDecimalTokenClient::new(&env, &usdc_id);
+    usdc.init(&6);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, usdc_id.clone()], Vec::new(&env)));
+    assert_eq!(vault.get_share_decimals(), 7);
+
+    // 1 USDC buys 1.0 shares: ten share units per base unit
+    let alice = Address::generate(&env);
+    usdc.mint(&alice, &1_000000);
+    assert_eq!(vault.deposit(&alice, &1_000000), 1_0000000);
+    assert_eq!(vault.get_user_entry_price(&alice), 1_0000000);
+
+    let bob = Address::generate(&env);
+    usdc.mint(&bob, &2_500000);
+    assert_eq!(vault.deposit(&bob, &2_500000), 2_5000000);
+    assert_eq!(vault.get_state().total_value, 3_500000);
+
+    // Half of Alice's shares redeem for half her USDC, in base units
+    assert_eq!(vault.withdraw(&alice, &5000000).net_amount, 500000);
+    assert_eq!(usdc.balance(&alice), 500000);
+    assert_eq!(vault.withdraw(&bob, &2_5000000).net_amount, 2_500000);
+    assert_eq!(usdc.balance(&bob), 2_500000);
+}
+
+#[test]
+fn deposit_for_pulls_from_the_payer_and_credits_the_beneficiary() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    let payer = Address::generate(&env);
+    let holder = Address::generate(&env);
+    t.admins[0].mint(&payer, &1000);
+
+    // Nothing approved yet
+    assert!(t.vault.try_deposit_for(&payer, &holder, &400).is_err());
+
+    t.token(0).approve(&payer, &t.vault.address, &400, &env.ledger().sequence());
+    assert_eq!(t.vault.deposit_for(&payer, &holder, &400), 400);
+    assert_eq!(t.vault.get_position(&holder).shares, 400);
+    assert_eq!(t.vault.get_position(&payer).shares, 0);
+    assert_eq!(t.token(0).balance(&payer), 600);
+    assert_eq!(t.balance(0), 400);
+}
+
+#[test]
+fn gifted_deposit_is_paid_by_the_payer_and_held_by_the_beneficiary() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 100_000, 100_000);
+    let payer = Address::generate(&env);
+    let friend = Address::generate(&env);
+    t.admins[0].mint(&payer, &1000);
+    t.admins[1].mint(&payer, &1000);
+
+    t.advance_time(100);
+    assert_eq!(t.vault.deposit_for_with_token(&payer, &friend, &500, &t.token(0).address), 500);
+    let auths = env.auths();
+    assert_eq!(auths[0].0, payer);
+
+    // A non-base gift is swapped first, and still lands with the beneficiary
+    let swapped = t.vault.deposit_for_with_token(&payer, &friend, &500, &t.token(1).address);
+    assert!(swapped > 0 && swapped < 500);
+
+    assert_eq!((t.token(0).balance(&payer), t.token(1).balance(&payer)), (500, 500));
+    let position = t.vault.get_position(&friend);
+    assert_eq!(position.shares, 500 + swapped);
+    assert_eq!(position.last_deposit, env.ledger().timestamp());
+    assert_eq!(t.vault.get_position(&payer).shares, 0);
+
+    // Only the beneficiary can take it out
+    assert_eq!(t.vault.try_withdraw(&payer, &1), Err(Ok(VaultError::InsufficientShares)));
+    assert_eq!(t.vault.withdraw(&friend, &500).net_amount, 500);
+}
+
+#[test]
+fn deposits_for_someone_else_emit_share_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    let payer = Address::generate(&env);
+    let friend = Address::generate(&env);
+    t.admins[0].mint(&payer, &1000);
+
+    let shares = t.vault.deposit_for_with_token(&payer, &friend, &300, &t.token(0).address);
+    let (topics, data) = find_event(&env, "share_transfer").unwrap();
+    assert_eq!(Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap(), payer);
+    assert_eq!(Address::try_from_val(&env, &topics.get(2).unwrap()).unwrap(), friend);
+    assert_eq!(i128::try_from_val(&env, &data).unwrap(), shares);
+
+    t.token(0).approve(&payer, &t.vault.address, &200, &env.ledger().sequence());
+    let shares = t.vault.deposit_for(&payer, &friend, &200);
+    let (topics, data) = find_event(&env, "share_transfer").unwrap();
+    assert_eq!(Address::try_from_val(&env, &topics.get(1).unwrap()).unwrap(), payer);
+    assert_eq!(Address::try_from_val(&env, &topics.get(2).unwrap()).unwrap(), friend);
+    assert_eq!(i128::try_from_val(&env, &data).unwrap(), shares);
+
+    // Depositing for yourself is not a transfer
+    let seen = count_events(&env, "share_transfer");
+    t.deposit(100);
+    assert_eq!(count_events(&env, "share_transfer"), seen);
+
+    // And the category can be switched off
+    t.vault.set_event_flags(&t.owner, &(crate::events::EVENTS_ALL & !crate::events::EVENTS_TRANSFERS));
+    t.vault.deposit_for_with_token(&payer, &friend, &100, &t.token(0).address);
+    assert_eq!(count_events(&env, "share_transfer"), seen);
+}
+
+#[test]
+fn dust_left_by_a_withdrawal_is_swept() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    let alice = t.deposit(1000);
+    let bob = t.deposit(1000);
+
+    // Off by default: a 1-share remainder stays behind
+    t.vault.withdraw(&alice, &999);
+    assert_eq!(t.vault.get_position(&alice).shares, 1);
+    t.vault.withdraw_all(&alice);
+    assert_eq!(t.vault.get_position(&alice).shares, 0);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_set_dust_threshold(&stranger, &2, &true), Err(Ok(VaultError::Unauthorized)));
+
+    // Credited to the owner
+    t.vault.set_dust_threshold(&t.owner, &2, &true);
+    let carol = t.deposit(1000);
+    t.vault.withdraw(&carol, &998);
+    assert_eq!(t.vault.get_position(&carol).shares, 0);
+    assert_eq!(t.vault.get_position(&t.owner).shares, 2);
+    assert_eq!(t.vault.get_state().total_shares, 1002);
+
+    // Burned, leaving the value with the remaining holders
+    t.vault.set_dust_threshold(&t.owner, &2, &false);
+    let dave = t.deposit(1000);
+    t.vault.withdraw(&dave, &999);
+    assert_eq!(t.vault.get_position(&dave).shares, 0);
+    assert_eq!(t.vault.get_state().total_shares, 1002);
+    assert_eq!(t.vault.get_state().total_value, 1003);
+    assert_eq!(t.vault.withdraw_all(&bob).gross_amount, 1003 * 1000 / 1002);
+    assert_eq!(t.vault.get_position(&bob).shares, 0);
+}
+
+#[test]
+fn allocation_status_matches_planner_targets() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token_a, admin_a) = create_token(&env, &owner);
+    let (token_b, admin_b) = create_token(&env, &owner);
+    let pool = create_staking_pool(&env, &token_a.address);
+
+    let rebalance_rule = rule(&env, "allocation", 0, "rebalance", &[60_0000, 40_0000]);
+    let rules = vec![&env, rebalance_rule.clone(), rule(&env, "time", 20_0000, "stake", &[])];
+    let mut cfg = config(&env, &owner, vec![&env, token_a.address.clone(), token_b.address.clone()], rules);
+    cfg.staking_pool_address = Some(pool);
+    let vault = create_vault(&env, &cfg);
+    let oracle = create_oracle(&env, &[(&token_a.address, 1_00000000000000), (&token_b.address, 1_00000000000000)]);
+    vault.set_price_oracle(&owner, &Some(oracle), &600);
+
+    admin_a.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+    admin_b.mint(&vault.address, &500);
+
+    // 200 of token A moves into the staking pool but still counts as token A
+    vault.force_stake(&owner);
+
+    let status = vault.get_allocation_status();
+    let a = status.get(0).unwrap();
+    let b = status.get(1).unwrap();
+    assert_eq!((a.value, a.target_bps, a.current_bps, a.drift_bps), (1000, 6000, 6666, 666));
+    assert_eq!((b.value, b.target_bps, b.current_bps, b.drift_bps), (500, 4000, 3333, -667));
+    assert!(a.has_target && b.has_target);
+
+    // Targets line up with what the planner would size for the same total
+    let total_value = vault.get_state().total_value;
+    let planned = env.as_contract(&vault.address, || {
+        crate::rebalance::target_amounts(&env, &rebalance_rule, total_value).unwrap()
+    });
+    for i in 0..status.len() {
+        let entry = status.get(i).unwrap();
+        assert_eq!(entry.target_bps * total_value / 10000, planned.get(i).unwrap());
+    }
+}
+
+#[test]
+fn allocation_status_without_rebalance_rule() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], vec![&env]));
+
+    token_admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    let entry = vault.get_allocation_status().get(0).unwrap();
+    assert_eq!((entry.value, entry.target_bps, entry.current_bps, entry.drift_bps), (1000, 0, 10000, 0));
+    assert!(!entry.has_target);
+}
+
+// Native XLM SAC plus a classic account holding `balance` stroops
+fn native_asset_and_account(env: &Env, balance: i64) -> (TokenClient<'static>, Address) {
+    use soroban_sdk::xdr;
+    use std::rc::Rc;
+
+    // Native asset XDR is just the ASSET_TYPE_NATIVE discriminant
+    let native = env.deployer()
+        .with_stellar_asset(soroban_sdk::Bytes::from_array(env, &[0u8; 4]))
+        .deploy();
+
+    let account_id = xdr::AccountId(xdr::PublicKey::PublicKeyTypeEd25519(xdr::Uint256([7u8; 32])));
+    let budget = env.host().budget_cloned();
+    env.host()
+        .with_mut_storage(|storage| {
+            let key = Rc::new(xdr::LedgerKey::Account(xdr::LedgerKeyAccount {
+                account_id: account_id.clone(),
+            }));
+            let entry = Rc::new(xdr::LedgerEntry {
+                data: xdr::LedgerEntryData::Account(xdr::AccountEntry {
+                    account_id: account_id.clone(),
+                    balance,
+                    flags: 0,
+                    home_domain: Default::default(),
+                    inflation_dest: None,
+                    num_sub_entries: 0,
+                    seq_num: xdr::SequenceNumber(0),
+                    thresholds: xdr::Thresholds([1; 4]),
+                    signers: xdr::VecM::default(),
+                    ext: xdr::AccountEntryExt::V0,
+                }),
+                last_modified_ledger_seq: 0,
+                ext: xdr::LedgerEntryExt::V0,
+            });
+            storage.put(&key, &entry, None, &budget)
+        })
+        .unwrap();
+
+    let account = Address::try_from_val(env, &xdr::ScAddress::Account(account_id)).unwrap();
+    (TokenClient::new(env, &native), account)
+}
+
+#[test]
+fn native_xlm_round_trips_to_account_balance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let (xlm, user) = native_asset_and_account(&env, 100_0000000);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, xlm.address.clone()], vec![&env]));
+
+    assert!(vault.base_asset_is_native());
+
+    vault.deposit(&user, &40_0000000);
+    assert_eq!(xlm.balance(&user), 60_0000000);
+    assert_eq!(xlm.balance(&vault.address), 40_0000000);
+
+    let receipt = vault.withdraw(&user, &40_0000000);
+    assert_eq!(receipt.payout_token, xlm.address);
+    assert_eq!(xlm.balance(&user), 100_0000000);
+    assert_eq!(xlm.balance(&vault.address), 0);
+}
+
+#[test]
+fn rules_can_be_evaluated_at_hypothetical_times() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+
+    let owner = Address::generate(&env);
+    let (token, _) = create_token(&env, &owner);
+    let rules = vec![
+        &env,
+        rule(&env, "time", 3_600, "rebalance", &[100_0000]),
+        rule(&env, "time", 86_400, "stake", &[]),
+    ];
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token.address.clone()], rules));
+
+    // last_rebalance is 1_000, set at initialize
+    assert_eq!(vault.evaluate_rules_view(&None), vec![&env, false, false]);
+    assert_eq!(vault.evaluate_rules_view(&Some(4_599)), vec![&env, false, false]);
+    assert_eq!(vault.evaluate_rules_view(&Some(4_600)), vec![&env, true, false]);
+    assert_eq!(vault.evaluate_rules_view(&Some(87_400)), vec![&env, true, true]);
+
+    // Asking about the future doesn't move the ledger
+    assert_eq!(env.ledger().timestamp(), 1_000);
+    assert_eq!(vault.evaluate_rules_view(&None), vec![&env, false, false]);
+}
+
+#[test]
+fn rebalance_reverts_when_cumulative_loss_exceeds_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 10_000, 10_000);
+    t.deposit(2_000);
+
+    // Selling 1_000 A into a 10_000/10_000 pool returns 906 B: within the
+    // per-swap 5% bound, but a 4.7% loss of the vault's value
+    t.vault.set_max_rebalance_loss(&t.owner, &Some(100));
+    assert_eq!(t.vault.try_force_rebalance(), Err(Ok(VaultError::RebalanceLossExceeded)));
+    assert_eq!((t.balance(0), t.balance(1)), (2_000, 0));
+
+    t.vault.set_max_rebalance_loss(&t.owner, &Some(500));
+    t.vault.force_rebalance();
+    assert_eq!((t.balance(0), t.balance(1)), (1_000, 906));
+}
+
+#[test]
+fn allocation_sums_within_the_epsilon_are_normalized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let build = |targets: &[i128]| {
+        let t = TestVault::new(&env)
+            .with_assets(2)
+            .with_rule("allocation", 0, "rebalance", targets)
+            .with_mock_dex()
+            .build();
+        t.seed_pool(0, 1, 1_000_000, 1_000_000);
+        t.deposit(2_000);
+        t
+    };
+
+    // Rounded percentages a hair either side of 100% still rebalance
+    let under = build(&[50_0000, 49_9999]);
+    assert_eq!(under.vault.get_allocation_epsilon(), 100);
+    under.vault.force_rebalance();
+    assert_eq!(under.balance(0), 1_000);
+
+    let over = build(&[50_0001, 50_0000]);
+    over.vault.force_rebalance();
+    assert_eq!(over.balance(0), 1_000);
+
+    // Further off is still a configuration error, until the owner widens the epsilon
+    let off = build(&[50_0000, 49_9000]);
+    assert_eq!(off.vault.try_force_rebalance(), Err(Ok(VaultError::InvalidConfiguration)));
+    assert_eq!(off.balance(0), 2_000);
+    assert_eq!(
+        off.vault.try_set_allocation_epsilon(&off.owner, &1_0001),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+    off.vault.set_allocation_epsilon(&off.owner, &1_000);
+    off.vault.force_rebalance();
+    assert_eq!(off.balance(0), 2_000 * 50_0000 / 99_9000);
+
+    // And a zero epsilon demands an exact 100%
+    under.vault.set_allocation_epsilon(&under.owner, &0);
+    assert_eq!(under.vault.try_force_rebalance(), Err(Ok(VaultError::InvalidConfiguration)));
+}
+
+#[test]
+fn rebalance_clamps_buys_at_allocation_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token_a, admin_a) = create_token(&env, &owner);
+    let (token_b, admin_b) = create_token(&env, &owner);
+    create_pair(&env, &admin_a, &admin_b, 10_000, 10_000);
+
+    let rules = vec![&env, rule(&env, "allocation", 0, "rebalance", &[50_0000, 50_0000])];
+    let mut cfg = config(&env, &owner, vec![&env, token_a.address.clone(), token_b.address.clone()], rules);
+    cfg.router_address = Some(Address::generate(&env));
+    let vault = create_vault(&env, &cfg);
+    // Priced at par, so the cap check values holdings in the units the planner sizes in
+    let oracle = create_oracle(&env, &[(&token_a.address, 1_00000000000000), (&token_b.address, 1_00000000000000)]);
+    vault.set_price_oracle(&owner, &Some(oracle), &600);
+
+    admin_a.mint(&user, &2_000);
+    vault.deposit(&user, &2_000);
+
+    // The rule wants 50% B, the cap allows 40%
+    vault.set_max_allocation_bps(&owner, &token_b.address, &Some(4000));
+    vault.force_rebalance();
+
+    let held_a = token_a.balance(&vault.address);
+    let held_b = token_b.balance(&vault.address);
+    assert_eq!((held_a, held_b), (1_162, 771));
+    assert!(held_b * 10000 / (held_a + held_b) <= 4000);
+    assert!(find_event(&env, "cap_clamp").is_some());
+    assert_eq!(vault.check_allocation_caps(), vec![&env]);
+}
+
+#[test]
+fn assets_above_cap_are_flagged() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let user = Address::generate(&env);
+    let (token_a, admin_a) = create_token(&env, &owner);
+    let (token_b, admin_b) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token_a.address.clone(), token_b.address.clone()], vec![&env]));
+    let oracle = create_oracle(&env, &[(&token_a.address, 1_00000000000000), (&token_b.address, 1_00000000000000)]);
+    vault.set_price_oracle(&owner, &Some(oracle), &600);
+
+    admin_a.mint(&user, &1_000);
+    vault.deposit(&user, &1_000);
+    admin_b.mint(&vault.address, &1_000);
+
+    assert_eq!(
+        vault.try_set_max_allocation_bps(&owner, &Address::generate(&env), &Some(4000)),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+    vault.set_max_allocation_bps(&owner, &token_b.address, &Some(4000));
+    assert_eq!(vault.check_allocation_caps(), vec![&env, (token_b.address.clone(), 5000, 4000)]);
+
+    vault.sync_total_value(&owner);
+    let (topics, data) = find_event(&env, "allocation_breach").unwrap();
+    let asset: Address = topics.get(1).unwrap().into_val(&env);
+    let (current_bps, cap_bps): (i128, i128) = data.into_val(&env);
+    assert_eq!((asset, current_bps, cap_bps), (token_b.address.clone(), 5000, 4000));
+
+    vault.set_max_allocation_bps(&owner, &token_b.address, &None);
+    assert_eq!(vault.check_allocation_caps(), vec![&env]);
+}
+
+#[test]
+fn outstanding_allowances_are_listed_and_revocable() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let (token_a, _) = create_token(&env, &owner);
+    let (token_b, _) = create_token(&env, &owner);
+    let vault = create_vault(&env, &config(&env, &owner, vec![&env, token_a.address.clone(), token_b.address.clone()], vec![&env]));
+
+    let router = Address::generate(&env);
+    let pool = Address::generate(&env);
+    env.as_contract(&vault.address, || {
+        use crate::token_client::approve_router;
+        approve_router(&env, &token_a.address, &router, 100).unwrap();
+        approve_router(&env, &token_b.address, &router, 200).unwrap();
+        approve_router(&env, &token_a.address, &pool, 300).unwrap();
+        // Re-approving updates the existing entry rather than adding one
+        approve_router(&env, &token_b.address, &router, 250).unwrap();
+    });
+
+    let allowances = vault.get_outstanding_allowances();
+    assert_eq!(allowances.len(), 3);
+    let amounts: std::vec::Vec<(Address, Address, i128)> = allowances
+        .iter()
+        .map(|info| (info.token, info.spender, info.amount))
+        .collect();
+    assert_eq!(
+        amounts,
+        std::vec![
+            (token_a.address.clone(), router.clone(), 100),
+            (token_b.address.clone(), router.clone(), 250),
+            (token_a.address.clone(), pool.clone(), 300),
+        ]
+    );
+    assert_eq!(allowances.get(0).unwrap().expiry_ledger, env.ledger().sequence() + 100);
+
+    // An allowance zeroed outside the registry drops out of the view
+    env.as_contract(&vault.address, || {
+        TokenClient::new(&env, &token_a.address).approve(&vault.address, &pool, &0, &env.ledger().sequence());
+    });
+    assert_eq!(vault.get_outstanding_allowances().len(), 2);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(vault.try_revoke_all_allowances(&stranger), Err(Ok(VaultError::Unauthorized)));
+
+    assert_eq!(vault.revoke_all_allowances(&owner), 2);
+    assert_eq!(token_a.allowance(&vault.address, &router), 0);
+    assert_eq!(token_b.allowance(&vault.address, &router), 0);
+    assert_eq!(vault.get_outstanding_allowances().len(), 0);
+}
+
+#[test]
+fn transfer_tokens_moves_balances_on_both_sides() {
+    let env = Env::default();
+    // The sender authorizes from inside the vault's frame rather than at the root
+    env.mock_all_auths_allowing_non_root_auth();
+
+    let admin = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &admin);
+    let from = Address::generate(&env);
+    let to = Address::generate(&env);
+    token_admin.mint(&from, &1000);
+
+    let vault = create_vault(&env, &config(&env, &admin, vec![&env, token.address.clone()], vec![&env]));
+    env.as_contract(&vault.address, || {
+        use crate::token_client::transfer_tokens;
+        assert_eq!(transfer_tokens(&env, &token.address, &from, &to, 0), Err(VaultError::InvalidAmount));
+        transfer_tokens(&env, &token.address, &from, &to, 400).unwrap();
+    });
+
+    assert_eq!(token.balance(&from), 600);
+    assert_eq!(token.balance(&to), 400);
+}
+
+#[test]
+fn router_allowance_is_readable_after_approval() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let router = Address::generate(&env);
+    let sequence = env.ledger().sequence();
+
+    env.as_contract(&t.vault.address, || {
+        use crate::token_client::{approve_router, approve_router_for, check_allowance, DEFAULT_APPROVAL_LEDGERS};
+        assert_eq!(check_allowance(&env, &t.token(0).address, &router), 0);
+
+        approve_router(&env, &t.token(0).address, &router, 700).unwrap();
+        approve_router_for(&env, &t.token(1).address, &router, 300, 5_000).unwrap();
+        assert_eq!(approve_router_for(&env, &t.token(1).address, &router, 300, 0), Err(VaultError::InvalidAmount));
+
+        assert_eq!(check_allowance(&env, &t.token(0).address, &router), 700);
+        assert_eq!(check_allowance(&env, &t.token(1).address, &router), 300);
+        assert_eq!(DEFAULT_APPROVAL_LEDGERS, 100);
+    });
+
+    let expiries: std::vec::Vec<u32> = t.vault.get_outstanding_allowances()
+        .iter()
+        .map(|info| info.expiry_ledger)
+        .collect();
+    assert_eq!(expiries, std::vec![sequence + 100, sequence + 5_000]);
+
+    // The longer approval outlives the default one
+    env.ledger().with_mut(|li| li.sequence_number = sequence + 101);
+    assert_eq!(t.token(0).allowance(&t.vault.address, &router), 0);
+    assert_eq!(t.token(1).allowance(&t.vault.address, &router), 300);
+}
+
+#[test]
+fn vault_balance_reads_the_token_contract() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    let holder = Address::generate(&env);
+    t.admins[0].mint(&t.vault.address, &1234);
+    t.admins[0].mint(&holder, &50);
+
+    env.as_contract(&t.vault.address, || {
+        use crate::token_client::{get_balance, get_vault_balance};
+        assert_eq!(get_vault_balance(&env, &t.token(0).address), 1234);
+        assert_eq!(get_balance(&env, &t.token(0).address, &holder), 50);
+    });
+}
+
+// Two-asset vault with 1_000 of its 2_000 A staked and a 50/50 target
+fn half_staked_vault(env: &Env) -> crate::testutils::TestVaultHandles {
+    let t = TestVault::new(env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_dex()
+        .with_mock_staking()
+        .build();
+    t.seed_pool(0, 1, 10_000, 10_000);
+    t.deposit(2_000);
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.balance(0), 1_000);
+    t
+}
+
+#[test]
+fn rebalance_counts_staked_value_toward_its_asset() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let t = half_staked_vault(&env);
+
+    // Holdings are 2_000 A (half staked) and 0 B, so the target is 1_000 each
+    // and the liquid 1_000 A is sold for B
+    assert!(t.vault.get_rebalance_includes_positions());
+    t.vault.trigger_rebalance();
+    assert_eq!((t.balance(0), t.balance(1)), (0, 906));
+}
+
+#[test]
+fn rebalance_can_leave_positions_out_of_sizing() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let t = half_staked_vault(&env);
+
+    // Only the liquid 1_000 A is split, 500 each
+    t.vault.set_rebalance_includes_positions(&t.owner, &false);
+    t.vault.trigger_rebalance();
+    assert_eq!((t.balance(0), t.balance(1)), (500, 474));
+}
+
+#[test]
+fn stake_below_minimum_is_skipped() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("apy", 5_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    assert_eq!(t.vault.get_min_stake_amount(), 1);
+
+    // 5% of 1_000 is 50, below the minimum
+    t.vault.set_min_stake_amount(&t.owner, &100);
+    assert!(matches!(t.vault.trigger_stake(), TriggerResult::Executed(_)));
+    assert!(!t.vault.has_staking_position());
+    assert_eq!(t.balance(0), 1000);
+    let (_, data) = find_event(&env, "stake_skip").unwrap();
+    let skipped: (i128, i128) = data.into_val(&env);
+    assert_eq!(skipped, (50, 100));
+
+    t.vault.set_min_stake_amount(&t.owner, &50);
+    t.vault.trigger_stake();
+    assert_eq!(t.vault.get_staking_position().staked_amount, 50);
+
+    assert_eq!(t.vault.try_set_min_stake_amount(&t.owner, &-1), Err(Ok(VaultError::InvalidAmount)));
+}
+
+#[test]
+fn stake_is_clamped_to_the_per_pool_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("apy", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+
+    assert_eq!(
+        t.vault.try_set_max_stake_per_pool_bps(&t.owner, &Some(10001)),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+    t.vault.set_max_stake_per_pool_bps(&t.owner, &Some(2000));
+    assert_eq!(t.vault.get_max_stake_per_pool_bps(), Some(2000));
+
+    // The rule asks for 50% of 1_000; the cap allows 20%
+    t.vault.trigger_stake();
+    assert_eq!(t.vault.get_staking_position().staked_amount, 200);
+    assert_eq!(t.balance(0), 800);
+    let (_, data) = find_event(&env, "stake_capped").unwrap();
+    let capped: (i128, i128) = data.into_val(&env);
+    assert_eq!(capped, (500, 200));
+
+    // At the cap, nothing more is staked
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.vault.get_staking_position().staked_amount, 200);
+    assert_eq!(t.balance(0), 800);
+}
+
+#[test]
+fn paused_vault_refuses_deposits_but_allows_withdrawals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    let user = t.deposit(1000);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_pause(&stranger), Err(Ok(VaultError::Unauthorized)));
+    assert!(!t.vault.paused());
+    t.vault.pause(&t.owner);
+    assert!(t.vault.paused());
+
+    t.admins[0].mint(&user, &500);
+    assert_eq!(t.vault.try_deposit(&user, &500), Err(Ok(VaultError::Paused)));
+    assert_eq!(
+        t.vault.try_deposit_with_token(&user, &500, &t.token(0).address),
+        Err(Ok(VaultError::Paused))
+    );
+    // Keepers are told the triggers were skipped, not failed
+    assert_eq!(t.vault.trigger_rebalance(), TriggerResult::Skipped(SkipReason::Paused));
+    assert_eq!(t.vault.trigger_stake(), TriggerResult::Skipped(SkipReason::Paused));
+    let (topics, data) = find_event(&env, "trigger_skip").unwrap();
+    let action: Symbol = topics.get(1).unwrap().into_val(&env);
+    let reason: SkipReason = data.into_val(&env);
+    assert_eq!((action, reason), (Symbol::new(&env, "stake"), SkipReason::Paused));
+    assert_eq!(t.vault.trigger_liquidity(), TriggerResult::Skipped(SkipReason::Paused));
+    t.vault.force_rebalance();
+    assert_eq!(t.vault.try_force_stake(&t.owner), Err(Ok(VaultError::Paused)));
+    assert!(!t.vault.has_staking_position());
+
+    // Users can always get out
+    assert_eq!(t.vault.withdraw(&user, &400).net_amount, 400);
+    assert_eq!(t.token(0).balance(&user), 900);
+
+    t.vault.unpause(&t.owner);
+    assert!(!t.vault.paused());
+    assert_eq!(t.vault.deposit(&user, &500), 500);
+}
+
+#[test]
+fn emergency_mode_only_allows_proportional_exits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let alice = t.deposit(1000);
+    let bob = t.deposit(3000);
+    t.admins[1].mint(&t.vault.address, &800);
+
+    let guardian = Address::generate(&env);
+    assert_eq!(t.vault.try_set_guardian(&guardian, &Some(guardian.clone())), Err(Ok(VaultError::Unauthorized)));
+    t.vault.set_guardian(&t.owner, &Some(guardian.clone()));
+    assert_eq!(t.vault.get_guardian(), Some(guardian.clone()));
+
+    // Not an incident yet: no emergency exits
+    assert_eq!(t.vault.try_emergency_withdraw(&alice, &100), Err(Ok(VaultError::InvalidConfiguration)));
+    assert_eq!(t.vault.try_enter_emergency_mode(&alice), Err(Ok(VaultError::Unauthorized)));
+    t.vault.enter_emergency_mode(&guardian);
+    assert!(t.vault.is_emergency_mode());
+
+    t.admins[0].mint(&alice, &500);
+    assert_eq!(t.vault.try_deposit(&alice, &500), Err(Ok(VaultError::EmergencyMode)));
+    assert_eq!(t.vault.try_withdraw(&alice, &100), Err(Ok(VaultError::EmergencyMode)));
+    assert_eq!(t.vault.try_force_rebalance(), Err(Ok(VaultError::EmergencyMode)));
+
+    // A quarter of the shares takes a quarter of each asset held
+    let payouts = t.vault.emergency_withdraw(&alice, &1000);
+    assert_eq!(
+        payouts,
+        vec![&env, (t.token(0).address.clone(), 1000), (t.token(1).address.clone(), 200)]
+    );
+    assert_eq!((t.token(0).balance(&alice), t.token(1).balance(&alice)), (1500, 200));
+    assert_eq!(t.vault.get_position(&alice).shares, 0);
+    let state = t.vault.get_state();
+    assert_eq!((state.total_shares, state.total_value), (3000, 3000));
+    assert_eq!(
+        t.vault.try_emergency_withdraw(&bob, &3001),
+        Err(Ok(VaultError::InsufficientShares))
+    );
+
+    // Only the owner leaves emergency mode, and then normal withdrawals resume
+    assert_eq!(t.vault.try_exit_emergency_mode(&guardian), Err(Ok(VaultError::Unauthorized)));
+    t.vault.exit_emergency_mode(&t.owner);
+    assert!(!t.vault.is_emergency_mode());
+    assert_eq!(t.vault.withdraw(&bob, &1000).net_amount, 1000);
+}
+
+#[test]
+fn wound_down_vault_pays_every_share_the_same() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    let alice = t.deposit(1000);
+    let bob = t.deposit(1000);
+    let carol = t.deposit(1000);
+    t.vault.force_stake(&t.owner);
+
+    assert_eq!(t.vault.try_initiate_wind_down(&alice), Err(Ok(VaultError::Unauthorized)));
+    let terms = t.vault.initiate_wind_down(&t.owner);
+    assert!(!t.vault.has_staking_position());
+    assert_eq!((terms.price, terms.frozen_shares), (1_0000000, 3000));
+    assert_eq!(t.vault.get_wind_down(), Some(terms));
+    assert_eq!(t.vault.try_initiate_wind_down(&t.owner), Err(Ok(VaultError::Closed)));
+
+    // Closed for good: no deposits, no reopening, no exits at other prices
+    t.admins[0].mint(&alice, &500);
+    assert_eq!(t.vault.try_deposit(&alice, &500), Err(Ok(VaultError::Paused)));
+    assert_eq!(t.vault.try_unpause(&t.owner), Err(Ok(VaultError::Closed)));
+    assert_eq!(t.vault.try_withdraw_in_kind(&alice, &100), Err(Ok(VaultError::Closed)));
+    assert_eq!(t.vault.try_enter_emergency_mode(&t.owner), Err(Ok(VaultError::Closed)));
+    assert_eq!(t.vault.try_claim_true_up(&alice), Err(Ok(VaultError::InvalidConfiguration)));
+
+    // Proceeds arriving after the freeze don't move the price
+    let alice_paid = t.vault.withdraw(&alice, &1000).net_amount;
+    t.admins[0].mint(&t.vault.address, &300);
+    env.ledger().with_mut(|l| l.timestamp += 86_400);
+    let bob_paid = t.vault.withdraw(&bob, &1000).net_amount;
+    assert_eq!((alice_paid, bob_paid), (1000, 1000));
+
+    // They go to every frozen share alike, redeemed or not
+    assert_eq!(t.vault.try_finalize_true_up(&alice), Err(Ok(VaultError::Unauthorized)));
+    assert_eq!(t.vault.finalize_true_up(&t.owner), 300);
+    assert_eq!(t.vault.try_finalize_true_up(&t.owner), Err(Ok(VaultError::InvalidConfiguration)));
+    assert_eq!(t.vault.claim_true_up(&alice), 100);
+    assert_eq!(t.vault.claim_true_up(&bob), 100);
+    assert_eq!(t.vault.claim_true_up(&carol), 100);
+    assert_eq!(t.vault.try_claim_true_up(&bob), Err(Ok(VaultError::InsufficientShares)));
+    assert_eq!(t.token(0).balance(&alice), 1100 + 500);
+    assert_eq!(t.token(0).balance(&bob), 1100);
+
+    // A holder who claimed first still redeems at the frozen price, once
+    assert_eq!(t.vault.withdraw(&carol, &1000).net_amount, 1000);
+    assert_eq!(t.vault.try_claim_true_up(&carol), Err(Ok(VaultError::InsufficientShares)));
+    assert_eq!(t.token(0).balance(&carol), 1100);
+    assert_eq!(t.token(0).balance(&t.vault.address), 0);
+}
+
+#[test]
+fn unstake_rule_trims_the_staking_position() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_rule("apy", 50_0000, "unstake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+
+    // Nothing staked yet, so the rule fires without moving anything
+    assert!(matches!(t.vault.trigger_unstake(), TriggerResult::Executed(_)));
+    assert!(!t.vault.has_staking_position());
+
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.balance(0), 500);
+
+    // Each run unstakes half of what is left
+    t.vault.trigger_unstake();
+    let position = t.vault.get_staking_position();
+    assert_eq!((position.staked_amount, position.st_token_amount), (250, 250));
+    assert_eq!(t.balance(0), 750);
+
+    t.vault.trigger_unstake();
+    assert_eq!(t.vault.get_staking_position().staked_amount, 125);
+    assert_eq!(t.balance(0), 875);
+}
+
+#[test]
+fn unstake_rule_waits_for_its_condition_and_can_exit_fully() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_rule("time", 100_0000, "unstake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    t.vault.force_stake(&t.owner);
+
+    assert_eq!(t.vault.trigger_unstake(), TriggerResult::Skipped(SkipReason::Cooldown));
+    assert!(t.vault.has_staking_position());
+
+    t.advance_time(100_0000);
+    t.vault.trigger_unstake();
+    assert!(!t.vault.has_staking_position());
+    assert_eq!(t.balance(0), 1000);
+    assert!(find_event(&env, "unstaked").is_some());
+}
+
+#[test]
+fn replayed_op_id_executes_once() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_rule("apy", 50_0000, "unstake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    t.vault.force_stake(&t.owner);
+
+    let unstake = Symbol::new(&env, "unstake");
+    let op_id = BytesN::from_array(&env, &[7; 32]);
+    assert!(matches!(t.vault.trigger_with_op_id(&unstake, &op_id), TriggerResult::Executed(_)));
+    assert_eq!(
+        t.vault.trigger_with_op_id(&unstake, &op_id),
+        TriggerResult::Skipped(SkipReason::Duplicate)
+    );
+
+    // Half unstaked once, not twice
+    assert_eq!(t.vault.get_staking_position().staked_amount, 250);
+    assert_eq!(t.balance(0), 750);
+
+    // A fresh id runs again
+    let next = BytesN::from_array(&env, &[8; 32]);
+    t.vault.trigger_with_op_id(&unstake, &next);
+    assert_eq!(t.vault.get_staking_position().staked_amount, 125);
+}
+
+#[test]
+fn op_id_window_is_bounded() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("apy", 50_0000, "unstake", &[])
+        .build();
+    t.deposit(1000);
+
+    let unstake = Symbol::new(&env, "unstake");
+    let first = BytesN::from_array(&env, &[0; 32]);
+    t.vault.trigger_with_op_id(&unstake, &first);
+
+    // 32 newer ids push the first one out of the window
+    for i in 1..=32u8 {
+        t.vault.trigger_with_op_id(&unstake, &BytesN::from_array(&env, &[i; 32]));
+    }
+    assert!(matches!(t.vault.trigger_with_op_id(&unstake, &first), TriggerResult::Executed(_)));
+    assert_eq!(
+        t.vault.trigger_with_op_id(&unstake, &BytesN::from_array(&env, &[32; 32])),
+        TriggerResult::Skipped(SkipReason::Duplicate)
+    );
+
+    let unknown = Symbol::new(&env, "withdraw");
+    assert_eq!(
+        t.vault.try_trigger_with_op_id(&unknown, &BytesN::from_array(&env, &[99; 32])),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    // A typo'd action is reported as such, not as a replay of the id
+    let typo = Symbol::new(&env, "unstak");
+    assert_eq!(
+        t.vault.try_trigger_with_op_id(&typo, &BytesN::from_array(&env, &[32; 32])),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn ledger_interval_rule_fires_every_n_ledgers() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.sequence_number = 100);
+
+    let t = TestVault::new(&env)
+        .with_rule("ledger_interval", 10, "unstake", &[])
+        .build();
+    t.deposit(1000);
+
+    let set_sequence = |sequence: u32| env.ledger().with_mut(|l| l.sequence_number = sequence);
+
+    // Counted from the ledger the vault was created in
+    set_sequence(109);
+    assert_eq!(t.vault.trigger_unstake(), TriggerResult::Skipped(SkipReason::Cooldown));
+    set_sequence(110);
+    assert!(matches!(t.vault.trigger_unstake(), TriggerResult::Executed(_)));
+
+    // Then from the ledger it last ran in, whatever the wall clock says
+    set_sequence(115);
+    t.advance_time(1_000_000);
+    assert_eq!(t.vault.trigger_unstake(), TriggerResult::Skipped(SkipReason::Cooldown));
+    set_sequence(120);
+    assert!(matches!(t.vault.trigger_unstake(), TriggerResult::Executed(_)));
+    assert_eq!(t.vault.trigger_unstake(), TriggerResult::Skipped(SkipReason::Cooldown));
+}
+
+#[test]
+fn ledger_interval_below_minimum_is_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let (token, _) = create_token(&env, &owner);
+    let vault_id = env.register_contract(None, crate::vault::VaultContract);
+    let vault = crate::vault::VaultContractClient::new(&env, &vault_id);
+
+    let too_fast = vec![&env, rule(&env, "ledger_interval", 9, "rebalance", &[100_0000])];
+    assert_eq!(
+        vault.try_initialize(&config(&env, &owner, vec![&env, token.address.clone()], too_fast)),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    let minimum = vec![&env, rule(&env, "ledger_interval", 10, "rebalance", &[100_0000])];
+    vault.initialize(&config(&env, &owner, vec![&env, token.address.clone()], minimum));
+}
+
+// Stand-in for a config migration that reverses the asset list in place
+fn reverse_assets(t: &crate::testutils::TestVaultHandles) {
+    t.env.as_contract(&t.vault.address, || {
+        let mut cfg = crate::migrations::read_config(&t.env).unwrap();
+        let mut reversed = Vec::new(&t.env);
+        for asset in cfg.assets.iter() {
+            reversed.push_front(asset);
+        }
+        cfg.assets = reversed;
+        crate::migrations::write_config(&t.env, &cfg);
+    });
+}
+
+#[test]
+fn withdraw_pays_the_base_asset_after_assets_are_reordered() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let user = t.deposit(1000);
+    t.admins[1].mint(&t.vault.address, &1000);
+
+    reverse_assets(&t);
+    assert_eq!(t.vault.get_config().assets.get(0).unwrap(), t.token(1).address);
+    assert_eq!(t.vault.get_base_asset(), t.token(0).address);
+
+    let receipt = t.vault.withdraw(&user, &400);
+    assert_eq!(receipt.payout_token, t.token(0).address);
+    assert_eq!(t.token(0).balance(&user), 400);
+    assert_eq!(t.token(1).balance(&user), 0);
+}
+
+#[test]
+fn stake_and_liquidity_defaults_follow_the_base_asset() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(3)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    t.admins[2].mint(&t.vault.address, &1000);
+
+    reverse_assets(&t);
+    t.vault.force_stake(&t.owner);
+    let position = t.vault.get_staking_position();
+    assert_eq!(position.original_token, t.token(0).address);
+    assert_eq!(position.staked_amount, 500);
+    assert_eq!(t.balance(2), 1000);
+
+    // Reversed order is [C, B, A]: the pair is the base asset and the first other one
+    let assets = t.vault.get_config().assets;
+    let pair = env.as_contract(&t.vault.address, || {
+        crate::rebalance::liquidity_pair(&env, &assets).unwrap()
+    });
+    assert_eq!(pair, (t.token(0).address.clone(), t.token(2).address.clone()));
+}
+
+#[test]
+fn dropping_the_base_asset_is_an_error_not_a_switch() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let user = t.deposit(1000);
+
+    env.as_contract(&t.vault.address, || {
+        let mut cfg = crate::migrations::read_config(&env).unwrap();
+        cfg.assets.pop_front();
+        crate::migrations::write_config(&env, &cfg);
+    });
+
+    assert_eq!(t.vault.try_get_base_asset(), Err(Ok(VaultError::InvalidConfiguration)));
+    assert_eq!(t.vault.try_withdraw(&user, &100), Err(Ok(VaultError::InvalidConfiguration)));
+}
+
+#[test]
+fn token_deposits_fail_when_the_swap_returns_too_little() {
+    use crate::testutils::oracle::{MockOracle, MockOracleClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 10_000, 10_000);
+    let user = Address::generate(&env);
+    t.admins[1].mint(&user, &3000);
+
+    // 1_000 into a 10_000/10_000 pool returns 906
+    assert_eq!(
+        t.vault.try_deposit_with_token_min(&user, &1000, &t.token(1).address, &950),
+        Err(Ok(VaultError::SlippageTooHigh))
+    );
+    assert_eq!(t.token(1).balance(&user), 3000);
+    assert_eq!(t.vault.get_position(&user).shares, 0);
+    assert_eq!(t.vault.deposit_with_token_min(&user, &1000, &t.token(1).address, &900), 906);
+
+    // Base deposits have nothing to swap, but the floor still applies
+    t.admins[0].mint(&user, &100);
+    assert_eq!(
+        t.vault.try_deposit_with_token_min(&user, &100, &t.token(0).address, &101),
+        Err(Ok(VaultError::SlippageTooHigh))
+    );
+
+    // With an oracle pricing both at 1.0, plain deposits need 99% of it back
+    let now = env.ledger().timestamp();
+    let oracle_id = env.register_contract(None, MockOracle);
+    let oracle = MockOracleClient::new(&env, &oracle_id);
+    oracle.set_price(&t.token(0).address, &1_00000000000000, &now);
+    oracle.set_price(&t.token(1).address, &1_00000000000000, &now);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle_id), &600);
+    assert_eq!(
+        t.vault.try_deposit_with_token(&user, &1000, &t.token(1).address),
+        Err(Ok(VaultError::SlippageTooHigh))
+    );
+
+    // Without a fresh oracle price there is no independent floor, as before
+    t.advance_time(601);
+    assert!(t.vault.deposit_with_token(&user, &1000, &t.token(1).address) > 0);
+}
+
+#[test]
+fn basket_round_trip_is_valued_at_one_price() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    // The pool quotes 1.0 and the oracle 2.0; deposits and withdrawals both take the oracle
+    t.seed_pool(0, 1, 10_000, 10_000);
+    let oracle = create_oracle(&env, &[(&t.token(0).address, 1_00000000000000), (&t.token(1).address, 2_00000000000000)]);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle), &600);
+    let alice = t.deposit(2_000);
+
+    let bob = Address::generate(&env);
+    t.admins[0].mint(&bob, &1_000);
+    t.admins[1].mint(&bob, &500);
+    let basket = vec![&env, (t.token(0).address.clone(), 1_000), (t.token(1).address.clone(), 500)];
+    assert_eq!(t.vault.deposit_multi(&bob, &basket), 2_000);
+    assert_eq!(t.vault.get_state().total_value, 4_000);
+
+    // Paid from 3_000 of the base asset and 500 of the other, in proportion.
+    // Nothing was gained, so no performance fee comes off the 2_000.
+    let paid = t.vault.withdraw_in_kind(&bob, &2_000);
+    assert_eq!(paid, vec![&env, (t.token(0).address.clone(), 1_500), (t.token(1).address.clone(), 250)]);
+    assert_eq!(t.token(0).balance(&bob) + 2 * t.token(1).balance(&bob), 2_000);
+    assert_eq!(t.vault.get_position(&bob).shares, 0);
+    assert_eq!(t.vault.get_state().total_value, 2_000);
+    assert_eq!(t.vault.get_position(&alice).shares, 2_000);
+
+    // Only configured assets are accepted
+    let stray = vec![&env, (Address::generate(&env), 100)];
+    assert_eq!(t.vault.try_deposit_multi(&bob, &stray), Err(Ok(VaultError::InvalidConfiguration)));
+}
+
+#[test]
+fn basket_deposits_ignore_a_skewed_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    t.deposit(1_000);
+    // The pool has been pushed to quote the second asset at 10.0
+    t.seed_pool(0, 1, 10_000, 1_000);
+
+    let bob = Address::generate(&env);
+    t.admins[1].mint(&bob, &1_000);
+    let basket = vec![&env, (t.token(1).address.clone(), 1_000)];
+    assert_eq!(t.vault.try_deposit_multi(&bob, &basket), Err(Ok(VaultError::PriceUnavailable)));
+
+    // Valued at the oracle's 1.0, not the pool's 10.0
+    let oracle = create_oracle(&env, &[(&t.token(0).address, 1_00000000000000), (&t.token(1).address, 1_00000000000000)]);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle), &600);
+    let half = vec![&env, (t.token(1).address.clone(), 500)];
+    assert_eq!(t.vault.deposit_multi(&bob, &half), 500);
+    assert_eq!(t.vault.get_state().total_value, 1_500);
+
+    // A stale quote doesn't fall back to the pool either
+    t.advance_time(601);
+    assert_eq!(t.vault.try_deposit_multi(&bob, &half), Err(Ok(VaultError::PriceUnavailable)));
+    assert_eq!(t.token(1).balance(&bob), 500);
+}
+
+#[test]
+fn in_kind_exits_take_fees_batching_and_dust_like_withdraw() {
+    use crate::testutils::oracle::MockOracleClient;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let oracle = create_oracle(&env, &[(&t.token(0).address, 1_00000000000000), (&t.token(1).address, 1_00000000000000)]);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle.clone()), &600);
+    t.deposit(2_000);
+
+    let bob = Address::generate(&env);
+    t.admins[0].mint(&bob, &1_000);
+    t.admins[1].mint(&bob, &1_000);
+    let basket = vec![&env, (t.token(0).address.clone(), 1_000), (t.token(1).address.clone(), 1_000)];
+    assert_eq!(t.vault.deposit_multi(&bob, &basket), 2_000);
+
+    // The second asset doubles, so shares are worth 1.25 and bob has a gain
+    t.vault.set_performance_fee(&t.owner, &2000);
+    MockOracleClient::new(&env, &oracle).set_price(&t.token(1).address, &2_00000000000000, &env.ledger().timestamp());
+    assert_eq!(t.vault.sync_total_value(&t.owner), 5_000);
+
+    // 1_000 shares are worth 1_250, of which 250 is gain and 50 the fee.
+    // What bob and the owner receive is worth the 1_250 between them.
+    let paid = t.vault.withdraw_in_kind(&bob, &1_000);
+    assert_eq!(paid, vec![&env, (t.token(0).address.clone(), 720), (t.token(1).address.clone(), 240)]);
+    let bob_value = t.token(0).balance(&bob) + 2 * t.token(1).balance(&bob);
+    let fee_value = t.token(0).balance(&t.owner) + 2 * t.token(1).balance(&t.owner);
+    assert_eq!((bob_value, fee_value), (1_200, 50));
+    assert_eq!(t.vault.get_state().total_value, 3_750);
+
+    // Large in-kind exits are batched as withdraw's are
+    t.vault.set_exit_batching(&t.owner, &Some(1_000));
+    assert_eq!(t.vault.try_withdraw_in_kind(&bob, &900), Err(Ok(VaultError::WithdrawalBatched)));
+    t.vault.set_exit_batching(&t.owner, &None);
+
+    // And a leftover under the dust threshold is swept
+    t.vault.set_dust_threshold(&t.owner, &10, &false);
+    t.vault.withdraw_in_kind(&bob, &995);
+    assert_eq!(t.vault.get_position(&bob).shares, 0);
+    assert!(find_event(&env, "dust_swept").is_some());
+    assert_eq!(t.vault.get_state().total_shares, 2_000);
+}
+
+#[test]
+fn no_swap_needed_is_reported_only_for_base_asset_deposits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 10_000, 10_000);
+
+    let user = Address::generate(&env);
+    t.admins[1].mint(&user, &1000);
+    t.vault.deposit_with_token(&user, &1000, &t.token(1).address);
+    assert!(find_event(&env, "no_swap_needed").is_none());
+
+    t.deposit(500);
+    let (topics, data) = find_event(&env, "no_swap_needed").unwrap();
+    let token: Address = topics.get(1).unwrap().into_val(&env);
+    let amount: i128 = data.into_val(&env);
+    assert_eq!((token, amount), (t.token(0).address.clone(), 500));
+
+    // Quiet level drops it
+    assert_eq!(t.vault.get_log_level(), 1);
+    t.vault.set_log_level(&t.owner, &0);
+    let before = env.events().all().len();
+    t.deposit(500);
+    let quiet = env.events().all().len() - before;
+    t.vault.set_log_level(&t.owner, &1);
+    let before = env.events().all().len();
+    t.deposit(500);
+    assert_eq!(env.events().all().len() - before, quiet + 1);
+    assert_eq!(t.vault.try_set_log_level(&t.owner, &3), Err(Ok(VaultError::InvalidConfiguration)));
+}
+
+#[test]
+fn disabled_event_categories_emit_nothing() {
+    use crate::events::{EVENTS_ALL, EVENTS_DEBUG, EVENTS_DEPOSITS};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    assert_eq!(t.vault.get_event_flags(), EVENTS_ALL);
+    assert!(t.vault.get_all_events_enabled());
+
+    t.vault.set_event_flags(&t.owner, &(EVENTS_ALL & !EVENTS_DEPOSITS & !EVENTS_DEBUG));
+    assert!(!t.vault.get_all_events_enabled());
+    let user = t.deposit(1000);
+    assert!(find_event(&env, "deposit").is_none());
+    assert!(find_event(&env, "debug").is_none());
+
+    // Withdrawals are still enabled
+    t.vault.withdraw(&user, &400);
+    assert!(find_event(&env, "withdraw").is_some());
+
+    t.vault.set_event_flags(&t.owner, &EVENTS_ALL);
+    t.deposit(1000);
+    assert!(find_event(&env, "deposit").is_some());
+    assert!(find_event(&env, "debug").is_some());
+
+    assert_eq!(
+        t.vault.try_set_event_flags(&t.owner, &(EVENTS_ALL + 1)),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_set_event_flags(&stranger, &0), Err(Ok(VaultError::Unauthorized)));
+}
+
+#[test]
+fn pre_versioning_storage_keeps_working_and_migrates() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    let user = t.deposit(1000);
+    assert_eq!(t.vault.get_storage_version(), 3);
+
+    // Rewrite storage to the layout a vault had before versioning
+    t.env.as_contract(&t.vault.address, || {
+        let config = crate::migrations::read_config(&t.env).unwrap();
+        let state = crate::migrations::read_state(&t.env).unwrap();
+        t.env.storage().instance().set(&Symbol::new(&t.env, "CONFIG"), &config);
+        t.env.storage().instance().set(&Symbol::new(&t.env, "STATE"), &state);
+        t.env.storage().instance().remove(&Symbol::new(&t.env, "STOR_VER"));
+        t.env.storage().instance().remove(&Symbol::new(&t.env, "BASE_AST"));
+    });
+    assert_eq!(t.vault.get_storage_version(), 1);
+    assert_eq!(t.vault.get_base_asset(), t.token(0).address);
+    t.vault.withdraw(&user, &100);
+
+    assert_eq!(t.vault.migrate_storage(&t.owner), 3);
+    assert_eq!(t.vault.get_storage_version(), 3);
+    let (_, data) = find_event(&env, "storage_migrated").unwrap();
+    let versions: (u32, u32) = data.into_val(&env);
+    assert_eq!(versions, (1, 3));
+    let state = t.vault.get_state();
+    t.env.as_contract(&t.vault.address, || {
+        let stored: crate::migrations::StoredState =
+            t.env.storage().instance().get(&Symbol::new(&t.env, "STATE")).unwrap();
+        assert_eq!(stored, crate::migrations::StoredState::V1(state));
+    });
+
+    // The base asset is pinned now, so reordering assets no longer moves it
+    reverse_assets(&t);
+    assert_eq!(t.vault.get_base_asset(), t.token(0).address);
+
+    // Running it again changes nothing; storage from newer code is refused
+    assert_eq!(t.vault.migrate_storage(&t.owner), 3);
+    t.env.as_contract(&t.vault.address, || {
+        t.env.storage().instance().set(&Symbol::new(&t.env, "STOR_VER"), &4u32);
+    });
+    assert_eq!(t.vault.try_migrate_storage(&t.owner), Err(Ok(VaultError::InvalidConfiguration)));
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_migrate_storage(&stranger), Err(Ok(VaultError::Unauthorized)));
+}
+
+// The vault as first released, before storage versioning, and the current build
+const VAULT_V1_WASM: &[u8] = include_bytes!("../test_fixtures/syft_vault_v1.wasm");
+const VAULT_WASM: &[u8] = include_bytes!("../../../backend/contracts/syft_vault.wasm");
+
+#[test]
+fn upgrading_a_v1_vault_keeps_its_storage_readable() {
+    use crate::migrations::{StoredConfig, StoredPosition, StoredState};
+    use crate::types::{UserPosition, VaultState};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    // A vault deployed from the first release, holding one deposit
+    let owner = Address::generate(&env);
+    let (token, admin) = create_token(&env, &owner);
+    let cfg = config(&env, &owner, vec![&env, token.address.clone()], Vec::new(&env));
+    let vault_id = env.register_contract_wasm(None, VAULT_V1_WASM);
+    let vault = crate::VaultContractClient::new(&env, &vault_id);
+    vault.initialize(&cfg);
+    let user = Address::generate(&env);
+    admin.mint(&user, &1000);
+    vault.deposit(&user, &1000);
+
+    let new_code = env.deployer().upload_contract_wasm(VAULT_WASM);
+    env.as_contract(&vault_id, || env.deployer().update_current_contract_wasm(new_code));
+
+    // New code reads the old layout before anything is migrated
+    assert_eq!(vault.get_storage_version(), 1);
+    assert_eq!(vault.get_config(), cfg);
+    assert_eq!(vault.get_state().total_shares, 1000);
+    assert_eq!(vault.get_position(&user).shares, 1000);
+    vault.withdraw(&user, &400);
+    assert_eq!(token.balance(&user), 400);
+
+    assert_eq!(vault.migrate_storage(&owner), 3);
+    assert_eq!(vault.get_storage_version(), 3);
+    assert_eq!(vault.get_base_asset(), token.address);
+
+    // The migration tagged the config and state, the withdrawal the position
+    env.as_contract(&vault_id, || {
+        let instance = env.storage().instance();
+        let stored: StoredConfig = instance.get(&Symbol::new(&env, "CONFIG")).unwrap();
+        assert_eq!(stored, StoredConfig::V1(cfg.clone()));
+        let stored: StoredState = instance.get(&Symbol::new(&env, "STATE")).unwrap();
+        assert!(matches!(stored, StoredState::V1(VaultState { total_shares: 600, .. })));
+        let stored: StoredPosition = instance.get(&(Symbol::new(&env, "POSITION"), user.clone())).unwrap();
+        assert!(matches!(stored, StoredPosition::V1(UserPosition { shares: 600, .. })));
+    });
+
+    vault.withdraw(&user, &600);
+    assert_eq!(token.balance(&user), 1000);
+}
+
+#[test]
+fn router_only_vault_never_swaps_through_the_pool() {
+    use crate::testutils::router::{MockRouter, MockRouterClient};
+    use crate::types::Venue;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).with_mock_dex().build();
+    let pair = t.seed_pool(0, 1, 10_000, 10_000);
+    let router = MockRouterClient::new(&env, &env.register_contract(None, MockRouter));
+    t.admins[0].mint(&router.address, &5_000);
+    t.vault.set_router(&router.address);
+    assert_eq!(t.vault.get_venue_preference(), Vec::new(&env));
+
+    let user = Address::generate(&env);
+    t.admins[1].mint(&user, &2000);
+
+    // Default order: the pool is found, so the router is never called
+    t.vault.deposit_with_token(&user, &1000, &t.token(1).address);
+    assert_eq!(t.token(1).balance(&pair), 11_000);
+    assert_eq!(router.swaps(), 0);
+
+    // Router only: the pool is left alone even though it exists
+    t.vault.set_venue_preference(&t.owner, &vec![&env, Venue::SoroswapRouter]);
+    let reserves = (t.token(0).balance(&pair), t.token(1).balance(&pair));
+    t.vault.deposit_with_token(&user, &1000, &t.token(1).address);
+    assert_eq!(router.swaps(), 1);
+    assert_eq!((t.token(0).balance(&pair), t.token(1).balance(&pair)), reserves);
+    assert_eq!(t.token(1).balance(&router.address), 1000);
+
+    // Venues without an integration are passed over
+    t.vault.set_venue_preference(&t.owner, &vec![&env, Venue::Phoenix]);
+    t.admins[1].mint(&user, &1000);
+    assert_eq!(
+        t.vault.try_deposit_with_token(&user, &1000, &t.token(1).address),
+        Err(Ok(VaultError::SwapFailed))
+    );
+    assert_eq!(
+        t.vault.try_set_venue_preference(&t.owner, &vec![&env, Venue::DirectPool, Venue::DirectPool]),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn router_swaps_authorize_the_transfer_into_the_pair() {
+    use crate::testutils::soroswap_router::MockSoroswapRouter;
+    use crate::types::Venue;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+        .with_mock_dex()
+        .build();
+    let pair = t.seed_pool(0, 1, 1_000_000, 1_000_000);
+    let router = env.register_contract(None, MockSoroswapRouter);
+    t.vault.set_router(&router);
+    t.vault.set_venue_preference(&t.owner, &vec![&env, Venue::SoroswapRouter]);
+    t.deposit(2_000);
+
+    // No mocked signatures from here: the vault has to authorize the
+    // router's transfer into the pair itself
+    env.set_auths(&[]);
+    t.vault.force_rebalance();
+
+    let bought = 1_000 * 997 * 1_000_000 / (1_000_000 * 1000 + 1_000 * 997);
+    assert_eq!((t.balance(0), t.balance(1)), (1_000, bought));
+    assert_eq!(t.token(0).balance(&pair), 1_001_000);
+    assert_eq!(t.token(1).balance(&pair), 1_000_000 - bought);
+}
+
+#[test]
+fn liquidity_is_recovered_through_the_pool_when_the_router_is_broken() {
+    use crate::testutils::soroswap::MockPairClient;
+    use crate::types::LiquidityPosition;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    // The mock DEX router is a bare address, so every router call fails
+    let t = TestVault::new(&env).with_assets(2).with_mock_dex().build();
+    let pair = t.seed_pool(0, 1, 10_000, 20_000);
+    let pair_client = MockPairClient::new(&env, &pair);
+    pair_client.mint_lp(&t.vault.address, &1000);
+    pair_client.mint_lp(&Address::generate(&env), &1000);
+
+    // Position recorded with the pair's tokens in the opposite order
+    t.env.as_contract(&t.vault.address, || {
+        let position = LiquidityPosition {
+            pool_address: pair.clone(),
+            token_a: t.token(1).address.clone(),
+            token_b: t.token(0).address.clone(),
+            lp_tokens: 1000,
+            amount_a_provided: 10_000,
+            amount_b_provided: 5_000,
+            timestamp: 0,
+        };
+        t.env.storage().instance().set(&soroban_sdk::String::from_str(&t.env, "lp_position"), &position);
+    });
+
+    assert_eq!(t.vault.recover_liquidity(&t.owner, &500), (5_000, 2_500));
+    assert_eq!((t.balance(0), t.balance(1)), (2_500, 5_000));
+    let position = t.vault.get_liquidity_position();
+    assert_eq!(
+        (position.lp_tokens, position.amount_a_provided, position.amount_b_provided),
+        (500, 5_000, 2_500)
+    );
+    assert_eq!(pair_client.balance(&t.vault.address), 500);
+
+    assert_eq!(t.vault.try_recover_liquidity(&t.owner, &501), Err(Ok(VaultError::InvalidAmount)));
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_recover_liquidity(&stranger, &500), Err(Ok(VaultError::Unauthorized)));
+
+    t.vault.recover_liquidity(&t.owner, &500);
+    assert!(!t.vault.has_liquidity_position());
+    assert_eq!((t.balance(0), t.balance(1)), (5_000, 10_000));
+}
+
+#[test]
+fn withdraw_liquidity_burns_the_position_and_returns_both_tokens() {
+    use crate::testutils::router::{MockRouter, MockRouterClient};
+    use crate::testutils::soroswap::MockPairClient;
+    use crate::types::LiquidityPosition;
+
+    let env = Env::default();
+    env.mock_all_auths_allowing_non_root_auth();
+
+    let t = TestVault::new(&env).with_assets(2).with_mock_dex().build();
+    let router = MockRouterClient::new(&env, &env.register_contract(None, MockRouter));
+    t.vault.set_router(&router.address);
+    let pair = t.seed_pool(0, 1, 10_000, 20_000);
+    let pair_client = MockPairClient::new(&env, &pair);
+    pair_client.mint_lp(&t.vault.address, &1000);
+    pair_client.mint_lp(&Address::generate(&env), &1000);
+    t.deposit(3_000);
+
+    assert_eq!(t.vault.try_withdraw_liquidity(&t.owner, &5), Err(Ok(VaultError::NotInitialized)));
+
+    t.env.as_contract(&t.vault.address, || {
+        let position = LiquidityPosition {
+            pool_address: pair.clone(),
+            token_a: t.token(1).address.clone(),
+            token_b: t.token(0).address.clone(),
+            lp_tokens: 1000,
+            amount_a_provided: 9_000,
+            amount_b_provided: 4_000,
+            timestamp: 0,
+        };
+        t.env.storage().instance().set(&soroban_sdk::String::from_str(&t.env, "lp_position"), &position);
+    });
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_withdraw_liquidity(&stranger, &5), Err(Ok(VaultError::Unauthorized)));
+
+    assert_eq!(t.vault.withdraw_liquidity(&t.owner, &5), (10_000, 5_000));
+    assert_eq!(pair_client.balance(&t.vault.address), 0);
+    assert_eq!((t.balance(0), t.balance(1)), (8_000, 10_000));
+    assert!(!t.vault.has_liquidity_position());
+    let (_, data) = find_event(&env, "lp_removed").unwrap();
+    assert_eq!(<(i128, i128, i128)>::try_from_val(&env, &data).unwrap(), (1000, 10_000, 5_000));
+
+    // 2_000 more came back than was provided
+    assert_eq!(t.vault.get_state().total_value, 5_000);
+}
+
+#[test]
+fn silent_keepers_flag_and_optionally_block_deposits() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let t = TestVault::new(&env)
+        .with_rule("apy", 50_0000, "unstake", &[])
+        .build();
+    t.vault.set_keeper_silence(&t.owner, &Some(3600), &false);
+    assert_eq!(t.vault.get_keeper_heartbeat(), 1_000);
+
+    // Past the limit deposits still go through, with a warning
+    t.advance_time(3601);
+    t.deposit(1000);
+    assert_eq!(count_events(&env, "keeper_stale"), 1);
+    let (_, data) = find_event(&env, "keeper_stale").unwrap();
+    let (heartbeat, now): (u64, u64) = data.into_val(&env);
+    assert_eq!((heartbeat, now), (1_000, 4_601));
+
+    // A trigger call, even one that skips, is a heartbeat
+    t.vault.trigger_rebalance();
+    assert_eq!(t.vault.get_keeper_heartbeat(), 4_601);
+    let warnings = count_events(&env, "keeper_stale");
+    t.deposit(1000);
+    assert_eq!(count_events(&env, "keeper_stale"), warnings);
+
+    // Blocking mode refuses deposits until keepers or the owner step in
+    t.vault.set_keeper_silence(&t.owner, &Some(3600), &true);
+    assert_eq!(t.vault.get_keeper_silence(), (Some(3600), true));
+    t.advance_time(3601);
+    let user = Address::generate(&env);
+    t.admins[0].mint(&user, &1000);
+    assert_eq!(t.vault.try_deposit(&user, &1000), Err(Ok(VaultError::KeeperStale)));
+
+    t.vault.override_keeper_silence(&t.owner);
+    t.vault.deposit(&user, &1000);
+    t.advance_time(3601);
+    t.vault.trigger_unstake();
+    t.deposit(1000);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_override_keeper_silence(&stranger), Err(Ok(VaultError::Unauthorized)));
+    assert_eq!(
+        t.vault.try_set_keeper_silence(&t.owner, &Some(0), &true),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn stale_oracle_price_falls_back_to_spot() {
+    use crate::testutils::oracle::{MockOracle, MockOracleClient};
+    use crate::types::PriceSource;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(3).with_mock_dex().build();
+    let (base, asset) = (t.token(0).address.clone(), t.token(1).address.clone());
+    t.seed_pool(0, 1, 2000_0000000, 1000_0000000);
+    t.advance_time(10_000);
+    let now = env.ledger().timestamp();
+
+    // 14-decimal quotes: the asset at 2.1 base
+    let oracle_id = env.register_contract(None, MockOracle);
+    let oracle = MockOracleClient::new(&env, &oracle_id);
+    oracle.set_price(&base, &1_00000000000000, &now);
+    oracle.set_price(&asset, &2_10000000000000, &now);
+
+    assert_eq!(t.vault.get_price(&base), 1_0000000);
+    assert_eq!(t.vault.get_price(&asset), 2_0000000);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle_id.clone()), &600);
+    assert_eq!(t.vault.get_price(&asset), 2_1000000);
+
+    // Past the staleness limit the pool's spot price is used
+    t.advance_time(601);
+    assert_eq!(t.vault.get_price(&asset), 2_0000000);
+
+    // Spot first when the owner prefers it, even with a fresh quote
+    oracle.set_price(&base, &1_00000000000000, &env.ledger().timestamp());
+    oracle.set_price(&asset, &2_10000000000000, &env.ledger().timestamp());
+    t.vault.set_price_source_priority(&t.owner, &vec![&env, PriceSource::Spot, PriceSource::Oracle]);
+    assert_eq!(t.vault.get_price(&asset), 2_0000000);
+
+    // Only a stale oracle, or no source at all for the asset: unavailable
+    t.vault.set_price_source_priority(&t.owner, &vec![&env, PriceSource::Oracle]);
+    t.advance_time(601);
+    assert_eq!(t.vault.try_get_price(&asset), Err(Ok(VaultError::PriceUnavailable)));
+    t.vault.set_price_source_priority(&t.owner, &vec![&env, PriceSource::Oracle, PriceSource::Spot]);
+    assert_eq!(t.vault.try_get_price(&t.token(2).address), Err(Ok(VaultError::PriceUnavailable)));
+    assert_eq!(
+        t.vault.try_set_price_source_priority(&t.owner, &vec![&env, PriceSource::Spot, PriceSource::Spot]),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn config_sizes_are_capped() {
+    use crate::types::{MAX_ASSETS, MAX_RULES};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let owner = Address::generate(&env);
+    let mut assets = Vec::new(&env);
+    for _ in 0..MAX_ASSETS {
+        assets.push_back(create_token(&env, &owner).0.address);
+    }
+    let mut rules = Vec::new(&env);
+    for _ in 0..MAX_RULES {
+        rules.push_back(rule(&env, "time", 3600, "rebalance", &[100_0000]));
+    }
+
+    // Exactly at each cap is fine, and the rule loops run over every rule
+    let vault = create_vault(&env, &config(&env, &owner, assets.clone(), rules.clone()));
+    assert_eq!(vault.evaluate_rules_view(&None).len(), MAX_RULES);
+
+    let vault_id = env.register_contract(None, crate::VaultContract);
+    let vault = crate::VaultContractClient::new(&env, &vault_id);
+    let mut too_many_assets = assets.clone();
+    too_many_assets.push_back(create_token(&env, &owner).0.address);
+    assert_eq!(
+        vault.try_initialize(&config(&env, &owner, too_many_assets, rules.clone())),
+        Err(Ok(VaultError::TooManyItems))
+    );
+
+    let mut too_many_rules = rules.clone();
+    too_many_rules.push_back(rule(&env, "time", 3600, "rebalance", &[100_0000]));
+    assert_eq!(
+        vault.try_initialize(&config(&env, &owner, assets.clone(), too_many_rules)),
+        Err(Ok(VaultError::TooManyItems))
+    );
+
+    let wide_target = [10_0000; MAX_ASSETS as usize + 1];
+    let wide_rules = vec![&env, rule(&env, "time", 3600, "rebalance", &wide_target)];
+    assert_eq!(
+        vault.try_initialize(&config(&env, &owner, assets, wide_rules)),
+        Err(Ok(VaultError::TooManyItems))
+    );
+}
+
+#[test]
+fn strategy_descriptor_summarizes_config_and_settings() {
+    use crate::types::{PriceSource, Venue};
+    use soroban_sdk::{map, String};
+
+    let env = Env::default();
+    env.mock_all_auths();
+    let s = |v: &str| String::from_str(&env, v);
+
+    // Staking only: no swaps, no oracle
+    let staker = TestVault::new(&env)
+        .with_rule("apy", 10_0000, "stake", &[])
+        .with_rule("apy", 30_0000, "stake", &[])
+        .with_rule("time", 7200, "unstake", &[])
+        .with_mock_staking()
+        .build();
+    let d = staker.vault.get_strategy_descriptor();
+    assert_eq!(d.version, 1);
+    assert_eq!(d.actions, vec![&env, s("stake"), s("unstake")]);
+    assert_eq!(d.max_deployment_bps, map![&env, (s("stake"), 3000)]);
+    assert_eq!(d.venues, Vec::new(&env));
+    assert_eq!((d.router, d.oracle, d.buffer_pool), (None, None, None));
+    assert_eq!(d.staking_pools, vec![&env, staker.staking_pool.clone().unwrap()]);
+    assert_eq!(d.performance_fee_bps, 0);
+    assert_eq!(d.limits, staker.vault.get_limits());
+
+    // Rebalancing with LP exposure, an oracle and a fee
+    let lp = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[60_0000, 40_0000])
+        .with_rule("apy", 20_0000, "liquidity", &[])
+        .with_mock_dex()
+        .build();
+    let oracle = Address::generate(&env);
+    lp.vault.set_price_oracle(&lp.owner, &Some(oracle.clone()), &600);
+    lp.vault.set_performance_fee(&lp.owner, &1000);
+    let d = lp.vault.get_strategy_descriptor();
+    assert_eq!(d.actions, vec![&env, s("rebalance"), s("liquidity")]);
+    assert_eq!(d.max_deployment_bps, map![&env, (s("rebalance"), 4000), (s("liquidity"), 2000)]);
+    assert_eq!(d.venues, vec![&env, Venue::DirectPool, Venue::SoroswapRouter]);
+    assert!(d.router.is_some());
+    assert_eq!(d.staking_pools, Vec::new(&env));
+    assert_eq!(d.oracle, Some(oracle));
+    assert_eq!(d.price_sources, vec![&env, PriceSource::Oracle, PriceSource::Spot]);
+    assert_eq!(d.performance_fee_bps, 1000);
+}
+
+#[test]
+fn limits_report_defaults_and_owner_overrides() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+
+    let limits = t.vault.get_limits();
+    assert_eq!(limits.version, 2);
+    assert_eq!((limits.max_assets, limits.max_rules), (10, 20));
+    assert_eq!((limits.allocation_scale, limits.bps_scale, limits.price_scale), (100_0000, 10000, 1_0000000));
+    assert_eq!(limits.rebalance_swap_slippage_pct, 5);
+    assert_eq!((limits.lp_base_slippage_bps, limits.lp_max_slippage_bps), (50, 2000));
+    assert_eq!((limits.lp_slippage_pct, limits.max_rebalance_loss_bps), (None, None));
+    assert_eq!(limits.allocation_caps.len(), 0);
+    assert_eq!((limits.performance_fee_bps, limits.max_performance_fee_bps), (0, 10000));
+
+    t.vault.set_lp_slippage(&t.owner, &Some(3));
+    t.vault.set_max_rebalance_loss(&t.owner, &Some(250));
+    t.vault.set_max_allocation_bps(&t.owner, &t.token(1).address, &Some(4000));
+    t.vault.set_performance_fee(&t.owner, &1500);
+
+    let limits = t.vault.get_limits();
+    assert_eq!((limits.lp_slippage_pct, limits.max_rebalance_loss_bps), (Some(3), Some(250)));
+    assert_eq!(limits.allocation_caps.get(t.token(1).address.clone()), Some(4000));
+    assert_eq!(limits.performance_fee_bps, 1500);
+}
+
+#[test]
+fn deposit_can_land_in_target_allocation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 10_000, 10_000);
+
+    let user = Address::generate(&env);
+    t.admins[0].mint(&user, &4_000);
+
+    // Without the flag everything stays in the base asset
+    assert_eq!(t.vault.deposit_with_rebalance(&user, &2_000, &false), 2_000);
+    assert_eq!((t.balance(0), t.balance(1)), (2_000, 0));
+    assert!(find_event(&env, "rebalance").is_none());
+
+    // With it the vault is split in the same call
+    assert_eq!(t.vault.deposit_with_rebalance(&user, &2_000, &true), 2_000);
+    assert_eq!(t.balance(0), 2_000);
+    assert_eq!(t.balance(1), 1_662);
+    assert!(find_event(&env, "rebalance").is_some());
+}
+
+#[test]
+fn distributions_must_cover_the_registered_profit_share() {
+    use crate::profit_share::SOURCE_TRADING;
+    use crate::testutils::nft::{MockProfitShareNft, MockProfitShareNftClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    t.deposit(1000);
+
+    let nft_id = env.register_contract(None, MockProfitShareNft);
+    let nft = MockProfitShareNftClient::new(&env, &nft_id);
+    nft.set_profit_share(&t.vault.address, &8000);
+
+    // Not wired up yet
+    assert_eq!(t.vault.try_distribute_profits(&t.owner, &100, &SOURCE_TRADING), Err(Ok(VaultError::InvalidConfiguration)));
+    t.vault.set_nft_contract(&t.owner, &Some(nft_id.clone()));
+
+    // Price goes 1.0 -> 1.5, a 500 gain of which holders are owed 80%
+    t.admins[0].mint(&t.vault.address, &500);
+    t.vault.sync_total_value(&t.owner);
+    let status = t.vault.get_profit_share();
+    assert_eq!(status.nft_contract, Some(nft_id.clone()));
+    assert_eq!((status.profit_share_bps, status.high_water_mark), (8000, 1_0000000));
+    assert_eq!((status.realized_gain, status.minimum_distribution), (500, 400));
+
+    assert_eq!(t.vault.try_distribute_profits(&t.owner, &399, &SOURCE_TRADING), Err(Ok(VaultError::ProfitShareUnderpaid)));
+    assert_eq!(t.vault.try_distribute_profits(&t.owner, &501, &SOURCE_TRADING), Err(Ok(VaultError::InvalidAmount)));
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_distribute_profits(&stranger, &400, &SOURCE_TRADING), Err(Ok(VaultError::Unauthorized)));
+
+    assert_eq!(t.vault.distribute_profits(&t.owner, &400, &SOURCE_TRADING), 400);
+    assert_eq!(t.token(0).balance(&nft_id), 400);
+    assert_eq!(nft.distributed(&t.vault.address), 400);
+    assert_eq!(t.vault.get_state().total_value, 1100);
+
+    // The retained gain isn't counted again; only new gains are
+    let status = t.vault.get_profit_share();
+    assert_eq!((status.high_water_mark, status.realized_gain, status.minimum_distribution), (1_1000000, 0, 0));
+
+    t.admins[0].mint(&t.vault.address, &110);
+    t.vault.sync_total_value(&t.owner);
+    assert_eq!(t.vault.get_profit_share().minimum_distribution, 88);
+}
+
+#[test]
+fn distribution_events_carry_the_profit_source() {
+    use crate::profit_share::SOURCE_STAKING;
+    use crate::testutils::nft::{MockProfitShareNft, MockProfitShareNftClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).build();
+    t.deposit(1000);
+
+    let nft_id = env.register_contract(None, MockProfitShareNft);
+    MockProfitShareNftClient::new(&env, &nft_id).set_profit_share(&t.vault.address, &5000);
+    t.vault.set_nft_contract(&t.owner, &Some(nft_id));
+
+    t.admins[0].mint(&t.vault.address, &200);
+    t.vault.sync_total_value(&t.owner);
+
+    // Only the known sources are accepted
+    assert_eq!(
+        t.vault.try_distribute_profits(&t.owner, &100, &Symbol::new(&env, "airdrop")),
+        Err(Ok(VaultError::InvalidConfiguration))
+    );
+
+    t.vault.distribute_profits(&t.owner, &100, &SOURCE_STAKING);
+    let (_, data) = find_event(&env, "profit_distributed").unwrap();
+    let (amount, _, _, source) = <(i128, i128, i128, Symbol)>::try_from_val(&env, &data).unwrap();
+    assert_eq!((amount, source), (100, SOURCE_STAKING));
+}
+
+#[test]
+fn swap_fees_accumulate_across_swaps() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_mock_dex()
+        .build();
+    t.seed_pool(0, 1, 100_000, 100_000);
+    assert_eq!(t.vault.total_swap_fees_paid(), 0);
+
+    // Base deposits don't swap
+    t.deposit(1000);
+    assert_eq!(t.vault.total_swap_fees_paid(), 0);
+
+    let user = Address::generate(&env);
+    t.admins[1].mint(&user, &12_000);
+    t.vault.deposit_with_token(&user, &10_000, &t.token(1).address);
+    assert_eq!(t.vault.total_swap_fees_paid(), 30);
+
+    t.vault.deposit_with_token(&user, &2_000, &t.token(1).address);
+    assert_eq!(t.vault.total_swap_fees_paid(), 36);
+}
+
+#[test]
+fn same_ledger_exits_share_slippage_per_share() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+        .with_mock_dex()
+        .build();
+    // B trades at a discount, so selling it back for A costs the exits
+    t.seed_pool(0, 1, 100_000, 150_000);
+    let whale_a = t.deposit(1000);
+    let whale_b = t.deposit(1000);
+    t.vault.trigger_rebalance();
+    assert_eq!((t.balance(0), t.balance(1)), (1326, 1001));
+
+    t.vault.set_exit_batching(&t.owner, &Some(500));
+
+    // Small exits are paid straight away
+    let minnow = t.deposit(100);
+    assert_eq!(t.vault.withdraw(&minnow, &100).net_amount, 100);
+
+    // Large ones are queued rather than racing each other for the liquid A
+    assert_eq!(t.vault.try_withdraw(&whale_a, &800), Err(Ok(VaultError::WithdrawalBatched)));
+    assert_eq!(t.vault.request_withdraw(&whale_a, &800), 800);
+    assert_eq!(t.vault.request_withdraw(&whale_b, &800), 800);
+    assert_eq!(t.vault.get_position(&whale_a).shares, 200);
+    assert_eq!(t.vault.get_pending_exit(&whale_b), 800);
+
+    // Nothing settles until the ledger closes
+    assert_eq!(t.vault.settle_exit_batch(), 0);
+    env.ledger().with_mut(|li| li.sequence_number += 1);
+
+    // The first claim of the next ledger settles the whole batch with one unwind
+    let paid_a = t.vault.claim_exit(&whale_a);
+    assert_eq!(t.vault.get_pending_exit(&whale_b), 0);
+    assert_eq!(t.vault.get_claimable_exit(&whale_b), paid_a);
+    assert_eq!(t.vault.claim_exit(&whale_b), paid_a);
+    assert_eq!(t.vault.get_claimable_exit(&whale_b), 0);
+
+    // B was sold to cover the 274 of 1_600 that A couldn't; the loss is split evenly
+    let (_, data) = find_event(&env, "exit_batch").unwrap();
+    let (batch_shares, gross, realized): (i128, i128, i128) = data.into_val(&env);
+    assert_eq!((batch_shares, gross), (1600, 1600));
+    assert!(realized < gross);
+    assert_eq!(paid_a, realized / 2);
+    assert_eq!(t.token(0).balance(&whale_a), paid_a);
+    assert_eq!(t.token(0).balance(&whale_b), paid_a);
+
+    let state = t.vault.get_state();
+    assert_eq!((state.total_shares, state.total_value), (400, 400));
+}
+
+#[test]
+fn mainnet_vault_refuses_integrations_its_factory_denylists() {
+    use crate::testutils::denylist::{MockDenylistFactory, MockDenylistFactoryClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory = MockDenylistFactoryClient::new(&env, &env.register_contract(None, MockDenylistFactory));
+    let mainnet = Symbol::new(&env, "mainnet");
+
+    // A vault initialized directly stays unstamped; nobody can stamp it later
+    let direct = TestVault::new(&env).build();
+    assert_eq!(
+        direct.vault.try_initialize_from_factory(&factory.address, &mainnet, &direct.vault.get_config()),
+        Err(Ok(VaultError::AlreadyInitialized))
+    );
+    assert_eq!(direct.vault.get_network_status().network, None);
+
+    let t = TestVault::new(&env).from_factory(&factory.address, "mainnet").build();
+    assert_eq!(t.vault.get_network_status().network, Some(mainnet.clone()));
+
+    let testnet_router = Address::generate(&env);
+    factory.set_denied(&testnet_router, &true);
+    assert_eq!(t.vault.try_set_router(&testnet_router), Err(Ok(VaultError::DeniedIntegration)));
+    assert_eq!(
+        t.vault.try_set_staking_pool(&t.owner, &testnet_router),
+        Err(Ok(VaultError::DeniedIntegration))
+    );
+    assert_eq!(t.vault.get_config().router_address, None);
+
+    factory.set_denied(&testnet_router, &false);
+    t.vault.set_router(&testnet_router);
+    assert_eq!(t.vault.get_config().router_address, Some(testnet_router.clone()));
+
+    // Denylisted after the fact, it shows up in the health check
+    factory.set_denied(&testnet_router, &true);
+    let status = t.vault.get_network_status();
+    assert_eq!(status.network, Some(mainnet));
+    assert_eq!(status.denied, vec![&env, (Symbol::new(&env, "router"), testnet_router)]);
+}
+
+#[test]
+fn stamped_vaults_report_stats_to_their_factory() {
+    use crate::testutils::denylist::{MockDenylistFactory, MockDenylistFactoryClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let factory = MockDenylistFactoryClient::new(&env, &env.register_contract(None, MockDenylistFactory));
+    let t = TestVault::new(&env).from_factory(&factory.address, "testnet").build();
+    assert_eq!(factory.stats(&t.vault.address), None);
+
+    // Each holder counts once, however many deposits they make
+    let alice = t.deposit(1_000);
+    t.admins[0].mint(&alice, &500);
+    t.vault.deposit(&alice, &500);
+    let bob = t.deposit(500);
+    assert_eq!(factory.stats(&t.vault.address), Some((2_000, 2)));
+
+    // Leaving drops them from the count
+    t.vault.withdraw_all(&bob);
+    assert_eq!(factory.stats(&t.vault.address), Some((1_500, 1)));
+
+    // A refused report doesn't block the withdrawal behind it
+    factory.set_refusing(&true);
+    t.vault.withdraw_all(&alice);
+    assert_eq!(factory.stats(&t.vault.address), Some((1_500, 1)));
+    assert!(find_event(&env, "stats_refused").is_some());
+    assert_eq!(t.vault.get_state().total_value, 0);
+}
+
+#[test]
+fn pools_listing_tokens_in_either_order_quote_and_swap_alike() {
+    use crate::pool_client::{calculate_swap_input, calculate_swap_output, orient_reserves, swap_via_pool};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(3).with_mock_dex().build();
+    t.deposit(1000);
+    let (a, b) = (t.token(0).address.clone(), t.token(1).address.clone());
+
+    let forward = t.seed_pool(0, 1, 10_000, 20_000);
+    let forward_price = t.vault.get_price(&b);
+    let reversed = t.seed_pool(1, 0, 20_000, 10_000);
+    assert_eq!(t.vault.get_price(&b), forward_price);
+    assert_eq!(forward_price, 5000000);
+
+    env.as_contract(&t.vault.address, || {
+        for pool in [&forward, &reversed] {
+            assert_eq!(orient_reserves(&env, pool, &a), Ok((10_000, 20_000)));
+            assert_eq!(orient_reserves(&env, pool, &b), Ok((20_000, 10_000)));
+            assert_eq!(orient_reserves(&env, pool, &t.token(2).address), Err(VaultError::InvalidConfiguration));
+            assert_eq!(calculate_swap_output(&env, pool, &a, &b, 100), Ok(197));
+            assert_eq!(calculate_swap_output(&env, pool, &b, &a, 100), Ok(49));
+            assert_eq!(calculate_swap_input(&env, pool, &a, &b, 197), Ok(100));
+        }
+
+        let out_forward = swap_via_pool(&env, &forward, &a, &b, 100, 0).unwrap();
+        let out_reversed = swap_via_pool(&env, &reversed, &a, &b, 100, 0).unwrap();
+        assert_eq!((out_forward, out_reversed), (197, 197));
+    });
+    assert_eq!(t.balance(1), 394);
+}
+
+#[test]
+fn flow_stats_accumulate_deposits_and_withdrawals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env).with_assets(2).build();
+    assert_eq!(t.vault.flow_stats(), (0, 0));
+
+    let alice = t.deposit(1000);
+    let bob = t.deposit(500);
+    assert_eq!(t.vault.flow_stats(), (1500, 0));
+
+    let half = t.vault.get_position(&alice).shares / 2;
+    t.vault.withdraw(&alice, &half);
+    t.admins[0].mint(&bob, &250);
+    t.vault.deposit(&bob, &250);
+    t.vault.withdraw_all(&bob);
+    assert_eq!(t.vault.flow_stats(), (1750, 1250));
+
+    // Nothing gained or lost: what's left is exactly deposits less withdrawals
+    let (deposited, withdrawn) = t.vault.flow_stats();
+    assert_eq!(t.vault.get_state().total_value + withdrawn - deposited, 0);
+}
+
+#[test]
+fn stake_rejects_a_pool_that_under_mints() {
+    use crate::testutils::staking_pool::MockStakingPoolClient;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    let pool = MockStakingPoolClient::new(&env, t.staking_pool.as_ref().unwrap());
+
+    // Quotes 1:1 but mints a tenth of that
+    pool.set_mint_bps(&1000);
+    assert_eq!(t.vault.try_force_stake(&t.owner), Err(Ok(VaultError::SlippageTooHigh)));
+    assert!(!t.vault.has_staking_position());
+    assert_eq!(t.balance(0), 1000);
+
+    // A shortfall within the tolerance still stakes
+    pool.set_mint_bps(&9900);
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.vault.get_staking_position().st_token_amount, 495);
+}
+
+#[test]
+fn withdraw_staking_returns_the_whole_position_to_the_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+
+    // Nothing staked yet
+    assert_eq!(t.vault.try_withdraw_staking(&t.owner), Err(Ok(VaultError::NotInitialized)));
+
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.balance(0), 500);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_withdraw_staking(&stranger), Err(Ok(VaultError::Unauthorized)));
+
+    assert_eq!(t.vault.withdraw_staking(&t.owner), 500);
+    let (_, data) = find_event(&env, "staking_withdrawn").unwrap();
+    assert_eq!(<(i128, i128, i128)>::try_from_val(&env, &data).unwrap(), (500, 500, 500));
+    assert_eq!(t.balance(0), 1000);
+    assert_eq!(t.vault.get_state().total_value, 1000);
+    assert!(!t.vault.has_staking_position());
+    assert_eq!(t.vault.try_withdraw_staking(&t.owner), Err(Ok(VaultError::NotInitialized)));
+}
+
+#[test]
+fn active_positions_reports_stake_and_liquidity_together() {
+    use crate::types::LiquidityPosition;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    t.deposit(1000);
+    assert_eq!(t.vault.active_positions(), (None, Vec::new(&env)));
+
+    t.vault.force_stake(&t.owner);
+    let (staked, liquidity) = t.vault.active_positions();
+    assert_eq!(staked, Some(t.vault.get_staking_position()));
+    assert!(liquidity.is_empty());
+
+    let position = LiquidityPosition {
+        pool_address: Address::generate(&env),
+        token_a: t.token(0).address.clone(),
+        token_b: Address::generate(&env),
+        lp_tokens: 100,
+        amount_a_provided: 200,
+        amount_b_provided: 300,
+        timestamp: 0,
+    };
+    t.env.as_contract(&t.vault.address, || {
+        t.env.storage().instance().set(&soroban_sdk::String::from_str(&t.env, "lp_position"), &position);
+    });
+    let (staked, liquidity) = t.vault.active_positions();
+    assert_eq!(staked.unwrap().staked_amount, 500);
+    assert_eq!(liquidity, vec![&env, position]);
+}
+
+#[test]
+fn withdrawal_unstakes_only_its_shortfall_when_opted_in() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_rule("time", 50_0000, "stake", &[])
+        .with_mock_staking()
+        .build();
+    let user = t.deposit(1000);
+    t.vault.force_stake(&t.owner);
+    assert_eq!(t.balance(0), 500);
+
+    // 800 of value against 500 liquid
+    let shares = t.vault.get_position(&user).shares * 8 / 10;
+    assert_eq!(t.vault.try_withdraw(&user, &shares), Err(Ok(VaultError::InsufficientLiquidity)));
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_set_auto_unwind_on_withdraw(&stranger, &true), Err(Ok(VaultError::Unauthorized)));
+    t.vault.set_auto_unwind_on_withdraw(&t.owner, &true);
+    assert!(t.vault.get_auto_unwind_on_withdraw());
+
+    let receipt = t.vault.withdraw(&user, &shares);
+    assert_eq!(receipt.net_amount, 800);
+    assert_eq!(t.token(0).balance(&user), 800);
+
+    // Just the 300 shortfall came out of the stake
+    assert_eq!(t.balance(0), 0);
+    let position = t.vault.get_staking_position();
+    assert_eq!((position.staked_amount, position.st_token_amount), (200, 200));
+}
+
+#[test]
+fn guarded_valuation_holds_through_a_skewed_pool() {
+    use crate::testutils::oracle::{MockOracle, MockOracleClient};
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let t = TestVault::new(&env)
+        .with_assets(2)
+        .with_rule("allocation", 1, "rebalance", &[50_0000, 50_0000])
+        .with_mock_dex()
+        .build();
+    let asset = t.token(1).address.clone();
+    let pair = t.seed_pool(0, 1, 2000_0000000, 1000_0000000);
+
+    let stranger = Address::generate(&env);
+    assert_eq!(t.vault.try_set_price_guard(&stranger, &Some(500)), Err(Ok(VaultError::Unauthorized)));
+    for bad in [0, -1, 10001] {
+        assert_eq!(t.vault.try_set_price_guard(&t.owner, &Some(bad)), Err(Ok(VaultError::InvalidConfiguration)));
+    }
+    t.vault.set_price_guard(&t.owner, &Some(500));
+    assert_eq!(t.vault.get_price_guard(), Some(500));
+
+    // The first spot price has nothing to be checked against
+    assert_eq!(t.vault.get_price(&asset), 2_0000000);
+    let user = t.deposit(1000);
+    let shares_before = t.vault.get_position(&user).shares;
+    let state_before = t.vault.get_state();
+    let rules_before = t.vault.evaluate_rules_view(&None);
+
+    // A 3% move is within the bound and becomes the new reference
+    t.admins[0].mint(&pair, &60_0000000);
+    assert_eq!(t.vault.get_price(&asset), 2_0600000);
+    assert_eq!(count_events(&env, "price_guard"), 0);
+
+    // Doubling the pool's price in one go is refused
+    t.admins[0].mint(&pair, &2000_0000000);
+    assert_eq!(t.vault.get_price(&asset), 2_0600000);
+    let (_, data) = find_event(&env, "price_guard").unwrap();
+    let (spot, reference, used): (i128, i128, i128) = data.into_val(&env);
+    assert_eq!((spot, reference, used), (4_0600000, 2_0600000, 2_0600000));
+
+    // Nothing else moved with the pool
+    assert_eq!(t.vault.get_state(), state_before);
+    assert_eq!(t.vault.evaluate_rules_view(&None), rules_before);
+    let other = t.deposit(1000);
+    assert_eq!(t.vault.get_position(&other).shares, shares_before);
+
+    // A fresh oracle quote is the reference when there is one
+    let oracle_id = env.register_contract(None, MockOracle);
+    let oracle = MockOracleClient::new(&env, &oracle_id);
+    let now = env.ledger().timestamp();
+    oracle.set_price(&t.token(0).address, &1_00000000000000, &now);
+    oracle.set_price(&asset, &4_00000000000000, &now);
+    t.vault.set_price_oracle(&t.owner, &Some(oracle_id), &600);
+    t.vault.set_price_source_priority(&t.owner, &vec![&env, crate::types::PriceSource::Spot]);
+    assert_eq!(t.vault.get_price(&asset), 4_0600000);
+
+    // Unguarded, spot is taken as it is
+    t.vault.set_price_guard(&t.owner, &None);
+    t.admins[0].mint(&pair, &4000_0000000);
+    assert_eq!(t.vault.get_price(&asset), 8_0600000);
+}
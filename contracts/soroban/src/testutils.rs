@@ -0,0 +1,782 @@
+// Test fixtures for vault scenarios
+// Compiled only for this crate's tests or with the `testutils` feature, so
+// none of it ships in the release wasm.
+//
+// TestVault::new(&env)
+//     .with_assets(2)
+//     .with_rule("allocation", 0, "rebalance", &[50_0000, 50_0000])
+//     .with_mock_dex()
+//     .with_mock_staking()
+//     .build()
+//
+// deploys the tokens, mock integrations and an initialized vault, and returns
+// a TestVaultHandles with every client the scenario needs.
+extern crate std;
+
+use soroban_sdk::{
+    contracttype,
+    testutils::{Address as _, Ledger},
+    token::{StellarAssetClient, TokenClient},
+    Address, Env, String, Symbol, Vec,
+};
+
+use crate::types::{RebalanceRule, VaultConfig};
+use crate::vault::{VaultContract, VaultContractClient};
+
+// ---------------------------------------------------------------------------
+// Mock contracts. Each lives in its own module since contractimpl generates
+// module-level items named after each function.
+// ---------------------------------------------------------------------------
+
+#[contracttype]
+#[derive(Clone)]
+enum MockKey {
+    Token,
+    Balance(Address),
+    MintBps,
+    Reenter,
+    Reentered,
+}
+
+/// Liquid staking pool implementing StakingPoolInterface.
+/// The vault transfers the base token in before calling deposit, so the pool
+/// only books the stake and hands back st-tokens at a quoted 1:1 rate.
+/// `set_mint_bps` makes it mint less than it quotes. `set_reenter` makes it
+/// call back into the vault's trigger_stake from deposit; `reentered` reports
+/// whether that call got through.
+pub mod staking_pool {
+    use super::MockKey;
+    use crate::vault::VaultContractClient;
+    use soroban_sdk::{contract, contractimpl, token::TokenClient, Address, Env};
+
+    #[contract]
+    pub struct MockStakingPool;
+
+    #[contractimpl]
+    impl MockStakingPool {
+        pub fn init(env: Env, token: Address) {
+            env.storage().instance().set(&MockKey::Token, &token);
+        }
+
+        pub fn set_mint_bps(env: Env, bps: i128) {
+            env.storage().instance().set(&MockKey::MintBps, &bps);
+        }
+
+        pub fn set_reenter(env: Env, vault: Address) {
+            env.storage().instance().set(&MockKey::Reenter, &vault);
+        }
+
+        pub fn reentered(env: Env) -> bool {
+            env.storage().instance().get(&MockKey::Reentered).unwrap_or(false)
+        }
+
+        pub fn deposit(env: Env, sender: Address, amount: i128) -> i128 {
+            if let Some(vault) = env.storage().instance().get::<_, Address>(&MockKey::Reenter) {
+                let result = VaultContractClient::new(&env, &vault).try_trigger_stake();
+                env.storage().instance().set(&MockKey::Reentered, &result.is_ok());
+            }
+            let key = MockKey::Balance(sender);
+            let staked: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(staked + amount));
+            let mint_bps: i128 = env.storage().instance().get(&MockKey::MintBps).unwrap_or(10000);
+            amount * mint_bps / 10000
+        }
+
+        pub fn withdraw(env: Env, sender: Address, amount: i128) -> i128 {
+            let key = MockKey::Balance(sender.clone());
+            let staked: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(staked - amount));
+            let token: Address = env.storage().instance().get(&MockKey::Token).unwrap();
+            TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &sender, &amount);
+            amount
+        }
+
+        pub fn get_exchange_rate(_env: Env) -> (i128, i128) {
+            (1, 1)
+        }
+
+        pub fn get_staked_balance(env: Env, user: Address) -> i128 {
+            env.storage().instance().get(&MockKey::Balance(user)).unwrap_or(0)
+        }
+    }
+}
+
+/// Instant-redeem pool implementing BufferPoolInterface.
+/// Supplied tokens arrive before supply is called; `accrue` simulates yield
+/// by crediting extra underlying that the test has minted to the pool.
+pub mod buffer_pool {
+    use super::MockKey;
+    use soroban_sdk::{contract, contractimpl, token::TokenClient, Address, Env};
+
+    #[contract]
+    pub struct MockBufferPool;
+
+    #[contractimpl]
+    impl MockBufferPool {
+        pub fn init(env: Env, token: Address) {
+            env.storage().instance().set(&MockKey::Token, &token);
+        }
+
+        pub fn supply(env: Env, sender: Address, amount: i128) -> i128 {
+            let key = MockKey::Balance(sender);
+            let supplied: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(supplied + amount));
+            amount
+        }
+
+        pub fn redeem(env: Env, sender: Address, amount: i128) -> i128 {
+            let key = MockKey::Balance(sender.clone());
+            let supplied: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            if amount > supplied {
+                panic!("redeem exceeds balance");
+            }
+            env.storage().instance().set(&key, &(supplied - amount));
+            let token: Address = env.storage().instance().get(&MockKey::Token).unwrap();
+            TokenClient::new(&env, &token).transfer(&env.current_contract_address(), &sender, &amount);
+            amount
+        }
+
+        pub fn balance_of(env: Env, user: Address) -> i128 {
+            env.storage().instance().get(&MockKey::Balance(user)).unwrap_or(0)
+        }
+
+        pub fn accrue(env: Env, user: Address, amount: i128) {
+            let key = MockKey::Balance(user);
+            let supplied: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(supplied + amount));
+        }
+    }
+}
+
+/// Soroswap stand-ins: a factory registered at the address the vault has
+/// hardcoded, and constant-product pairs whose reserves are their real balances.
+/// Swaps pay out whatever the caller asks for; the vault prices them itself.
+/// Pairs are their own LP token, minted by the test with mint_lp.
+pub mod soroswap {
+    use soroban_sdk::{contract, contractimpl, contracttype, token::TokenClient, Address, Env};
+
+    #[contracttype]
+    #[derive(Clone)]
+    enum PairKey {
+        Pair(Address, Address),
+        Token0,
+        Token1,
+        Lp(Address),
+        LpSupply,
+    }
+
+    #[contract]
+    pub struct MockSoroswapFactory;
+
+    #[contractimpl]
+    impl MockSoroswapFactory {
+        pub fn register_pair(env: Env, token_a: Address, token_b: Address, pair: Address) {
+            env.storage().instance().set(&PairKey::Pair(token_a.clone(), token_b.clone()), &pair);
+            env.storage().instance().set(&PairKey::Pair(token_b, token_a), &pair);
+        }
+
+        pub fn get_pair(env: Env, token_a: Address, token_b: Address) -> Address {
+            env.storage().instance().get(&PairKey::Pair(token_a, token_b)).unwrap()
+        }
+    }
+
+    #[contract]
+    pub struct MockPair;
+
+    #[contractimpl]
+    impl MockPair {
+        pub fn setup(env: Env, token_0: Address, token_1: Address) {
+            env.storage().instance().set(&PairKey::Token0, &token_0);
+            env.storage().instance().set(&PairKey::Token1, &token_1);
+        }
+
+        pub fn token_0(env: Env) -> Address {
+            env.storage().instance().get(&PairKey::Token0).unwrap()
+        }
+
+        pub fn token_1(env: Env) -> Address {
+            env.storage().instance().get(&PairKey::Token1).unwrap()
+        }
+
+        pub fn get_reserves(env: Env) -> (i128, i128) {
+            let this = env.current_contract_address();
+            (
+                TokenClient::new(&env, &Self::token_0(env.clone())).balance(&this),
+                TokenClient::new(&env, &Self::token_1(env.clone())).balance(&this),
+            )
+        }
+
+        /// Issue LP tokens against the current reserves
+        pub fn mint_lp(env: Env, to: Address, amount: i128) {
+            let key = PairKey::Lp(to);
+            let held: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(held + amount));
+            let supply: i128 = env.storage().instance().get(&PairKey::LpSupply).unwrap_or(0);
+            env.storage().instance().set(&PairKey::LpSupply, &(supply + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().instance().get(&PairKey::Lp(id)).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+            let held = Self::balance(env.clone(), from.clone());
+            env.storage().instance().set(&PairKey::Lp(from), &(held - amount));
+            let received = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&PairKey::Lp(to), &(received + amount));
+        }
+
+        /// Burn the LP tokens sent here, paying out their share of the reserves
+        pub fn burn(env: Env, to: Address) -> (i128, i128) {
+            let this = env.current_contract_address();
+            let liquidity = Self::balance(env.clone(), this.clone());
+            let supply: i128 = env.storage().instance().get(&PairKey::LpSupply).unwrap();
+            let (reserve0, reserve1) = Self::get_reserves(env.clone());
+            let (amount0, amount1) = (reserve0 * liquidity / supply, reserve1 * liquidity / supply);
+
+            env.storage().instance().set(&PairKey::Lp(this), &0i128);
+            env.storage().instance().set(&PairKey::LpSupply, &(supply - liquidity));
+            Self::swap(env, amount0, amount1, to);
+            (amount0, amount1)
+        }
+
+        pub fn swap(env: Env, amount0_out: i128, amount1_out: i128, to: Address) {
+            let this = env.current_contract_address();
+            if amount0_out > 0 {
+                TokenClient::new(&env, &Self::token_0(env.clone())).transfer(&this, &to, &amount0_out);
+            }
+            if amount1_out > 0 {
+                TokenClient::new(&env, &Self::token_1(env.clone())).transfer(&this, &to, &amount1_out);
+            }
+        }
+    }
+}
+
+/// Router stand-in that swaps 1:1 out of its own balance and counts swaps.
+/// Takes the input from `to` like Soroswap does, with itself as the "pair".
+/// Removes liquidity by burning the caller's LP at the mock factory's pair.
+// remove_liquidity has to keep the router's eight-argument signature
+#[allow(clippy::too_many_arguments)]
+pub mod router {
+    use super::soroswap::{MockPairClient, MockSoroswapFactoryClient};
+    use soroban_sdk::{contract, contractimpl, symbol_short, token::TokenClient, vec, Address, Env, String, Vec};
+
+    #[contract]
+    pub struct MockRouter;
+
+    #[contractimpl]
+    impl MockRouter {
+        pub fn swap_exact_tokens_for_tokens(
+            env: Env,
+            amount_in: i128,
+            _amount_out_min: i128,
+            path: Vec<Address>,
+            to: Address,
+            _deadline: u64,
+        ) -> Vec<i128> {
+            to.require_auth();
+            let this = env.current_contract_address();
+            let token_in = path.get(0).unwrap();
+            let token_out = path.get(path.len() - 1).unwrap();
+            TokenClient::new(&env, &token_in).transfer(&to, &this, &amount_in);
+            TokenClient::new(&env, &token_out).transfer(&this, &to, &amount_in);
+
+            let swaps: u32 = env.storage().instance().get(&symbol_short!("swaps")).unwrap_or(0);
+            env.storage().instance().set(&symbol_short!("swaps"), &(swaps + 1));
+            vec![&env, amount_in, amount_in]
+        }
+
+        pub fn swaps(env: Env) -> u32 {
+            env.storage().instance().get(&symbol_short!("swaps")).unwrap_or(0)
+        }
+
+        pub fn router_pair_for(env: Env, _token_a: Address, _token_b: Address) -> Address {
+            env.current_contract_address()
+        }
+
+        pub fn remove_liquidity(
+            env: Env,
+            token_a: Address,
+            token_b: Address,
+            liquidity: i128,
+            _amount_a_min: i128,
+            _amount_b_min: i128,
+            to: Address,
+            _deadline: u64,
+        ) -> (i128, i128) {
+            let factory = Address::from_string(&String::from_str(&env, super::SOROSWAP_FACTORY));
+            let pair = MockSoroswapFactoryClient::new(&env, &factory).get_pair(&token_a, &token_b);
+            let pair_client = MockPairClient::new(&env, &pair);
+            pair_client.transfer(&to, &pair, &liquidity);
+            let (amount_0, amount_1) = pair_client.burn(&to);
+            if pair_client.token_0() == token_a {
+                (amount_0, amount_1)
+            } else {
+                (amount_1, amount_0)
+            }
+        }
+    }
+}
+
+/// Soroswap router stand-in that behaves like the real one: it requires `to`'s
+/// auth, transfers the input from `to` straight into the mock factory's pair
+/// and has the pair pay out the constant-product amount (0.3% fee).
+pub mod soroswap_router {
+    use super::soroswap::{MockPairClient, MockSoroswapFactoryClient};
+    use soroban_sdk::{contract, contractimpl, token::TokenClient, vec, Address, Env, String, Vec};
+
+    #[contract]
+    pub struct MockSoroswapRouter;
+
+    #[contractimpl]
+    impl MockSoroswapRouter {
+        pub fn router_pair_for(env: Env, token_a: Address, token_b: Address) -> Address {
+            let factory = Address::from_string(&String::from_str(&env, super::SOROSWAP_FACTORY));
+            MockSoroswapFactoryClient::new(&env, &factory).get_pair(&token_a, &token_b)
+        }
+
+        pub fn get_amounts_out(env: Env, amount_in: i128, path: Vec<Address>) -> Vec<i128> {
+            let token_in = path.get(0).unwrap();
+            let pair = Self::router_pair_for(env.clone(), token_in.clone(), path.get(1).unwrap());
+            let pair_client = MockPairClient::new(&env, &pair);
+            let (reserve_0, reserve_1) = pair_client.get_reserves();
+            let (reserve_in, reserve_out) = if pair_client.token_0() == token_in {
+                (reserve_0, reserve_1)
+            } else {
+                (reserve_1, reserve_0)
+            };
+            let amount_out = amount_in * 997 * reserve_out / (reserve_in * 1000 + amount_in * 997);
+            vec![&env, amount_in, amount_out]
+        }
+
+        pub fn swap_exact_tokens_for_tokens(
+            env: Env,
+            amount_in: i128,
+            amount_out_min: i128,
+            path: Vec<Address>,
+            to: Address,
+            _deadline: u64,
+        ) -> Vec<i128> {
+            to.require_auth();
+            let amounts = Self::get_amounts_out(env.clone(), amount_in, path.clone());
+            let amount_out = amounts.get(1).unwrap();
+            assert!(amount_out >= amount_out_min, "insufficient output amount");
+
+            let token_in = path.get(0).unwrap();
+            let pair = Self::router_pair_for(env.clone(), token_in.clone(), path.get(1).unwrap());
+            TokenClient::new(&env, &token_in).transfer(&to, &pair, &amount_in);
+
+            let pair_client = MockPairClient::new(&env, &pair);
+            if pair_client.token_0() == token_in {
+                pair_client.swap(&0, &amount_out, &to);
+            } else {
+                pair_client.swap(&amount_out, &0, &to);
+            }
+            amounts
+        }
+    }
+}
+
+/// Minimal token with configurable decimals, for base assets that aren't
+/// 7-decimal Stellar assets (USDC-style 6 decimals and the like).
+pub mod decimal_token {
+    use super::MockKey;
+    use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env};
+
+    #[contract]
+    pub struct MockDecimalToken;
+
+    #[contractimpl]
+    impl MockDecimalToken {
+        pub fn init(env: Env, decimals: u32) {
+            env.storage().instance().set(&symbol_short!("decimals"), &decimals);
+        }
+
+        pub fn decimals(env: Env) -> u32 {
+            env.storage().instance().get(&symbol_short!("decimals")).unwrap()
+        }
+
+        pub fn mint(env: Env, to: Address, amount: i128) {
+            let held = Self::balance(env.clone(), to.clone());
+            env.storage().instance().set(&MockKey::Balance(to), &(held + amount));
+        }
+
+        pub fn balance(env: Env, id: Address) -> i128 {
+            env.storage().instance().get(&MockKey::Balance(id)).unwrap_or(0)
+        }
+
+        pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+            from.require_auth();
+            let held = Self::balance(env.clone(), from.clone());
+            if amount > held {
+                panic!("insufficient balance");
+            }
+            env.storage().instance().set(&MockKey::Balance(from), &(held - amount));
+            Self::mint(env, to, amount);
+        }
+    }
+}
+
+/// SEP-40 oracle stand-in with prices and quote times set by the test
+pub mod oracle {
+    use crate::pricing::{OracleAsset, PriceData};
+    use soroban_sdk::{contract, contractimpl, Address, Env};
+
+    #[contract]
+    pub struct MockOracle;
+
+    #[contractimpl]
+    impl MockOracle {
+        pub fn set_price(env: Env, asset: Address, price: i128, timestamp: u64) {
+            env.storage().instance().set(&asset, &PriceData { price, timestamp });
+        }
+
+        pub fn lastprice(env: Env, asset: OracleAsset) -> Option<PriceData> {
+            match asset {
+                OracleAsset::Stellar(asset) => env.storage().instance().get(&asset),
+                OracleAsset::Other(_) => None,
+            }
+        }
+    }
+}
+
+/// Vault factory stand-in that keeps an integration denylist and the stats
+/// vaults report, refusing reports while `set_refusing` is on
+pub mod denylist {
+    use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env};
+
+    #[contract]
+    pub struct MockDenylistFactory;
+
+    #[contractimpl]
+    impl MockDenylistFactory {
+        pub fn set_denied(env: Env, address: Address, denied: bool) {
+            env.storage().instance().set(&address, &denied);
+        }
+
+        pub fn is_denied(env: Env, address: Address) -> bool {
+            env.storage().instance().get(&address).unwrap_or(false)
+        }
+
+        pub fn set_refusing(env: Env, refusing: bool) {
+            env.storage().instance().set(&symbol_short!("REFUSING"), &refusing);
+        }
+
+        pub fn report_stats(env: Env, vault: Address, tvl: i128, users: u32) {
+            vault.require_auth();
+            if env.storage().instance().get(&symbol_short!("REFUSING")).unwrap_or(false) {
+                panic!("report refused");
+            }
+            env.storage().instance().set(&(symbol_short!("STATS"), vault), &(tvl, users));
+        }
+
+        pub fn stats(env: Env, vault: Address) -> Option<(i128, u32)> {
+            env.storage().instance().get(&(symbol_short!("STATS"), vault))
+        }
+    }
+}
+
+/// Vault NFT stand-in for profit sharing: stores a registered share per vault
+/// and tallies what each vault distributes, without splitting it among holders.
+pub mod nft {
+    use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Map};
+
+    #[contracttype]
+    #[derive(Clone)]
+    enum NftKey {
+        Share(Address),
+        Distributed(Address),
+    }
+
+    #[contract]
+    pub struct MockProfitShareNft;
+
+    #[contractimpl]
+    impl MockProfitShareNft {
+        pub fn set_profit_share(env: Env, vault_address: Address, profit_share_bps: i128) {
+            env.storage().instance().set(&NftKey::Share(vault_address), &profit_share_bps);
+        }
+
+        pub fn get_profit_share(env: Env, vault_address: Address) -> i128 {
+            env.storage().instance().get(&NftKey::Share(vault_address)).unwrap_or(0)
+        }
+
+        pub fn distribute_profits(env: Env, caller: Address, vault_address: Address, total_profit: i128, _token: Address) -> Map<Address, i128> {
+            caller.require_auth();
+            assert_eq!(caller, vault_address);
+            let key = NftKey::Distributed(vault_address);
+            let distributed: i128 = env.storage().instance().get(&key).unwrap_or(0);
+            env.storage().instance().set(&key, &(distributed + total_profit));
+            Map::new(&env)
+        }
+
+        pub fn distributed(env: Env, vault_address: Address) -> i128 {
+            env.storage().instance().get(&NftKey::Distributed(vault_address)).unwrap_or(0)
+        }
+    }
+}
+
+use soroswap::{MockPair, MockPairClient, MockSoroswapFactory, MockSoroswapFactoryClient};
+use staking_pool::{MockStakingPool, MockStakingPoolClient};
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+// Soroswap factory address the vault looks pools up through
+pub const SOROSWAP_FACTORY: &str = "CDJTMBYKNUGINFQALHDMPLZYNGUV42GPN4B7QOYTWHRC4EE5IYJM6AES";
+
+/// Register a Stellar asset administered by `admin`
+pub fn create_token<'a>(env: &Env, admin: &Address) -> (TokenClient<'a>, StellarAssetClient<'a>) {
+    let sac = env.register_stellar_asset_contract_v2(admin.clone());
+    (
+        TokenClient::new(env, &sac.address()),
+        StellarAssetClient::new(env, &sac.address()),
+    )
+}
+
+/// Build a rule from plain values
+pub fn rule(env: &Env, condition: &str, threshold: i128, action: &str, target: &[i128]) -> RebalanceRule {
+    let mut target_allocation = Vec::new(env);
+    for t in target {
+        target_allocation.push_back(*t);
+    }
+    RebalanceRule {
+        condition_type: String::from_str(env, condition),
+        threshold,
+        action: String::from_str(env, action),
+        target_allocation,
+    }
+}
+
+/// Vault config with no integrations set
+pub fn config(env: &Env, owner: &Address, assets: Vec<Address>, rules: Vec<RebalanceRule>) -> VaultConfig {
+    VaultConfig {
+        owner: owner.clone(),
+        name: String::from_str(env, "Test Vault"),
+        assets,
+        rules,
+        router_address: None,
+        staking_pool_address: None,
+        factory_address: None,
+    }
+}
+
+/// Register and initialize a vault
+pub fn create_vault<'a>(env: &Env, config: &VaultConfig) -> VaultContractClient<'a> {
+    let vault_id = env.register_contract(None, VaultContract);
+    let vault = VaultContractClient::new(env, &vault_id);
+    vault.initialize(config);
+    vault
+}
+
+/// Register a mock staking pool paying out `token`
+pub fn create_staking_pool(env: &Env, token: &Address) -> Address {
+    let pool_id = env.register_contract(None, MockStakingPool);
+    MockStakingPoolClient::new(env, &pool_id).init(token);
+    pool_id
+}
+
+/// Register the mock Soroswap factory at the address the vault expects
+pub fn create_soroswap_factory(env: &Env) -> Address {
+    let factory_id = Address::from_string(&String::from_str(env, SOROSWAP_FACTORY));
+    env.register_contract(&factory_id, MockSoroswapFactory);
+    factory_id
+}
+
+/// Register a mock Soroswap pair holding the given reserves with an existing factory
+pub fn register_pair(
+    env: &Env,
+    token_0: &StellarAssetClient,
+    token_1: &StellarAssetClient,
+    reserve_0: i128,
+    reserve_1: i128,
+) -> Address {
+    let factory_id = Address::from_string(&String::from_str(env, SOROSWAP_FACTORY));
+    let pair_id = env.register_contract(None, MockPair);
+    MockPairClient::new(env, &pair_id).setup(&token_0.address, &token_1.address);
+    MockSoroswapFactoryClient::new(env, &factory_id)
+        .register_pair(&token_0.address, &token_1.address, &pair_id);
+
+    token_0.mint(&pair_id, &reserve_0);
+    token_1.mint(&pair_id, &reserve_1);
+    pair_id
+}
+
+/// Register the mock factory and a single pair holding the given reserves
+pub fn create_pair(
+    env: &Env,
+    token_0: &StellarAssetClient,
+    token_1: &StellarAssetClient,
+    reserve_0: i128,
+    reserve_1: i128,
+) -> Address {
+    create_soroswap_factory(env);
+    register_pair(env, token_0, token_1, reserve_0, reserve_1)
+}
+
+/// Register a mock oracle quoting each (token, price) as of now
+pub fn create_oracle(env: &Env, prices: &[(&Address, i128)]) -> Address {
+    let oracle_id = env.register_contract(None, oracle::MockOracle);
+    let oracle = oracle::MockOracleClient::new(env, &oracle_id);
+    for (token, price) in prices {
+        oracle.set_price(token, price, &env.ledger().timestamp());
+    }
+    oracle_id
+}
+
+// ---------------------------------------------------------------------------
+// Builder
+// ---------------------------------------------------------------------------
+
+/// Builder for a vault and everything it talks to
+pub struct TestVault {
+    env: Env,
+    asset_count: u32,
+    rules: Vec<RebalanceRule>,
+    mock_dex: bool,
+    mock_staking: bool,
+    factory: Option<(Address, Symbol)>,
+}
+
+impl TestVault {
+    /// Single-asset vault with no rules and no integrations
+    pub fn new(env: &Env) -> Self {
+        TestVault {
+            env: env.clone(),
+            asset_count: 1,
+            rules: Vec::new(env),
+            mock_dex: false,
+            mock_staking: false,
+            factory: None,
+        }
+    }
+
+    /// Number of fresh tokens the vault holds; the first is the base asset
+    pub fn with_assets(mut self, count: u32) -> Self {
+        self.asset_count = count;
+        self
+    }
+
+    /// Append a rule, in the order it should appear in the config
+    pub fn with_rule(mut self, condition: &str, threshold: i128, action: &str, target: &[i128]) -> Self {
+        let rule = rule(&self.env, condition, threshold, action, target);
+        self.rules.push_back(rule);
+        self
+    }
+
+    /// Register the mock Soroswap factory and give the vault a router
+    pub fn with_mock_dex(mut self) -> Self {
+        self.mock_dex = true;
+        self
+    }
+
+    /// Register a mock staking pool for the base asset and point the vault at it
+    pub fn with_mock_staking(mut self) -> Self {
+        self.mock_staking = true;
+        self
+    }
+
+    /// Initialize the vault as `factory` deploying it for `network` would
+    pub fn from_factory(mut self, factory: &Address, network: &str) -> Self {
+        self.factory = Some((factory.clone(), Symbol::new(&self.env, network)));
+        self
+    }
+
+    /// Deploy and initialize everything
+    pub fn build(self) -> TestVaultHandles {
+        let env = self.env;
+        let owner = Address::generate(&env);
+
+        let mut tokens = std::vec::Vec::new();
+        let mut admins = std::vec::Vec::new();
+        let mut assets = Vec::new(&env);
+        for _ in 0..self.asset_count {
+            let (token, admin) = create_token(&env, &owner);
+            assets.push_back(token.address.clone());
+            tokens.push(token);
+            admins.push(admin);
+        }
+
+        let mut cfg = config(&env, &owner, assets, self.rules);
+
+        if self.mock_dex {
+            create_soroswap_factory(&env);
+            // The vault swaps through the factory directly; the router only has to be set
+            cfg.router_address = Some(Address::generate(&env));
+        }
+
+        let staking_pool = if self.mock_staking {
+            let pool = create_staking_pool(&env, &tokens[0].address);
+            cfg.staking_pool_address = Some(pool.clone());
+            Some(pool)
+        } else {
+            None
+        };
+
+        let vault = match self.factory {
+            Some((factory, network)) => {
+                let vault = VaultContractClient::new(&env, &env.register_contract(None, VaultContract));
+                vault.initialize_from_factory(&factory, &network, &cfg);
+                vault
+            }
+            None => create_vault(&env, &cfg),
+        };
+
+        TestVaultHandles {
+            env,
+            vault,
+            owner,
+            tokens,
+            admins,
+            staking_pool,
+            has_dex: self.mock_dex,
+        }
+    }
+}
+
+/// Clients for a vault built by TestVault
+pub struct TestVaultHandles {
+    pub env: Env,
+    pub vault: VaultContractClient<'static>,
+    pub owner: Address,
+    /// Token clients in config order
+    pub tokens: std::vec::Vec<TokenClient<'static>>,
+    /// Admin clients matching `tokens`, for minting
+    pub admins: std::vec::Vec<StellarAssetClient<'static>>,
+    pub staking_pool: Option<Address>,
+    has_dex: bool,
+}
+
+impl TestVaultHandles {
+    /// Token client for the asset at `index`
+    pub fn token(&self, index: usize) -> &TokenClient<'static> {
+        &self.tokens[index]
+    }
+
+    /// The vault's liquid balance of the asset at `index`
+    pub fn balance(&self, index: usize) -> i128 {
+        self.tokens[index].balance(&self.vault.address)
+    }
+
+    /// Mint `amount` of the base asset to a fresh user and deposit it
+    pub fn deposit(&self, amount: i128) -> Address {
+        let user = Address::generate(&self.env);
+        self.admins[0].mint(&user, &amount);
+        self.vault.deposit(&user, &amount);
+        user
+    }
+
+    /// Add a pair between assets `a` and `b` holding the given reserves
+    /// Needs with_mock_dex
+    pub fn seed_pool(&self, a: usize, b: usize, reserve_a: i128, reserve_b: i128) -> Address {
+        assert!(self.has_dex, "seed_pool needs with_mock_dex");
+        register_pair(&self.env, &self.admins[a], &self.admins[b], reserve_a, reserve_b)
+    }
+
+    /// Move the ledger clock forward
+    pub fn advance_time(&self, secs: u64) {
+        self.env.ledger().with_mut(|li| li.timestamp += secs);
+    }
+}
@@ -1,6 +1,15 @@
 // Token client utilities for interacting with Stellar Asset Contract tokens
-use soroban_sdk::{Address, Env, token};
+use soroban_sdk::{symbol_short, Address, Bytes, Env, Symbol, Vec, token};
 use crate::errors::VaultError;
+use crate::types::AllowanceInfo;
+
+// Every (token, spender) the vault has approved, with the expiry it set
+const APPROVALS: Symbol = symbol_short!("APPROVALS");
+// Asset deposits are denominated in and withdrawals pay out, pinned at initialize
+const BASE_ASSET: Symbol = symbol_short!("BASE_AST");
+
+/// Ledgers a router approval stays live for unless the caller asks for longer
+pub const DEFAULT_APPROVAL_LEDGERS: u32 = 100;
 
 /// Transfer tokens from one address to another
 /// Uses the standard Stellar Asset Contract interface
@@ -22,6 +31,36 @@ pub fn transfer_tokens(
     Ok(())
 }
 
+/// Address of the Stellar Asset Contract wrapping native XLM on this network
+/// Derived from the native `Asset` XDR (a bare ASSET_TYPE_NATIVE discriminant),
+/// so it is correct whether or not the SAC has been deployed yet.
+pub fn native_asset_address(env: &Env) -> Address {
+    let native_asset_xdr = Bytes::from_array(env, &[0u8; 4]);
+    env.deployer().with_stellar_asset(native_asset_xdr).deployed_address()
+}
+
+/// Check whether a token address is the native XLM SAC
+pub fn is_native_asset(env: &Env, token_address: &Address) -> bool {
+    *token_address == native_asset_address(env)
+}
+
+/// Pin the vault's base asset
+pub fn pin_base_asset(env: &Env, asset: &Address) {
+    env.storage().instance().set(&BASE_ASSET, asset);
+}
+
+/// The vault's base asset, independent of where it sits in `assets`
+/// Vaults initialized before the base asset was pinned fall back to the first
+/// asset. A pinned base asset that is no longer configured is an error rather
+/// than a silent switch to whatever token now comes first.
+pub fn base_asset(env: &Env, assets: &Vec<Address>) -> Result<Address, VaultError> {
+    match env.storage().instance().get::<Symbol, Address>(&BASE_ASSET) {
+        Some(asset) if assets.contains(&asset) => Ok(asset),
+        Some(_) => Err(VaultError::InvalidConfiguration),
+        None => assets.get(0).ok_or(VaultError::InvalidConfiguration),
+    }
+}
+
 /// Transfer tokens from vault to user (for withdrawals)
 pub fn transfer_from_vault(
     env: &Env,
@@ -55,34 +94,107 @@ pub fn get_balance(
 }
 
 /// Get vault's balance of a specific token
+/// Settled exits waiting to be claimed belong to their users and aren't counted.
 pub fn get_vault_balance(
     env: &Env,
     token_address: &Address,
 ) -> i128 {
     let vault_address = env.current_contract_address();
-    get_balance(env, token_address, &vault_address)
+    get_balance(env, token_address, &vault_address) - crate::exit_batch::reserved(env, token_address)
 }
 
 /// Approve router to spend vault's tokens for swaps
+/// The approval expires DEFAULT_APPROVAL_LEDGERS from now.
 pub fn approve_router(
     env: &Env,
     token_address: &Address,
     router: &Address,
     amount: i128,
 ) -> Result<(), VaultError> {
-    if amount <= 0 {
+    approve_router_for(env, token_address, router, amount, DEFAULT_APPROVAL_LEDGERS)
+}
+
+/// Approve router to spend vault's tokens for `expiry_ledgers` ledgers
+/// For callers whose approval has to outlive a single transaction.
+pub fn approve_router_for(
+    env: &Env,
+    token_address: &Address,
+    router: &Address,
+    amount: i128,
+    expiry_ledgers: u32,
+) -> Result<(), VaultError> {
+    if amount <= 0 || expiry_ledgers == 0 {
         return Err(VaultError::InvalidAmount);
     }
 
     let token_client = token::TokenClient::new(env, token_address);
     let vault_address = env.current_contract_address();
-    let expiration_ledger = env.ledger().sequence() + 100;
+    let expiration_ledger = env.ledger().sequence()
+        .checked_add(expiry_ledgers)
+        .ok_or(VaultError::InvalidAmount)?;
     
     token_client.approve(&vault_address, router, &amount, &expiration_ledger);
+    record_approval(env, token_address, router, expiration_ledger);
     
     Ok(())
 }
 
+/// Remember a spender approval so it can be audited and revoked later
+fn record_approval(env: &Env, token_address: &Address, spender: &Address, expiry_ledger: u32) {
+    let mut approvals = get_approvals(env);
+    let existing = approvals.iter()
+        .position(|(token, who, _)| token == *token_address && who == *spender);
+    match existing {
+        Some(i) => approvals.set(i as u32, (token_address.clone(), spender.clone(), expiry_ledger)),
+        None => approvals.push_back((token_address.clone(), spender.clone(), expiry_ledger)),
+    }
+    env.storage().instance().set(&APPROVALS, &approvals);
+}
+
+fn get_approvals(env: &Env) -> Vec<(Address, Address, u32)> {
+    env.storage().instance().get(&APPROVALS).unwrap_or(Vec::new(env))
+}
+
+/// Live allowances over the vault's tokens, from the approval registry
+/// Entries whose allowance has been spent, revoked or has expired are pruned.
+pub fn outstanding_allowances(env: &Env) -> Vec<AllowanceInfo> {
+    let vault_address = env.current_contract_address();
+    let mut live = Vec::new(env);
+    let mut kept = Vec::new(env);
+
+    for (token_address, spender, expiry_ledger) in get_approvals(env).iter() {
+        let amount = token::TokenClient::new(env, &token_address)
+            .allowance(&vault_address, &spender);
+        if amount > 0 {
+            kept.push_back((token_address.clone(), spender.clone(), expiry_ledger));
+            live.push_back(AllowanceInfo {
+                token: token_address,
+                spender,
+                amount,
+                expiry_ledger,
+            });
+        }
+    }
+
+    env.storage().instance().set(&APPROVALS, &kept);
+    live
+}
+
+/// Zero every allowance in the approval registry and clear it
+/// Returns the number of allowances that were still live
+pub fn revoke_all_allowances(env: &Env) -> u32 {
+    let vault_address = env.current_contract_address();
+    let live = outstanding_allowances(env);
+
+    for info in live.iter() {
+        token::TokenClient::new(env, &info.token)
+            .approve(&vault_address, &info.spender, &0, &env.ledger().sequence());
+    }
+
+    env.storage().instance().remove(&APPROVALS);
+    live.len()
+}
+
 /// Check if router has sufficient allowance
 pub fn check_allowance(
     env: &Env,
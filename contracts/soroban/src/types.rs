@@ -1,5 +1,5 @@
 // Vault data structures and types
-use soroban_sdk::{contracttype, Address, String, Vec};
+use soroban_sdk::{contracttype, Address, Map, String, Symbol, Vec};
 
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -65,3 +65,171 @@ pub struct LiquidityPosition {
     pub amount_b_provided: i128,  // Original amount of token B
     pub timestamp: u64,           // When liquidity was provided
 }
+
+/// Why a trigger call returned without executing anything
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    RulesNotMet,  // No rule for the action evaluated true
+    Cooldown,     // A time rule for the action has not elapsed yet
+    Paused,       // Vault is paused
+    EmptyVault,   // Nothing deposited, nothing to act on
+    Duplicate,    // The op id was already executed
+}
+
+/// Where a swap can be executed, for set_venue_preference
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Venue {
+    DirectPool,      // Soroswap pair, swapped against directly
+    SoroswapRouter,  // Soroswap router contract
+    Phoenix,         // Phoenix DEX (not integrated yet, always passed over)
+}
+
+/// Version of the vault's contract interface; bumped whenever the generated client types change
+/// 2: trigger functions return TriggerResult instead of ()
+pub const CONTRACT_VERSION: u32 = 2;
+
+/// Version of the StrategyDescriptor layout; bumped whenever fields are appended
+pub const STRATEGY_DESCRIPTOR_VERSION: u32 = 1;
+
+/// Machine-readable summary of a vault's strategy, for off-chain risk scoring
+/// Assembled from the config and owner settings, never from free text.
+/// Fields are only ever appended; check `version` before reading newer ones.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StrategyDescriptor {
+    pub version: u32,
+    pub actions: Vec<String>,                   // Distinct rule actions, in config order
+    pub max_deployment_bps: Map<String, i128>,  // Most of the vault's value one run of each deploying action moves
+    pub venues: Vec<Venue>,                     // Swap venues that may be used, in order (empty: no swaps)
+    pub router: Option<Address>,
+    pub staking_pools: Vec<Address>,
+    pub buffer_pool: Option<Address>,           // Yield pool for the idle withdrawal buffer
+    pub oracle: Option<Address>,                // Price oracle, if configured
+    pub price_sources: Vec<PriceSource>,
+    pub performance_fee_bps: i128,
+    pub limits: Limits,
+}
+
+/// Where an asset price can come from, for set_price_source_priority
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PriceSource {
+    Oracle,  // SEP-40 oracle set with set_price_oracle, if its quotes are fresh
+    Spot,    // Soroswap pair reserves against the base asset
+}
+
+/// Summary of an executed trigger
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TriggerReport {
+    pub timestamp: u64,
+    pub total_value: i128,
+}
+
+/// Outcome of trigger_rebalance / trigger_stake / trigger_unstake / trigger_liquidity
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TriggerResult {
+    Executed(TriggerReport),
+    Skipped(SkipReason),
+}
+
+/// Breakdown of a withdrawal
+/// net_amount = gross_amount - sum of fees, paid out in payout_token
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawReceipt {
+    pub shares_burned: i128,
+    pub gross_amount: i128,        // Value of the burned shares before fees
+    pub fees: Vec<(Symbol, i128)>, // Each fee charged, tagged by kind
+    pub net_amount: i128,          // Amount actually transferred to the user
+    pub payout_token: Address,
+}
+
+/// Redemption terms of a wound-down vault, frozen by initiate_wind_down
+/// Prices are base asset per share, scaled by PRICE_SCALE.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WindDown {
+    pub price: i128,          // Paid by withdraw for every share
+    pub frozen_shares: i128,  // Shares outstanding at the freeze
+    pub true_up: i128,        // Paid by claim_true_up per frozen share, once finalized
+    pub finalized: bool,      // Whether the true-up has been set
+}
+
+/// Target vs current allocation of one asset, for charting
+/// Basis points: 10000 = 100%. drift_bps = current_bps - target_bps.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AllocationStatus {
+    pub asset: Address,
+    pub target_bps: i128,
+    pub current_bps: i128,
+    pub drift_bps: i128,
+    pub value: i128,       // Held amount, including staked and LP positions
+    pub has_target: bool,  // false when there is no rebalance rule
+}
+
+/// A live allowance the vault has granted over one of its tokens
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AllowanceInfo {
+    pub token: Address,
+    pub spender: Address,
+    pub amount: i128,
+    pub expiry_ledger: u32,
+}
+
+/// Profit sharing configuration and what a distribution must pay right now
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProfitShareStatus {
+    pub nft_contract: Option<Address>,
+    pub profit_share_bps: i128,      // Registered on the NFT contract, 0 when none
+    pub high_water_mark: i128,       // Share price gains are measured from, 1_0000000 = 1.0
+    pub realized_gain: i128,         // Value above the high-water mark
+    pub minimum_distribution: i128,  // Least distribute_profits will accept
+}
+
+/// Network the vault was stamped for and any integration its factory denylists
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkStatus {
+    pub network: Option<Symbol>,       // None for vaults no factory has stamped
+    pub denied: Vec<(Symbol, Address)>, // Configured integrations on the denylist, by kind
+}
+
+/// Most assets a vault config may hold (and entries a target allocation may have)
+pub const MAX_ASSETS: u32 = 10;
+
+/// Most rules a vault config may hold
+pub const MAX_RULES: u32 = 20;
+
+/// Most users one exit batch may hold
+pub const MAX_EXIT_BATCH: u32 = 50;
+
+/// Version of the Limits layout; bumped whenever fields are appended
+pub const LIMITS_VERSION: u32 = 2;
+
+/// Effective limits for this build and vault, for client-side validation
+/// Fields are only ever appended; check `version` before reading newer ones.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    pub version: u32,
+    pub allocation_scale: i128,               // target_allocation / threshold scale, 100_0000 = 100%
+    pub bps_scale: i128,                      // Basis point scale, 10000 = 100%
+    pub price_scale: i128,                    // Share price fixed point, 1_0000000 = 1.0
+    pub rebalance_swap_slippage_pct: i128,    // Per-swap tolerance during rebalance
+    pub lp_base_slippage_bps: i128,           // Derived LP tolerance floor
+    pub lp_max_slippage_bps: i128,            // Derived LP tolerance ceiling
+    pub lp_slippage_pct: Option<i128>,        // Owner's flat LP override, 0..=100
+    pub max_rebalance_loss_bps: Option<i128>, // Owner's cumulative loss cap, 0..=10000
+    pub allocation_caps: Map<Address, i128>,  // Owner's per-asset caps in bps
+    pub performance_fee_bps: i128,            // Current rate
+    pub max_performance_fee_bps: i128,        // Highest rate the owner may set
+    pub max_assets: u32,                      // Since version 2: cap on config assets
+    pub max_rules: u32,                       // Since version 2: cap on config rules
+}
@@ -0,0 +1,39 @@
+// Asset valuation in base asset units
+// Anything that compares amounts of different assets prices them here. Syncs
+// and allocation checks use pricing::get_price, in the vault's configured
+// priority order and subject to its price guard. Basket deposits and in-kind
+// withdrawals mint and burn shares against the value, so they only take a
+// fresh oracle price: a spot price could be skewed by the depositor.
+use soroban_sdk::{Address, Env};
+use crate::errors::VaultError;
+use crate::fees::PRICE_SCALE;
+
+/// Value of `amount` of `token` in base asset units
+/// The base asset is worth its amount. A zero amount is worth zero without
+/// asking for a price, so assets the vault doesn't hold never need one.
+pub fn value_asset(env: &Env, token: &Address, amount: i128) -> Result<i128, VaultError> {
+    if amount == 0 {
+        return Ok(0);
+    }
+
+    let price = crate::pricing::get_price(env, token)?;
+    amount.checked_mul(price)
+        .and_then(|v| v.checked_div(PRICE_SCALE))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Value of everything the vault holds of `asset`, staked and LP positions included
+pub fn held_value(env: &Env, asset: &Address) -> Result<i128, VaultError> {
+    value_asset(env, asset, crate::rebalance::held_amount(env, asset)?)
+}
+
+/// Value of `amount` of `token` in base asset units, for minting or burning shares
+/// Like value_asset, but only at a fresh oracle price, never spot. Fails with
+/// PriceUnavailable when the oracle has no fresh quote.
+pub fn basket_value(env: &Env, token: &Address, amount: i128) -> Result<i128, VaultError> {
+    if amount == 0 {
+        return Ok(0);
+    }
+
+    crate::pricing::oracle_value(env, token, amount).ok_or(VaultError::PriceUnavailable)
+}
@@ -1,13 +1,27 @@
 // Vault core contract functionality
-use soroban_sdk::{contract, contractimpl, Address, Env, Symbol, symbol_short, token, log};
+use soroban_sdk::{contract, contractimpl, Address, BytesN, Env, Map, Symbol, symbol_short, token, log, Vec};
 
-use crate::types::{VaultConfig, VaultState, UserPosition};
+use crate::types::{AllocationStatus, AllowanceInfo, CONTRACT_VERSION, Limits, LIMITS_VERSION, MAX_ASSETS, MAX_RULES, NetworkStatus, PriceSource, ProfitShareStatus, StrategyDescriptor, STRATEGY_DESCRIPTOR_VERSION, VaultConfig, VaultState, UserPosition, SkipReason, TriggerReport, TriggerResult, Venue, WindDown, WithdrawReceipt};
+use syft_errors::bounded_iter;
 use crate::errors::VaultError;
-use crate::events::{emit_deposit, emit_withdraw};
+use crate::events::{emit_deposit, emit_withdraw, emit_trigger_skip, EVENTS_ALL, EVENTS_DEBUG, EVENTS_REBALANCES};
 
 const CONFIG: Symbol = symbol_short!("CONFIG");
-const STATE: Symbol = symbol_short!("STATE");
 const POSITION: Symbol = symbol_short!("POSITION");
+const FACTORY_ADMIN: Symbol = symbol_short!("FACT_ADM");
+const ALLOW_FACTORY_ADMIN: Symbol = symbol_short!("ALLOW_FA");
+const LP_SLIPPAGE: Symbol = symbol_short!("LP_SLIP");
+const MAX_REBALANCE_LOSS: Symbol = symbol_short!("MAX_LOSS");
+const ALLOCATION_CAPS: Symbol = symbol_short!("ALLOC_CAP");
+const REBALANCE_POSITIONS: Symbol = symbol_short!("REB_POS");
+const LOG_LEVEL: Symbol = symbol_short!("LOG_LVL");
+const MIN_STAKE: Symbol = symbol_short!("MIN_STAKE");
+const EVENT_FLAGS: Symbol = symbol_short!("EVT_FLAGS");
+const DUST_THRESHOLD: Symbol = symbol_short!("DUST_THR");
+const DUST_TO_OWNER: Symbol = symbol_short!("DUST_OWN");
+const AUTO_UNWIND: Symbol = symbol_short!("AUTO_UNW");
+const ALLOCATION_EPSILON: Symbol = symbol_short!("ALLOC_EPS");
+const MAX_STAKE_PER_POOL: Symbol = symbol_short!("MAX_STAKE");
 
 #[contract]
 pub struct VaultContract;
@@ -25,17 +39,41 @@ impl VaultContract {
         if config.assets.is_empty() {
             return Err(VaultError::InvalidConfiguration);
         }
+        if config.assets.len() > MAX_ASSETS {
+            return Err(VaultError::TooManyItems);
+        }
+        for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+            if rule.target_allocation.len() > MAX_ASSETS {
+                return Err(VaultError::TooManyItems);
+            }
+        }
+        if !crate::engine::validate_rules(&env, &config.rules) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+        for (_, integration) in crate::network::integrations(&env, &config).iter() {
+            crate::network::check_integration(&env, &integration)?;
+        }
 
         // Initialize vault state
         let state = VaultState {
             total_shares: 0,
             total_value: 0,
-            last_rebalance: env.ledger().timestamp(),
+            last_rebalance: crate::clock::now(&env),
         };
 
         // Store configuration and state
-        env.storage().instance().set(&CONFIG, &config);
-        env.storage().instance().set(&STATE, &state);
+        crate::migrations::write_config(&env, &config);
+        crate::migrations::write_state(&env, &state);
+
+        // The first asset is the base asset for the vault's lifetime
+        let base_token = config.assets.get(0)
+            .ok_or(VaultError::InvalidConfiguration)?;
+        crate::token_client::pin_base_asset(&env, &base_token);
+
+        // Ledger-interval rules count from the ledger the vault was created in
+        crate::engine::record_ledger_rules(&env, None);
+
+        crate::migrations::stamp_current(&env);
 
         Ok(())
     }
@@ -44,79 +82,145 @@ impl VaultContract {
     /// If deposit_token is different from base token, it will be swapped automatically
     pub fn deposit(env: Env, user: Address, amount: i128) -> Result<i128, VaultError> {
         // Call deposit_with_token using the base asset (first asset)
-        let config: VaultConfig = env.storage().instance().get(&CONFIG)
+        let config: VaultConfig = crate::migrations::read_config(&env)
             .ok_or(VaultError::NotInitialized)?;
         
         if config.assets.is_empty() {
             return Err(VaultError::InvalidConfiguration);
         }
         
-        let base_token = config.assets.get(0)
-            .ok_or(VaultError::InvalidConfiguration)?;
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
         
         Self::deposit_with_token(env, user, amount, base_token)
     }
 
+    /// Deposit the base asset and optionally rebalance straight away
+    /// With `target_rebalance` the deposit is spread across the target allocation
+    /// in the same call, exactly as force_rebalance would; without it this is
+    /// a plain deposit and the funds stay in the base asset.
+    pub fn deposit_with_rebalance(env: Env, user: Address, amount: i128, target_rebalance: bool) -> Result<i128, VaultError> {
+        let shares = Self::deposit(env.clone(), user, amount)?;
+
+        if target_rebalance {
+            Self::force_rebalance(env)?;
+        }
+
+        Ok(shares)
+    }
+
     /// Deposit with specific token (will auto-swap if not base asset)
+    /// The swap must return at least 99% of the deposit's oracle value when a
+    /// fresh oracle price is available; use deposit_with_token_min to set the
+    /// floor yourself.
     pub fn deposit_with_token(env: Env, user: Address, amount: i128, deposit_token: Address) -> Result<i128, VaultError> {
+        Self::deposit_for_with_token(env, user.clone(), user, amount, deposit_token)
+    }
+
+    /// Deposit with specific token, failing with SlippageTooHigh if it comes to
+    /// less than `min_base_amount` of the base asset after the auto-swap
+    pub fn deposit_with_token_min(
+        env: Env,
+        user: Address,
+        amount: i128,
+        deposit_token: Address,
+        min_base_amount: i128,
+    ) -> Result<i128, VaultError> {
+        if min_base_amount < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+        Self::deposit_token_for(env, user.clone(), user, amount, deposit_token, Some(min_base_amount))
+    }
+
+    /// Deposit with specific token, crediting the shares to someone else
+    /// `payer` authorizes and funds the deposit; `beneficiary` gets the shares,
+    /// the position and its last_deposit time. For gifts and escrow.
+    pub fn deposit_for_with_token(
+        env: Env,
+        payer: Address,
+        beneficiary: Address,
+        amount: i128,
+        deposit_token: Address,
+    ) -> Result<i128, VaultError> {
+        Self::deposit_token_for(env, payer, beneficiary, amount, deposit_token, None)
+    }
+
+    // Shared body of the token deposits; None takes the default swap floor
+    fn deposit_token_for(
+        env: Env,
+        payer: Address,
+        beneficiary: Address,
+        amount: i128,
+        deposit_token: Address,
+        min_base_amount: Option<i128>,
+    ) -> Result<i128, VaultError> {
         // Debug: Entry point
-        env.events().publish((symbol_short!("debug"),), symbol_short!("start"));
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("start"));
         
-        // Require authorization from the user first
-        user.require_auth();
-        env.events().publish((symbol_short!("debug"),), symbol_short!("auth_ok"));
+        // Require authorization from the payer first
+        payer.require_auth();
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("auth_ok"));
         
         // Check vault is initialized
         if !env.storage().instance().has(&CONFIG) {
             return Err(VaultError::NotInitialized);
         }
-        env.events().publish((symbol_short!("debug"),), symbol_short!("init_ok"));
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("init_ok"));
+
+        crate::pause::when_not_paused(&env)?;
+
+        // Nobody may be running the strategy any more
+        crate::keeper::check_deposit(&env)?;
 
         // Validate amount
         if amount <= 0 {
             return Err(VaultError::InvalidAmount);
         }
-        env.events().publish((symbol_short!("debug"),), symbol_short!("amt_ok"));
-
-        // Get user position first (before any transfers)
-        let mut position = Self::get_position(env.clone(), user.clone());
-        env.events().publish((symbol_short!("debug"),), symbol_short!("pos_ok"));
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("amt_ok"));
 
         // Get config to determine base asset (first asset in the vault)
-        let config: VaultConfig = env.storage().instance().get(&CONFIG)
+        let config: VaultConfig = crate::migrations::read_config(&env)
             .ok_or(VaultError::NotInitialized)?;
-        env.events().publish((symbol_short!("debug"),), symbol_short!("cfg_ok"));
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("cfg_ok"));
         
         if config.assets.is_empty() {
             return Err(VaultError::InvalidConfiguration);
         }
         
-        let base_token = config.assets.get(0)
-            .ok_or(VaultError::InvalidConfiguration)?;
-        env.events().publish((symbol_short!("debug"),), symbol_short!("tok_ok"));
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("tok_ok"));
 
         // Get vault address
         let vault_address = env.current_contract_address();
-        env.events().publish((symbol_short!("debug"),), symbol_short!("addr_ok"));
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("addr_ok"));
         
-        // Transfer deposit token from user to vault
-        env.events().publish((symbol_short!("debug"),), symbol_short!("b4_xfer"));
+        // Transfer deposit token from payer to vault
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("b4_xfer"));
         let deposit_token_client = token::TokenClient::new(&env, &deposit_token);
-        deposit_token_client.transfer(&user, &vault_address, &amount);
-        env.events().publish((symbol_short!("debug"),), symbol_short!("xfer_ok"));
+        deposit_token_client.transfer(&payer, &vault_address, &amount);
+        crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("xfer_ok"));
 
         // AUTO-SWAP: If deposit token differs from base token, automatically swap to base token
         // This allows users to deposit ANY token (e.g., XLM) into vaults with different base assets (e.g., USDC)
         // The vault will automatically swap the deposited token to match the base asset
         let final_amount = if deposit_token != base_token {
             // Deposit token is different from base token - need to swap
-            env.events().publish((symbol_short!("debug"),), symbol_short!("swap_req"));
+            crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("swap_req"));
             
             // Check if router is configured
             let router_address = config.router_address
                 .ok_or(VaultError::RouterNotSet)?;
             
-            env.events().publish((symbol_short!("debug"),), symbol_short!("swap_go"));
+            crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("swap_go"));
+            
+            // Without an explicit floor, bound the swap by the oracle; the pool's
+            // own reserves can't, since they are what a sandwich moves
+            let min_amount_out = match min_base_amount {
+                Some(min_base_amount) => min_base_amount,
+                None => crate::pricing::oracle_value(&env, &deposit_token, amount)
+                    .and_then(|value| value.checked_mul(10000 - crate::swap_router::DEFAULT_DEPOSIT_SLIPPAGE_BPS))
+                    .map(|value| value / 10000)
+                    .unwrap_or(0),
+            };
             
             // Swap deposit token to base token via router
             let swapped_amount = crate::swap_router::swap_via_router(
@@ -125,47 +229,25 @@ impl VaultContract {
                 &deposit_token,
                 &base_token,
                 amount,
-                0, // min_amount_out = 0 (accept any slippage for now)
+                min_amount_out,
             )?;
             
-            env.events().publish((symbol_short!("debug"),), symbol_short!("swap_ok"));
+            crate::events::publish(&env, EVENTS_DEBUG, (symbol_short!("debug"),), symbol_short!("swap_ok"));
             swapped_amount
         } else {
             // Deposit token matches base token - no swap needed
+            crate::events::emit_no_swap_needed(&env, &deposit_token, amount);
             amount
         };
 
-        // Get current state
-        let mut state: VaultState = env.storage().instance().get(&STATE)
-            .ok_or(VaultError::NotInitialized)?;
-
-        // Calculate shares to mint based on final amount (after swap if needed)
-        let shares = if state.total_shares == 0 {
-            final_amount // First deposit: 1:1 ratio
-        } else {
-            // shares = (final_amount * total_shares) / total_value
-            final_amount.checked_mul(state.total_shares)
-                .and_then(|v| v.checked_div(state.total_value))
-                .ok_or(VaultError::InvalidAmount)?
-        };
-
-        // Update state with final amount
-        state.total_shares = state.total_shares.checked_add(shares)
-            .ok_or(VaultError::InvalidAmount)?;
-        state.total_value = state.total_value.checked_add(final_amount)
-            .ok_or(VaultError::InvalidAmount)?;
-
-        // Update user position (position was already fetched at the start)
-        position.shares = position.shares.checked_add(shares)
-            .ok_or(VaultError::InvalidAmount)?;
-        position.last_deposit = env.ledger().timestamp();
-
-        // Store updates
-        env.storage().instance().set(&STATE, &state);
-        env.storage().instance().set(&(POSITION, user.clone()), &position);
+        if final_amount < min_base_amount.unwrap_or(0) {
+            return Err(VaultError::SlippageTooHigh);
+        }
 
-        // Emit event with final amount (after swap)
-        emit_deposit(&env, &user, final_amount, shares);
+        let shares = Self::credit_deposit(&env, &beneficiary, final_amount, &base_token)?;
+        if payer != beneficiary {
+            crate::events::emit_share_transfer(&env, &payer, &beneficiary, shares);
+        }
 
         // NOTE: Auto-swap is now ENABLED for deposits
         // If user deposits a token different from the vault's base token, it will automatically swap
@@ -177,8 +259,85 @@ impl VaultContract {
         Ok(shares)
     }
 
+    /// Deposit the base asset on someone else's behalf
+    /// Pulls `amount` from `payer` through the allowance it has given the vault
+    /// and credits the shares to `beneficiary`. Lets contracts such as the vault
+    /// NFT reinvest what a holder is owed straight into the vault.
+    pub fn deposit_for(env: Env, payer: Address, beneficiary: Address, amount: i128) -> Result<i128, VaultError> {
+        payer.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        crate::pause::when_not_paused(&env)?;
+        crate::keeper::check_deposit(&env)?;
+
+        if amount <= 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        let vault_address = env.current_contract_address();
+        token::TokenClient::new(&env, &base_token)
+            .transfer_from(&vault_address, &payer, &vault_address, &amount);
+
+        let shares = Self::credit_deposit(&env, &beneficiary, amount, &base_token)?;
+        if payer != beneficiary {
+            crate::events::emit_share_transfer(&env, &payer, &beneficiary, shares);
+        }
+
+        Ok(shares)
+    }
+
+    /// Deposit a basket of the vault's assets in one call
+    /// Every token must be one of the configured assets. The basket is valued
+    /// with valuation::basket_value, at the fresh oracle prices withdraw_in_kind
+    /// also pays out at, and shares are minted against that value. Without a
+    /// fresh oracle quote for a non-base asset this fails with PriceUnavailable
+    /// rather than trust a pool spot price. Returns the shares.
+    pub fn deposit_multi(env: Env, user: Address, deposits: Vec<(Address, i128)>) -> Result<i128, VaultError> {
+        user.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        crate::pause::when_not_paused(&env)?;
+        crate::keeper::check_deposit(&env)?;
+
+        // Value the whole basket before any of it moves
+        let mut value: i128 = 0;
+        for (token, amount) in bounded_iter(&deposits, MAX_ASSETS, VaultError::TooManyItems)? {
+            if amount <= 0 {
+                return Err(VaultError::InvalidAmount);
+            }
+            if !config.assets.contains(&token) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+            value = value.checked_add(crate::valuation::basket_value(&env, &token, amount)?)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+        if value <= 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        let vault_address = env.current_contract_address();
+        for (token, amount) in deposits.iter() {
+            token::TokenClient::new(&env, &token).transfer(&user, &vault_address, &amount);
+        }
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        Self::credit_deposit(&env, &user, value, &base_token)
+    }
+
     /// Withdraw assets from the vault
-    pub fn withdraw(env: Env, user: Address, shares: i128) -> Result<i128, VaultError> {
+    /// Returns a receipt with the gross value of the burned shares, every fee
+    /// charged, and the net amount paid out
+    /// When the base asset is native XLM the payout goes through the native SAC,
+    /// which credits a classic account's XLM balance directly (the account must
+    /// already exist) and holds a contract balance for contract addresses.
+    /// Once the vault is wound down every share pays the frozen redemption
+    /// price, with no fees, batching or dust sweep (see initiate_wind_down).
+    pub fn withdraw(env: Env, user: Address, shares: i128) -> Result<WithdrawReceipt, VaultError> {
         // Require authorization from the user first
         user.require_auth();
         
@@ -187,6 +346,9 @@ impl VaultContract {
             return Err(VaultError::NotInitialized);
         }
 
+        // Payouts may swap or unwind; in emergency mode only emergency_withdraw pays out
+        crate::pause::when_not_emergency(&env)?;
+
         // Validate shares
         if shares <= 0 {
             return Err(VaultError::InvalidAmount);
@@ -198,8 +360,12 @@ impl VaultContract {
             return Err(VaultError::InsufficientShares);
         }
 
+        if let Some(terms) = crate::wind_down::terms(&env) {
+            return Self::redeem_closed(&env, &user, shares, position, &terms);
+        }
+
         // Get current state
-        let mut state: VaultState = env.storage().instance().get(&STATE)
+        let mut state: VaultState = crate::migrations::read_state(&env)
             .ok_or(VaultError::NotInitialized)?;
 
         // Guard against division by zero
@@ -209,258 +375,1995 @@ impl VaultContract {
 
         // Calculate amount to return
         // amount = (shares * total_value) / total_shares
-        let amount = shares.checked_mul(state.total_value)
+        let gross_amount = shares.checked_mul(state.total_value)
             .and_then(|v| v.checked_div(state.total_shares))
             .ok_or(VaultError::InvalidAmount)?;
 
+        // Fees charged on this withdrawal, tagged by kind
+        // Performance fee is charged on this user's gain over their own entry price
+        let mut fees: Vec<(Symbol, i128)> = Vec::new(&env);
+        let perf_fee = crate::fees::performance_fee(&env, &user, shares, gross_amount)?;
+        if perf_fee > 0 {
+            fees.push_back((symbol_short!("perf_fee"), perf_fee));
+        }
+        let mut total_fees: i128 = 0;
+        for (_, fee) in fees.iter() {
+            total_fees = total_fees.checked_add(fee)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+        let net_amount = gross_amount.checked_sub(total_fees)
+            .ok_or(VaultError::InvalidAmount)?;
+
+        // With a fee recipient set the fee never leaves the vault: the part of
+        // the burned shares it is worth is minted to the recipient instead
+        let fee_recipient = crate::fees::get_performance_fee_recipient(&env);
+        let (payout, fee_shares) = match &fee_recipient {
+            Some(_) => (net_amount, crate::fees::fee_shares(shares, perf_fee, gross_amount)?),
+            None => (gross_amount, 0),
+        };
+
+        // Large exits share slippage through a batch instead
+        if let Some(min_amount) = crate::exit_batch::batch_threshold(&env) {
+            if gross_amount >= min_amount {
+                return Err(VaultError::WithdrawalBatched);
+            }
+        }
+
         // Get config to determine base asset
-        let config: VaultConfig = env.storage().instance().get(&CONFIG)
+        let config: VaultConfig = crate::migrations::read_config(&env)
             .ok_or(VaultError::NotInitialized)?;
         
         if config.assets.is_empty() {
             return Err(VaultError::InvalidConfiguration);
         }
         
-        let base_token = config.assets.get(0)
-            .ok_or(VaultError::InvalidConfiguration)?;
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
 
         // Get vault address
         let vault_address = env.current_contract_address();
+
+        // Pull any shortfall back from the buffer pool before paying out, and
+        // then out of positions if the owner opted in
+        crate::buffer_pool::ensure_liquid(&env, &base_token, payout)?;
+        if crate::token_client::get_vault_balance(&env, &base_token) < payout
+            && crate::rebalance::auto_unwind_on_withdraw(&env)
+        {
+            crate::rebalance::unwind_for_withdrawal(&env, &base_token, payout)?;
+        }
+        if crate::token_client::get_vault_balance(&env, &base_token) < payout {
+            return Err(VaultError::InsufficientLiquidity);
+        }
         
         // Transfer tokens from vault to user using token contract
         // DO NOT call user.require_auth() - vault doesn't need user auth to send funds to them
         let token_client = token::TokenClient::new(&env, &base_token);
-        token_client.transfer(&vault_address, &user, &amount);
+        token_client.transfer(&vault_address, &user, &net_amount);
+
+        // Without a fee recipient the performance fee goes to the vault owner
+        if perf_fee > 0 && fee_recipient.is_none() {
+            token_client.transfer(&vault_address, &config.owner, &perf_fee);
+        }
 
         // Update state
         state.total_shares = state.total_shares.checked_sub(shares)
+            .and_then(|v| v.checked_add(fee_shares))
             .ok_or(VaultError::InvalidAmount)?;
-        state.total_value = state.total_value.checked_sub(amount)
+        state.total_value = state.total_value.checked_sub(payout)
             .ok_or(VaultError::InvalidAmount)?;
 
         // Update user position
         position.shares = position.shares.checked_sub(shares)
             .ok_or(VaultError::InvalidAmount)?;
 
+        Self::sweep_dust(&env, &user, &config.owner, &mut state, &mut position)?;
+
         // Store updates
-        env.storage().instance().set(&STATE, &state);
+        crate::migrations::write_state(&env, &state);
+        crate::flows::record_withdrawal(&env, payout);
         if position.shares == 0 {
-            env.storage().instance().remove(&(POSITION, user.clone()));
+            Self::remove_position(&env, &user);
+            crate::fees::clear_entry(&env, &user);
         } else {
-            env.storage().instance().set(&(POSITION, user.clone()), &position);
+            Self::store_position(&env, &user, &position);
         }
 
+        // Credited after the user's position is stored, in case they are the recipient
+        if let (Some(recipient), true) = (fee_recipient, fee_shares > 0) {
+            Self::credit_fee_shares(&env, &state, &recipient, fee_shares, perf_fee)?;
+        }
+        crate::stats::report(&env, state.total_value);
+
+        let receipt = WithdrawReceipt {
+            shares_burned: shares,
+            gross_amount,
+            fees,
+            net_amount,
+            payout_token: base_token,
+        };
+
         // Emit event
-        emit_withdraw(&env, &user, shares, amount);
+        emit_withdraw(&env, &user, &receipt);
 
-        Ok(amount)
+        Ok(receipt)
     }
 
-    /// Get vault state
-    pub fn get_state(env: Env) -> VaultState {
-        env.storage().instance().get(&STATE)
-            .unwrap_or(VaultState {
-                total_shares: 0,
-                total_value: 0,
-                last_rebalance: 0,
-            })
-    }
+    // A remainder at or below the dust threshold is swept rather than left as
+    // a position too small to withdraw cleanly
+    fn sweep_dust(
+        env: &Env,
+        user: &Address,
+        owner: &Address,
+        state: &mut VaultState,
+        position: &mut UserPosition,
+    ) -> Result<(), VaultError> {
+        let (dust_threshold, dust_to_owner) = Self::get_dust_threshold(env.clone());
+        if position.shares <= 0 || position.shares > dust_threshold {
+            return Ok(());
+        }
 
-    /// Get user position
-    pub fn get_position(env: Env, user: Address) -> UserPosition {
-        env.storage().instance().get(&(POSITION, user))
-            .unwrap_or(UserPosition {
-                shares: 0,
-                last_deposit: 0,
-            })
+        let dust = position.shares;
+        position.shares = 0;
+        if dust_to_owner && user != owner {
+            let mut owner_position = Self::get_position(env.clone(), owner.clone());
+            let price = crate::fees::share_price(env, state)?;
+            crate::fees::record_entry(env, owner, owner_position.shares, dust, price)?;
+            owner_position.shares = owner_position.shares.checked_add(dust)
+                .ok_or(VaultError::InvalidAmount)?;
+            Self::store_position(env, owner, &owner_position);
+        } else {
+            // Burned: the value behind it stays with the remaining holders
+            state.total_shares = state.total_shares.checked_sub(dust)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+        env.events().publish(
+            (Symbol::new(env, "dust_swept"), user.clone()),
+            (dust, dust_to_owner),
+        );
+        Ok(())
     }
 
-    /// Get vault configuration
-    pub fn get_config(env: Env) -> Result<VaultConfig, VaultError> {
-        env.storage().instance().get(&CONFIG)
-            .ok_or(VaultError::NotInitialized)
-    }
+    // Pay `shares` out of a closed vault at the frozen price
+    fn redeem_closed(
+        env: &Env,
+        user: &Address,
+        shares: i128,
+        mut position: UserPosition,
+        terms: &WindDown,
+    ) -> Result<WithdrawReceipt, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(env)
+            .ok_or(VaultError::NotInitialized)?;
+        let mut state: VaultState = crate::migrations::read_state(env)
+            .ok_or(VaultError::NotInitialized)?;
+        let base_token = crate::token_client::base_asset(env, &config.assets)?;
 
-    /// Set router address for swaps (owner only)
-    pub fn set_router(env: Env, router: Address) -> Result<(), VaultError> {
-        // Check vault is initialized
-        if !env.storage().instance().has(&CONFIG) {
-            return Err(VaultError::NotInitialized);
+        let payout = crate::wind_down::redemption_value(terms, shares)?;
+        if crate::token_client::get_vault_balance(env, &base_token) < payout {
+            return Err(VaultError::InsufficientLiquidity);
+        }
+        if payout > 0 {
+            crate::token_client::transfer_from_vault(env, &base_token, user, payout)?;
         }
 
-        // Get config and verify owner
-        let mut config: VaultConfig = env.storage().instance().get(&CONFIG)
-            .ok_or(VaultError::NotInitialized)?;
-        
-        config.owner.require_auth();
-        
-        // Update router address
-        config.router_address = Some(router);
-        
-        // Store updated config
-        env.storage().instance().set(&CONFIG, &config);
-        
-        Ok(())
-    }
+        state.total_shares = state.total_shares.checked_sub(shares)
+            .ok_or(VaultError::InvalidAmount)?;
+        state.total_value = state.total_value.checked_sub(payout)
+            .ok_or(VaultError::InvalidAmount)?
+            .max(0);
+        crate::migrations::write_state(env, &state);
+        crate::flows::record_withdrawal(env, payout);
+        crate::wind_down::record_redemption(env, user, shares)?;
 
-    /// Set the staking pool address for liquid staking (e.g., stXLM)
-    pub fn set_staking_pool(env: Env, caller: Address, staking_pool: Address) -> Result<(), VaultError> {
-        caller.require_auth();
-        
-        let mut config: VaultConfig = env.storage().instance().get(&CONFIG)
-            .ok_or(VaultError::NotInitialized)?;
-        
-        // Only owner can update staking pool
-        if caller != config.owner {
-            return Err(VaultError::Unauthorized);
+        position.shares = position.shares.checked_sub(shares)
+            .ok_or(VaultError::InvalidAmount)?;
+        if position.shares == 0 {
+            Self::remove_position(env, user);
+            crate::fees::clear_entry(env, user);
+        } else {
+            Self::store_position(env, user, &position);
         }
-        
-        config.staking_pool_address = Some(staking_pool);
-        
-        // Store updated config
-        env.storage().instance().set(&CONFIG, &config);
-        
-        Ok(())
+        crate::stats::report(env, state.total_value);
+
+        let receipt = WithdrawReceipt {
+            shares_burned: shares,
+            gross_amount: payout,
+            fees: Vec::new(env),
+            net_amount: payout,
+            payout_token: base_token,
+        };
+        emit_withdraw(env, user, &receipt);
+
+        Ok(receipt)
     }
 
-    /// Set the factory address for finding liquidity pools
-    pub fn set_factory(env: Env, caller: Address, factory: Address) -> Result<(), VaultError> {
-        caller.require_auth();
-        
-        let mut config: VaultConfig = env.storage().instance().get(&CONFIG)
+    /// Withdraw a proportional basket of the vault's liquid assets, without swaps
+    /// The shares are worth their usual part of total_value. That value is paid
+    /// out of every configured asset's liquid balance in the same proportion,
+    /// with each asset priced by valuation::basket_value, as deposit_multi does.
+    /// The performance fee, exit batch threshold and dust sweep apply as in
+    /// withdraw: exits at or above the threshold fail with WithdrawalBatched
+    /// and go through request_withdraw, and a fee paid to the owner is taken
+    /// in kind. Positions and the buffer pool aren't drawn on, so this fails
+    /// with InsufficientLiquidity when the liquid assets fall short. Returns
+    /// the amount of each asset paid to the user.
+    pub fn withdraw_in_kind(env: Env, user: Address, shares: i128) -> Result<Vec<(Address, i128)>, VaultError> {
+        user.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
             .ok_or(VaultError::NotInitialized)?;
-        
-        // Only owner can update factory
-        if caller != config.owner {
-            return Err(VaultError::Unauthorized);
-        }
-        
-        config.factory_address = Some(factory);
-        
-        // Store updated config
-        env.storage().instance().set(&CONFIG, &config);
-        
-        Ok(())
-    }
 
-    /// Trigger a rebalance based on configured rules (only rebalance actions)
-    /// Can be called by anyone, but only executes if rebalance rules are met
-    pub fn trigger_rebalance(env: Env) -> Result<(), VaultError> {
-        // Check vault is initialized
-        if !env.storage().instance().has(&CONFIG) {
-            return Err(VaultError::NotInitialized);
+        crate::pause::when_not_emergency(&env)?;
+        crate::wind_down::when_open(&env)?;
+
+        if shares <= 0 {
+            return Err(VaultError::InvalidAmount);
         }
 
-        // Check if rebalancing should occur based on rules
-        // NOTE: Anyone can call this, but it only rebalances if rules are satisfied
-        // This prevents griefing while allowing automated rebalancing
-        if !crate::engine::should_rebalance(&env) {
-            return Ok(()); // No rebalancing needed
+        let mut position = Self::get_position(env.clone(), user.clone());
+        if position.shares < shares {
+            return Err(VaultError::InsufficientShares);
         }
 
-        let _config: VaultConfig = env.storage().instance().get(&CONFIG)
-            .ok_or(VaultError::NotInitialized)?;
-        
-        let mut state: VaultState = env.storage().instance().get(&STATE)
+        let mut state: VaultState = crate::migrations::read_state(&env)
             .ok_or(VaultError::NotInitialized)?;
+        if state.total_shares == 0 {
+            return Err(VaultError::InvalidAmount);
+        }
 
-        // Execute only rebalance actions
-        crate::rebalance::execute_rebalance_only(&env)?;
-
-        // Update last rebalance timestamp
-        state.last_rebalance = env.ledger().timestamp();
-        env.storage().instance().set(&STATE, &state);
+        let gross_amount = shares.checked_mul(state.total_value)
+            .and_then(|v| v.checked_div(state.total_shares))
+            .ok_or(VaultError::InvalidAmount)?;
+        if gross_amount <= 0 {
+            return Err(VaultError::InvalidAmount);
+        }
 
-        // Emit rebalance event
-        crate::events::emit_rebalance(&env, state.last_rebalance);
+        if let Some(min_amount) = crate::exit_batch::batch_threshold(&env) {
+            if gross_amount >= min_amount {
+                return Err(VaultError::WithdrawalBatched);
+            }
+        }
 
-        Ok(())
-    }
+        let perf_fee = crate::fees::performance_fee(&env, &user, shares, gross_amount)?;
+        let net_amount = gross_amount.checked_sub(perf_fee)
+            .ok_or(VaultError::InvalidAmount)?;
+        let fee_recipient = crate::fees::get_performance_fee_recipient(&env);
+        let (payout, fee_shares) = match &fee_recipient {
+            Some(_) => (net_amount, crate::fees::fee_shares(shares, perf_fee, gross_amount)?),
+            None => (gross_amount, 0),
+        };
 
-    /// Trigger staking based on configured rules (only stake actions)
-    /// Can be called by anyone, but only executes if stake rules are met
-    pub fn trigger_stake(env: Env) -> Result<(), VaultError> {
-        // Check vault is initialized
-        if !env.storage().instance().has(&CONFIG) {
-            return Err(VaultError::NotInitialized);
+        let mut balances: Vec<i128> = Vec::new(&env);
+        let mut liquid_value: i128 = 0;
+        for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+            let balance = crate::token_client::get_vault_balance(&env, &asset);
+            liquid_value = liquid_value.checked_add(crate::valuation::basket_value(&env, &asset, balance)?)
+                .ok_or(VaultError::InvalidAmount)?;
+            balances.push_back(balance);
+        }
+        if liquid_value < payout {
+            return Err(VaultError::InsufficientLiquidity);
         }
 
-        // Check if staking should occur based on rules
-        if !crate::engine::should_stake(&env) {
-            return Ok(()); // No staking needed
+        // Each asset pays payout / liquid_value of its balance; the owner's fee comes off the top
+        let mut payouts: Vec<(Address, i128)> = Vec::new(&env);
+        for i in 0..config.assets.len() {
+            let asset = config.assets.get(i).ok_or(VaultError::InvalidConfiguration)?;
+            let amount = balances.get(i).unwrap_or(0)
+                .checked_mul(payout)
+                .and_then(|v| v.checked_div(liquid_value))
+                .ok_or(VaultError::InvalidAmount)?;
+            let paid = amount.checked_mul(net_amount)
+                .and_then(|v| v.checked_div(payout))
+                .ok_or(VaultError::InvalidAmount)?;
+            if paid > 0 {
+                crate::token_client::transfer_from_vault(&env, &asset, &user, paid)?;
+            }
+            if amount > paid {
+                crate::token_client::transfer_from_vault(&env, &asset, &config.owner, amount - paid)?;
+            }
+            payouts.push_back((asset, paid));
         }
 
-        let _config: VaultConfig = env.storage().instance().get(&CONFIG)
-            .ok_or(VaultError::NotInitialized)?;
-        
-        let mut state: VaultState = env.storage().instance().get(&STATE)
-            .ok_or(VaultError::NotInitialized)?;
+        state.total_shares = state.total_shares.checked_sub(shares)
+            .and_then(|v| v.checked_add(fee_shares))
+            .ok_or(VaultError::InvalidAmount)?;
+        state.total_value = state.total_value.checked_sub(payout)
+            .ok_or(VaultError::InvalidAmount)?;
 
-        // Execute only stake actions
-        crate::rebalance::execute_stake_only(&env)?;
+        position.shares -= shares;
+        Self::sweep_dust(&env, &user, &config.owner, &mut state, &mut position)?;
+        crate::migrations::write_state(&env, &state);
+        crate::flows::record_withdrawal(&env, payout);
+        if position.shares == 0 {
+            Self::remove_position(&env, &user);
+            crate::fees::clear_entry(&env, &user);
+        } else {
+            Self::store_position(&env, &user, &position);
+        }
 
-        // Update last rebalance timestamp
-        state.last_rebalance = env.ledger().timestamp();
-        env.storage().instance().set(&STATE, &state);
+        if let (Some(recipient), true) = (fee_recipient, fee_shares > 0) {
+            Self::credit_fee_shares(&env, &state, &recipient, fee_shares, perf_fee)?;
+        }
+        crate::stats::report(&env, state.total_value);
 
-        // Emit stake event
-        env.events().publish((symbol_short!("staked"),), state.last_rebalance);
+        env.events().publish(
+            (Symbol::new(&env, "withdraw_in_kind"), user),
+            (shares, payouts.clone()),
+        );
 
-        Ok(())
+        Ok(payouts)
     }
 
-    /// Trigger liquidity provision based on configured rules (only liquidity actions)
-    /// Can be called by anyone, but only executes if liquidity rules are met
-    pub fn trigger_liquidity(env: Env) -> Result<(), VaultError> {
-        // Check vault is initialized
+    /// Queue a large withdrawal for this ledger's exit batch
+    /// The shares leave the position now; the batch settles on a later ledger
+    /// (through settle_exit_batch or the next request or claim) and every
+    /// participant realizes the same value per share. Returns the user's
+    /// queued shares.
+    pub fn request_withdraw(env: Env, user: Address, shares: i128) -> Result<i128, VaultError> {
+        user.require_auth();
+
         if !env.storage().instance().has(&CONFIG) {
             return Err(VaultError::NotInitialized);
         }
 
-        // Check if liquidity provision should occur based on rules
-        if !crate::engine::should_provide_liquidity(&env) {
-            return Ok(()); // No liquidity provision needed
+        crate::pause::when_not_emergency(&env)?;
+        crate::wind_down::when_open(&env)?;
+
+        if crate::exit_batch::batch_threshold(&env).is_none() {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        if shares <= 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        crate::exit_batch::settle_due(&env)?;
+
+        let mut position = Self::get_position(env.clone(), user.clone());
+        if position.shares < shares {
+            return Err(VaultError::InsufficientShares);
+        }
+
+        // The entry price stays until settlement, which charges the performance fee
+        position.shares -= shares;
+        if position.shares == 0 {
+            Self::remove_position(&env, &user);
+        } else {
+            Self::store_position(&env, &user, &position);
+        }
+
+        let queued = crate::exit_batch::queue(&env, &user, shares)?;
+        crate::stats::report(&env, Self::get_state(env.clone()).total_value);
+
+        env.events().publish(
+            (Symbol::new(&env, "exit_queued"), user),
+            (shares, env.ledger().sequence()),
+        );
+
+        Ok(queued)
+    }
+
+    /// Settle an exit batch whose ledger has closed (callable by anyone, e.g. a keeper)
+    /// Returns the base asset realized for it, 0 when nothing was due.
+    pub fn settle_exit_batch(env: Env) -> Result<i128, VaultError> {
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+
+        crate::pause::when_not_emergency(&env)?;
+
+        crate::exit_batch::settle_due(&env)
+    }
+
+    /// Pay out the user's settled exits, settling a due batch first
+    /// In emergency mode batches aren't settled, but what already was is paid.
+    pub fn claim_exit(env: Env, user: Address) -> Result<i128, VaultError> {
+        user.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if !crate::pause::emergency_mode(&env) {
+            crate::exit_batch::settle_due(&env)?;
+        }
+
+        let amount = crate::exit_batch::take_claim(&env, &user)?;
+        if amount > 0 {
+            let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+            crate::token_client::transfer_from_vault(&env, &base_token, &user, amount)?;
+            env.events().publish((Symbol::new(&env, "exit_claimed"), user), amount);
+        }
+
+        Ok(amount)
+    }
+
+    /// Shares the user has queued in the open exit batch
+    pub fn get_pending_exit(env: Env, user: Address) -> i128 {
+        crate::exit_batch::pending_shares(&env, &user)
+    }
+
+    /// Settled base asset the user can claim
+    pub fn get_claimable_exit(env: Env, user: Address) -> i128 {
+        crate::exit_batch::claimable(&env, &user)
+    }
+
+    /// Exit in emergency mode with a proportional share of what the vault holds
+    /// Pays shares / total_shares of the vault's balance of every configured
+    /// asset, with no swaps, unwinding or fees. Value still in staking, LP or
+    /// the buffer pool isn't paid; the owner can bring it back first with
+    /// withdraw_staking, withdraw_liquidity and unwind_buffer. Returns the
+    /// amount paid of each asset.
+    pub fn emergency_withdraw(env: Env, user: Address, shares: i128) -> Result<Vec<(Address, i128)>, VaultError> {
+        user.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if !crate::pause::emergency_mode(&env) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        if shares <= 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        let mut position = Self::get_position(env.clone(), user.clone());
+        if position.shares < shares {
+            return Err(VaultError::InsufficientShares);
+        }
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        if state.total_shares == 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        let mut payouts: Vec<(Address, i128)> = Vec::new(&env);
+        for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+            let amount = crate::token_client::get_vault_balance(&env, &asset)
+                .checked_mul(shares)
+                .and_then(|v| v.checked_div(state.total_shares))
+                .ok_or(VaultError::InvalidAmount)?;
+            if amount > 0 {
+                crate::token_client::transfer_from_vault(&env, &asset, &user, amount)?;
+            }
+            payouts.push_back((asset, amount));
+        }
+
+        // The same fraction of the book value leaves with them
+        let book_value = state.total_value.checked_mul(shares)
+            .and_then(|v| v.checked_div(state.total_shares))
+            .ok_or(VaultError::InvalidAmount)?;
+        state.total_shares -= shares;
+        state.total_value -= book_value;
+        crate::migrations::write_state(&env, &state);
+        crate::flows::record_withdrawal(&env, book_value);
+
+        position.shares -= shares;
+        if position.shares == 0 {
+            Self::remove_position(&env, &user);
+            crate::fees::clear_entry(&env, &user);
+        } else {
+            Self::store_position(&env, &user, &position);
+        }
+        crate::stats::report(&env, state.total_value);
+
+        env.events().publish(
+            (Symbol::new(&env, "emergency_withdraw"), user),
+            (shares, payouts.clone()),
+        );
+
+        Ok(payouts)
+    }
+
+    /// Name (or clear) the guardian, who can enter emergency mode (owner only)
+    pub fn set_guardian(env: Env, caller: Address, guardian: Option<Address>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::pause::set_guardian(&env, guardian);
+        Ok(())
+    }
+
+    /// Get the guardian, if any
+    pub fn get_guardian(env: Env) -> Option<Address> {
+        crate::pause::guardian(&env)
+    }
+
+    /// Enter emergency mode (guardian or owner)
+    /// Deposits, strategy runs and regular withdrawals are refused with
+    /// EmergencyMode; users exit through emergency_withdraw.
+    pub fn enter_emergency_mode(env: Env, caller: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner && Some(caller.clone()) != crate::pause::guardian(&env) {
+            return Err(VaultError::Unauthorized);
+        }
+        // A closed vault's balance is owed at the frozen price
+        crate::wind_down::when_open(&env)?;
+
+        crate::pause::set_emergency_mode(&env, true);
+        env.events().publish((Symbol::new(&env, "emergency_mode"), caller), true);
+        Ok(())
+    }
+
+    /// Leave emergency mode (owner only)
+    pub fn exit_emergency_mode(env: Env, caller: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::pause::set_emergency_mode(&env, false);
+        env.events().publish((Symbol::new(&env, "emergency_mode"), caller), false);
+        Ok(())
+    }
+
+    /// Get whether the vault is in emergency mode
+    pub fn is_emergency_mode(env: Env) -> bool {
+        crate::pause::emergency_mode(&env)
+    }
+
+    /// Close the vault at a frozen redemption price (owner only)
+    /// Unstakes, removes liquidity and redeems the buffer pool, then prices
+    /// every share at the base asset held over the shares outstanding. Other
+    /// assets aren't sold and don't count, so rebalance into the base asset
+    /// first. The vault stays paused from then on, and withdraw pays every
+    /// share exactly that price. Fails with InvalidConfiguration while an exit
+    /// batch is open, and Closed if already wound down. Returns the terms.
+    pub fn initiate_wind_down(env: Env, caller: Address) -> Result<WindDown, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        crate::pause::when_not_emergency(&env)?;
+        crate::wind_down::when_open(&env)?;
+        if crate::exit_batch::pending_batch(&env).is_some() {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        if Self::has_staking_position(env.clone()) {
+            crate::rebalance::withdraw_staking(&env)?;
+        }
+        let lp_key = soroban_sdk::String::from_str(&env, "lp_position");
+        if let Some(position) = env.storage().instance().get::<_, crate::types::LiquidityPosition>(&lp_key) {
+            crate::rebalance::recover_liquidity(&env, position.lp_tokens)?;
+        }
+        crate::buffer_pool::redeem_all(&env)?;
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        let terms = crate::wind_down::freeze(&env, &base_token, &mut state)?;
+        crate::migrations::write_state(&env, &state);
+        crate::pause::set_paused(&env, true);
+        crate::stats::report(&env, state.total_value);
+
+        env.events().publish(
+            (Symbol::new(&env, "wind_down"), caller),
+            (terms.price, terms.frozen_shares),
+        );
+
+        Ok(terms)
+    }
+
+    /// Set the true-up of a closed vault (owner only, once)
+    /// Whatever base asset the vault holds beyond what outstanding shares
+    /// redeem for is split equally across every share frozen. Returns that
+    /// surplus.
+    pub fn finalize_true_up(env: Env, caller: Address) -> Result<i128, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        let state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        let surplus = crate::wind_down::finalize(&env, &base_token, &state)?;
+
+        env.events().publish((Symbol::new(&env, "true_up"), caller), surplus);
+        Ok(surplus)
+    }
+
+    /// Claim the true-up on every share `user` held when the vault closed
+    /// Shares redeemed since count as well as those still held. Fails with
+    /// InvalidConfiguration before the true-up is finalized and
+    /// InsufficientShares when there is nothing left to claim. Returns the
+    /// amount paid.
+    pub fn claim_true_up(env: Env, user: Address) -> Result<i128, VaultError> {
+        user.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let held = Self::get_position(env.clone(), user.clone()).shares;
+        let amount = crate::wind_down::take_true_up(&env, &user, held)?;
+        if amount > 0 {
+            let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+            crate::token_client::transfer_from_vault(&env, &base_token, &user, amount)?;
+        }
+
+        env.events().publish((Symbol::new(&env, "true_up_claim"), user), amount);
+        Ok(amount)
+    }
+
+    /// Get the frozen redemption terms, once the vault is wound down
+    pub fn get_wind_down(env: Env) -> Option<WindDown> {
+        crate::wind_down::terms(&env)
+    }
+
+    /// Withdraw the user's whole position, dust included
+    pub fn withdraw_all(env: Env, user: Address) -> Result<WithdrawReceipt, VaultError> {
+        let shares = Self::get_position(env.clone(), user.clone()).shares;
+        Self::withdraw(env, user, shares)
+    }
+
+    /// Withdraw and return only the net amount paid out
+    /// Compatibility entry point for integrators built against the old withdraw signature
+    pub fn withdraw_net(env: Env, user: Address, shares: i128) -> Result<i128, VaultError> {
+        Ok(Self::withdraw(env, user, shares)?.net_amount)
+    }
+
+    /// Get vault state
+    pub fn get_state(env: Env) -> VaultState {
+        crate::migrations::read_state(&env)
+            .unwrap_or(VaultState {
+                total_shares: 0,
+                total_value: 0,
+                last_rebalance: 0,
+            })
+    }
+
+    /// Get user position
+    pub fn get_position(env: Env, user: Address) -> UserPosition {
+        crate::migrations::read_position(&env, &user)
+            .unwrap_or(UserPosition {
+                shares: 0,
+                last_deposit: 0,
+            })
+    }
+
+    /// Get the share price a user entered at, scaled by 1_0000000
+    /// Share-weighted across top-ups; 0 if the user holds no shares
+    pub fn get_user_entry_price(env: Env, user: Address) -> i128 {
+        crate::fees::get_entry_price(&env, &user)
+    }
+
+    /// Get the decimals vault shares are denominated in
+    /// SHARE_DECIMALS, or the base asset's own when it has more. Vaults that
+    /// took deposits before share scaling keep the base asset's decimals.
+    pub fn get_share_decimals(env: Env) -> Result<u32, VaultError> {
+        let base = Self::get_base_asset(env.clone())?;
+        let base_decimals = token::TokenClient::new(&env, &base).decimals();
+        let state = Self::get_state(env.clone());
+        if crate::fees::share_scale(&env) > 1 || state.total_shares == 0 {
+            Ok(base_decimals.max(crate::fees::SHARE_DECIMALS))
+        } else {
+            Ok(base_decimals)
+        }
+    }
+
+    /// Estimated DEX fees paid on the vault's swaps so far
+    /// 0.3% of each swap's input, summed in raw token units like total_value.
+    /// Useful for judging whether rebalancing is too aggressive.
+    pub fn total_swap_fees_paid(env: Env) -> i128 {
+        crate::fees::total_swap_fees_paid(&env)
+    }
+
+    /// Lifetime gross deposits and withdrawals as (deposited, withdrawn)
+    /// Base asset in raw units like total_value; withdrawals count fees and
+    /// batched exits at what they realized. Net PnL is total_value + withdrawn - deposited.
+    pub fn flow_stats(env: Env) -> (i128, i128) {
+        crate::flows::flow_stats(&env)
+    }
+
+    /// Get the performance fee rate in basis points
+    pub fn get_performance_fee(env: Env) -> i128 {
+        crate::fees::get_performance_fee_bps(&env)
+    }
+
+    /// Set the performance fee rate in basis points (owner only)
+    /// Charged at withdrawal on each user's gain over their own entry price
+    /// and paid to the owner in the base asset, or minted as shares to the
+    /// fee recipient when one is set.
+    pub fn set_performance_fee(env: Env, caller: Address, fee_bps: i128) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::fees::set_performance_fee_bps(&env, fee_bps)
+    }
+
+    /// Get the fees the vault charges, in basis points, and who receives them
+    /// Returns (management, performance, deposit, recipient). The vault only
+    /// charges a performance fee today, so the other two are always 0; the
+    /// recipient is the fee recipient when set and the owner otherwise.
+    pub fn fee_config(env: Env) -> Result<(i128, i128, i128, Address), VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let recipient = crate::fees::get_performance_fee_recipient(&env).unwrap_or(config.owner);
+        Ok((0, crate::fees::get_performance_fee_bps(&env), 0, recipient))
+    }
+
+    /// Get the address performance fees are minted to as shares, if any
+    pub fn get_performance_fee_recipient(env: Env) -> Option<Address> {
+        crate::fees::get_performance_fee_recipient(&env)
+    }
+
+    /// Mint performance fees as shares to `recipient` instead of paying the
+    /// owner in the base asset (owner only); None restores the payout
+    pub fn set_performance_fee_recipient(
+        env: Env,
+        caller: Address,
+        recipient: Option<Address>,
+    ) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::fees::set_performance_fee_recipient(&env, recipient);
+        Ok(())
+    }
+
+    /// Point the vault at the NFT contract it shares profits through (owner only)
+    pub fn set_nft_contract(env: Env, caller: Address, nft_contract: Option<Address>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::profit_share::set_nft_contract(&env, nft_contract);
+
+        Ok(())
+    }
+
+    /// Profit sharing configuration, for factories and health checks to verify
+    pub fn get_profit_share(env: Env) -> Result<ProfitShareStatus, VaultError> {
+        let state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let nft_contract = crate::profit_share::get_nft_contract(&env);
+        let profit_share_bps = match &nft_contract {
+            Some(nft_contract) => crate::profit_share::profit_share_bps(&env, nft_contract),
+            None => 0,
+        };
+        let realized_gain = crate::profit_share::realized_gain(&env, &state)?;
+
+        Ok(ProfitShareStatus {
+            nft_contract,
+            profit_share_bps,
+            high_water_mark: crate::profit_share::high_water_mark(&env),
+            realized_gain,
+            minimum_distribution: crate::profit_share::minimum_distribution(realized_gain, profit_share_bps)?,
+        })
+    }
+
+    /// Pay `amount` of realized gains to NFT holders (owner only)
+    /// Gains are the share price rise over the high-water mark. The amount must
+    /// cover the profit share registered on the NFT contract and can't exceed
+    /// the gain itself. Afterwards the high-water mark moves to the new price.
+    /// `profit_source` (trading, staking or lp_fees) is carried in the event.
+    pub fn distribute_profits(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        profit_source: Symbol,
+    ) -> Result<i128, VaultError> {
+        caller.require_auth();
+
+        if !crate::profit_share::is_known_source(&profit_source) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        let nft_contract = crate::profit_share::get_nft_contract(&env)
+            .ok_or(VaultError::InvalidConfiguration)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let gain = crate::profit_share::realized_gain(&env, &state)?;
+        let share_bps = crate::profit_share::profit_share_bps(&env, &nft_contract);
+        let minimum = crate::profit_share::minimum_distribution(gain, share_bps)?;
+
+        if amount < minimum {
+            return Err(VaultError::ProfitShareUnderpaid);
+        }
+        if amount <= 0 || amount > gain {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        // Fund the NFT contract first; it checks its balance covers what it records
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+        crate::buffer_pool::ensure_liquid(&env, &base_token, amount)?;
+        crate::token_client::transfer_from_vault(&env, &base_token, &nft_contract, amount)?;
+
+        crate::profit_share::ProfitShareNFTClient::new(&env, &nft_contract)
+            .try_distribute_profits(&env.current_contract_address(), &env.current_contract_address(), &amount, &base_token)
+            .map_err(|err| match err {
+                Ok(nft_err) => VaultError::from(nft_err),
+                Err(_) => VaultError::TransferFailed,
+            })?
+            .map_err(|_| VaultError::TransferFailed)?;
+
+        // The payout leaves the vault; gains are measured from here on
+        state.total_value = state.total_value.checked_sub(amount)
+            .ok_or(VaultError::InvalidAmount)?;
+        crate::migrations::write_state(&env, &state);
+        crate::profit_share::set_high_water_mark(&env, crate::fees::share_price(&env, &state)?);
+
+        env.events().publish(
+            (Symbol::new(&env, "profit_distributed"), nft_contract),
+            (amount, gain, minimum, profit_source),
+        );
+
+        Ok(amount)
+    }
+
+    /// Re-read total_value from what the vault actually holds (owner only)
+    /// Counts every configured asset held directly, staked or provided as
+    /// liquidity, each priced with valuation::value_asset, plus the base asset
+    /// parked in the buffer pool.
+    pub fn sync_total_value(env: Env, caller: Address) -> Result<i128, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        let mut total_value = crate::buffer_pool::parked_balance(&env);
+        for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+            total_value = total_value.checked_add(crate::valuation::held_value(&env, &asset)?)
+                .ok_or(VaultError::InvalidAmount)?;
+        }
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        state.total_value = total_value;
+        crate::migrations::write_state(&env, &state);
+
+        env.events().publish((symbol_short!("synced"),), total_value);
+        crate::stats::report(&env, total_value);
+
+        // Flag any asset sitting above its concentration cap
+        for (asset, current_bps, cap_bps) in crate::rebalance::allocation_breaches(&env, &config.assets)?.iter() {
+            crate::events::emit_allocation_breach(&env, &asset, current_bps, cap_bps);
+        }
+
+        Ok(total_value)
+    }
+
+    /// Rewrite storage written by an older version of the vault (owner only)
+    /// Run once after upgrading the contract code. A no-op when storage is
+    /// already current. Returns the storage version now in place.
+    pub fn migrate_storage(env: Env, caller: Address) -> Result<u32, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        let from = crate::migrations::migrate(&env)?;
+        if from != crate::migrations::STORAGE_VERSION {
+            env.events().publish(
+                (Symbol::new(&env, "storage_migrated"),),
+                (from, crate::migrations::STORAGE_VERSION),
+            );
+        }
+
+        Ok(crate::migrations::STORAGE_VERSION)
+    }
+
+    /// Version of the contract interface, for clients built against generated types
+    pub fn get_contract_version(_env: Env) -> u32 {
+        CONTRACT_VERSION
+    }
+
+    /// Layout version of the vault's storage
+    pub fn get_storage_version(env: Env) -> u32 {
+        crate::migrations::stored_version(&env)
+    }
+
+    /// Get the asset deposits are denominated in and withdrawals pay out
+    pub fn get_base_asset(env: Env) -> Result<Address, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        crate::token_client::base_asset(&env, &config.assets)
+    }
+
+    /// Check whether the vault's base asset is native XLM (via its SAC)
+    pub fn base_asset_is_native(env: Env) -> Result<bool, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+
+        Ok(crate::token_client::is_native_asset(&env, &base_token))
+    }
+
+    /// Allowances the vault currently grants over its tokens
+    /// Built from every spender approved via approve_router, checked against the
+    /// live token allowance; spent or expired entries are dropped from the registry.
+    pub fn get_outstanding_allowances(env: Env) -> Vec<AllowanceInfo> {
+        crate::token_client::outstanding_allowances(&env)
+    }
+
+    /// Revoke every outstanding allowance over the vault's tokens (owner only)
+    /// Returns the number of allowances revoked
+    pub fn revoke_all_allowances(env: Env, caller: Address) -> Result<u32, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        let revoked = crate::token_client::revoke_all_allowances(&env);
+        env.events().publish((symbol_short!("revoked"),), revoked);
+
+        Ok(revoked)
+    }
+
+    /// Get vault configuration
+    pub fn get_config(env: Env) -> Result<VaultConfig, VaultError> {
+        crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)
+    }
+
+    /// Set router address for swaps (owner only)
+    pub fn set_router(env: Env, router: Address) -> Result<(), VaultError> {
+        // Check vault is initialized
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+
+        // Get config and verify owner
+        let mut config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        
+        config.owner.require_auth();
+        
+        crate::network::check_integration(&env, &router)?;
+        
+        // Update router address
+        config.router_address = Some(router);
+        
+        // Store updated config
+        crate::migrations::write_config(&env, &config);
+        
+        Ok(())
+    }
+
+    /// Choose which swap venues may be used and in what order (owner only)
+    /// An empty list restores the default: the direct pool, falling back to the
+    /// router. Listing a venue twice is rejected.
+    pub fn set_venue_preference(env: Env, caller: Address, venues: Vec<Venue>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        for (i, venue) in venues.iter().enumerate() {
+            if venues.first_index_of(venue) != Some(i as u32) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        crate::swap_router::set_venue_preference(&env, &venues);
+
+        Ok(())
+    }
+
+    /// Get the swap venue preference (empty when the default order applies)
+    pub fn get_venue_preference(env: Env) -> Vec<Venue> {
+        crate::swap_router::venue_preference(&env)
+    }
+
+    /// Set (or clear) the SEP-40 price oracle and how old its quotes may be (owner only)
+    /// Quotes older than `max_age_secs` are treated as stale and skipped.
+    pub fn set_price_oracle(env: Env, caller: Address, oracle: Option<Address>, max_age_secs: u64) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if oracle.is_some() && max_age_secs == 0 {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        crate::pricing::set_oracle_config(&env, oracle.map(|oracle| (oracle, max_age_secs)));
+
+        Ok(())
+    }
+
+    /// Get the price oracle and its staleness limit in seconds, if configured
+    pub fn get_price_oracle(env: Env) -> Option<(Address, u64)> {
+        crate::pricing::oracle_config(&env)
+    }
+
+    /// Set (or clear) the guarded valuation bound in basis points (owner only)
+    /// While set, a spot price further than this from the oracle price (or the
+    /// last price accepted) is replaced by the last good price.
+    pub fn set_price_guard(env: Env, caller: Address, max_deviation_bps: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if let Some(bps) = max_deviation_bps {
+            if bps <= 0 || bps > 10000 {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        crate::pricing::set_price_guard_bps(&env, max_deviation_bps);
+
+        Ok(())
+    }
+
+    /// Get the guarded valuation bound in basis points, if set
+    pub fn get_price_guard(env: Env) -> Option<i128> {
+        crate::pricing::price_guard_bps(&env)
+    }
+
+    /// Set the order price sources are tried in (owner only)
+    /// Sources left out are never used; each may appear once.
+    pub fn set_price_source_priority(env: Env, caller: Address, sources: Vec<PriceSource>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if sources.is_empty() {
+            return Err(VaultError::InvalidConfiguration);
+        }
+        for (i, source) in sources.iter().enumerate() {
+            if sources.first_index_of(source) != Some(i as u32) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        crate::pricing::set_price_source_priority(&env, &sources);
+
+        Ok(())
+    }
+
+    /// Get the order price sources are tried in
+    pub fn get_price_source_priority(env: Env) -> Vec<PriceSource> {
+        crate::pricing::price_source_priority(&env)
+    }
+
+    /// Get the price of `token` in base asset units, scaled by 1_0000000
+    /// Uses the first price source that has a usable quote
+    pub fn get_price(env: Env, token: Address) -> Result<i128, VaultError> {
+        crate::pricing::get_price(&env, &token)
+    }
+
+    /// Set the staking pool address for liquid staking (e.g., stXLM)
+    pub fn set_staking_pool(env: Env, caller: Address, staking_pool: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+        
+        let mut config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        
+        // Only owner can update staking pool
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        
+        crate::network::check_integration(&env, &staking_pool)?;
+        
+        config.staking_pool_address = Some(staking_pool);
+        
+        // Store updated config
+        crate::migrations::write_config(&env, &config);
+        
+        Ok(())
+    }
+
+    /// Set the factory address for finding liquidity pools
+    pub fn set_factory(env: Env, caller: Address, factory: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+        
+        let mut config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        
+        // Only owner can update factory
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        
+        crate::network::check_integration(&env, &factory)?;
+        
+        config.factory_address = Some(factory);
+        
+        // Store updated config
+        crate::migrations::write_config(&env, &config);
+        
+        Ok(())
+    }
+
+    /// Pin a flat slippage percent for liquidity provision (owner only)
+    /// None (the default) derives minimum amounts from the pool's reserves instead
+    pub fn set_lp_slippage(env: Env, caller: Address, slippage_percent: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if let Some(percent) = slippage_percent {
+            if !(0..=100).contains(&percent) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        env.storage().instance().set(&LP_SLIPPAGE, &slippage_percent);
+
+        Ok(())
+    }
+
+    /// Get the flat liquidity slippage override, if any
+    pub fn get_lp_slippage(env: Env) -> Option<i128> {
+        env.storage().instance().get(&LP_SLIPPAGE).unwrap_or(None)
+    }
+
+    /// Cap the value a whole rebalance may lose, in basis points (owner only)
+    /// Checked against real balances before and after all swaps; a rebalance
+    /// that loses more is reverted. None (the default) disables the check.
+    pub fn set_max_rebalance_loss(env: Env, caller: Address, max_loss_bps: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if let Some(bps) = max_loss_bps {
+            if !(0..=10000).contains(&bps) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        env.storage().instance().set(&MAX_REBALANCE_LOSS, &max_loss_bps);
+
+        Ok(())
+    }
+
+    /// Get the whole-rebalance loss cap in basis points, if any
+    pub fn get_max_rebalance_loss(env: Env) -> Option<i128> {
+        env.storage().instance().get(&MAX_REBALANCE_LOSS).unwrap_or(None)
+    }
+
+    /// Set the diagnostic event log level (owner only)
+    /// 0 = quiet, 1 = info (default), 2 = debug
+    pub fn set_log_level(env: Env, caller: Address, level: u32) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if level > crate::events::LOG_DEBUG {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        env.storage().instance().set(&LOG_LEVEL, &level);
+
+        Ok(())
+    }
+
+    /// Get the diagnostic event log level
+    pub fn get_log_level(env: Env) -> u32 {
+        crate::events::log_level(&env)
+    }
+
+    /// Choose which event categories are emitted (owner only)
+    /// `flags` is a bitmask: 1 deposits, 2 withdrawals, 4 rebalances (including
+    /// stake, unstake and liquidity runs), 8 swaps, 16 debug, 32 share transfers. Fee, profit-share
+    /// and admin events are always emitted.
+    pub fn set_event_flags(env: Env, caller: Address, flags: u32) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if flags & !EVENTS_ALL != 0 {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        env.storage().instance().set(&EVENT_FLAGS, &flags);
+
+        Ok(())
+    }
+
+    /// Get the enabled event categories as a bitmask
+    pub fn get_event_flags(env: Env) -> u32 {
+        crate::events::event_flags(&env)
+    }
+
+    /// Whether every event category is enabled
+    pub fn get_all_events_enabled(env: Env) -> bool {
+        crate::events::event_flags(&env) == EVENTS_ALL
+    }
+
+    /// Limit how long keepers may go without calling a trigger (owner only)
+    /// Past `max_silence_secs` every deposit emits keeper_stale, and with
+    /// `block_deposits` it is refused with KeeperStale until a trigger call or
+    /// override_keeper_silence. None disables the check. Restarts the clock.
+    pub fn set_keeper_silence(env: Env, caller: Address, max_silence_secs: Option<u64>, block_deposits: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if max_silence_secs == Some(0) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        crate::keeper::set_silence_config(&env, max_silence_secs, block_deposits);
+
+        Ok(())
+    }
+
+    /// Get the keeper silence limit and whether stale vaults refuse deposits
+    pub fn get_keeper_silence(env: Env) -> (Option<u64>, bool) {
+        crate::keeper::silence_config(&env)
+    }
+
+    /// Timestamp of the last trigger call (or owner override)
+    pub fn get_keeper_heartbeat(env: Env) -> u64 {
+        crate::keeper::last_heartbeat(&env)
+    }
+
+    /// Accept deposits again while keepers are down (owner only)
+    /// Counts as a heartbeat, so the silence limit runs again from now.
+    pub fn override_keeper_silence(env: Env, caller: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::keeper::heartbeat(&env);
+
+        Ok(())
+    }
+
+    /// Choose whether rebalance sizing counts staked and LP positions (owner only)
+    /// true (the default): targets are sized against liquid + staked + LP holdings,
+    /// with positions attributed to their underlying asset.
+    /// false: positions are left out and only liquid balances are redistributed.
+    pub fn set_rebalance_includes_positions(env: Env, caller: Address, include: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        env.storage().instance().set(&REBALANCE_POSITIONS, &include);
+
+        Ok(())
+    }
+
+    /// Set whether withdrawals unstake and pull liquidity when short of liquid base asset (owner only)
+    /// Only the shortfall is unwound. Off by default, so such withdrawals fail
+    /// with InsufficientLiquidity.
+    pub fn set_auto_unwind_on_withdraw(env: Env, caller: Address, enabled: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        env.storage().instance().set(&AUTO_UNWIND, &enabled);
+
+        Ok(())
+    }
+
+    /// Get whether withdrawals unwind positions to cover a shortfall
+    pub fn get_auto_unwind_on_withdraw(env: Env) -> bool {
+        crate::rebalance::auto_unwind_on_withdraw(&env)
+    }
+
+    /// Set how far a rebalance rule's target_allocation may sum from 100% (owner only)
+    /// In allocation units (100_0000 = 100%), at most 1_0000 (1%). Sums within
+    /// it are normalized; the default of 100 (0.01%) absorbs rounding.
+    pub fn set_allocation_epsilon(env: Env, caller: Address, epsilon: i128) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if !(0..=1_0000).contains(&epsilon) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        env.storage().instance().set(&ALLOCATION_EPSILON, &epsilon);
+
+        Ok(())
+    }
+
+    /// Get the tolerance around 100% for target_allocation sums
+    pub fn get_allocation_epsilon(env: Env) -> i128 {
+        crate::rebalance::allocation_epsilon(&env)
+    }
+
+    /// Get whether rebalance sizing counts staked and LP positions
+    pub fn get_rebalance_includes_positions(env: Env) -> bool {
+        crate::rebalance::rebalance_includes_positions(&env)
+    }
+
+    /// Set the smallest amount a stake rule will stake, in base asset units (owner only)
+    /// Smaller computed stakes are skipped with a stake_skip event.
+    pub fn set_min_stake_amount(env: Env, caller: Address, amount: i128) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if amount < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        env.storage().instance().set(&MIN_STAKE, &amount);
+
+        Ok(())
+    }
+
+    /// Get the smallest amount a stake rule will stake
+    pub fn get_min_stake_amount(env: Env) -> i128 {
+        crate::rebalance::min_stake_amount(&env)
+    }
+
+    /// Cap the staking pool's position at `max_bps` of total_value (owner only)
+    /// Stake rules that would go past it stake only up to the cap, with a
+    /// stake_capped event. None (the default) leaves staking uncapped.
+    pub fn set_max_stake_per_pool_bps(env: Env, caller: Address, max_bps: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if let Some(bps) = max_bps {
+            if !(0..=10000).contains(&bps) {
+                return Err(VaultError::InvalidConfiguration);
+            }
+        }
+
+        env.storage().instance().set(&MAX_STAKE_PER_POOL, &max_bps);
+
+        Ok(())
+    }
+
+    /// Get the per-pool staking cap in basis points, if any
+    pub fn get_max_stake_per_pool_bps(env: Env) -> Option<i128> {
+        crate::rebalance::max_stake_per_pool_bps(&env)
+    }
+
+    /// Set the share balance at or below which a withdrawal's remainder is swept (owner only)
+    /// Swept dust is credited to the owner with `to_owner`, otherwise burned so
+    /// its value stays with the remaining holders. 0 turns sweeping off.
+    pub fn set_dust_threshold(env: Env, caller: Address, shares: i128, to_owner: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if shares < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        env.storage().instance().set(&DUST_THRESHOLD, &shares);
+        env.storage().instance().set(&DUST_TO_OWNER, &to_owner);
+
+        Ok(())
+    }
+
+    /// Get the dust threshold in shares and whether dust is credited to the owner
+    pub fn get_dust_threshold(env: Env) -> (i128, bool) {
+        (
+            env.storage().instance().get(&DUST_THRESHOLD).unwrap_or(0),
+            env.storage().instance().get(&DUST_TO_OWNER).unwrap_or(false),
+        )
+    }
+
+    /// Batch withdrawals worth at least `min_amount` of the base asset (owner only)
+    /// Those must go through request_withdraw; smaller ones are paid at once.
+    /// None turns batching off.
+    pub fn set_exit_batching(env: Env, caller: Address, min_amount: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if min_amount.is_some_and(|amount| amount < 0) {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        crate::exit_batch::set_batch_threshold(&env, min_amount);
+
+        Ok(())
+    }
+
+    /// Get the smallest batched withdrawal, if batching is on
+    pub fn get_exit_batching(env: Env) -> Option<i128> {
+        crate::exit_batch::batch_threshold(&env)
+    }
+
+    /// Cap an asset's share of the vault in basis points (owner only)
+    /// The rebalance planner never buys an asset past its cap. Deposits in a
+    /// non-base token are always swapped to the base asset, so they can't push
+    /// that token over its cap. None removes the cap.
+    pub fn set_max_allocation_bps(env: Env, caller: Address, asset: Address, max_bps: Option<i128>) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if !config.assets.contains(&asset) {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        let mut caps = crate::rebalance::get_allocation_caps(&env);
+        match max_bps {
+            Some(bps) => {
+                if !(0..=10000).contains(&bps) {
+                    return Err(VaultError::InvalidConfiguration);
+                }
+                caps.set(asset, bps);
+            }
+            None => {
+                caps.remove(asset);
+            }
+        }
+        env.storage().instance().set(&ALLOCATION_CAPS, &caps);
+
+        Ok(())
+    }
+
+    /// Get the per-asset allocation caps in basis points
+    pub fn get_max_allocation_bps(env: Env) -> Map<Address, i128> {
+        crate::rebalance::get_allocation_caps(&env)
+    }
+
+    /// Health check: assets currently above their allocation cap
+    /// Returns (asset, current_bps, cap_bps) for each breach
+    pub fn check_allocation_caps(env: Env) -> Result<Vec<(Address, i128, i128)>, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        crate::rebalance::allocation_breaches(&env, &config.assets)
+    }
+
+    /// Configure the instant-redeem pool that idle base asset is parked in (owner only)
+    /// Base asset above `hot_threshold` is supplied to the pool after deposits and
+    /// on manage_buffer; withdrawals redeem from it on demand. Passing None
+    /// redeems everything parked and disables the buffer pool.
+    pub fn set_buffer_yield_pool(env: Env, caller: Address, pool: Option<Address>, hot_threshold: i128) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if hot_threshold < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+
+        // Never orphan funds in a pool we stop tracking
+        if let Some((current, _)) = crate::buffer_pool::get_buffer_config(&env) {
+            if pool.as_ref() != Some(&current) {
+                crate::buffer_pool::redeem_all(&env)?;
+            }
+        }
+
+        crate::buffer_pool::set_buffer_config(&env, pool, hot_threshold);
+
+        Ok(())
+    }
+
+    /// Get the buffer pool and hot threshold, if configured
+    pub fn get_buffer_yield_pool(env: Env) -> Option<(Address, i128)> {
+        crate::buffer_pool::get_buffer_config(&env)
+    }
+
+    /// Underlying base asset currently parked in the buffer pool
+    pub fn get_parked_buffer(env: Env) -> i128 {
+        crate::buffer_pool::parked_balance(&env)
+    }
+
+    /// Park idle base asset above the hot threshold (callable by anyone, e.g. a keeper)
+    pub fn manage_buffer(env: Env) -> Result<i128, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Whatever is parked is out of reach of emergency_withdraw
+        crate::pause::when_not_emergency(&env)?;
+
+        let base_token = crate::token_client::base_asset(&env, &config.assets)?;
+
+        crate::buffer_pool::park_idle(&env, &base_token)
+    }
+
+    /// Emergency unwind: redeem everything parked in the buffer pool (owner only)
+    pub fn unwind_buffer(env: Env, caller: Address) -> Result<i128, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::buffer_pool::redeem_all(&env)
+    }
+
+    /// Initialize on behalf of the deploying factory and record the network it serves
+    /// This is the only way a vault gets stamped, and `factory` must authorize
+    /// it, so a vault can't be stamped after the fact or for another factory.
+    /// The factory vets the config against its denylist before calling, as the
+    /// vault can't call back into it mid-deploy. On a "mainnet" vault every
+    /// later integration change is checked against that denylist.
+    pub fn initialize_from_factory(env: Env, factory: Address, network: Symbol, config: VaultConfig) -> Result<(), VaultError> {
+        factory.require_auth();
+
+        Self::initialize(env.clone(), config)?;
+        crate::network::set_stamp(&env, &factory, &network);
+
+        Ok(())
+    }
+
+    /// Health check: the vault's network tag and any integration its factory denylists
+    pub fn get_network_status(env: Env) -> Result<NetworkStatus, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let mut denied = Vec::new(&env);
+        for (kind, integration) in crate::network::integrations(&env, &config).iter() {
+            if crate::network::is_denied(&env, &integration) {
+                denied.push_back((kind, integration));
+            }
+        }
+
+        Ok(NetworkStatus {
+            network: crate::network::stamp(&env).map(|(_, network)| network),
+            denied,
+        })
+    }
+
+    /// Let a Syft vault factory act as co-admin for integration setters (owner only)
+    /// While allowed, `factory` may update the router, staking pool and Soroswap
+    /// factory through factory_set_integration. Pass allowed = false to revoke.
+    pub fn set_allow_factory_admin(env: Env, caller: Address, factory: Address, allowed: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        env.storage().instance().set(&FACTORY_ADMIN, &factory);
+        env.storage().instance().set(&ALLOW_FACTORY_ADMIN, &allowed);
+
+        Ok(())
+    }
+
+    /// Whether the trusted vault factory may update integration addresses
+    pub fn get_allow_factory_admin(env: Env) -> bool {
+        env.storage().instance().get(&ALLOW_FACTORY_ADMIN).unwrap_or(false)
+    }
+
+    /// Update an integration address on behalf of the trusted vault factory
+    /// kind: "router", "staking" or "factory" (Soroswap factory)
+    pub fn factory_set_integration(env: Env, factory: Address, kind: Symbol, address: Address) -> Result<(), VaultError> {
+        factory.require_auth();
+
+        let mut config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let trusted: Option<Address> = env.storage().instance().get(&FACTORY_ADMIN);
+        if trusted != Some(factory) || !Self::get_allow_factory_admin(env.clone()) {
+            return Err(VaultError::Unauthorized);
+        }
+
+        if kind == symbol_short!("router") {
+            config.router_address = Some(address);
+        } else if kind == symbol_short!("staking") {
+            config.staking_pool_address = Some(address);
+        } else if kind == symbol_short!("factory") {
+            config.factory_address = Some(address);
+        } else {
+            return Err(VaultError::InvalidConfiguration);
+        }
+
+        crate::migrations::write_config(&env, &config);
+
+        Ok(())
+    }
+
+    /// Halt deposits, rebalances, stakes and liquidity provision (owner only)
+    /// Withdrawals, unstaking and removing liquidity keep working.
+    pub fn pause(env: Env, caller: Address) -> Result<(), VaultError> {
+        Self::set_paused(env, caller, true)
+    }
+
+    /// Lift a pause (owner only)
+    pub fn unpause(env: Env, caller: Address) -> Result<(), VaultError> {
+        Self::set_paused(env, caller, false)
+    }
+
+    /// Get whether the vault is paused
+    pub fn paused(env: Env) -> bool {
+        crate::pause::paused(&env)
+    }
+
+    fn set_paused(env: Env, caller: Address, paused: bool) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        // A closed vault stays paused
+        if !paused {
+            crate::wind_down::when_open(&env)?;
         }
 
-        let _config: VaultConfig = env.storage().instance().get(&CONFIG)
+        crate::pause::set_paused(&env, paused);
+        let topic = if paused { symbol_short!("paused") } else { symbol_short!("unpaused") };
+        env.events().publish((topic,), caller);
+
+        Ok(())
+    }
+
+    /// Trigger a rebalance based on configured rules (only rebalance actions)
+    /// Can be called by anyone, but only executes if rebalance rules are met
+    /// Returns Skipped with the reason when nothing was done
+    pub fn trigger_rebalance(env: Env) -> Result<TriggerResult, VaultError> {
+        // Check vault is initialized
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+        crate::pause::when_not_emergency(&env)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
             .ok_or(VaultError::NotInitialized)?;
-        
-        let mut state: VaultState = env.storage().instance().get(&STATE)
+
+        // Any trigger call shows a keeper is alive, whether or not it executes
+        crate::keeper::heartbeat(&env);
+
+        // Check if rebalancing should occur based on rules
+        // NOTE: Anyone can call this, but it only rebalances if rules are satisfied
+        // This prevents griefing while allowing automated rebalancing
+        if let Some(reason) = Self::trigger_skip_reason(&env, &state, "rebalance") {
+            emit_trigger_skip(&env, symbol_short!("rebalance"), reason);
+            return Ok(TriggerResult::Skipped(reason));
+        }
+
+        // Execute only rebalance actions
+        crate::rebalance::execute_rebalance_only(&env)?;
+        crate::engine::record_ledger_rules(&env, Some("rebalance"));
+
+        // Update last rebalance timestamp
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
+
+        // Emit rebalance event
+        crate::events::emit_rebalance(&env, state.last_rebalance);
+
+        Ok(TriggerResult::Executed(TriggerReport {
+            timestamp: state.last_rebalance,
+            total_value: state.total_value,
+        }))
+    }
+
+    /// Trigger staking based on configured rules (only stake actions)
+    /// Can be called by anyone, but only executes if stake rules are met
+    /// Returns Skipped with the reason when nothing was done
+    pub fn trigger_stake(env: Env) -> Result<TriggerResult, VaultError> {
+        // Check vault is initialized
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+        crate::pause::when_not_emergency(&env)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Any trigger call shows a keeper is alive, whether or not it executes
+        crate::keeper::heartbeat(&env);
+
+        // Check if staking should occur based on rules
+        if let Some(reason) = Self::trigger_skip_reason(&env, &state, "stake") {
+            emit_trigger_skip(&env, symbol_short!("stake"), reason);
+            return Ok(TriggerResult::Skipped(reason));
+        }
+
+        // Execute only stake actions
+        crate::rebalance::execute_stake_only(&env)?;
+        crate::engine::record_ledger_rules(&env, Some("stake"));
+
+        // Update last rebalance timestamp
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
+
+        // Emit stake event
+        crate::events::publish(&env, EVENTS_REBALANCES, (symbol_short!("staked"),), state.last_rebalance);
+
+        Ok(TriggerResult::Executed(TriggerReport {
+            timestamp: state.last_rebalance,
+            total_value: state.total_value,
+        }))
+    }
+
+    /// Trigger unstaking based on configured rules (only unstake actions)
+    /// Can be called by anyone, but only executes if unstake rules are met
+    /// Returns Skipped with the reason when nothing was done
+    pub fn trigger_unstake(env: Env) -> Result<TriggerResult, VaultError> {
+        // Check vault is initialized
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Any trigger call shows a keeper is alive, whether or not it executes
+        crate::keeper::heartbeat(&env);
+
+        // Check if unstaking should occur based on rules
+        if let Some(reason) = Self::trigger_skip_reason(&env, &state, "unstake") {
+            emit_trigger_skip(&env, symbol_short!("unstake"), reason);
+            return Ok(TriggerResult::Skipped(reason));
+        }
+
+        // Execute only unstake actions
+        crate::rebalance::execute_unstake_only(&env)?;
+        crate::engine::record_ledger_rules(&env, Some("unstake"));
+
+        // Update last rebalance timestamp
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
+
+        // Emit unstake event
+        crate::events::publish(&env, EVENTS_REBALANCES, (symbol_short!("unstaked"),), state.last_rebalance);
+
+        Ok(TriggerResult::Executed(TriggerReport {
+            timestamp: state.last_rebalance,
+            total_value: state.total_value,
+        }))
+    }
+
+    /// Trigger liquidity provision based on configured rules (only liquidity actions)
+    /// Can be called by anyone, but only executes if liquidity rules are met
+    /// Returns Skipped with the reason when nothing was done
+    pub fn trigger_liquidity(env: Env) -> Result<TriggerResult, VaultError> {
+        // Check vault is initialized
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+        crate::pause::when_not_emergency(&env)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
             .ok_or(VaultError::NotInitialized)?;
 
+        // Any trigger call shows a keeper is alive, whether or not it executes
+        crate::keeper::heartbeat(&env);
+
+        // Check if liquidity provision should occur based on rules
+        if let Some(reason) = Self::trigger_skip_reason(&env, &state, "liquidity") {
+            emit_trigger_skip(&env, symbol_short!("liquidity"), reason);
+            return Ok(TriggerResult::Skipped(reason));
+        }
+
         // Execute only liquidity actions
         crate::rebalance::execute_liquidity_only(&env)?;
+        crate::engine::record_ledger_rules(&env, Some("liquidity"));
 
         // Update last rebalance timestamp
-        state.last_rebalance = env.ledger().timestamp();
-        env.storage().instance().set(&STATE, &state);
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
 
         // Emit liquidity event
-        env.events().publish((symbol_short!("liquidity"),), state.last_rebalance);
+        crate::events::publish(&env, EVENTS_REBALANCES, (symbol_short!("liquidity"),), state.last_rebalance);
 
-        Ok(())
+        Ok(TriggerResult::Executed(TriggerReport {
+            timestamp: state.last_rebalance,
+            total_value: state.total_value,
+        }))
+    }
+
+    /// Run trigger_<action> at most once per `op_id`
+    /// action: "rebalance", "stake", "unstake" or "liquidity". Replaying an op id
+    /// among the last OP_ID_WINDOW executed ones returns Skipped(Duplicate)
+    /// without doing anything. Skipped calls don't consume their op id.
+    pub fn trigger_with_op_id(env: Env, action: Symbol, op_id: BytesN<32>) -> Result<TriggerResult, VaultError> {
+        // An unknown action is refused even when its op id is a replay
+        type Trigger = fn(Env) -> Result<TriggerResult, VaultError>;
+        let triggers: [(Symbol, Trigger); 4] = [
+            (symbol_short!("rebalance"), Self::trigger_rebalance),
+            (symbol_short!("stake"), Self::trigger_stake),
+            (symbol_short!("unstake"), Self::trigger_unstake),
+            (symbol_short!("liquidity"), Self::trigger_liquidity),
+        ];
+        let trigger = triggers.iter()
+            .find(|(name, _)| *name == action)
+            .map(|(_, trigger)| *trigger)
+            .ok_or(VaultError::InvalidConfiguration)?;
+
+        if crate::op_ids::seen(&env, &op_id) {
+            crate::keeper::heartbeat(&env);
+            emit_trigger_skip(&env, action, SkipReason::Duplicate);
+            return Ok(TriggerResult::Skipped(SkipReason::Duplicate));
+        }
+
+        let result = trigger(env.clone())?;
+
+        if let TriggerResult::Executed(_) = result {
+            crate::op_ids::remember(&env, &op_id);
+        }
+
+        Ok(result)
     }
 
     /// Force rebalance to target allocation (for post-deposit swaps)
-    /// Always executes rebalance regardless of rules
+    /// Executes regardless of rules, but not while paused: like the triggers,
+    /// it then emits trigger_skip with Paused and does nothing
     pub fn force_rebalance(env: Env) -> Result<(), VaultError> {
         // Check vault is initialized
         if !env.storage().instance().has(&CONFIG) {
             return Err(VaultError::NotInitialized);
         }
+        crate::pause::when_not_emergency(&env)?;
+        if crate::pause::paused(&env) {
+            emit_trigger_skip(&env, symbol_short!("rebalance"), SkipReason::Paused);
+            return Ok(());
+        }
 
-        let _config: VaultConfig = env.storage().instance().get(&CONFIG)
+        let config: VaultConfig = crate::migrations::read_config(&env)
             .ok_or(VaultError::NotInitialized)?;
 
-        let mut state: VaultState = env.storage().instance().get(&STATE)
+        let mut state: VaultState = crate::migrations::read_state(&env)
             .ok_or(VaultError::NotInitialized)?;
 
+        // Without a rebalance rule there is no target allocation to move towards.
+        // Say so instead of reporting a rebalance that never happened.
+        if crate::rebalance::first_rebalance_rule(&env, &config).is_none() {
+            crate::events::emit_no_rebalance_rule(&env);
+            return Ok(());
+        }
+
         // Execute rebalance logic without checking rules
         crate::rebalance::execute_rebalance(&env)?;
 
         // Update last rebalance timestamp
-        state.last_rebalance = env.ledger().timestamp();
-        env.storage().instance().set(&STATE, &state);
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
 
         // Emit rebalance event
         crate::events::emit_rebalance(&env, state.last_rebalance);
@@ -468,6 +2371,309 @@ impl VaultContract {
         Ok(())
     }
 
+    /// Force staking per the configured stake rules (owner only)
+    /// Executes regardless of whether the rule conditions are met
+    pub fn force_stake(env: Env, caller: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        crate::pause::when_not_paused(&env)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Execute stake actions without checking rules
+        crate::rebalance::execute_stake_only(&env)?;
+
+        // Update last rebalance timestamp
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
+
+        // Emit stake event
+        crate::events::publish(&env, EVENTS_REBALANCES, (symbol_short!("staked"),), state.last_rebalance);
+
+        Ok(())
+    }
+
+    /// Force liquidity provision per the configured liquidity rules (owner only)
+    /// Executes regardless of whether the rule conditions are met
+    pub fn force_liquidity(env: Env, caller: Address) -> Result<(), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+        crate::pause::when_not_paused(&env)?;
+
+        let mut state: VaultState = crate::migrations::read_state(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Execute liquidity actions without checking rules
+        crate::rebalance::execute_liquidity_only(&env)?;
+
+        // Update last rebalance timestamp
+        state.last_rebalance = crate::clock::now(&env);
+        crate::migrations::write_state(&env, &state);
+
+        // Emit liquidity event
+        crate::events::publish(&env, EVENTS_REBALANCES, (symbol_short!("liquidity"),), state.last_rebalance);
+
+        Ok(())
+    }
+
+    /// Effective limits and scales for this build and vault
+    /// Mixes compile-time constants with the owner's per-vault overrides
+    pub fn get_limits(env: Env) -> Result<Limits, VaultError> {
+        if !env.storage().instance().has(&CONFIG) {
+            return Err(VaultError::NotInitialized);
+        }
+
+        Ok(Limits {
+            version: LIMITS_VERSION,
+            allocation_scale: 100_0000,
+            bps_scale: 10000,
+            price_scale: crate::fees::PRICE_SCALE,
+            rebalance_swap_slippage_pct: crate::rebalance::REBALANCE_SWAP_SLIPPAGE_PCT,
+            lp_base_slippage_bps: crate::liquidity_router::BASE_LP_SLIPPAGE_BPS,
+            lp_max_slippage_bps: crate::liquidity_router::MAX_LP_SLIPPAGE_BPS,
+            lp_slippage_pct: Self::get_lp_slippage(env.clone()),
+            max_rebalance_loss_bps: Self::get_max_rebalance_loss(env.clone()),
+            allocation_caps: crate::rebalance::get_allocation_caps(&env),
+            performance_fee_bps: crate::fees::get_performance_fee_bps(&env),
+            max_performance_fee_bps: crate::fees::MAX_PERFORMANCE_FEE_BPS,
+            max_assets: MAX_ASSETS,
+            max_rules: MAX_RULES,
+        })
+    }
+
+    /// Summary of the strategy for off-chain risk scoring
+    /// Deployment is measured the way each action sizes itself: stake and
+    /// liquidity move `threshold` of total value, rebalance moves whatever its
+    /// target allocation keeps outside the base asset. Unstake only unwinds.
+    pub fn get_strategy_descriptor(env: Env) -> Result<StrategyDescriptor, VaultError> {
+        use soroban_sdk::String;
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+        let base = crate::token_client::base_asset(&env, &config.assets)?;
+        let base_index = config.assets.first_index_of(&base).unwrap_or(0);
+
+        let rebalance = String::from_str(&env, "rebalance");
+        let stake = String::from_str(&env, "stake");
+        let liquidity = String::from_str(&env, "liquidity");
+
+        let mut actions: Vec<String> = Vec::new(&env);
+        let mut max_deployment_bps: Map<String, i128> = Map::new(&env);
+        for rule in bounded_iter(&config.rules, MAX_RULES, VaultError::TooManyItems)? {
+            if !actions.contains(&rule.action) {
+                actions.push_back(rule.action.clone());
+            }
+
+            let deployment_bps = if rule.action == stake || rule.action == liquidity {
+                rule.threshold / 100
+            } else if rule.action == rebalance {
+                10000 - rule.target_allocation.get(base_index).unwrap_or(0) / 100
+            } else {
+                continue;
+            };
+            let current = max_deployment_bps.get(rule.action.clone()).unwrap_or(0);
+            max_deployment_bps.set(rule.action, current.max(deployment_bps));
+        }
+
+        // With no preference, swaps go to the pool and fall back to the router
+        let mut venues = crate::swap_router::venue_preference(&env);
+        if venues.is_empty() && config.router_address.is_some() {
+            venues = soroban_sdk::vec![&env, Venue::DirectPool, Venue::SoroswapRouter];
+        }
+
+        let mut staking_pools: Vec<Address> = Vec::new(&env);
+        if let Some(pool) = config.staking_pool_address.clone() {
+            staking_pools.push_back(pool);
+        }
+
+        Ok(StrategyDescriptor {
+            version: STRATEGY_DESCRIPTOR_VERSION,
+            actions,
+            max_deployment_bps,
+            venues,
+            router: config.router_address,
+            staking_pools,
+            buffer_pool: crate::buffer_pool::get_buffer_config(&env).map(|(pool, _)| pool),
+            oracle: crate::pricing::oracle_config(&env).map(|(oracle, _)| oracle),
+            price_sources: crate::pricing::price_source_priority(&env),
+            performance_fee_bps: crate::fees::get_performance_fee_bps(&env),
+            limits: Self::get_limits(env.clone())?,
+        })
+    }
+
+    /// Evaluate every configured rule without executing anything
+    /// Returns one result per rule, in config order. `as_of` evaluates
+    /// time rules at a hypothetical timestamp instead of ledger time.
+    pub fn evaluate_rules_view(env: Env, as_of: Option<u64>) -> Result<Vec<bool>, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if config.rules.len() > MAX_RULES {
+            return Err(VaultError::TooManyItems);
+        }
+
+        let now = crate::clock::view_time(&env, as_of);
+        Ok(crate::engine::evaluate_rules_at(&env, &config.rules, now))
+    }
+
+    /// Target vs current weight of each asset, as (asset, target_bps, current_bps)
+    /// Basis points: 10000 = 100%. Targets come from the FIRST rule with action
+    /// "rebalance"; if there is none every target is reported as 0.
+    /// Current weights are the vault's token balances relative to their sum,
+    /// compared in raw token units the same way the rebalance planner does.
+    pub fn asset_weights(env: Env) -> Result<Vec<(Address, i128, i128)>, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let targets: Vec<i128> = crate::rebalance::first_rebalance_rule(&env, &config)
+            .map(|rule| rule.target_allocation)
+            .unwrap_or(Vec::new(&env));
+
+        let mut balances: Vec<i128> = Vec::new(&env);
+        let mut total_balance: i128 = 0;
+        for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+            let balance = crate::token_client::get_vault_balance(&env, &asset);
+            total_balance = total_balance.checked_add(balance)
+                .ok_or(VaultError::InvalidAmount)?;
+            balances.push_back(balance);
+        }
+
+        let mut weights = Vec::new(&env);
+        for i in 0..config.assets.len() {
+            let asset = config.assets.get(i).ok_or(VaultError::InvalidConfiguration)?;
+
+            // Rule allocations use 100_0000 = 100%, convert to basis points
+            let target_bps = targets.get(i).unwrap_or(0) / 100;
+
+            let current_bps = if total_balance > 0 {
+                balances.get(i).unwrap_or(0)
+                    .checked_mul(10000)
+                    .and_then(|v| v.checked_div(total_balance))
+                    .ok_or(VaultError::InvalidAmount)?
+            } else {
+                0
+            };
+
+            weights.push_back((asset, target_bps, current_bps));
+        }
+
+        Ok(weights)
+    }
+
+    /// Target vs current allocation per asset, for charting
+    /// Values are in base asset units from valuation::value_asset, with staked
+    /// and LP positions attributed to their underlying assets. Without a
+    /// rebalance rule every entry has target 0 and has_target false.
+    pub fn get_allocation_status(env: Env) -> Result<Vec<AllocationStatus>, VaultError> {
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        let rule = crate::rebalance::first_rebalance_rule(&env, &config);
+
+        let mut values: Vec<i128> = Vec::new(&env);
+        let mut total: i128 = 0;
+        for asset in bounded_iter(&config.assets, MAX_ASSETS, VaultError::TooManyItems)? {
+            let value = crate::valuation::held_value(&env, &asset)?;
+            total = total.checked_add(value)
+                .ok_or(VaultError::InvalidAmount)?;
+            values.push_back(value);
+        }
+
+        let mut statuses = Vec::new(&env);
+        for i in 0..config.assets.len() {
+            let asset = config.assets.get(i).ok_or(VaultError::InvalidConfiguration)?;
+            let value = values.get(i).unwrap_or(0);
+
+            let current_bps = if total > 0 {
+                value.checked_mul(10000)
+                    .and_then(|v| v.checked_div(total))
+                    .ok_or(VaultError::InvalidAmount)?
+            } else {
+                0
+            };
+
+            // Rule allocations use 100_0000 = 100%, convert to basis points
+            let (target_bps, has_target) = match &rule {
+                Some(rule) => (rule.target_allocation.get(i).unwrap_or(0) / 100, true),
+                None => (0, false),
+            };
+            let drift_bps = if has_target { current_bps - target_bps } else { 0 };
+
+            statuses.push_back(AllocationStatus {
+                asset,
+                target_bps,
+                current_bps,
+                drift_bps,
+                value,
+                has_target,
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    /// Withdraw `lp_tokens` of the liquidity position back into the vault (owner only)
+    /// Emergency exit: uses the router if it works and the pool directly if not.
+    /// Returns the amounts of the pair's two tokens received.
+    pub fn recover_liquidity(env: Env, caller: Address, lp_tokens: i128) -> Result<(i128, i128), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::rebalance::recover_liquidity(&env, lp_tokens)
+    }
+
+    /// Remove the whole liquidity position through the router (owner only)
+    /// Burns the position's LP tokens and returns both tokens to the vault,
+    /// booking any gain or loss against the amounts provided into total_value.
+    /// Returns the amounts of the position's token_a and token_b received.
+    pub fn withdraw_liquidity(env: Env, caller: Address, slippage_percent: i128) -> Result<(i128, i128), VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::rebalance::withdraw_liquidity(&env, slippage_percent)
+    }
+
+    /// Unstake the whole staking position back into the vault (owner only)
+    /// Books any gain or loss against the staked amount into total_value.
+    /// Returns the tokens received.
+    pub fn withdraw_staking(env: Env, caller: Address) -> Result<i128, VaultError> {
+        caller.require_auth();
+
+        let config: VaultConfig = crate::migrations::read_config(&env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        if caller != config.owner {
+            return Err(VaultError::Unauthorized);
+        }
+
+        crate::rebalance::withdraw_staking(&env)
+    }
+
     /// Get the current staking position for the vault
     pub fn get_staking_position(env: Env) -> Result<crate::types::StakingPosition, VaultError> {
         use soroban_sdk::String;
@@ -503,4 +2709,125 @@ impl VaultContract {
         let position_key = String::from_str(&env, "lp_position");
         env.storage().instance().has(&position_key)
     }
+
+    /// Get the staking and liquidity positions in one call
+    /// The vault keeps at most one liquidity position today, so the list holds
+    /// zero or one entries.
+    pub fn active_positions(
+        env: Env,
+    ) -> (Option<crate::types::StakingPosition>, Vec<crate::types::LiquidityPosition>) {
+        let mut liquidity = Vec::new(&env);
+        if let Ok(position) = Self::get_liquidity_position(env.clone()) {
+            liquidity.push_back(position);
+        }
+        (Self::get_staking_position(env).ok(), liquidity)
+    }
+
+    /// Mint shares for `final_amount` of base asset already received, credit
+    /// them to `user` and park any idle excess
+    fn credit_deposit(env: &Env, user: &Address, final_amount: i128, base_token: &Address) -> Result<i128, VaultError> {
+        // Get current state
+        let mut state: VaultState = crate::migrations::read_state(env)
+            .ok_or(VaultError::NotInitialized)?;
+
+        // Share price this deposit enters at, before it moves total_value
+        let entry_price = crate::fees::share_price(env, &state)?;
+
+        // Calculate shares to mint based on final amount (after swap if needed)
+        let shares = if state.total_shares == 0 {
+            // First deposit: 1:1 in value, expressed with SHARE_DECIMALS precision
+            let decimals = token::TokenClient::new(env, base_token).decimals();
+            let scale = crate::fees::set_share_scale(env, decimals);
+            final_amount.checked_mul(scale)
+                .ok_or(VaultError::InvalidAmount)?
+        } else {
+            // shares = (final_amount * total_shares) / total_value
+            final_amount.checked_mul(state.total_shares)
+                .and_then(|v| v.checked_div(state.total_value))
+                .ok_or(VaultError::InvalidAmount)?
+        };
+
+        // Update state with final amount
+        state.total_shares = state.total_shares.checked_add(shares)
+            .ok_or(VaultError::InvalidAmount)?;
+        state.total_value = state.total_value.checked_add(final_amount)
+            .ok_or(VaultError::InvalidAmount)?;
+
+        let mut position = Self::get_position(env.clone(), user.clone());
+
+        // Fold this deposit into the user's entry price for performance fees
+        crate::fees::record_entry(env, user, position.shares, shares, entry_price)?;
+
+        // Update user position
+        position.shares = position.shares.checked_add(shares)
+            .ok_or(VaultError::InvalidAmount)?;
+        position.last_deposit = crate::clock::now(env);
+
+        // Store updates
+        crate::migrations::write_state(env, &state);
+        Self::store_position(env, user, &position);
+        crate::flows::record_deposit(env, final_amount);
+        crate::stats::report(env, state.total_value);
+
+        // Park idle base asset above the hot threshold in the buffer pool, if configured
+        crate::buffer_pool::park_idle(env, base_token)?;
+
+        // Emit event with final amount (after swap)
+        emit_deposit(env, user, final_amount, shares);
+
+        Ok(shares)
+    }
+
+    // Store a position, counting its holder if it is new
+    fn store_position(env: &Env, user: &Address, position: &UserPosition) {
+        let key = (POSITION, user.clone());
+        if !env.storage().instance().has(&key) {
+            crate::stats::add_holder(env);
+        }
+        crate::migrations::write_position(env, user, position);
+    }
+
+    // Remove a position along with its holder's count
+    fn remove_position(env: &Env, user: &Address) {
+        let key = (POSITION, user.clone());
+        if env.storage().instance().has(&key) {
+            crate::stats::remove_holder(env);
+            env.storage().instance().remove(&key);
+        }
+    }
+
+    // Credit performance fee shares, worth `fee` at the current share price, to `recipient`
+    fn credit_fee_shares(
+        env: &Env,
+        state: &VaultState,
+        recipient: &Address,
+        fee_shares: i128,
+        fee: i128,
+    ) -> Result<(), VaultError> {
+        let mut recipient_position = Self::get_position(env.clone(), recipient.clone());
+        let price = crate::fees::share_price(env, state)?;
+        crate::fees::record_entry(env, recipient, recipient_position.shares, fee_shares, price)?;
+        recipient_position.shares = recipient_position.shares.checked_add(fee_shares)
+            .ok_or(VaultError::InvalidAmount)?;
+        Self::store_position(env, recipient, &recipient_position);
+        env.events().publish(
+            (Symbol::new(env, "fee_shares"), recipient.clone()),
+            (fee_shares, fee),
+        );
+
+        Ok(())
+    }
+
+    /// Work out whether a trigger for `action` should be skipped, and why
+    // A paused vault skips everything but unstaking, which only unwinds
+    fn trigger_skip_reason(env: &Env, state: &VaultState, action: &str) -> Option<SkipReason> {
+        if action != "unstake" && crate::pause::paused(env) {
+            return Some(SkipReason::Paused);
+        }
+        if state.total_value == 0 {
+            return Some(SkipReason::EmptyVault);
+        }
+
+        crate::engine::skip_reason(env, action)
+    }
 }
@@ -1,5 +1,5 @@
 // Vault NFT contract for fractional ownership
-use soroban_sdk::{contract, contractimpl, Address, Env, String, Vec, symbol_short, Map, Symbol};
+use soroban_sdk::{contract, contractimpl, Address, Env, Vec, symbol_short, Map, Symbol};
 use crate::nft_types::{VaultNFT, NFTMetadata};
 use crate::errors::VaultError;
 
@@ -44,7 +44,7 @@ impl VaultNFTContract {
             vault_address: vault_address.clone(),
             ownership_percentage,
             holder: minter.clone(),
-            metadata: format_metadata(&metadata),
+            metadata,
         };
         
         // Store NFT
@@ -184,9 +184,3 @@ impl VaultNFTContract {
         Ok(total)
     }
 }
-
-// Helper function to format metadata
-fn format_metadata(metadata: &NFTMetadata) -> String {
-    // Simple JSON-like formatting for metadata
-    metadata.name.clone()
-}
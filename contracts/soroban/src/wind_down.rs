@@ -0,0 +1,129 @@
+// Wind-down and closed-vault redemption
+// Selling out of a vault realizes progressively worse prices, so redeeming
+// against live book value would pay early exits more per share than late ones.
+// initiate_wind_down instead brings staking, liquidity and the buffer pool
+// back into the vault and freezes a redemption price: the base asset it holds
+// over the shares outstanding. From then on the vault is Closed. It stays
+// paused, and withdraw pays exactly that price for every share, in whatever
+// order holders come. Whatever the vault holds beyond what outstanding shares
+// are owed (proceeds that arrive after the freeze, rounding) is paid out once
+// through the true-up: when the owner finalizes it, every share frozen is
+// worth an equal part of the surplus, claimable with claim_true_up whether or
+// not it has been redeemed yet.
+use soroban_sdk::{symbol_short, Address, Env, Symbol};
+use crate::errors::VaultError;
+use crate::fees::PRICE_SCALE;
+use crate::types::{VaultState, WindDown};
+
+const WIND_DOWN: Symbol = symbol_short!("WIND_DOWN");
+const REDEEMED: Symbol = symbol_short!("CLOSE_RDM");
+const TRUE_UP_CLAIMED: Symbol = symbol_short!("TRUEUP_CL");
+
+/// Frozen redemption terms, once the vault is closed
+pub fn terms(env: &Env) -> Option<WindDown> {
+    env.storage().instance().get(&WIND_DOWN)
+}
+
+/// Fail with Closed once the vault has been wound down
+pub fn when_open(env: &Env) -> Result<(), VaultError> {
+    if terms(env).is_some() {
+        return Err(VaultError::Closed);
+    }
+    Ok(())
+}
+
+/// Freeze the redemption price from the base asset the vault holds
+/// Amounts set aside for settled exits aren't counted. The book value is
+/// realigned to what is now owed, and the terms returned.
+pub fn freeze(env: &Env, base_token: &Address, state: &mut VaultState) -> Result<WindDown, VaultError> {
+    if state.total_shares <= 0 {
+        return Err(VaultError::InvalidAmount);
+    }
+
+    let available = crate::token_client::get_vault_balance(env, base_token)
+        .checked_sub(crate::exit_batch::reserved(env, base_token))
+        .ok_or(VaultError::InvalidAmount)?
+        .max(0);
+    let price = available.checked_mul(PRICE_SCALE)
+        .and_then(|v| v.checked_div(state.total_shares))
+        .ok_or(VaultError::InvalidAmount)?;
+
+    let terms = WindDown {
+        price,
+        frozen_shares: state.total_shares,
+        true_up: 0,
+        finalized: false,
+    };
+    env.storage().instance().set(&WIND_DOWN, &terms);
+    state.total_value = owed(&terms, state.total_shares)?;
+    Ok(terms)
+}
+
+/// Base asset `shares` redeem for at the frozen price
+pub fn redemption_value(terms: &WindDown, shares: i128) -> Result<i128, VaultError> {
+    shares.checked_mul(terms.price)
+        .and_then(|v| v.checked_div(PRICE_SCALE))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+// Most the outstanding shares can redeem for, rounded up
+fn owed(terms: &WindDown, outstanding: i128) -> Result<i128, VaultError> {
+    outstanding.checked_mul(terms.price)
+        .and_then(|v| v.checked_add(PRICE_SCALE - 1))
+        .and_then(|v| v.checked_div(PRICE_SCALE))
+        .ok_or(VaultError::InvalidAmount)
+}
+
+/// Count shares `user` redeemed after the freeze toward their true-up
+pub fn record_redemption(env: &Env, user: &Address, shares: i128) -> Result<(), VaultError> {
+    let key = (REDEEMED, user.clone());
+    let redeemed: i128 = env.storage().instance().get(&key).unwrap_or(0);
+    let redeemed = redeemed.checked_add(shares).ok_or(VaultError::InvalidAmount)?;
+    env.storage().instance().set(&key, &redeemed);
+    Ok(())
+}
+
+/// Set the true-up from the surplus over what outstanding shares are owed
+/// Returns the surplus. Fails with InvalidConfiguration if already finalized.
+pub fn finalize(env: &Env, base_token: &Address, state: &VaultState) -> Result<i128, VaultError> {
+    let mut terms = terms(env).ok_or(VaultError::InvalidConfiguration)?;
+    if terms.finalized {
+        return Err(VaultError::InvalidConfiguration);
+    }
+
+    let surplus = crate::token_client::get_vault_balance(env, base_token)
+        .checked_sub(crate::exit_batch::reserved(env, base_token))
+        .and_then(|v| v.checked_sub(owed(&terms, state.total_shares).ok()?))
+        .ok_or(VaultError::InvalidAmount)?
+        .max(0);
+    terms.true_up = surplus.checked_mul(PRICE_SCALE)
+        .and_then(|v| v.checked_div(terms.frozen_shares))
+        .ok_or(VaultError::InvalidAmount)?;
+    terms.finalized = true;
+    env.storage().instance().set(&WIND_DOWN, &terms);
+    Ok(surplus)
+}
+
+/// Settle `user`'s true-up for `held` shares still in their position
+/// Covers every share they held at the freeze, redeemed or not, less what
+/// earlier claims covered. Returns the amount owed, which the caller pays.
+pub fn take_true_up(env: &Env, user: &Address, held: i128) -> Result<i128, VaultError> {
+    let terms = terms(env).ok_or(VaultError::InvalidConfiguration)?;
+    if !terms.finalized {
+        return Err(VaultError::InvalidConfiguration);
+    }
+
+    let redeemed: i128 = env.storage().instance().get(&(REDEEMED, user.clone())).unwrap_or(0);
+    let claim_key = (TRUE_UP_CLAIMED, user.clone());
+    let claimed: i128 = env.storage().instance().get(&claim_key).unwrap_or(0);
+    let frozen = held.checked_add(redeemed).ok_or(VaultError::InvalidAmount)?;
+    let shares = frozen.checked_sub(claimed).ok_or(VaultError::InvalidAmount)?;
+    if shares <= 0 {
+        return Err(VaultError::InsufficientShares);
+    }
+
+    env.storage().instance().set(&claim_key, &frozen);
+    shares.checked_mul(terms.true_up)
+        .and_then(|v| v.checked_div(PRICE_SCALE))
+        .ok_or(VaultError::InvalidAmount)
+}
@@ -0,0 +1,17 @@
+// Caps on user-influenced loops
+// Rules, assets, NFT lists and batch arguments all grow with user input. Every
+// loop over one goes through bounded_iter, so an oversized vector fails the
+// call up front with a clear error instead of running it out of budget midway.
+use soroban_sdk::{Env, IntoVal, TryFromVal, Val, Vec};
+
+/// Iterate `items`, or fail with `err` if it holds more than `cap` entries
+pub fn bounded_iter<T, E>(items: &Vec<T>, cap: u32, err: E) -> Result<impl Iterator<Item = T>, E>
+where
+    T: IntoVal<Env, Val> + TryFromVal<Env, Val> + Clone,
+    T::Error: core::fmt::Debug,
+{
+    if items.len() > cap {
+        return Err(err);
+    }
+    Ok(items.iter())
+}
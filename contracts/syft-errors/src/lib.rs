@@ -0,0 +1,147 @@
+#![no_std]
+
+// Error types shared by the Syft contracts
+// Discriminants are part of the public interface: off-chain clients map them
+// to messages, so never renumber a variant, only add new ones.
+use soroban_sdk::contracterror;
+
+mod bounds;  // Caps on loops over user-influenced vectors
+pub use bounds::bounded_iter;
+
+/// Errors returned by the vault contract
+/// NFT contract errors are re-encoded at NFT_ERROR_BASE + their own code so a
+/// vault call that fails inside the NFT contract reports the original cause.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum VaultError {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    Unauthorized = 3,
+    InsufficientBalance = 4,
+    InsufficientShares = 5,
+    InvalidAmount = 6,
+    InvalidConfiguration = 7,
+    RebalanceFailed = 8,
+    TransferFailed = 9,
+    NFTNotFound = 10,       // Legacy, use NftNotFound
+    InvalidOwnership = 11,  // Legacy, use NftInvalidOwnership
+    OwnershipExceeded = 12, // Legacy, use NftOwnershipExceeded
+    SlippageTooHigh = 13,
+    SwapFailed = 14,
+    PoolNotFound = 15,
+    InsufficientLiquidity = 16,
+    RouterNotSet = 17,
+    RebalanceLossExceeded = 18,
+    ProfitShareUnderpaid = 19,
+    KeeperStale = 20,
+    PriceUnavailable = 21,
+    TooManyItems = 22,
+    WithdrawalBatched = 23, // Large exits go through request_withdraw
+    DeniedIntegration = 24, // Denylisted by the factory on a mainnet vault
+    Paused = 25,            // Emergency stop; only exits are allowed
+    EmergencyMode = 26,     // Only emergency_withdraw until the owner exits it
+    Closed = 27,            // Wound down; shares only redeem at the frozen price
+
+    // VaultNFTError, offset by NFT_ERROR_BASE
+    NftUnauthorized = 101,
+    NftInvalidAmount = 102,
+    NftNotFound = 103,
+    NftInvalidOwnership = 104,
+    NftOwnershipExceeded = 105,
+    NftUnknownVault = 106,
+    NftUnderfunded = 107,
+    NftClaimExpired = 108,
+    NftTooManyItems = 109,
+    NftLocked = 110,
+    NftInvalidBps = 111,
+    NftInvalidPrice = 112,
+    NftAlreadyClaimed = 113,
+    NftInsufficientLiquidity = 114,
+    NftRedeemFailed = 115,
+    NftInvalidRecipient = 116,
+    NftPaused = 117,
+}
+
+/// Errors returned by the vault NFT contract
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum VaultNFTError {
+    Unauthorized = 1,
+    InvalidAmount = 2,
+    NFTNotFound = 3,
+    InvalidOwnership = 4,
+    OwnershipExceeded = 5,
+    UnknownVault = 6,
+    Underfunded = 7,
+    ClaimExpired = 8,
+    TooManyItems = 9,
+    Locked = 10,
+    InvalidBps = 11,   // Basis points outside 0..=10000
+    InvalidPrice = 12, // Sale prices must be positive
+    AlreadyClaimed = 13,
+    InsufficientLiquidity = 14, // Vault cannot pay a redemption out right now
+    RedeemFailed = 15,          // Vault refused the redemption for another reason
+    InvalidRecipient = 16,      // Payments can't be sent to that address
+    Paused = 17,                // Minting, transfers and distributions are halted
+}
+
+/// Offset of NFT contract errors inside VaultError
+pub const NFT_ERROR_BASE: u32 = 100;
+
+impl From<VaultNFTError> for VaultError {
+    fn from(err: VaultNFTError) -> Self {
+        // Exhaustive on purpose: a new NFT error must be given a vault code here
+        match err {
+            VaultNFTError::Unauthorized => VaultError::NftUnauthorized,
+            VaultNFTError::InvalidAmount => VaultError::NftInvalidAmount,
+            VaultNFTError::NFTNotFound => VaultError::NftNotFound,
+            VaultNFTError::InvalidOwnership => VaultError::NftInvalidOwnership,
+            VaultNFTError::OwnershipExceeded => VaultError::NftOwnershipExceeded,
+            VaultNFTError::UnknownVault => VaultError::NftUnknownVault,
+            VaultNFTError::Underfunded => VaultError::NftUnderfunded,
+            VaultNFTError::ClaimExpired => VaultError::NftClaimExpired,
+            VaultNFTError::TooManyItems => VaultError::NftTooManyItems,
+            VaultNFTError::Locked => VaultError::NftLocked,
+            VaultNFTError::InvalidBps => VaultError::NftInvalidBps,
+            VaultNFTError::InvalidPrice => VaultError::NftInvalidPrice,
+            VaultNFTError::AlreadyClaimed => VaultError::NftAlreadyClaimed,
+            VaultNFTError::InsufficientLiquidity => VaultError::NftInsufficientLiquidity,
+            VaultNFTError::RedeemFailed => VaultError::NftRedeemFailed,
+            VaultNFTError::InvalidRecipient => VaultError::NftInvalidRecipient,
+            VaultNFTError::Paused => VaultError::NftPaused,
+        }
+    }
+}
+
+impl TryFrom<VaultError> for VaultNFTError {
+    type Error = VaultError;
+
+    /// Recover the NFT contract error behind a vault error, if it is one
+    fn try_from(err: VaultError) -> Result<Self, Self::Error> {
+        match err {
+            VaultError::NftUnauthorized => Ok(VaultNFTError::Unauthorized),
+            VaultError::NftInvalidAmount => Ok(VaultNFTError::InvalidAmount),
+            VaultError::NftNotFound => Ok(VaultNFTError::NFTNotFound),
+            VaultError::NftInvalidOwnership => Ok(VaultNFTError::InvalidOwnership),
+            VaultError::NftOwnershipExceeded => Ok(VaultNFTError::OwnershipExceeded),
+            VaultError::NftUnknownVault => Ok(VaultNFTError::UnknownVault),
+            VaultError::NftUnderfunded => Ok(VaultNFTError::Underfunded),
+            VaultError::NftClaimExpired => Ok(VaultNFTError::ClaimExpired),
+            VaultError::NftTooManyItems => Ok(VaultNFTError::TooManyItems),
+            VaultError::NftLocked => Ok(VaultNFTError::Locked),
+            VaultError::NftInvalidBps => Ok(VaultNFTError::InvalidBps),
+            VaultError::NftInvalidPrice => Ok(VaultNFTError::InvalidPrice),
+            VaultError::NftAlreadyClaimed => Ok(VaultNFTError::AlreadyClaimed),
+            VaultError::NftInsufficientLiquidity => Ok(VaultNFTError::InsufficientLiquidity),
+            VaultError::NftRedeemFailed => Ok(VaultNFTError::RedeemFailed),
+            VaultError::NftInvalidRecipient => Ok(VaultNFTError::InvalidRecipient),
+            VaultError::NftPaused => Ok(VaultNFTError::Paused),
+            other => Err(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,103 @@
+#![cfg(test)]
+
+use crate::{VaultError, VaultNFTError, NFT_ERROR_BASE};
+
+// Every NFT error. The match forces this list to be updated with the enum.
+fn all_nft_errors() -> [VaultNFTError; 17] {
+    let all = [
+        VaultNFTError::Unauthorized,
+        VaultNFTError::InvalidAmount,
+        VaultNFTError::NFTNotFound,
+        VaultNFTError::InvalidOwnership,
+        VaultNFTError::OwnershipExceeded,
+        VaultNFTError::UnknownVault,
+        VaultNFTError::Underfunded,
+        VaultNFTError::ClaimExpired,
+        VaultNFTError::TooManyItems,
+        VaultNFTError::Locked,
+        VaultNFTError::InvalidBps,
+        VaultNFTError::InvalidPrice,
+        VaultNFTError::AlreadyClaimed,
+        VaultNFTError::InsufficientLiquidity,
+        VaultNFTError::RedeemFailed,
+        VaultNFTError::InvalidRecipient,
+        VaultNFTError::Paused,
+    ];
+    for err in all {
+        match err {
+            VaultNFTError::Unauthorized
+            | VaultNFTError::InvalidAmount
+            | VaultNFTError::NFTNotFound
+            | VaultNFTError::InvalidOwnership
+            | VaultNFTError::OwnershipExceeded
+            | VaultNFTError::UnknownVault
+            | VaultNFTError::Underfunded
+            | VaultNFTError::ClaimExpired
+            | VaultNFTError::TooManyItems
+            | VaultNFTError::Locked
+            | VaultNFTError::InvalidBps
+            | VaultNFTError::InvalidPrice
+            | VaultNFTError::AlreadyClaimed
+            | VaultNFTError::InsufficientLiquidity
+            | VaultNFTError::RedeemFailed
+            | VaultNFTError::InvalidRecipient
+            | VaultNFTError::Paused => {}
+        }
+    }
+    all
+}
+
+#[test]
+fn nft_errors_map_to_offset_vault_codes() {
+    for err in all_nft_errors() {
+        let vault_err = VaultError::from(err);
+        assert_eq!(vault_err as u32, NFT_ERROR_BASE + err as u32);
+        assert_eq!(VaultNFTError::try_from(vault_err), Ok(err));
+    }
+}
+
+#[test]
+fn vault_errors_are_not_nft_errors() {
+    assert_eq!(
+        VaultNFTError::try_from(VaultError::Unauthorized),
+        Err(VaultError::Unauthorized)
+    );
+    assert_eq!(
+        VaultNFTError::try_from(VaultError::NFTNotFound),
+        Err(VaultError::NFTNotFound)
+    );
+}
+
+#[test]
+fn discriminants_are_stable() {
+    assert_eq!(VaultError::AlreadyInitialized as u32, 1);
+    assert_eq!(VaultError::RouterNotSet as u32, 17);
+    assert_eq!(VaultError::ProfitShareUnderpaid as u32, 19);
+    assert_eq!(VaultError::KeeperStale as u32, 20);
+    assert_eq!(VaultError::PriceUnavailable as u32, 21);
+    assert_eq!(VaultError::TooManyItems as u32, 22);
+    assert_eq!(VaultError::WithdrawalBatched as u32, 23);
+    assert_eq!(VaultError::DeniedIntegration as u32, 24);
+    assert_eq!(VaultError::Paused as u32, 25);
+    assert_eq!(VaultError::EmergencyMode as u32, 26);
+    assert_eq!(VaultError::Closed as u32, 27);
+    assert_eq!(VaultNFTError::Unauthorized as u32, 1);
+    assert_eq!(VaultNFTError::Underfunded as u32, 7);
+}
+
+#[test]
+fn bounded_iter_allows_up_to_the_cap() {
+    use soroban_sdk::{Env, Vec};
+    use crate::bounded_iter;
+
+    let env = Env::default();
+    let mut items: Vec<u32> = Vec::new(&env);
+    for i in 0..4 {
+        items.push_back(i);
+    }
+
+    let sum: u32 = bounded_iter(&items, 4, VaultError::TooManyItems).unwrap().sum();
+    assert_eq!(sum, 6);
+    assert!(matches!(bounded_iter(&items, 3, VaultError::TooManyItems), Err(VaultError::TooManyItems)));
+    assert!(bounded_iter(&Vec::<u32>::new(&env), 0, VaultError::TooManyItems).is_ok());
+}
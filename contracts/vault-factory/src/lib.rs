@@ -1,11 +1,35 @@
 #![no_std]
 
-use soroban_sdk::{contract, contractimpl, contracttype, contracterror, Address, Env, BytesN, Symbol, symbol_short, String, Vec};
+use soroban_sdk::{contract, contractclient, contractimpl, contracttype, contracterror, Address, Env, BytesN, IntoVal, Map, Symbol, symbol_short, String, TryFromVal, Val, Vec};
+use syft_errors::bounded_iter;
 
 const WASM_HASH: Symbol = symbol_short!("WASM");
 const VAULT_COUNT: Symbol = symbol_short!("COUNT");
 const VAULT_LIST: Symbol = symbol_short!("VAULTS");
 const ADMIN: Symbol = symbol_short!("ADMIN");
+const RECORD: Symbol = symbol_short!("RECORD");
+const KNOWN: Symbol = symbol_short!("KNOWN");
+const NETWORK: Symbol = symbol_short!("NETWORK");
+const DENIED: Symbol = symbol_short!("DENIED");
+const STATS: Symbol = symbol_short!("STATS");
+const PROTOCOL_STATS: Symbol = symbol_short!("PROTOCOL");
+const ARCHIVED: Symbol = symbol_short!("ARCHIVED");
+const PERMISSIONLESS: Symbol = symbol_short!("OPEN");
+const OWNED: Symbol = symbol_short!("OWNED");
+
+// Network tag on which denylisted integrations are refused
+const MAINNET: Symbol = symbol_short!("mainnet");
+
+// Max vaults touched by one push_integration_update call; callers split longer lists
+const MAX_INTEGRATION_BATCH: u32 = 25;
+
+// Max denylisted addresses, keeping is_denied cheap for the vaults that call it
+const MAX_DENYLIST: u32 = 50;
+
+// Per-vault and per-owner entries live in persistent storage, extended on use
+const LEDGERS_PER_DAY: u32 = 17_280; // At ~5s per ledger
+const ENTRY_TTL_THRESHOLD: u32 = 30 * LEDGERS_PER_DAY; // Extend entries once they have less than this left
+const ENTRY_TTL_EXTEND_TO: u32 = 90 * LEDGERS_PER_DAY; // and bring them back up to this
 
 // Error types
 #[contracterror]
@@ -16,15 +40,63 @@ pub enum VaultFactoryError {
     NotInitialized = 2,
     InvalidConfiguration = 3,
     Unauthorized = 4,
+    DeniedIntegration = 5,
+    InvalidAmount = 6,
+    VaultArchived = 7,
 }
 
-// Minimal vault configuration for factory (we don't actually use this, but need it for function signature)
+// Mirror of the vault's VaultConfig, passed through to its initialize
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct VaultConfig {
     pub owner: Address,
     pub name: String,
     pub assets: Vec<Address>,
+    pub rules: Vec<RebalanceRule>,
+    pub router_address: Option<Address>,
+    pub staking_pool_address: Option<Address>,
+    pub factory_address: Option<Address>,
+}
+
+// Mirror of the vault's RebalanceRule
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RebalanceRule {
+    pub condition_type: String,
+    pub threshold: i128,
+    pub action: String,
+    pub target_allocation: Vec<i128>,
+}
+
+// How a vault was deployed, kept so anyone can re-derive its address
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaultRecord {
+    pub address: Address,
+    pub salt: BytesN<32>,
+    pub wasm_hash: BytesN<32>,
+    pub creator: Address,
+}
+
+// Running totals across this factory's vaults, built from their reported stats
+// Vaults this factory stamped report themselves after deposits, withdrawals
+// and syncs. A vault counts as active once it has reported and until it is
+// archived.
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProtocolStats {
+    pub total_tvl: i128,
+    pub vault_count_active: u32,
+    /// Holders summed per vault, so someone in two vaults counts twice
+    pub total_users: u32,
+}
+
+// The part of the vault interface the factory calls into
+#[contractclient(name = "VaultClient")]
+pub trait VaultInterface {
+    fn initialize(env: Env, config: VaultConfig);
+    fn initialize_from_factory(env: Env, factory: Address, network: Symbol, config: VaultConfig);
+    fn factory_set_integration(env: Env, factory: Address, kind: Symbol, address: Address);
 }
 
 #[contract]
@@ -33,13 +105,15 @@ pub struct VaultFactory;
 #[contractimpl]
 impl VaultFactory {
     /// Initialize the factory with vault contract WASM hash
-    pub fn initialize(env: Env, admin: Address, wasm_hash: BytesN<32>) -> Result<(), VaultFactoryError> {
+    /// `network` ("mainnet", "testnet", ...) is stamped into every vault it deploys.
+    pub fn initialize(env: Env, admin: Address, wasm_hash: BytesN<32>, network: Symbol) -> Result<(), VaultFactoryError> {
         if env.storage().instance().has(&WASM_HASH) {
             return Err(VaultFactoryError::AlreadyInitialized);
         }
         
         env.storage().instance().set(&ADMIN, &admin);
         env.storage().instance().set(&WASM_HASH, &wasm_hash);
+        env.storage().instance().set(&NETWORK, &network);
         env.storage().instance().set(&VAULT_COUNT, &0u32);
         
         let empty_list: Vec<Address> = Vec::new(&env);
@@ -68,8 +142,37 @@ impl VaultFactory {
         Ok(())
     }
 
-    /// Deploy a new vault instance
-    pub fn create_vault(env: Env, config: VaultConfig) -> Result<Address, VaultFactoryError> {
+    /// Deploy a new vault instance and initialize it with `config`
+    /// `caller` is the deployer and must sign. Only the factory admin may
+    /// deploy unless the factory has been opened with set_permissionless.
+    /// `config.owner` may be anyone, so the admin can deploy on someone
+    /// else's behalf; the vault is listed under that owner and recorded with
+    /// `caller` as its creator. The vault is initialized in the same call, so
+    /// nobody can claim it first; a config the vault rejects fails with
+    /// InvalidConfiguration.
+    pub fn create_vault(env: Env, caller: Address, config: VaultConfig) -> Result<Address, VaultFactoryError> {
+        caller.require_auth();
+        
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+        
+        if caller != stored_admin && !Self::is_permissionless(env.clone()) {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        
+        // The vault would ask this factory during initialize, which can't be
+        // re-entered mid-deploy, so the denylist is checked here instead
+        let network = Self::get_network(env.clone());
+        if network == Some(MAINNET) {
+            let integrations = [&config.router_address, &config.staking_pool_address, &config.factory_address];
+            for integration in integrations.into_iter().flatten() {
+                if Self::is_denied(env.clone(), integration.clone()) {
+                    return Err(VaultFactoryError::DeniedIntegration);
+                }
+            }
+        }
+        
         // Get WASM hash
         let wasm_hash: BytesN<32> = env.storage().instance()
             .get(&WASM_HASH)
@@ -88,11 +191,18 @@ impl VaultFactory {
         
         // Deploy new vault contract instance
         let vault_address = env.deployer()
-            .with_current_contract(salt)
-            .deploy(wasm_hash);
+            .with_current_contract(salt.clone())
+            .deploy(wasm_hash.clone());
         
-        // NOTE: Initialization must be done separately after deployment
-        // The factory only deploys the contract, initialization happens in a separate transaction
+        // Initializing stamps the vault with this factory and its network
+        let vault = VaultClient::new(&env, &vault_address);
+        let initialized = match network {
+            Some(network) => vault.try_initialize_from_factory(&env.current_contract_address(), &network, &config),
+            None => vault.try_initialize(&config),
+        };
+        if initialized.is_err() {
+            return Err(VaultFactoryError::InvalidConfiguration);
+        }
         
         // Update vault count and list
         env.storage().instance().set(&VAULT_COUNT, &vault_count);
@@ -103,9 +213,292 @@ impl VaultFactory {
         vaults.push_back(vault_address.clone());
         env.storage().instance().set(&VAULT_LIST, &vaults);
         
+        let record = VaultRecord {
+            address: vault_address.clone(),
+            salt,
+            wasm_hash,
+            creator: caller,
+        };
+        store_entry(&env, &(RECORD, &vault_address), &record);
+        
+        let mut owned = Self::get_vaults_by_owner(env.clone(), config.owner.clone());
+        owned.push_back(vault_address.clone());
+        store_entry(&env, &(OWNED, &config.owner), &owned);
+        
         Ok(vault_address)
     }
 
+    /// Let anyone deploy vaults through create_vault, or restrict it to the admin again (admin only)
+    pub fn set_permissionless(env: Env, admin: Address, permissionless: bool) -> Result<(), VaultFactoryError> {
+        admin.require_auth();
+        
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+        
+        if admin != stored_admin {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        
+        env.storage().instance().set(&PERMISSIONLESS, &permissionless);
+        
+        Ok(())
+    }
+
+    /// Whether create_vault is open to anyone; admin-only by default
+    pub fn is_permissionless(env: Env) -> bool {
+        env.storage().instance().get(&PERMISSIONLESS).unwrap_or(false)
+    }
+
+    /// Point many vaults at a new integration address in one call (admin only)
+    /// kind: "router", "staking" or "factory". Each vault must have opted in via
+    /// set_allow_factory_admin; vaults that refuse are skipped and returned.
+    /// At most MAX_INTEGRATION_BATCH vaults per call.
+    pub fn push_integration_update(
+        env: Env,
+        admin: Address,
+        kind: Symbol,
+        new_address: Address,
+        vaults: Vec<Address>,
+    ) -> Result<Vec<Address>, VaultFactoryError> {
+        admin.require_auth();
+
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+
+        if admin != stored_admin {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+
+        if Self::get_network(env.clone()) == Some(MAINNET) && Self::is_denied(env.clone(), new_address.clone()) {
+            return Err(VaultFactoryError::DeniedIntegration);
+        }
+
+        let batch = bounded_iter(&vaults, MAX_INTEGRATION_BATCH, VaultFactoryError::InvalidConfiguration)?;
+
+        let factory = env.current_contract_address();
+        let mut skipped: Vec<Address> = Vec::new(&env);
+
+        for vault in batch {
+            let client = VaultClient::new(&env, &vault);
+            match client.try_factory_set_integration(&factory, &kind, &new_address) {
+                Ok(_) => {
+                    env.events().publish(
+                        (symbol_short!("int_upd"), &vault),
+                        (kind.clone(), new_address.clone())
+                    );
+                }
+                Err(_) => {
+                    env.events().publish(
+                        (symbol_short!("int_skip"), &vault),
+                        kind.clone()
+                    );
+                    skipped.push_back(vault);
+                }
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    /// Network this factory was initialized for (None for factories initialized before tagging)
+    pub fn get_network(env: Env) -> Option<Symbol> {
+        env.storage().instance().get(&NETWORK)
+    }
+
+    /// Add or remove an address on the integration denylist (admin only)
+    /// Mainnet vaults refuse denylisted addresses as router, staking pool or
+    /// Soroswap factory, e.g. the testnet deployments of those contracts.
+    pub fn set_denied(
+        env: Env,
+        admin: Address,
+        address: Address,
+        denied: bool,
+    ) -> Result<(), VaultFactoryError> {
+        admin.require_auth();
+        
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+        
+        if admin != stored_admin {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        
+        let mut denylist = Self::get_denylist(env.clone());
+        match (denylist.first_index_of(&address), denied) {
+            (None, true) => {
+                if denylist.len() >= MAX_DENYLIST {
+                    return Err(VaultFactoryError::InvalidConfiguration);
+                }
+                denylist.push_back(address);
+            }
+            (Some(index), false) => {
+                denylist.remove(index);
+            }
+            _ => {}
+        }
+        env.storage().instance().set(&DENIED, &denylist);
+        
+        Ok(())
+    }
+
+    /// Whether `address` is on the integration denylist
+    pub fn is_denied(env: Env, address: Address) -> bool {
+        Self::get_denylist(env).contains(&address)
+    }
+
+    /// Every denylisted address
+    pub fn get_denylist(env: Env) -> Vec<Address> {
+        env.storage().instance()
+            .get(&DENIED)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Report a vault's current TVL and holder count (called by the vault itself)
+    /// Vaults deployed on a network push this after every deposit, withdrawal
+    /// and sync. Protocol totals move by the difference from the vault's
+    /// previous report, so they stay exact without re-reading every vault.
+    pub fn report_stats(env: Env, vault: Address, tvl: i128, users: u32) -> Result<(), VaultFactoryError> {
+        vault.require_auth();
+        
+        if !Self::is_vault(env.clone(), vault.clone()) {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        if has_entry(&env, &(ARCHIVED, &vault)) {
+            return Err(VaultFactoryError::VaultArchived);
+        }
+        if tvl < 0 {
+            return Err(VaultFactoryError::InvalidAmount);
+        }
+        
+        let previous = Self::get_vault_stats(env.clone(), vault.clone());
+        let mut stats = Self::get_protocol_stats(env.clone());
+        let (previous_tvl, previous_users) = match previous {
+            Some(previous) => previous,
+            None => {
+                stats.vault_count_active = stats.vault_count_active.checked_add(1)
+                    .ok_or(VaultFactoryError::InvalidAmount)?;
+                (0, 0)
+            }
+        };
+        
+        stats.total_tvl = stats.total_tvl.checked_sub(previous_tvl)
+            .and_then(|v| v.checked_add(tvl))
+            .ok_or(VaultFactoryError::InvalidAmount)?;
+        stats.total_users = stats.total_users.checked_sub(previous_users)
+            .and_then(|v| v.checked_add(users))
+            .ok_or(VaultFactoryError::InvalidAmount)?;
+        
+        store_entry(&env, &(STATS, &vault), &(tvl, users));
+        env.storage().instance().set(&PROTOCOL_STATS, &stats);
+        
+        Ok(())
+    }
+
+    /// Take a vault out of the protocol totals for good (admin only)
+    /// Its last report is subtracted and it can't report again.
+    pub fn archive_vault(env: Env, admin: Address, vault: Address) -> Result<(), VaultFactoryError> {
+        admin.require_auth();
+        
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+        
+        if admin != stored_admin {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        if !Self::is_vault(env.clone(), vault.clone()) {
+            return Err(VaultFactoryError::InvalidConfiguration);
+        }
+        if has_entry(&env, &(ARCHIVED, &vault)) {
+            return Err(VaultFactoryError::VaultArchived);
+        }
+        
+        if let Some((tvl, users)) = Self::get_vault_stats(env.clone(), vault.clone()) {
+            let mut stats = Self::get_protocol_stats(env.clone());
+            stats.total_tvl = stats.total_tvl.checked_sub(tvl)
+                .ok_or(VaultFactoryError::InvalidAmount)?;
+            stats.total_users = stats.total_users.checked_sub(users)
+                .ok_or(VaultFactoryError::InvalidAmount)?;
+            stats.vault_count_active = stats.vault_count_active.checked_sub(1)
+                .ok_or(VaultFactoryError::InvalidAmount)?;
+            env.storage().instance().set(&PROTOCOL_STATS, &stats);
+            remove_entry(&env, &(STATS, &vault));
+        }
+        store_entry(&env, &(ARCHIVED, &vault), &true);
+        
+        Ok(())
+    }
+
+    /// Whether a vault has been archived
+    pub fn is_archived(env: Env, vault: Address) -> bool {
+        has_entry(&env, &(ARCHIVED, &vault))
+    }
+
+    /// A vault's last reported (tvl, users), if it has reported and isn't archived
+    pub fn get_vault_stats(env: Env, vault: Address) -> Option<(i128, u32)> {
+        load_entry(&env, &(STATS, &vault))
+    }
+
+    /// Protocol-wide totals across every reporting vault
+    pub fn get_protocol_stats(env: Env) -> ProtocolStats {
+        env.storage().instance()
+            .get(&PROTOCOL_STATS)
+            .unwrap_or_default()
+    }
+
+    /// Publish (or clear) a canonical address for this deployment under `key` (admin only)
+    /// e.g. "router", "oracle", "nft", so clients discover them on-chain
+    pub fn set_known_address(
+        env: Env,
+        admin: Address,
+        key: Symbol,
+        address: Option<Address>,
+    ) -> Result<(), VaultFactoryError> {
+        admin.require_auth();
+        
+        let stored_admin: Address = env.storage().instance()
+            .get(&ADMIN)
+            .ok_or(VaultFactoryError::NotInitialized)?;
+        
+        if admin != stored_admin {
+            return Err(VaultFactoryError::Unauthorized);
+        }
+        
+        let mut known = Self::get_known_addresses(env.clone());
+        match address {
+            Some(address) => known.set(key, address),
+            None => {
+                known.remove(key);
+            }
+        }
+        env.storage().instance().set(&KNOWN, &known);
+        
+        Ok(())
+    }
+
+    /// Canonical address published under `key`, if any
+    pub fn get_known_address(env: Env, key: Symbol) -> Option<Address> {
+        Self::get_known_addresses(env).get(key)
+    }
+
+    /// Every published address, keyed as set
+    pub fn get_known_addresses(env: Env) -> Map<Symbol, Address> {
+        env.storage().instance()
+            .get(&KNOWN)
+            .unwrap_or(Map::new(&env))
+    }
+
+    /// Addresses a front-end needs in one read: this factory under "factory"
+    /// plus every published address
+    pub fn get_contract_addresses(env: Env) -> Map<Symbol, Address> {
+        let mut addresses = Self::get_known_addresses(env.clone());
+        addresses.set(symbol_short!("factory"), env.current_contract_address());
+        addresses
+    }
+
     /// Get vault contract WASM hash
     pub fn get_vault_wasm_hash(env: Env) -> Result<BytesN<32>, VaultFactoryError> {
         env.storage().instance()
@@ -127,6 +520,34 @@ impl VaultFactory {
             .unwrap_or(Vec::new(&env))
     }
 
+    /// Get the vaults deployed for `owner`, oldest first
+    pub fn get_vaults_by_owner(env: Env, owner: Address) -> Vec<Address> {
+        load_entry(&env, &(OWNED, owner))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Check whether an address is a vault deployed by this factory
+    pub fn is_vault(env: Env, vault: Address) -> bool {
+        has_entry(&env, &(RECORD, &vault))
+    }
+
+    /// Get the deployment record (salt, wasm hash, creator) for a vault
+    pub fn get_vault_record(env: Env, vault: Address) -> Result<VaultRecord, VaultFactoryError> {
+        load_entry(&env, &(RECORD, &vault))
+            .ok_or(VaultFactoryError::InvalidConfiguration)
+    }
+
+    /// Address this factory deploys to for a given salt
+    /// Soroban derives contract addresses from the deployer and salt alone, so
+    /// `hash` does not change the result; it is taken so a full VaultRecord can
+    /// be checked in one call.
+    pub fn recompute_address(env: Env, salt: BytesN<32>, hash: BytesN<32>) -> Address {
+        let _ = hash;
+        env.deployer()
+            .with_current_contract(salt)
+            .deployed_address()
+    }
+
     /// Get vault at specific index
     pub fn get_vault_at(env: Env, index: u32) -> Result<Address, VaultFactoryError> {
         let vaults: Vec<Address> = env.storage().instance()
@@ -138,6 +559,42 @@ impl VaultFactory {
     }
 }
 
+// Load a per-vault or per-owner entry, extending its TTL
+// Entries written to instance storage by earlier versions are moved over.
+fn load_entry<K, V>(env: &Env, key: &K) -> Option<V>
+where
+    K: IntoVal<Env, Val>,
+    V: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    if let Some(value) = env.storage().persistent().get::<K, V>(key) {
+        env.storage().persistent().extend_ttl(key, ENTRY_TTL_THRESHOLD, ENTRY_TTL_EXTEND_TO);
+        return Some(value);
+    }
+
+    let legacy: V = env.storage().instance().get(key)?;
+    env.storage().instance().remove(key);
+    store_entry(env, key, &legacy);
+    Some(legacy)
+}
+
+fn store_entry<K, V>(env: &Env, key: &K, value: &V)
+where
+    K: IntoVal<Env, Val>,
+    V: IntoVal<Env, Val>,
+{
+    env.storage().persistent().set(key, value);
+    env.storage().persistent().extend_ttl(key, ENTRY_TTL_THRESHOLD, ENTRY_TTL_EXTEND_TO);
+}
+
+fn has_entry<K: IntoVal<Env, Val>>(env: &Env, key: &K) -> bool {
+    env.storage().persistent().has(key) || env.storage().instance().has(key)
+}
+
+fn remove_entry<K: IntoVal<Env, Val>>(env: &Env, key: &K) {
+    env.storage().persistent().remove(key);
+    env.storage().instance().remove(key);
+}
+
 /// Create a unique salt for vault deployment
 fn create_salt(count: u32) -> [u8; 32] {
     let mut salt = [0u8; 32];
@@ -145,3 +602,6 @@ fn create_salt(count: u32) -> [u8; 32] {
     salt[0..4].copy_from_slice(&count_bytes);
     salt
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,507 @@
+#![cfg(test)]
+
+extern crate std;
+
+use soroban_sdk::{
+    contract, contracterror, contractimpl, contracttype, symbol_short,
+    testutils::Address as _,
+    token::{StellarAssetClient, TokenClient},
+    vec, Address, BytesN, Env, IntoVal, String, Symbol, Val, Vec,
+};
+
+use crate::{
+    ProtocolStats, RebalanceRule, VaultClient, VaultConfig, VaultFactory, VaultFactoryClient,
+    VaultFactoryError,
+};
+
+// Prebuilt vault contract used where the factory has to actually deploy
+const VAULT_WASM: &[u8] = include_bytes!("../../../backend/contracts/syft_vault.wasm");
+
+// The vault's get_network_status result
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkStatus {
+    pub network: Option<Symbol>,
+    pub denied: Vec<(Symbol, Address)>,
+}
+
+// Minimal vault that accepts integration updates only from its trusted factory
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum MockVaultError {
+    Unauthorized = 3,
+}
+
+#[contract]
+pub struct MockVault;
+
+#[contractimpl]
+impl MockVault {
+    pub fn init(env: Env, factory: Address, allowed: bool) {
+        env.storage().instance().set(&symbol_short!("FACTORY"), &factory);
+        env.storage().instance().set(&symbol_short!("ALLOWED"), &allowed);
+    }
+
+    pub fn factory_set_integration(
+        env: Env,
+        factory: Address,
+        kind: Symbol,
+        address: Address,
+    ) -> Result<(), MockVaultError> {
+        factory.require_auth();
+        let trusted: Address = env.storage().instance().get(&symbol_short!("FACTORY")).unwrap();
+        let allowed: bool = env.storage().instance().get(&symbol_short!("ALLOWED")).unwrap();
+        if trusted != factory || !allowed {
+            return Err(MockVaultError::Unauthorized);
+        }
+        env.storage().instance().set(&kind, &address);
+        Ok(())
+    }
+
+    pub fn get_integration(env: Env, kind: Symbol) -> Option<Address> {
+        env.storage().instance().get(&kind)
+    }
+}
+
+// Upload the vault wasm for the factory to deploy
+// Deploying and initializing the full vault is beyond the default test budget.
+fn upload_vault_wasm(env: &Env) -> BytesN<32> {
+    env.budget().reset_unlimited();
+    env.deployer().upload_contract_wasm(VAULT_WASM)
+}
+
+fn create_factory<'a>(env: &Env, admin: &Address) -> VaultFactoryClient<'a> {
+    create_factory_on(env, admin, symbol_short!("testnet"))
+}
+
+fn create_factory_on<'a>(env: &Env, admin: &Address, network: Symbol) -> VaultFactoryClient<'a> {
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(env, &factory_id);
+    factory.initialize(admin, &BytesN::from_array(env, &[1u8; 32]), &network);
+    factory
+}
+
+// Config for a vault of one fresh token, without rules or integrations
+fn vault_config(env: &Env, owner: &Address) -> VaultConfig {
+    let token = env.register_stellar_asset_contract_v2(owner.clone());
+    VaultConfig {
+        owner: owner.clone(),
+        name: String::from_str(env, "Vault"),
+        assets: vec![env, token.address()],
+        rules: vec![env],
+        router_address: None,
+        staking_pool_address: None,
+        factory_address: None,
+    }
+}
+
+fn create_mock_vault<'a>(env: &Env, factory: &Address, allowed: bool) -> MockVaultClient<'a> {
+    let vault_id = env.register_contract(None, MockVault);
+    let vault = MockVaultClient::new(env, &vault_id);
+    vault.init(factory, &allowed);
+    vault
+}
+
+#[test]
+fn push_integration_update_skips_vaults_without_opt_in() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory = create_factory(&env, &admin);
+
+    let allowed_a = create_mock_vault(&env, &factory.address, true);
+    let refused = create_mock_vault(&env, &factory.address, false);
+    let allowed_b = create_mock_vault(&env, &factory.address, true);
+
+    let router = Address::generate(&env);
+    let kind = symbol_short!("router");
+    let skipped = factory.push_integration_update(
+        &admin,
+        &kind,
+        &router,
+        &vec![&env, allowed_a.address.clone(), refused.address.clone(), allowed_b.address.clone()],
+    );
+
+    assert_eq!(skipped, vec![&env, refused.address.clone()]);
+    assert_eq!(allowed_a.get_integration(&kind), Some(router.clone()));
+    assert_eq!(allowed_b.get_integration(&kind), Some(router.clone()));
+    assert_eq!(refused.get_integration(&kind), None);
+}
+
+#[test]
+fn push_integration_update_is_admin_only_and_bounded() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory = create_factory(&env, &admin);
+    let router = Address::generate(&env);
+    let kind = symbol_short!("router");
+
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        factory.try_push_integration_update(&stranger, &kind, &router, &vec![&env]),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+
+    // A full batch of addresses that aren't vaults is accepted and skipped
+    let mut too_many = vec![&env];
+    for _ in 0..25 {
+        too_many.push_back(Address::generate(&env));
+    }
+    assert_eq!(factory.push_integration_update(&admin, &kind, &router, &too_many).len(), 25);
+
+    too_many.push_back(Address::generate(&env));
+    assert_eq!(
+        factory.try_push_integration_update(&admin, &kind, &router, &too_many),
+        Err(Ok(VaultFactoryError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn vault_records_recompute_to_their_addresses() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+    factory.set_permissionless(&admin, &true);
+
+    for _ in 0..3 {
+        let owner = Address::generate(&env);
+        let vault = factory.create_vault(&owner, &vault_config(&env, &owner));
+
+        let record = factory.get_vault_record(&vault);
+        assert_eq!(record.address, vault);
+        assert_eq!(record.wasm_hash, wasm_hash);
+        assert_eq!(record.creator, owner);
+        assert_eq!(factory.recompute_address(&record.salt, &record.wasm_hash), vault);
+        assert!(factory.is_vault(&vault));
+    }
+
+    let stranger = Address::generate(&env);
+    assert!(!factory.is_vault(&stranger));
+    assert_eq!(
+        factory.try_get_vault_record(&stranger),
+        Err(Ok(VaultFactoryError::InvalidConfiguration))
+    );
+}
+
+#[test]
+fn known_addresses_are_published_by_the_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory = create_factory(&env, &admin);
+    let router = Address::generate(&env);
+    let oracle = Address::generate(&env);
+    let nft = Address::generate(&env);
+
+    assert_eq!(factory.get_known_address(&symbol_short!("router")), None);
+
+    factory.set_known_address(&admin, &symbol_short!("router"), &Some(router.clone()));
+    factory.set_known_address(&admin, &symbol_short!("oracle"), &Some(oracle.clone()));
+    factory.set_known_address(&admin, &symbol_short!("nft"), &Some(nft.clone()));
+    assert_eq!(factory.get_known_address(&symbol_short!("router")), Some(router.clone()));
+    assert_eq!(factory.get_known_address(&symbol_short!("nft")), Some(nft.clone()));
+
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        factory.try_set_known_address(&stranger, &symbol_short!("router"), &Some(stranger.clone())),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+
+    // Clearing a key drops it from the bundle; the factory itself is always listed
+    factory.set_known_address(&admin, &symbol_short!("oracle"), &None);
+    let bundle = factory.get_contract_addresses();
+    assert_eq!(bundle.len(), 3);
+    assert_eq!(bundle.get(symbol_short!("factory")), Some(factory.address.clone()));
+    assert_eq!(bundle.get(symbol_short!("router")), Some(router));
+    assert_eq!(bundle.get(symbol_short!("oracle")), None);
+}
+
+#[test]
+fn mainnet_factory_refuses_denylisted_integrations_until_removed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory = create_factory_on(&env, &admin, symbol_short!("mainnet"));
+    assert_eq!(factory.get_network(), Some(symbol_short!("mainnet")));
+    let vault = create_mock_vault(&env, &factory.address, true);
+    let testnet_router = Address::generate(&env);
+    let kind = symbol_short!("router");
+
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        factory.try_set_denied(&stranger, &testnet_router, &true),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+
+    factory.set_denied(&admin, &testnet_router, &true);
+    assert!(factory.is_denied(&testnet_router));
+    assert_eq!(
+        factory.try_push_integration_update(&admin, &kind, &testnet_router, &vec![&env, vault.address.clone()]),
+        Err(Ok(VaultFactoryError::DeniedIntegration))
+    );
+    assert_eq!(vault.get_integration(&kind), None);
+
+    factory.set_denied(&admin, &testnet_router, &false);
+    assert_eq!(factory.get_denylist().len(), 0);
+    factory.push_integration_update(&admin, &kind, &testnet_router, &vec![&env, vault.address.clone()]);
+    assert_eq!(vault.get_integration(&kind), Some(testnet_router.clone()));
+
+    // Off mainnet the list is informational only
+    let testnet = create_factory(&env, &admin);
+    let testnet_vault = create_mock_vault(&env, &testnet.address, true);
+    testnet.set_denied(&admin, &testnet_router, &true);
+    testnet.push_integration_update(&admin, &kind, &testnet_router, &vec![&env, testnet_vault.address.clone()]);
+    assert_eq!(testnet_vault.get_integration(&kind), Some(testnet_router));
+}
+
+#[test]
+fn protocol_stats_follow_reports_and_archival() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+
+    let config = vault_config(&env, &admin);
+    let first = factory.create_vault(&admin, &config);
+    let second = factory.create_vault(&admin, &config);
+    assert_eq!(factory.get_protocol_stats(), ProtocolStats::default());
+
+    let stranger = Address::generate(&env);
+    assert_eq!(factory.try_report_stats(&stranger, &100, &1), Err(Ok(VaultFactoryError::Unauthorized)));
+    assert_eq!(factory.try_report_stats(&first, &-1, &1), Err(Ok(VaultFactoryError::InvalidAmount)));
+
+    factory.report_stats(&first, &1_000, &3);
+    factory.report_stats(&second, &500, &2);
+    assert_eq!(
+        factory.get_protocol_stats(),
+        ProtocolStats { total_tvl: 1_500, vault_count_active: 2, total_users: 5 }
+    );
+
+    // A later report moves the totals by its difference only
+    factory.report_stats(&first, &400, &1);
+    assert_eq!(
+        factory.get_protocol_stats(),
+        ProtocolStats { total_tvl: 900, vault_count_active: 2, total_users: 3 }
+    );
+    assert_eq!(factory.get_vault_stats(&first), Some((400, 1)));
+
+    assert_eq!(factory.try_archive_vault(&stranger, &first), Err(Ok(VaultFactoryError::Unauthorized)));
+    factory.archive_vault(&admin, &first);
+    assert_eq!(
+        factory.get_protocol_stats(),
+        ProtocolStats { total_tvl: 500, vault_count_active: 1, total_users: 2 }
+    );
+    assert!(factory.is_archived(&first));
+    assert_eq!(factory.get_vault_stats(&first), None);
+    assert_eq!(factory.try_report_stats(&first, &100, &1), Err(Ok(VaultFactoryError::VaultArchived)));
+    assert_eq!(factory.try_archive_vault(&admin, &first), Err(Ok(VaultFactoryError::VaultArchived)));
+
+    // Totals can't overflow
+    let third = factory.create_vault(&admin, &config);
+    factory.report_stats(&third, &(i128::MAX - 500), &0);
+    assert_eq!(factory.get_protocol_stats().total_tvl, i128::MAX);
+    assert_eq!(factory.try_report_stats(&second, &501, &2), Err(Ok(VaultFactoryError::InvalidAmount)));
+    assert_eq!(factory.get_vault_stats(&second), Some((500, 2)));
+}
+
+#[test]
+fn deployed_vaults_report_their_own_stats() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+
+    let config = vault_config(&env, &admin);
+    let vault = factory.create_vault(&admin, &config);
+    let token = StellarAssetClient::new(&env, &config.assets.get(0).unwrap());
+    let user = Address::generate(&env);
+    token.mint(&user, &1_000);
+
+    let deposit = Symbol::new(&env, "deposit");
+    let shares: i128 = env.invoke_contract(&vault, &deposit, vec![&env, user.to_val(), 600_i128.into_val(&env)]);
+    env.invoke_contract::<i128>(&vault, &deposit, vec![&env, user.to_val(), 400_i128.into_val(&env)]);
+    assert_eq!(factory.get_vault_stats(&vault), Some((1_000, 1)));
+    assert_eq!(
+        factory.get_protocol_stats(),
+        ProtocolStats { total_tvl: 1_000, vault_count_active: 1, total_users: 1 }
+    );
+
+    // Once archived its reports are refused, but its users can still leave
+    factory.archive_vault(&admin, &vault);
+    env.invoke_contract::<Val>(&vault, &Symbol::new(&env, "withdraw"), vec![&env, user.to_val(), shares.into_val(&env)]);
+    assert_eq!(factory.get_protocol_stats(), ProtocolStats::default());
+    assert_eq!(TokenClient::new(&env, &token.address).balance(&user), 600);
+}
+
+#[test]
+fn create_vault_is_admin_only_unless_permissionless() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+
+    let config_for = |owner: &Address| vault_config(&env, owner);
+    let stranger = Address::generate(&env);
+    assert!(!factory.is_permissionless());
+    assert_eq!(
+        factory.try_create_vault(&stranger, &config_for(&stranger)),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+    assert_eq!(
+        factory.try_create_vault(&stranger, &config_for(&admin)),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+    // The admin may deploy a vault owned by someone else
+    let vault = factory.create_vault(&admin, &config_for(&stranger));
+    assert_eq!(factory.get_vaults(), vec![&env, vault.clone()]);
+    assert_eq!(factory.get_vault_record(&vault).creator, admin);
+    assert_eq!(factory.get_vaults_by_owner(&stranger), vec![&env, vault]);
+
+    assert_eq!(
+        factory.try_set_permissionless(&stranger, &true),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+    factory.set_permissionless(&admin, &true);
+    let open_vault = factory.create_vault(&stranger, &config_for(&stranger));
+    assert_eq!(factory.get_vault_record(&open_vault).creator, stranger);
+
+    factory.set_permissionless(&admin, &false);
+    assert_eq!(
+        factory.try_create_vault(&stranger, &config_for(&stranger)),
+        Err(Ok(VaultFactoryError::Unauthorized))
+    );
+    assert_eq!(factory.get_vault_count(), 2);
+}
+
+#[test]
+fn vaults_are_listed_by_owner() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+    factory.set_permissionless(&admin, &true);
+
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let config_for = |owner: &Address| vault_config(&env, owner);
+    let alice_first = factory.create_vault(&alice, &config_for(&alice));
+    let bobs = factory.create_vault(&bob, &config_for(&bob));
+    let alice_second = factory.create_vault(&alice, &config_for(&alice));
+
+    assert_eq!(factory.get_vaults_by_owner(&alice), vec![&env, alice_first, alice_second]);
+    assert_eq!(factory.get_vaults_by_owner(&bob), vec![&env, bobs]);
+    assert_eq!(factory.get_vaults_by_owner(&admin), vec![&env]);
+}
+
+#[test]
+fn vault_entries_live_in_persistent_storage() {
+    use crate::{ENTRY_TTL_EXTEND_TO, OWNED, RECORD, STATS};
+    use soroban_sdk::testutils::storage::Persistent as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("testnet"));
+
+    let vault = factory.create_vault(&admin, &vault_config(&env, &admin));
+    factory.report_stats(&vault, &1_000, &3);
+    env.as_contract(&factory.address, || {
+        let persistent = env.storage().persistent();
+        for key in [(RECORD, vault.clone()), (OWNED, admin.clone()), (STATS, vault.clone())] {
+            assert!(!env.storage().instance().has(&key));
+            assert_eq!(persistent.get_ttl(&key), ENTRY_TTL_EXTEND_TO);
+        }
+    });
+
+    // Entries an earlier version left in instance storage move over when read
+    let carol = Address::generate(&env);
+    env.as_contract(&factory.address, || {
+        env.storage().instance().set(&(OWNED, carol.clone()), &vec![&env, vault.clone()]);
+    });
+    assert_eq!(factory.get_vaults_by_owner(&carol), vec![&env, vault.clone()]);
+    env.as_contract(&factory.address, || {
+        assert!(!env.storage().instance().has(&(OWNED, carol.clone())));
+        assert!(env.storage().persistent().has(&(OWNED, carol.clone())));
+    });
+}
+
+#[test]
+fn created_vaults_are_initialized_with_the_given_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let factory_id = env.register_contract(None, VaultFactory);
+    let factory = VaultFactoryClient::new(&env, &factory_id);
+    let wasm_hash = upload_vault_wasm(&env);
+    factory.initialize(&admin, &wasm_hash, &symbol_short!("mainnet"));
+    factory.set_permissionless(&admin, &true);
+
+    let owner = Address::generate(&env);
+    let mut config = vault_config(&env, &owner);
+    config.rules = vec![&env, RebalanceRule {
+        condition_type: String::from_str(&env, "time"),
+        threshold: 3600,
+        action: String::from_str(&env, "rebalance"),
+        target_allocation: vec![&env, 100_0000],
+    }];
+    config.router_address = Some(Address::generate(&env));
+    let vault = factory.create_vault(&owner, &config);
+
+    // Nobody can initialize or stamp it again, and it already holds the config
+    let vault_client = VaultClient::new(&env, &vault);
+    assert!(vault_client.try_initialize(&config).is_err());
+    assert!(vault_client.try_initialize_from_factory(&owner, &symbol_short!("testnet"), &config).is_err());
+    let stored: VaultConfig = env.invoke_contract(&vault, &Symbol::new(&env, "get_config"), vec![&env]);
+    assert_eq!(stored, config);
+    let status: NetworkStatus = env.invoke_contract(&vault, &Symbol::new(&env, "get_network_status"), vec![&env]);
+    assert_eq!(status.network, Some(symbol_short!("mainnet")));
+
+    // Configs the vault refuses, or denylisted integrations, deploy nothing
+    let mut no_assets = vault_config(&env, &owner);
+    no_assets.assets = vec![&env];
+    assert_eq!(
+        factory.try_create_vault(&owner, &no_assets),
+        Err(Ok(VaultFactoryError::InvalidConfiguration))
+    );
+    let testnet_router = Address::generate(&env);
+    factory.set_denied(&admin, &testnet_router, &true);
+    let mut denied = vault_config(&env, &owner);
+    denied.router_address = Some(testnet_router);
+    assert_eq!(
+        factory.try_create_vault(&owner, &denied),
+        Err(Ok(VaultFactoryError::DeniedIntegration))
+    );
+    assert_eq!(factory.get_vaults(), vec![&env, vault]);
+}
@@ -1,28 +1,98 @@
 #![no_std]
 
-use soroban_sdk::{contract, contractimpl, contracttype, contracterror, Address, Env, String, Vec, symbol_short, Map, Symbol};
+use soroban_sdk::{contract, contractclient, contractimpl, contracttype, token, Address, Env, IntoVal, String, TryFromVal, Val, Vec, symbol_short, Map, Symbol};
+use syft_errors::bounded_iter;
 
 const NFT_COUNTER: Symbol = symbol_short!("NFT_CNT");
+const ADMIN: Symbol = symbol_short!("ADMIN");
+const FACTORY: Symbol = symbol_short!("FACTORY");
+const CLAIM_WINDOW: Symbol = symbol_short!("CLM_WIN");
+const DIST_COUNTER: Symbol = symbol_short!("DIST_CNT");
+const PAUSED: Symbol = symbol_short!("PAUSED");
 const NFT_PREFIX: &str = "NFT";
 const VAULT_NFTS_PREFIX: &str = "V_NFTS";
+const OWED_PREFIX: &str = "OWED";
+const TOTAL_OWED_PREFIX: &str = "OWED_TOT";
+const OWED_AT_PREFIX: &str = "OWED_AT";
+const PROFIT_SHARE_PREFIX: &str = "P_SHARE";
+const VAULT_MINTER_PREFIX: &str = "V_MINTER";
+const DISTRIBUTOR_PREFIX: &str = "DISTRIB";
+const REINVEST_PREFIX: &str = "REINVEST";
+const REINVEST_OWED_PREFIX: &str = "RINV_OWE";
+const OWNERSHIP_TOTAL_PREFIX: &str = "OWN_TOT";
+const LOCKED_UNTIL_PREFIX: &str = "LOCKED";
+const APPROVAL_PREFIX: &str = "APPROVE";
+const OPERATOR_PREFIX: &str = "OPERATOR";
+const HOLDER_NFTS_PREFIX: &str = "H_NFTS";
+const NFT_METADATA_PREFIX: &str = "NFT_META";
+// Recorded distributions live in persistent storage, one entry per holder
+const DISTRIBUTION_PREFIX: &str = "DIST";
+const ENTITLEMENT_PREFIX: &str = "ENTITLE";
+const DIST_CLAIMED_PREFIX: &str = "D_CLAIMED";
+const HOLDER_DISTS_PREFIX: &str = "H_DISTS";
+const TRANSFER_HISTORY_PREFIX: &str = "XFER_HIST";
+const ROYALTY_PREFIX: &str = "ROYALTY";
+const NFT_SHARES_PREFIX: &str = "NFT_SHRS";
+const REDEEM_BASE_PREFIX: &str = "RDM_BASE";
 const MAX_OWNERSHIP_PCT: i128 = 10000; // 100% = 10000 basis points
+const MAX_VAULT_NFTS: u32 = 100; // NFTs per vault, bounding distribution loops
+const MAX_HOLDER_NFTS: u32 = 200; // NFTs per holder, bounding portfolio reads
+const MAX_HOLDER_DISTS: u32 = 200; // Unclaimed distributions per holder, bounding get_claimable
+const MAX_ROYALTY_BPS: i128 = 1000; // 10% of a sale price
+const MAX_TRANSFER_HISTORY: u32 = 20; // Transfer records kept per NFT; older ones are dropped
+const LEDGERS_PER_DAY: u32 = 17_280; // At ~5s per ledger
+const NFT_TTL_THRESHOLD: u32 = 30 * LEDGERS_PER_DAY; // Extend NFT records once they have less than this left
+const NFT_TTL_EXTEND_TO: u32 = 90 * LEDGERS_PER_DAY; // and bring them back up to this
 
 // Error types
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum VaultNFTError {
-    Unauthorized = 1,
-    InvalidAmount = 2,
-    NFTNotFound = 3,
-    InvalidOwnership = 4,
-    OwnershipExceeded = 5,
+pub use syft_errors::VaultNFTError;
+use syft_errors::VaultError;
+
+// Vault registry exposed by the vault factory
+#[contractclient(name = "FactoryClient")]
+pub trait VaultRegistryInterface {
+    fn is_vault(env: Env, vault: Address) -> bool;
+}
+
+// Vault entry point profits are reinvested and NFTs funded through
+#[contractclient(name = "VaultDepositClient")]
+pub trait VaultDepositInterface {
+    fn deposit_for(env: Env, payer: Address, beneficiary: Address, amount: i128) -> i128;
+}
+
+// Vault entry points an NFT is redeemed through
+#[contractclient(name = "VaultSharesClient")]
+pub trait VaultSharesInterface {
+    fn withdraw(env: Env, user: Address, shares: i128) -> Result<WithdrawReceipt, VaultError>;
+}
+
+/// Mirror of the vault's WithdrawReceipt
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WithdrawReceipt {
+    pub shares_burned: i128,
+    pub gross_amount: i128,
+    pub fees: Vec<(Symbol, i128)>,
+    pub net_amount: i128,
+    pub payout_token: Address,
 }
 
 // Data structures
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct VaultNFT {
+    pub nft_id: u64,
+    pub vault_address: Address,
+    pub ownership_percentage: i128,
+    pub holder: Address,
+    pub metadata: NFTMetadata,
+}
+
+// NFT record as earlier versions stored it, keeping only the metadata's name
+// here and the full metadata under NFT_METADATA_PREFIX
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LegacyVaultNFT {
     pub nft_id: u64,
     pub vault_address: Address,
     pub ownership_percentage: i128,
@@ -39,13 +109,74 @@ pub struct NFTMetadata {
     pub vault_performance: i128,
 }
 
+/// One change of an NFT's holder; `from` is None for the mint
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransferRecord {
+    pub from: Option<Address>,
+    pub to: Address,
+    pub timestamp: u64,
+}
+
+/// Version of the NFTLimits layout; bumped whenever fields are appended
+pub const LIMITS_VERSION: u32 = 2;
+
+/// Effective limits of this build, for client-side validation
+/// Fields are only ever appended; check `version` before reading newer ones.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NFTLimits {
+    pub version: u32,
+    pub min_ownership_bps: i128,  // Smallest ownership_percentage mint_nft accepts
+    pub max_ownership_bps: i128,  // Largest, and the cap on a vault's total minted
+    pub bps_scale: i128,          // 10000 = 100%
+    pub max_nfts_per_vault: u32,  // Since version 2: NFTs mint_nft allows per vault
+}
+
 #[contract]
 pub struct VaultNFTContract;
 
 #[contractimpl]
 impl VaultNFTContract {
+    /// Set the contract admin
+    /// Deploy scripts should call this straight away; every admin-only setter
+    /// returns Unauthorized until it has been called.
+    pub fn initialize(env: Env, admin: Address) -> Result<(), VaultNFTError> {
+        admin.require_auth();
+        
+        if env.storage().instance().has(&ADMIN) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        env.storage().instance().set(&ADMIN, &admin);
+        
+        Ok(())
+    }
+    
+    /// Contract admin, if one has been set
+    pub fn get_admin(env: Env) -> Option<Address> {
+        env.storage().instance().get(&ADMIN)
+    }
+    
+    /// Halt minting, transfers and profit distribution (admin only)
+    /// Reads, claims, burns and redemptions keep working.
+    pub fn pause(env: Env, admin: Address) -> Result<(), VaultNFTError> {
+        set_paused(&env, &admin, true)
+    }
+    
+    /// Lift a pause (admin only)
+    pub fn unpause(env: Env, admin: Address) -> Result<(), VaultNFTError> {
+        set_paused(&env, &admin, false)
+    }
+    
+    /// Whether the contract is paused
+    pub fn paused(env: Env) -> bool {
+        env.storage().instance().get(&PAUSED).unwrap_or(false)
+    }
+    
     /// Mint a new vault NFT
     /// T125: Implement NFT minting function with ownership percentage
+    /// Only the minter registered for the vault with set_vault_minter may mint;
+    /// the NFT goes to the minter, who hands it on with transfer.
     pub fn mint_nft(
         env: Env,
         minter: Address,
@@ -56,49 +187,67 @@ impl VaultNFTContract {
         // Verify minter is authorized
         minter.require_auth();
         
-        // Validate ownership percentage (1-10000 basis points = 0.01% - 100%)
-        if ownership_percentage <= 0 || ownership_percentage > MAX_OWNERSHIP_PCT {
-            return Err(VaultNFTError::InvalidOwnership);
-        }
-        
-        // Get next NFT ID
-        let nft_id: u64 = env.storage()
-            .instance()
-            .get(&NFT_COUNTER)
-            .unwrap_or(0);
-        
-        let next_id = nft_id + 1;
-        
-        // Create NFT
-        let nft = VaultNFT {
-            nft_id: next_id,
-            vault_address: vault_address.clone(),
-            ownership_percentage,
-            holder: minter.clone(),
-            metadata: format_metadata(&metadata),
-        };
+        mint_to(&env, &minter, &vault_address, &minter, ownership_percentage, &metadata)
+    }
+
+    /// Mint one NFT of a vault per (recipient, ownership_percentage) pair
+    /// Same rules as mint_nft, but each NFT goes straight to its recipient and
+    /// the combined percentage must fit in the vault's remaining ownership, so
+    /// the batch mints in full or not at all. Returns the new ids in order.
+    pub fn batch_mint(
+        env: Env,
+        minter: Address,
+        vault_address: Address,
+        recipients: Vec<(Address, i128)>,
+        metadata: NFTMetadata,
+    ) -> Result<Vec<u64>, VaultNFTError> {
+        minter.require_auth();
         
-        // Store NFT
-        env.storage().instance().set(&(NFT_PREFIX, next_id), &nft);
+        if recipients.is_empty() {
+            return Err(VaultNFTError::InvalidAmount);
+        }
         
-        // Update counter
-        env.storage().instance().set(&NFT_COUNTER, &next_id);
+        let mut batch_total: i128 = 0;
+        for (_, ownership_percentage) in bounded_iter(&recipients, MAX_VAULT_NFTS, VaultNFTError::TooManyItems)? {
+            validate_ownership(ownership_percentage)?;
+            batch_total = batch_total.checked_add(ownership_percentage)
+                .ok_or(VaultNFTError::OwnershipExceeded)?;
+        }
+        if batch_total > Self::available_ownership(env.clone(), vault_address.clone())? {
+            return Err(VaultNFTError::OwnershipExceeded);
+        }
         
-        // Add to vault's NFT list
-        let mut vault_nfts: Vec<u64> = env.storage()
-            .instance()
-            .get(&(VAULT_NFTS_PREFIX, &vault_address))
-            .unwrap_or(Vec::new(&env));
-        vault_nfts.push_back(next_id);
-        env.storage().instance().set(&(VAULT_NFTS_PREFIX, &vault_address), &vault_nfts);
+        let mut nft_ids = Vec::new(&env);
+        for (recipient, ownership_percentage) in recipients.iter() {
+            nft_ids.push_back(mint_to(&env, &minter, &vault_address, &recipient, ownership_percentage, &metadata)?);
+        }
         
-        // Emit event
         env.events().publish(
-            (symbol_short!("NFT_MINT"), &vault_address),
-            (next_id, &minter, ownership_percentage)
+            (symbol_short!("NFT_BATCH"), &vault_address),
+            (nft_ids.len(), batch_total)
         );
         
-        Ok(next_id)
+        Ok(nft_ids)
+    }
+
+    /// Mint a vault NFT that can't be transferred before `locked_until`
+    /// For vesting fractional ownership; profit sharing is unaffected by the lock.
+    pub fn mint_nft_locked(
+        env: Env,
+        minter: Address,
+        vault_address: Address,
+        ownership_percentage: i128,
+        metadata: NFTMetadata,
+        locked_until: u64,
+    ) -> Result<u64, VaultNFTError> {
+        let nft_id = Self::mint_nft(env.clone(), minter, vault_address, ownership_percentage, metadata)?;
+        store_nft_record(&env, &(LOCKED_UNTIL_PREFIX, nft_id), &locked_until);
+        Ok(nft_id)
+    }
+
+    /// Timestamp an NFT is locked until, if it was minted locked
+    pub fn get_locked_until(env: Env, nft_id: u64) -> Option<u64> {
+        load_nft_record(&env, &(LOCKED_UNTIL_PREFIX, nft_id))
     }
 
     /// Transfer NFT ownership
@@ -113,9 +262,7 @@ impl VaultNFTContract {
         from.require_auth();
         
         // Get NFT
-        let mut nft: VaultNFT = env.storage()
-            .instance()
-            .get(&(NFT_PREFIX, nft_id))
+        let mut nft: VaultNFT = load_nft(&env, nft_id)
             .ok_or(VaultNFTError::NFTNotFound)?;
         
         // Verify ownership
@@ -123,101 +270,1551 @@ impl VaultNFTContract {
             return Err(VaultNFTError::Unauthorized);
         }
         
-        // Update holder
-        nft.holder = to.clone();
+        move_nft(&env, &mut nft, &to)
+    }
+
+    /// Sell an NFT to `buyer` for `price` of `payment_token`
+    /// Seller and buyer both sign. If the NFT's vault has a royalty set, that
+    /// cut of the price goes to its recipient and the seller gets the rest.
+    /// Returns the royalty paid.
+    pub fn buy_nft(
+        env: Env,
+        buyer: Address,
+        seller: Address,
+        nft_id: u64,
+        payment_token: Address,
+        price: i128,
+    ) -> Result<i128, VaultNFTError> {
+        buyer.require_auth();
+        seller.require_auth();
+        
+        validate_positive_amount(price).map_err(|_| VaultNFTError::InvalidPrice)?;
+        
+        let mut nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != seller {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        let (royalty, recipient) = match Self::get_royalty(env.clone(), nft.vault_address.clone()) {
+            Some((royalty_bps, recipient)) => {
+                let royalty = price.checked_mul(royalty_bps)
+                    .ok_or(VaultNFTError::InvalidAmount)?
+                    / MAX_OWNERSHIP_PCT;
+                (royalty, Some(recipient))
+            }
+            None => (0, None),
+        };
+        
+        move_nft(&env, &mut nft, &buyer)?;
+        
+        let token_client = token::Client::new(&env, &payment_token);
+        if let Some(recipient) = recipient.filter(|_| royalty > 0) {
+            token_client.transfer(&buyer, &recipient, &royalty);
+        }
+        token_client.transfer(&buyer, &seller, &(price - royalty));
+        
+        env.events().publish(
+            (symbol_short!("NFT_SALE"), nft_id),
+            (&seller, &buyer, payment_token, price, royalty)
+        );
+        
+        Ok(royalty)
+    }
+
+    /// Let `spender` transfer one NFT on the holder's behalf until `live_until_ledger`
+    /// Replaces any earlier approval for the NFT. The approval is cleared when
+    /// the NFT moves or is burned.
+    pub fn approve(
+        env: Env,
+        holder: Address,
+        spender: Address,
+        nft_id: u64,
+        live_until_ledger: u32,
+    ) -> Result<(), VaultNFTError> {
+        holder.require_auth();
+        
+        let nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != holder {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        if live_until_ledger < env.ledger().sequence() {
+            return Err(VaultNFTError::InvalidAmount);
+        }
         
-        // Save updated NFT
-        env.storage().instance().set(&(NFT_PREFIX, nft_id), &nft);
+        store_nft_record(&env, &(APPROVAL_PREFIX, nft_id), &(spender.clone(), live_until_ledger));
         
-        // Emit event
         env.events().publish(
-            (symbol_short!("NFT_XFER"), nft_id),
-            (&from, &to)
+            (symbol_short!("NFT_APPR"), nft_id),
+            (&holder, &spender, live_until_ledger)
         );
         
         Ok(())
     }
 
-    /// Get NFT details
-    pub fn get_nft(env: Env, nft_id: u64) -> Result<VaultNFT, VaultNFTError> {
-        env.storage()
-            .instance()
-            .get(&(NFT_PREFIX, nft_id))
-            .ok_or(VaultNFTError::NFTNotFound)
+    /// Spender currently approved for an NFT, if the approval hasn't expired
+    pub fn get_approved(env: Env, nft_id: u64) -> Option<Address> {
+        let (spender, live_until_ledger): (Address, u32) =
+            load_nft_record(&env, &(APPROVAL_PREFIX, nft_id))?;
+        
+        if live_until_ledger < env.ledger().sequence() {
+            return None;
+        }
+        
+        Some(spender)
     }
-    
-    /// Get all NFTs for a vault
-    pub fn get_vault_nfts(env: Env, vault_address: Address) -> Vec<u64> {
-        env.storage()
-            .instance()
-            .get(&(VAULT_NFTS_PREFIX, &vault_address))
-            .unwrap_or(Vec::new(&env))
+
+    /// Let (or stop letting) `operator` transfer every NFT `holder` has
+    pub fn set_approval_for_all(env: Env, holder: Address, operator: Address, approved: bool) {
+        holder.require_auth();
+        
+        let key = (OPERATOR_PREFIX, holder.clone(), operator.clone());
+        if approved {
+            store_nft_record(&env, &key, &true);
+        } else {
+            remove_nft_record(&env, &key);
+        }
+        
+        env.events().publish(
+            (symbol_short!("NFT_OPER"), &holder),
+            (&operator, approved)
+        );
     }
 
-    /// Distribute profits to NFT holders
-    /// T126: Implement profit distribution logic proportional to shares
-    pub fn distribute_profits(
+    /// Whether `operator` may transfer every NFT `holder` has
+    pub fn is_approved_for_all(env: Env, holder: Address, operator: Address) -> bool {
+        load_nft_record::<_, bool>(&env, &(OPERATOR_PREFIX, holder, operator)).is_some()
+    }
+
+    /// Transfer an NFT as an approved spender or operator of its holder
+    pub fn transfer_from(
+        env: Env,
+        spender: Address,
+        from: Address,
+        to: Address,
+        nft_id: u64,
+    ) -> Result<(), VaultNFTError> {
+        spender.require_auth();
+        
+        let mut nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != from {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        let approved = Self::get_approved(env.clone(), nft_id).as_ref() == Some(&spender)
+            || Self::is_approved_for_all(env.clone(), from, spender);
+        if !approved {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        move_nft(&env, &mut nft, &to)
+    }
+
+    /// Burn an NFT, releasing its ownership percentage for new mints
+    /// Only the current holder may burn. Profit already owed to the holder
+    /// stays claimable.
+    pub fn burn_nft(env: Env, holder: Address, nft_id: u64) -> Result<(), VaultNFTError> {
+        holder.require_auth();
+        
+        let nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != holder {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        remove_nft(&env, &nft)
+    }
+
+    /// Deposit `amount` into a vault on behalf of its NFTs
+    /// The shares are minted to this contract through the vault's deposit_for,
+    /// which draws on the payer's allowance to the vault, and counted as
+    /// backing that vault's NFTs. Shares anyone else holds in the vault are
+    /// never redeemed for NFTs. Returns the shares minted.
+    pub fn fund_nfts(
         env: Env,
+        payer: Address,
         vault_address: Address,
-        total_profit: i128,
-        _token: Address,
-    ) -> Result<Map<Address, i128>, VaultNFTError> {
-        // Verify caller
-        vault_address.require_auth();
+        amount: i128,
+    ) -> Result<i128, VaultNFTError> {
+        payer.require_auth();
+        
+        if amount <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        
+        let this = env.current_contract_address();
+        let shares = VaultDepositClient::new(&env, &vault_address)
+            .deposit_for(&payer, &this, &amount);
+        
+        let held = Self::get_nft_shares(env.clone(), vault_address.clone())
+            .checked_add(shares)
+            .ok_or(VaultNFTError::InvalidAmount)?;
+        store_nft_record(&env, &(NFT_SHARES_PREFIX, vault_address.clone()), &held);
+        
+        // Once redemptions have started, new shares join the fixed base too
+        let base_key = (REDEEM_BASE_PREFIX, vault_address.clone());
+        if let Some(base) = load_nft_record::<_, i128>(&env, &base_key) {
+            let base = base.checked_add(shares).ok_or(VaultNFTError::InvalidAmount)?;
+            store_nft_record(&env, &base_key, &base);
+        }
+        
+        env.events().publish(
+            (symbol_short!("NFT_FUND"), &vault_address),
+            (payer, amount, shares)
+        );
+        
+        Ok(shares)
+    }
+    
+    /// Vault shares this contract holds for `vault_address`'s NFTs
+    pub fn get_nft_shares(env: Env, vault_address: Address) -> i128 {
+        load_nft_record(&env, &(NFT_SHARES_PREFIX, vault_address)).unwrap_or(0)
+    }
+
+    /// Burn an NFT and pay its holder the same fraction of the vault
+    /// Only the shares deposited through fund_nfts back a vault's NFTs. Their
+    /// count is recorded at the vault's first redemption, and an NFT worth N%
+    /// always redeems N% of that record, so earlier redemptions don't shrink
+    /// later ones. The record is dropped once the vault has no NFTs left. The
+    /// payout is sent to the holder in the vault's payout token, and the
+    /// amount is returned. Nothing is burned if the vault cannot pay.
+    pub fn redeem(env: Env, holder: Address, nft_id: u64) -> Result<i128, VaultNFTError> {
+        holder.require_auth();
+        
+        let nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != holder {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        require_unlocked(&env, nft_id)?;
+        
+        let vault = VaultSharesClient::new(&env, &nft.vault_address);
+        let held = Self::get_nft_shares(env.clone(), nft.vault_address.clone());
+        let base_key = (REDEEM_BASE_PREFIX, nft.vault_address.clone());
+        let backing: i128 = load_nft_record(&env, &base_key).unwrap_or(held);
+        let shares = backing
+            .checked_mul(nft.ownership_percentage)
+            .ok_or(VaultNFTError::InvalidAmount)?
+            / MAX_OWNERSHIP_PCT;
+        if shares <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        let held = held.checked_sub(shares)
+            .filter(|left| *left >= 0)
+            .ok_or(VaultNFTError::InvalidAmount)?;
+        
+        let this = env.current_contract_address();
+        let receipt = match vault.try_withdraw(&this, &shares) {
+            Ok(Ok(receipt)) => receipt,
+            Err(Ok(VaultError::InsufficientLiquidity)) => {
+                return Err(VaultNFTError::InsufficientLiquidity);
+            }
+            _ => return Err(VaultNFTError::RedeemFailed),
+        };
+        
+        remove_nft(&env, &nft)?;
+        store_nft_record(&env, &(NFT_SHARES_PREFIX, nft.vault_address.clone()), &held);
+        if Self::get_vault_nfts(env.clone(), nft.vault_address.clone()).is_empty() {
+            remove_nft_record(&env, &base_key);
+        } else {
+            store_nft_record(&env, &base_key, &backing);
+        }
+        token::Client::new(&env, &receipt.payout_token)
+            .transfer(&this, &holder, &receipt.net_amount);
+        
+        env.events().publish(
+            (symbol_short!("NFT_RDEEM"), &nft.vault_address),
+            (nft_id, shares, receipt.net_amount)
+        );
+        
+        Ok(receipt.net_amount)
+    }
+
+    /// Split part of an NFT's ownership off into a new NFT for the same holder
+    /// The vault's total ownership is unchanged, and the new NFT inherits any
+    /// vesting lock. Returns the new NFT's id.
+    pub fn split_nft(
+        env: Env,
+        holder: Address,
+        nft_id: u64,
+        split_percentage: i128,
+    ) -> Result<u64, VaultNFTError> {
+        holder.require_auth();
+        
+        let mut nft: VaultNFT = load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        
+        if nft.holder != holder {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        validate_ownership(split_percentage)?;
+        if split_percentage >= nft.ownership_percentage {
+            return Err(VaultNFTError::InvalidOwnership);
+        }
+        
+        let mut vault_nfts = Self::get_vault_nfts(env.clone(), nft.vault_address.clone());
+        if vault_nfts.len() >= MAX_VAULT_NFTS {
+            return Err(VaultNFTError::TooManyItems);
+        }
+        
+        let new_id: u64 = env.storage()
+            .instance()
+            .get::<_, u64>(&NFT_COUNTER)
+            .unwrap_or(0) + 1;
+        
+        let split = VaultNFT {
+            nft_id: new_id,
+            vault_address: nft.vault_address.clone(),
+            ownership_percentage: split_percentage,
+            holder: holder.clone(),
+            metadata: nft.metadata.clone(),
+        };
+        nft.ownership_percentage -= split_percentage;
         
-        if total_profit <= 0 {
+        store_nft_record(&env, &(NFT_PREFIX, nft_id), &nft);
+        store_nft_record(&env, &(NFT_PREFIX, new_id), &split);
+        env.storage().instance().set(&NFT_COUNTER, &new_id);
+        vault_nfts.push_back(new_id);
+        store_nft_record(&env, &(VAULT_NFTS_PREFIX, &nft.vault_address), &vault_nfts);
+        add_holder_nft(&env, &holder, new_id)?;
+        store_nft_record(&env, &(TRANSFER_HISTORY_PREFIX, new_id), &Self::get_transfer_history(env.clone(), nft_id));
+        if let Some(locked_until) = Self::get_locked_until(env.clone(), nft_id) {
+            store_nft_record(&env, &(LOCKED_UNTIL_PREFIX, new_id), &locked_until);
+        }
+        
+        env.events().publish(
+            (symbol_short!("NFT_SPLIT"), &nft.vault_address),
+            (nft_id, nft.ownership_percentage, new_id, split_percentage)
+        );
+        
+        Ok(new_id)
+    }
+
+    /// Merge several NFTs of one vault and holder into the first of them
+    /// The survivor carries the summed ownership and the latest of the merged
+    /// vesting locks; the others are removed. The vault's total ownership is
+    /// unchanged. Returns the surviving id.
+    pub fn merge_nfts(env: Env, holder: Address, nft_ids: Vec<u64>) -> Result<u64, VaultNFTError> {
+        holder.require_auth();
+        
+        if nft_ids.len() < 2 {
             return Err(VaultNFTError::InvalidAmount);
         }
         
-        // Get all NFTs for this vault
-        let nft_ids: Vec<u64> = Self::get_vault_nfts(env.clone(), vault_address.clone());
+        let survivor_id = nft_ids.get(0).ok_or(VaultNFTError::InvalidAmount)?;
+        let mut survivor: VaultNFT = load_nft(&env, survivor_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        if survivor.holder != holder {
+            return Err(VaultNFTError::Unauthorized);
+        }
         
-        let mut distributions = Map::new(&env);
-        let mut total_distributed: i128 = 0;
+        let vault_address = survivor.vault_address.clone();
+        let mut vault_nfts = Self::get_vault_nfts(env.clone(), vault_address.clone());
+        let mut locked_until = Self::get_locked_until(env.clone(), survivor_id);
+        let mut merged = Vec::new(&env);
         
-        // Calculate distribution for each NFT holder
-        for i in 0..nft_ids.len() {
-            let nft_id = nft_ids.get(i).unwrap();
-            let nft: VaultNFT = Self::get_nft(env.clone(), nft_id)?;
+        for nft_id in bounded_iter(&nft_ids, MAX_VAULT_NFTS, VaultNFTError::TooManyItems)?.skip(1) {
+            if nft_id == survivor_id || merged.contains(nft_id) {
+                return Err(VaultNFTError::InvalidAmount);
+            }
             
-            // Calculate holder's share based on ownership percentage
-            // ownership_percentage is in basis points (10000 = 100%)
-            let holder_share = (total_profit * nft.ownership_percentage) / MAX_OWNERSHIP_PCT;
+            let nft: VaultNFT = load_nft(&env, nft_id)
+                .ok_or(VaultNFTError::NFTNotFound)?;
+            if nft.holder != holder {
+                return Err(VaultNFTError::Unauthorized);
+            }
+            if nft.vault_address != vault_address {
+                return Err(VaultNFTError::InvalidOwnership);
+            }
             
-            if holder_share > 0 {
-                // Add to or update holder's distribution
-                let current = distributions.get(nft.holder.clone()).unwrap_or(0);
-                distributions.set(nft.holder.clone(), current + holder_share);
-                total_distributed += holder_share;
+            survivor.ownership_percentage = survivor.ownership_percentage
+                .checked_add(nft.ownership_percentage)
+                .ok_or(VaultNFTError::InvalidOwnership)?;
+            locked_until = locked_until.max(Self::get_locked_until(env.clone(), nft_id));
+            if let Some(index) = vault_nfts.first_index_of(nft_id) {
+                vault_nfts.remove(index);
             }
+            merged.push_back(nft_id);
+        }
+        
+        for nft_id in merged.iter() {
+            remove_nft_record(&env, &(NFT_PREFIX, nft_id));
+            remove_nft_record(&env, &(LOCKED_UNTIL_PREFIX, nft_id));
+            remove_nft_record(&env, &(APPROVAL_PREFIX, nft_id));
+            remove_nft_record(&env, &(TRANSFER_HISTORY_PREFIX, nft_id));
+            remove_holder_nft(&env, &holder, nft_id);
+        }
+        store_nft_record(&env, &(NFT_PREFIX, survivor_id), &survivor);
+        store_nft_record(&env, &(VAULT_NFTS_PREFIX, &vault_address), &vault_nfts);
+        if let Some(locked_until) = locked_until {
+            store_nft_record(&env, &(LOCKED_UNTIL_PREFIX, survivor_id), &locked_until);
         }
         
-        // Emit distribution event
         env.events().publish(
-            (symbol_short!("PROFIT"), &vault_address),
-            (total_profit, total_distributed, distributions.len())
+            (symbol_short!("NFT_MERGE"), &vault_address),
+            (survivor_id, merged, survivor.ownership_percentage)
         );
         
-        Ok(distributions)
+        Ok(survivor_id)
     }
-    
-    /// Get total ownership percentage for a vault (should not exceed 100%)
-    pub fn get_total_ownership(env: Env, vault_address: Address) -> Result<i128, VaultNFTError> {
-        let nft_ids: Vec<u64> = Self::get_vault_nfts(env.clone(), vault_address);
-        let mut total: i128 = 0;
+
+    /// Configure (or clear) the factory used to verify vault addresses on mint
+    /// Admin only.
+    pub fn set_factory(
+        env: Env,
+        admin: Address,
+        factory: Option<Address>,
+    ) -> Result<(), VaultNFTError> {
+        admin.require_auth();
+        require_admin(&env, &admin)?;
         
-        for i in 0..nft_ids.len() {
-            let nft_id = nft_ids.get(i).unwrap();
-            let nft: VaultNFT = Self::get_nft(env.clone(), nft_id)?;
-            total += nft.ownership_percentage;
+        match factory {
+            Some(factory) => env.storage().instance().set(&FACTORY, &factory),
+            None => env.storage().instance().remove(&FACTORY),
         }
         
-        Ok(total)
+        Ok(())
     }
-}
 
-// Helper function to format metadata
-fn format_metadata(metadata: &NFTMetadata) -> String {
-    // Simple JSON-like formatting for metadata
-    metadata.name.clone()
-}
+    /// Configure (or clear) how long holders have to claim a distribution, in seconds
+    /// Once a holder's window has passed the admin may reclaim what they are owed
+    /// with reclaim_expired. Admin only.
+    pub fn set_claim_window(
+        env: Env,
+        admin: Address,
+        window_secs: Option<u64>,
+    ) -> Result<(), VaultNFTError> {
+        admin.require_auth();
+        require_admin(&env, &admin)?;
+        
+        match window_secs {
+            Some(window_secs) => env.storage().instance().set(&CLAIM_WINDOW, &window_secs),
+            None => env.storage().instance().remove(&CLAIM_WINDOW),
+        }
+        
+        Ok(())
+    }
+    
+    /// Claim window in seconds, if one is configured
+    pub fn get_claim_window(env: Env) -> Option<u64> {
+        env.storage().instance().get(&CLAIM_WINDOW)
+    }
+    
+    /// Last moment `holder` can claim what they are owed in `token`
+    /// None when nothing is owed or no claim window is configured.
+    pub fn get_claim_deadline(env: Env, holder: Address, token: Address) -> Option<u64> {
+        if Self::get_owed(env.clone(), holder.clone(), token.clone()) <= 0 {
+            return None;
+        }
+        claim_deadline(&env, &holder, &token)
+    }
+
+    /// Register the minter allowed to mint NFTs for `vault_address` (admin only)
+    /// Typically the vault contract itself or its owner.
+    pub fn set_vault_minter(
+        env: Env,
+        admin: Address,
+        vault_address: Address,
+        minter: Address,
+    ) -> Result<(), VaultNFTError> {
+        admin.require_auth();
+        require_admin(&env, &admin)?;
+        
+        require_known_vault(&env, &vault_address)?;
+        
+        env.storage().instance().set(&(VAULT_MINTER_PREFIX, vault_address.clone()), &minter);
+        
+        env.events().publish(
+            (symbol_short!("MINTER"), &vault_address),
+            minter
+        );
+        
+        Ok(())
+    }
+
+    /// Register the share of a vault's realized gains owed to its NFT holders
+    /// Only the vault's registered minter may set it, and the share can only go up.
+    pub fn register_profit_share(
+        env: Env,
+        minter: Address,
+        vault_address: Address,
+        profit_share_bps: i128,
+    ) -> Result<(), VaultNFTError> {
+        minter.require_auth();
+        
+        require_known_vault(&env, &vault_address)?;
+        
+        if Self::get_vault_minter(env.clone(), vault_address.clone()) != Some(minter) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        validate_bps(profit_share_bps)?;
+        if profit_share_bps == 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        if profit_share_bps < Self::get_profit_share(env.clone(), vault_address.clone()) {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        
+        env.storage().instance().set(&(PROFIT_SHARE_PREFIX, vault_address.clone()), &profit_share_bps);
+        
+        env.events().publish(
+            (symbol_short!("P_SHARE"), &vault_address),
+            profit_share_bps
+        );
+        
+        Ok(())
+    }
+    
+    /// Share of realized gains, in basis points, the vault must distribute (0 when unregistered)
+    pub fn get_profit_share(env: Env, vault_address: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&(PROFIT_SHARE_PREFIX, vault_address))
+            .unwrap_or(0)
+    }
+    
+    /// Minter registered for a vault, if any
+    pub fn get_vault_minter(env: Env, vault_address: Address) -> Option<Address> {
+        env.storage().instance().get(&(VAULT_MINTER_PREFIX, vault_address))
+    }
+
+    /// Let `distributor` distribute profits on the vault's behalf, or revoke it with None
+    /// For vaults managed by an account rather than contract code. Only the
+    /// vault's registered minter can set it.
+    pub fn set_distributor(
+        env: Env,
+        minter: Address,
+        vault_address: Address,
+        distributor: Option<Address>,
+    ) -> Result<(), VaultNFTError> {
+        minter.require_auth();
+        
+        if Self::get_vault_minter(env.clone(), vault_address.clone()) != Some(minter) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        let key = (DISTRIBUTOR_PREFIX, vault_address);
+        match distributor {
+            Some(distributor) => env.storage().instance().set(&key, &distributor),
+            None => env.storage().instance().remove(&key),
+        }
+        
+        Ok(())
+    }
+    
+    /// Take `royalty_bps` of every buy_nft sale of the vault's NFTs for `recipient`
+    /// Only the vault's registered minter can set it; 0 turns the royalty off.
+    /// Capped at MAX_ROYALTY_BPS. Plain transfers never pay a royalty.
+    pub fn set_royalty(
+        env: Env,
+        minter: Address,
+        vault_address: Address,
+        royalty_bps: i128,
+        recipient: Address,
+    ) -> Result<(), VaultNFTError> {
+        minter.require_auth();
+        
+        if Self::get_vault_minter(env.clone(), vault_address.clone()) != Some(minter) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        validate_bps(royalty_bps)?;
+        if royalty_bps > MAX_ROYALTY_BPS {
+            return Err(VaultNFTError::InvalidBps);
+        }
+        // Tokens sent here would be mistaken for funding of owed profits
+        if recipient == env.current_contract_address() {
+            return Err(VaultNFTError::InvalidRecipient);
+        }
+        
+        let key = (ROYALTY_PREFIX, vault_address.clone());
+        if royalty_bps == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &(royalty_bps, recipient.clone()));
+        }
+        
+        env.events().publish(
+            (symbol_short!("ROYALTY"), &vault_address),
+            (royalty_bps, recipient)
+        );
+        
+        Ok(())
+    }
+    
+    /// Royalty in basis points and its recipient for a vault's NFT sales, if set
+    pub fn get_royalty(env: Env, vault_address: Address) -> Option<(i128, Address)> {
+        env.storage().instance().get(&(ROYALTY_PREFIX, vault_address))
+    }
+
+    /// Distributor allowed to distribute profits for a vault besides the vault itself
+    pub fn get_distributor(env: Env, vault_address: Address) -> Option<Address> {
+        env.storage().instance().get(&(DISTRIBUTOR_PREFIX, vault_address))
+    }
+
+    /// Opt in to (or out of) reinvesting profits from `vault_address` into the vault
+    /// While enabled, distributions from the vault are held for process_reinvest
+    /// instead of becoming claimable. Amounts already held are unaffected.
+    pub fn set_reinvest(env: Env, holder: Address, vault_address: Address, enabled: bool) {
+        holder.require_auth();
+        
+        let key = (REINVEST_PREFIX, holder.clone(), vault_address.clone());
+        if enabled {
+            store_nft_record(&env, &key, &true);
+        } else {
+            remove_nft_record(&env, &key);
+        }
+        
+        env.events().publish(
+            (symbol_short!("REINV_SET"), &holder),
+            (vault_address, enabled)
+        );
+    }
+    
+    /// Whether `holder` reinvests profits from `vault_address`
+    pub fn get_reinvest(env: Env, holder: Address, vault_address: Address) -> bool {
+        load_nft_record(&env, &(REINVEST_PREFIX, holder, vault_address)).unwrap_or(false)
+    }
+    
+    /// Amount of `token` from `vault_address` held for reinvesting on `holder`'s behalf
+    pub fn get_pending_reinvest(env: Env, holder: Address, vault_address: Address, token: Address) -> i128 {
+        load_nft_record(&env, &(REINVEST_OWED_PREFIX, holder, vault_address, token)).unwrap_or(0)
+    }
+
+    /// Get the factory used for mint verification, if any
+    pub fn get_factory(env: Env) -> Option<Address> {
+        env.storage().instance().get(&FACTORY)
+    }
+
+    /// Ownership limits enforced by mint_nft
+    pub fn get_limits(_env: Env) -> NFTLimits {
+        NFTLimits {
+            version: LIMITS_VERSION,
+            min_ownership_bps: 1,
+            max_ownership_bps: MAX_OWNERSHIP_PCT,
+            bps_scale: MAX_OWNERSHIP_PCT,
+            max_nfts_per_vault: MAX_VAULT_NFTS,
+        }
+    }
+
+    /// Get NFT details
+    pub fn get_nft(env: Env, nft_id: u64) -> Result<VaultNFT, VaultNFTError> {
+        load_nft(&env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)
+    }
+    
+    /// Get an NFT's full metadata
+    pub fn get_nft_metadata(env: Env, nft_id: u64) -> Result<NFTMetadata, VaultNFTError> {
+        Ok(Self::get_nft(env, nft_id)?.metadata)
+    }
+    
+    /// Keep an NFT and what is stored about it alive for at least `ledgers` more ledgers
+    /// Covers its record, lock, approval and transfer history, its holder's NFT
+    /// list and reinvest choice for its vault, and the vault's NFT list and
+    /// ownership total. Anyone may pay to extend them; records are also
+    /// extended whenever used.
+    pub fn extend_nft_ttl(env: Env, nft_id: u64, ledgers: u32) -> Result<(), VaultNFTError> {
+        validate_ttl(&env, ledgers)?;
+        
+        // Reading first moves an instance-stored record over
+        let nft = Self::get_nft(env.clone(), nft_id)?;
+        
+        extend_record(&env, &(NFT_PREFIX, nft_id), ledgers);
+        extend_record(&env, &(LOCKED_UNTIL_PREFIX, nft_id), ledgers);
+        extend_record(&env, &(APPROVAL_PREFIX, nft_id), ledgers);
+        extend_record(&env, &(TRANSFER_HISTORY_PREFIX, nft_id), ledgers);
+        extend_record(&env, &(HOLDER_NFTS_PREFIX, &nft.holder), ledgers);
+        extend_record(&env, &(REINVEST_PREFIX, nft.holder.clone(), nft.vault_address.clone()), ledgers);
+        extend_record(&env, &(VAULT_NFTS_PREFIX, &nft.vault_address), ledgers);
+        extend_record(&env, &(OWNERSHIP_TOTAL_PREFIX, &nft.vault_address), ledgers);
+        
+        Ok(())
+    }
+    
+    /// Keep `holder`'s unclaimed `token` balances alive for at least `ledgers` more ledgers
+    /// Covers what claim_profit pays, its claim window, the contract's total
+    /// owed in `token`, what is held to reinvest from each vault the holder
+    /// has NFTs in, and the holder's unclaimed recorded distributions (in any
+    /// token). Operator approvals are kept alive by setting them again.
+    pub fn extend_claim_ttl(env: Env, holder: Address, token: Address, ledgers: u32) -> Result<(), VaultNFTError> {
+        validate_ttl(&env, ledgers)?;
+        
+        extend_record(&env, &(OWED_PREFIX, holder.clone(), token.clone()), ledgers);
+        extend_record(&env, &(OWED_AT_PREFIX, holder.clone(), token.clone()), ledgers);
+        extend_record(&env, &(TOTAL_OWED_PREFIX, token.clone()), ledgers);
+        
+        let nft_ids = Self::get_holder_nfts(env.clone(), holder.clone());
+        let mut vaults: Vec<Address> = Vec::new(&env);
+        for nft_id in bounded_iter(&nft_ids, MAX_HOLDER_NFTS, VaultNFTError::TooManyItems)? {
+            if let Some(nft) = load_nft(&env, nft_id) {
+                if !vaults.contains(&nft.vault_address) {
+                    vaults.push_back(nft.vault_address);
+                }
+            }
+        }
+        for vault_address in vaults.iter() {
+            extend_record(&env, &(REINVEST_OWED_PREFIX, holder.clone(), vault_address, token.clone()), ledgers);
+        }
+        
+        let open_key = (HOLDER_DISTS_PREFIX, holder.clone());
+        let open: Vec<u64> = load_nft_record(&env, &open_key).unwrap_or(Vec::new(&env));
+        extend_record(&env, &open_key, ledgers);
+        for distribution_id in open.iter() {
+            extend_record(&env, &(DISTRIBUTION_PREFIX, distribution_id), ledgers);
+            extend_record(&env, &(ENTITLEMENT_PREFIX, holder.clone(), distribution_id), ledgers);
+        }
+        
+        Ok(())
+    }
+    
+    /// Get all NFTs for a vault
+    pub fn get_vault_nfts(env: Env, vault_address: Address) -> Vec<u64> {
+        load_nft_record(&env, &(VAULT_NFTS_PREFIX, vault_address))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get an NFT's most recent holder changes, oldest first
+    /// Starts at the mint and keeps the last MAX_TRANSFER_HISTORY entries; a
+    /// split-off NFT starts with its parent's history. NFTs minted before the
+    /// history was kept start at their next transfer.
+    pub fn get_transfer_history(env: Env, nft_id: u64) -> Vec<TransferRecord> {
+        load_nft_record(&env, &(TRANSFER_HISTORY_PREFIX, nft_id)).unwrap_or(Vec::new(&env))
+    }
+    
+    /// Get all NFTs an address holds, across vaults
+    /// Kept up to date on mint, transfer, split, merge and burn. NFTs that
+    /// haven't moved since before the index was kept aren't listed.
+    pub fn get_holder_nfts(env: Env, holder: Address) -> Vec<u64> {
+        load_nft_record(&env, &(HOLDER_NFTS_PREFIX, &holder)).unwrap_or(Vec::new(&env))
+    }
+
+    /// Ownership `holder` has in one vault, in basis points
+    pub fn get_holder_ownership(env: Env, holder: Address, vault_address: Address) -> Result<i128, VaultNFTError> {
+        let nft_ids = Self::get_holder_nfts(env.clone(), holder);
+        let mut total: i128 = 0;
+        for nft_id in bounded_iter(&nft_ids, MAX_HOLDER_NFTS, VaultNFTError::TooManyItems)? {
+            let nft = Self::get_nft(env.clone(), nft_id)?;
+            if nft.vault_address == vault_address {
+                total = total.checked_add(nft.ownership_percentage)
+                    .ok_or(VaultNFTError::InvalidOwnership)?;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Number of distinct holders of a vault's NFTs
+    /// distribute_profits writes one owed entry per holder, so this is what
+    /// its cost scales with; a holder of several NFTs counts once.
+    pub fn holder_count(env: Env, vault_address: Address) -> Result<u32, VaultNFTError> {
+        let nft_ids = Self::get_vault_nfts(env.clone(), vault_address);
+        let mut holders: Map<Address, bool> = Map::new(&env);
+        for nft_id in bounded_iter(&nft_ids, MAX_VAULT_NFTS, VaultNFTError::TooManyItems)? {
+            let nft = Self::get_nft(env.clone(), nft_id)?;
+            holders.set(nft.holder, true);
+        }
+        Ok(holders.len())
+    }
+
+    /// Distribute profits to NFT holders
+    /// T126: Implement profit distribution logic proportional to shares
+    /// `caller` is the vault itself or the distributor registered for it.
+    /// Each holder's cut is recorded as owed and paid out by claim_profit.
+    /// The contract must already hold enough `token` to cover everything owed,
+    /// including this distribution, or the call fails with Underfunded.
+    pub fn distribute_profits(
+        env: Env,
+        caller: Address,
+        vault_address: Address,
+        total_profit: i128,
+        token: Address,
+    ) -> Result<Map<Address, i128>, VaultNFTError> {
+        // Verify caller
+        require_distributor(&env, &caller, &vault_address)?;
+        when_not_paused(&env)?;
+        
+        validate_positive_amount(total_profit)?;
+        
+        let (distributions, total_distributed) =
+            compute_distribution(&env, &vault_address, total_profit)?;
+        
+        // Every owed claim must be backed by tokens held here, so early
+        // claimers can't drain what later ones are owed
+        let total_owed = funded_total_owed(&env, &token, total_distributed)?;
+        
+        for (holder, amount) in distributions.iter() {
+            // Opted-in holders' cuts wait for process_reinvest instead
+            if Self::get_reinvest(env.clone(), holder.clone(), vault_address.clone()) {
+                let key = (REINVEST_OWED_PREFIX, holder, vault_address.clone(), token.clone());
+                let pending: i128 = load_nft_record(&env, &key).unwrap_or(0);
+                let pending = pending.checked_add(amount).ok_or(VaultNFTError::InvalidAmount)?;
+                store_nft_record(&env, &key, &pending);
+                continue;
+            }
+            
+            let key = (OWED_PREFIX, holder.clone(), token.clone());
+            let owed: i128 = load_nft_record(&env, &key).unwrap_or(0);
+            let owed = owed.checked_add(amount).ok_or(VaultNFTError::InvalidAmount)?;
+            store_nft_record(&env, &key, &owed);
+            // A new distribution restarts the holder's claim window
+            store_nft_record(
+                &env,
+                &(OWED_AT_PREFIX, holder, token.clone()),
+                &env.ledger().timestamp(),
+            );
+        }
+        store_nft_record(&env, &(TOTAL_OWED_PREFIX, token.clone()), &total_owed);
+        
+        // Emit distribution event
+        env.events().publish(
+            (symbol_short!("PROFIT"), &vault_address),
+            (total_profit, total_distributed, distributions.len(), caller)
+        );
+        
+        Ok(distributions)
+    }
+    
+    /// Pay out everything owed to `holder` in `token`
+    /// Fails with ClaimExpired once the claim window has passed.
+    pub fn claim_profit(
+        env: Env,
+        holder: Address,
+        token: Address,
+    ) -> Result<i128, VaultNFTError> {
+        holder.require_auth();
+        
+        let key = (OWED_PREFIX, holder.clone(), token.clone());
+        let owed: i128 = load_nft_record(&env, &key).unwrap_or(0);
+        if owed <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        if claim_expired(&env, &holder, &token) {
+            return Err(VaultNFTError::ClaimExpired);
+        }
+        
+        settle_owed(&env, &holder, &token, owed)?;
+        
+        token::TokenClient::new(&env, &token)
+            .transfer(&env.current_contract_address(), &holder, &owed);
+        
+        env.events().publish(
+            (symbol_short!("CLAIM"), &holder),
+            (token, owed)
+        );
+        
+        Ok(owed)
+    }
+    
+    /// Reinvest what is held for `holder` from `vault_address` into the vault
+    /// Deposits it through the vault's deposit_for under a one-off allowance,
+    /// minting vault shares to the holder. Anyone may call it. If the holder
+    /// has since opted out, or the deposit fails (paused vault, wrong base
+    /// asset), the amount becomes an ordinary claimable balance instead.
+    /// Returns the shares minted, 0 on fallback.
+    pub fn process_reinvest(
+        env: Env,
+        holder: Address,
+        vault_address: Address,
+        token: Address,
+    ) -> Result<i128, VaultNFTError> {
+        let key = (REINVEST_OWED_PREFIX, holder.clone(), vault_address.clone(), token.clone());
+        let pending: i128 = load_nft_record(&env, &key).unwrap_or(0);
+        if pending <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        remove_nft_record(&env, &key);
+        
+        let this = env.current_contract_address();
+        let token_client = token::TokenClient::new(&env, &token);
+        
+        if Self::get_reinvest(env.clone(), holder.clone(), vault_address.clone()) {
+            // Allowance covers exactly this deposit and lapses with the ledger
+            token_client.approve(&this, &vault_address, &pending, &env.ledger().sequence());
+            let deposited = VaultDepositClient::new(&env, &vault_address)
+                .try_deposit_for(&this, &holder, &pending);
+            
+            if let Ok(Ok(shares)) = deposited {
+                let total_owed = Self::total_owed(env.clone(), token.clone())
+                    .checked_sub(pending)
+                    .ok_or(VaultNFTError::InvalidAmount)?;
+                store_nft_record(&env, &(TOTAL_OWED_PREFIX, token.clone()), &total_owed);
+                
+                env.events().publish(
+                    (symbol_short!("REINVEST"), &holder),
+                    (vault_address, token, pending, shares)
+                );
+                return Ok(shares);
+            }
+            
+            token_client.approve(&this, &vault_address, &0, &env.ledger().sequence());
+        }
+        
+        // Fall back to a claimable balance, with a fresh claim window
+        let owed_key = (OWED_PREFIX, holder.clone(), token.clone());
+        let owed: i128 = load_nft_record(&env, &owed_key).unwrap_or(0);
+        let owed = owed.checked_add(pending).ok_or(VaultNFTError::InvalidAmount)?;
+        store_nft_record(&env, &owed_key, &owed);
+        store_nft_record(
+            &env,
+            &(OWED_AT_PREFIX, holder.clone(), token.clone()),
+            &env.ledger().timestamp(),
+        );
+        
+        env.events().publish(
+            (symbol_short!("RINV_FAIL"), &holder),
+            (vault_address, token, pending)
+        );
+        
+        Ok(0)
+    }
+    
+    /// Record a distribution for holders to claim one by one
+    /// Same caller rules, funding check and split as distribute_profits, but
+    /// each holder's cut is kept in persistent storage under the returned
+    /// distribution id and paid out by `claim`. Reinvest opt-ins and the claim
+    /// window don't apply to recorded distributions. A holder who already has
+    /// MAX_HOLDER_DISTS unclaimed ones gets their cut added to their owed
+    /// balance instead, claimed with claim_profit as distribute_profits' cuts
+    /// are, so one idle holder can't hold up everyone else's distributions.
+    pub fn record_distribution(
+        env: Env,
+        caller: Address,
+        vault_address: Address,
+        total_profit: i128,
+        token: Address,
+    ) -> Result<u64, VaultNFTError> {
+        require_distributor(&env, &caller, &vault_address)?;
+        when_not_paused(&env)?;
+        
+        validate_positive_amount(total_profit)?;
+        
+        let (distributions, total_distributed) =
+            compute_distribution(&env, &vault_address, total_profit)?;
+        let total_owed = funded_total_owed(&env, &token, total_distributed)?;
+        
+        let distribution_id: u64 = env.storage().instance().get(&DIST_COUNTER).unwrap_or(0) + 1;
+        env.storage().instance().set(&DIST_COUNTER, &distribution_id);
+        store_nft_record(
+            &env,
+            &(DISTRIBUTION_PREFIX, distribution_id),
+            &(vault_address.clone(), token.clone()),
+        );
+        
+        for (holder, amount) in distributions.iter() {
+            let key = (HOLDER_DISTS_PREFIX, holder.clone());
+            let mut open: Vec<u64> = load_nft_record(&env, &key).unwrap_or(Vec::new(&env));
+            if open.len() >= MAX_HOLDER_DISTS {
+                let owed_key = (OWED_PREFIX, holder.clone(), token.clone());
+                let owed: i128 = load_nft_record(&env, &owed_key).unwrap_or(0);
+                let owed = owed.checked_add(amount).ok_or(VaultNFTError::InvalidAmount)?;
+                store_nft_record(&env, &owed_key, &owed);
+                store_nft_record(
+                    &env,
+                    &(OWED_AT_PREFIX, holder.clone(), token.clone()),
+                    &env.ledger().timestamp(),
+                );
+                env.events().publish(
+                    (symbol_short!("DIST_OWED"), &holder),
+                    (distribution_id, token.clone(), amount)
+                );
+                continue;
+            }
+            open.push_back(distribution_id);
+            store_nft_record(&env, &key, &open);
+            store_nft_record(&env, &(ENTITLEMENT_PREFIX, holder, distribution_id), &amount);
+        }
+        store_nft_record(&env, &(TOTAL_OWED_PREFIX, token.clone()), &total_owed);
+        
+        env.events().publish(
+            (symbol_short!("DIST"), &vault_address),
+            (distribution_id, token, total_profit, total_distributed, distributions.len())
+        );
+        
+        Ok(distribution_id)
+    }
+    
+    /// Pay out `holder`'s cut of a recorded distribution
+    /// Fails with AlreadyClaimed the second time, and with InvalidAmount when
+    /// the holder had no cut of it.
+    pub fn claim(env: Env, holder: Address, distribution_id: u64) -> Result<i128, VaultNFTError> {
+        holder.require_auth();
+        
+        let claimed_key = (DIST_CLAIMED_PREFIX, holder.clone(), distribution_id);
+        if load_nft_record::<_, bool>(&env, &claimed_key).is_some() {
+            return Err(VaultNFTError::AlreadyClaimed);
+        }
+        
+        let entitlement_key = (ENTITLEMENT_PREFIX, holder.clone(), distribution_id);
+        let amount: i128 = load_nft_record(&env, &entitlement_key).unwrap_or(0);
+        if amount <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        let (_, token): (Address, Address) = load_nft_record(&env, &(DISTRIBUTION_PREFIX, distribution_id))
+            .ok_or(VaultNFTError::InvalidAmount)?;
+        
+        remove_nft_record(&env, &entitlement_key);
+        store_nft_record(&env, &claimed_key, &true);
+        let open_key = (HOLDER_DISTS_PREFIX, holder.clone());
+        let mut open: Vec<u64> = load_nft_record(&env, &open_key).unwrap_or(Vec::new(&env));
+        if let Some(index) = open.first_index_of(distribution_id) {
+            open.remove(index);
+        }
+        if open.is_empty() {
+            remove_nft_record(&env, &open_key);
+        } else {
+            store_nft_record(&env, &open_key, &open);
+        }
+        let total_owed = Self::total_owed(env.clone(), token.clone())
+            .checked_sub(amount)
+            .ok_or(VaultNFTError::InvalidAmount)?;
+        store_nft_record(&env, &(TOTAL_OWED_PREFIX, token.clone()), &total_owed);
+        
+        token::TokenClient::new(&env, &token)
+            .transfer(&env.current_contract_address(), &holder, &amount);
+        
+        env.events().publish(
+            (symbol_short!("CLAIM_DST"), &holder),
+            (distribution_id, token, amount)
+        );
+        
+        Ok(amount)
+    }
+    
+    /// Send what `holder` failed to claim in time to `treasury` (admin only)
+    /// Only possible once the holder's claim window has passed.
+    pub fn reclaim_expired(
+        env: Env,
+        admin: Address,
+        holder: Address,
+        token: Address,
+        treasury: Address,
+    ) -> Result<i128, VaultNFTError> {
+        admin.require_auth();
+        
+        let stored_admin: Option<Address> = env.storage().instance().get(&ADMIN);
+        if stored_admin != Some(admin) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        let owed = Self::get_owed(env.clone(), holder.clone(), token.clone());
+        if owed <= 0 {
+            return Err(VaultNFTError::InvalidAmount);
+        }
+        if !claim_expired(&env, &holder, &token) {
+            return Err(VaultNFTError::Unauthorized);
+        }
+        
+        settle_owed(&env, &holder, &token, owed)?;
+        
+        token::TokenClient::new(&env, &token)
+            .transfer(&env.current_contract_address(), &treasury, &owed);
+        
+        env.events().publish(
+            (symbol_short!("RECLAIM"), &holder),
+            (token, owed, treasury)
+        );
+        
+        Ok(owed)
+    }
+    
+    /// Amount of `token` owed to `holder` and not yet claimed
+    pub fn get_owed(env: Env, holder: Address, token: Address) -> i128 {
+        load_nft_record(&env, &(OWED_PREFIX, holder, token)).unwrap_or(0)
+    }
+    
+    /// What `holder` can claim across recorded distributions, totalled per token
+    /// Tokens with nothing left to claim are absent.
+    pub fn get_claimable(env: Env, holder: Address) -> Result<Map<Address, i128>, VaultNFTError> {
+        let open: Vec<u64> = load_nft_record(&env, &(HOLDER_DISTS_PREFIX, holder.clone()))
+            .unwrap_or(Vec::new(&env));
+        let mut claimable: Map<Address, i128> = Map::new(&env);
+        for distribution_id in open.iter() {
+            let recorded: Option<(Address, Address)> =
+                load_nft_record(&env, &(DISTRIBUTION_PREFIX, distribution_id));
+            let amount: i128 = load_nft_record(&env, &(ENTITLEMENT_PREFIX, holder.clone(), distribution_id))
+                .unwrap_or(0);
+            if let (Some((_, token)), true) = (recorded, amount > 0) {
+                let total = claimable.get(token.clone()).unwrap_or(0)
+                    .checked_add(amount)
+                    .ok_or(VaultNFTError::InvalidAmount)?;
+                claimable.set(token, total);
+            }
+        }
+        Ok(claimable)
+    }
+    
+    /// Total amount of `token` owed to all holders and not yet claimed
+    pub fn total_owed(env: Env, token: Address) -> i128 {
+        load_nft_record(&env, &(TOTAL_OWED_PREFIX, token)).unwrap_or(0)
+    }
+    
+    /// Preview each holder's cut of a distribution without executing it
+    /// Uses the same computation as distribute_profits; no auth, no events
+    pub fn preview_distribution(
+        env: Env,
+        vault_address: Address,
+        total_profit: i128,
+    ) -> Result<Map<Address, i128>, VaultNFTError> {
+        validate_positive_amount(total_profit)?;
+        
+        let (distributions, _) = compute_distribution(&env, &vault_address, total_profit)?;
+        Ok(distributions)
+    }
+    
+    /// Get total ownership percentage for a vault (should not exceed 100%)
+    pub fn get_total_ownership(env: Env, vault_address: Address) -> Result<i128, VaultNFTError> {
+        ownership_total(&env, &vault_address)
+    }
+    
+    /// Get how much of a vault is still available to mint, in basis points
+    /// MAX_OWNERSHIP_PCT minus everything already minted, never below zero
+    pub fn available_ownership(env: Env, vault_address: Address) -> Result<i128, VaultNFTError> {
+        let minted = Self::get_total_ownership(env, vault_address)?;
+        Ok((MAX_OWNERSHIP_PCT - minted).max(0))
+    }
+}
+
+// NFT records, the per-vault and per-holder id lists, and holder balances and
+// approvals live in persistent storage, one entry each, with their TTL
+// extended whenever they are touched.
+// Entries written to instance storage by earlier versions are moved over the
+// first time they are read.
+fn load_nft_record<K, V>(env: &Env, key: &K) -> Option<V>
+where
+    K: IntoVal<Env, Val>,
+    V: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    if let Some(value) = env.storage().persistent().get::<K, V>(key) {
+        env.storage().persistent().extend_ttl(key, NFT_TTL_THRESHOLD, NFT_TTL_EXTEND_TO);
+        return Some(value);
+    }
+    
+    let legacy: V = env.storage().instance().get(key)?;
+    env.storage().instance().remove(key);
+    store_nft_record(env, key, &legacy);
+    Some(legacy)
+}
+
+// Load an NFT, upgrading a record stored in the earlier LegacyVaultNFT shape
+// Its full metadata comes from the record kept alongside it, or is just the
+// name when there is none.
+fn load_nft(env: &Env, nft_id: u64) -> Option<VaultNFT> {
+    let key = (NFT_PREFIX, nft_id);
+    let raw: Val = load_nft_record(env, &key)?;
+    if let Ok(nft) = VaultNFT::try_from_val(env, &raw) {
+        return Some(nft);
+    }
+    
+    let legacy = LegacyVaultNFT::try_from_val(env, &raw).ok()?;
+    let metadata_key = (NFT_METADATA_PREFIX, nft_id);
+    let metadata = load_nft_record(env, &metadata_key).unwrap_or(NFTMetadata {
+        name: legacy.metadata,
+        description: String::from_str(env, ""),
+        image_url: String::from_str(env, ""),
+        vault_performance: 0,
+    });
+    remove_nft_record(env, &metadata_key);
+    
+    let nft = VaultNFT {
+        nft_id: legacy.nft_id,
+        vault_address: legacy.vault_address,
+        ownership_percentage: legacy.ownership_percentage,
+        holder: legacy.holder,
+        metadata,
+    };
+    store_nft_record(env, &key, &nft);
+    Some(nft)
+}
+
+fn store_nft_record<K, V>(env: &Env, key: &K, value: &V)
+where
+    K: IntoVal<Env, Val>,
+    V: IntoVal<Env, Val>,
+{
+    env.storage().persistent().set(key, value);
+    env.storage().persistent().extend_ttl(key, NFT_TTL_THRESHOLD, NFT_TTL_EXTEND_TO);
+}
+
+// Extend a persistent entry to live `ledgers` more ledgers, if it exists
+fn extend_record<K: IntoVal<Env, Val>>(env: &Env, key: &K, ledgers: u32) {
+    if env.storage().persistent().has(key) {
+        env.storage().persistent().extend_ttl(key, ledgers, ledgers);
+    }
+}
+
+// A TTL extension must be positive and within the network's maximum
+fn validate_ttl(env: &Env, ledgers: u32) -> Result<(), VaultNFTError> {
+    if ledgers == 0 || ledgers > env.storage().max_ttl() {
+        return Err(VaultNFTError::InvalidAmount);
+    }
+    Ok(())
+}
+
+fn remove_nft_record<K: IntoVal<Env, Val>>(env: &Env, key: &K) {
+    env.storage().persistent().remove(key);
+    env.storage().instance().remove(key);
+}
+
+// Mint an NFT of `vault_address` to `holder`, once `minter` has authorized it
+// Checks the minter is the vault's registered one and that the vault's NFTs
+// stay within 100% together.
+fn mint_to(
+    env: &Env,
+    minter: &Address,
+    vault_address: &Address,
+    holder: &Address,
+    ownership_percentage: i128,
+    metadata: &NFTMetadata,
+) -> Result<u64, VaultNFTError> {
+    when_not_paused(env)?;
+    
+    validate_ownership(ownership_percentage)?;
+    
+    require_known_vault(env, vault_address)?;
+    
+    // Only the vault's registered minter mints for it; vaults without one reject every mint
+    if VaultNFTContract::get_vault_minter(env.clone(), vault_address.clone()) != Some(minter.clone()) {
+        return Err(VaultNFTError::Unauthorized);
+    }
+    
+    // The vault's NFTs together can't claim more than all of it
+    let minted = ownership_total(env, vault_address)?;
+    let new_total = minted.checked_add(ownership_percentage)
+        .ok_or(VaultNFTError::OwnershipExceeded)?;
+    if new_total > MAX_OWNERSHIP_PCT {
+        return Err(VaultNFTError::OwnershipExceeded);
+    }
+    
+    // Get next NFT ID
+    let nft_id: u64 = env.storage()
+        .instance()
+        .get(&NFT_COUNTER)
+        .unwrap_or(0);
+    
+    let next_id = nft_id + 1;
+    
+    // Create NFT
+    let nft = VaultNFT {
+        nft_id: next_id,
+        vault_address: vault_address.clone(),
+        ownership_percentage,
+        holder: holder.clone(),
+        metadata: metadata.clone(),
+    };
+    
+    // Store NFT
+    store_nft_record(env, &(NFT_PREFIX, next_id), &nft);
+    
+    // Update counter
+    env.storage().instance().set(&NFT_COUNTER, &next_id);
+    
+    // Add to vault's NFT list
+    let mut vault_nfts = VaultNFTContract::get_vault_nfts(env.clone(), vault_address.clone());
+    if vault_nfts.len() >= MAX_VAULT_NFTS {
+        return Err(VaultNFTError::TooManyItems);
+    }
+    vault_nfts.push_back(next_id);
+    store_nft_record(env, &(VAULT_NFTS_PREFIX, vault_address), &vault_nfts);
+    store_nft_record(env, &(OWNERSHIP_TOTAL_PREFIX, vault_address), &new_total);
+    add_holder_nft(env, holder, next_id)?;
+    record_transfer(env, next_id, None, holder);
+    
+    // Emit event
+    env.events().publish(
+        (symbol_short!("NFT_MINT"), vault_address),
+        (next_id, holder, ownership_percentage)
+    );
+    
+    Ok(next_id)
+}
+
+// Hand an NFT to `to` once the caller has checked who may move it
+// Refuses while the NFT is still vesting, and drops any single-NFT approval.
+fn move_nft(env: &Env, nft: &mut VaultNFT, to: &Address) -> Result<(), VaultNFTError> {
+    when_not_paused(env)?;
+    let nft_id = nft.nft_id;
+    require_unlocked(env, nft_id)?;
+    
+    let from = nft.holder.clone();
+    nft.holder = to.clone();
+    store_nft_record(env, &(NFT_PREFIX, nft_id), &*nft);
+    remove_nft_record(env, &(APPROVAL_PREFIX, nft_id));
+    remove_holder_nft(env, &from, nft_id);
+    add_holder_nft(env, to, nft_id)?;
+    record_transfer(env, nft_id, Some(from.clone()), to);
+    
+    env.events().publish(
+        (symbol_short!("NFT_XFER"), nft_id),
+        (&from, to)
+    );
+    
+    Ok(())
+}
+
+// Refuse while an NFT is still vesting
+fn require_unlocked(env: &Env, nft_id: u64) -> Result<(), VaultNFTError> {
+    if let Some(locked_until) = load_nft_record::<_, u64>(env, &(LOCKED_UNTIL_PREFIX, nft_id)) {
+        if env.ledger().timestamp() < locked_until {
+            return Err(VaultNFTError::Locked);
+        }
+    }
+    Ok(())
+}
+
+// Delete an NFT and everything stored about it, once the caller has checked
+// who may burn it. Its ownership is released back to the vault.
+fn remove_nft(env: &Env, nft: &VaultNFT) -> Result<(), VaultNFTError> {
+    let nft_id = nft.nft_id;
+    let vault_address = nft.vault_address.clone();
+    let remaining = ownership_total(env, &vault_address)?
+        .checked_sub(nft.ownership_percentage)
+        .ok_or(VaultNFTError::InvalidOwnership)?;
+    
+    let mut vault_nfts = VaultNFTContract::get_vault_nfts(env.clone(), vault_address.clone());
+    if let Some(index) = vault_nfts.first_index_of(nft_id) {
+        vault_nfts.remove(index);
+    }
+    store_nft_record(env, &(VAULT_NFTS_PREFIX, &vault_address), &vault_nfts);
+    store_nft_record(env, &(OWNERSHIP_TOTAL_PREFIX, &vault_address), &remaining);
+    remove_nft_record(env, &(NFT_PREFIX, nft_id));
+    remove_nft_record(env, &(LOCKED_UNTIL_PREFIX, nft_id));
+    remove_nft_record(env, &(APPROVAL_PREFIX, nft_id));
+    remove_nft_record(env, &(TRANSFER_HISTORY_PREFIX, nft_id));
+    remove_holder_nft(env, &nft.holder, nft_id);
+    
+    env.events().publish(
+        (symbol_short!("NFT_BURN"), &vault_address),
+        (nft_id, nft.ownership_percentage)
+    );
+    
+    Ok(())
+}
+
+// Append a holder change to an NFT's history, dropping the oldest when full
+fn record_transfer(env: &Env, nft_id: u64, from: Option<Address>, to: &Address) {
+    let mut history = VaultNFTContract::get_transfer_history(env.clone(), nft_id);
+    if history.len() >= MAX_TRANSFER_HISTORY {
+        history.pop_front();
+    }
+    history.push_back(TransferRecord {
+        from,
+        to: to.clone(),
+        timestamp: env.ledger().timestamp(),
+    });
+    store_nft_record(env, &(TRANSFER_HISTORY_PREFIX, nft_id), &history);
+}
+
+// Record that `holder` now holds `nft_id`
+fn add_holder_nft(env: &Env, holder: &Address, nft_id: u64) -> Result<(), VaultNFTError> {
+    let mut nft_ids = VaultNFTContract::get_holder_nfts(env.clone(), holder.clone());
+    if nft_ids.len() >= MAX_HOLDER_NFTS {
+        return Err(VaultNFTError::TooManyItems);
+    }
+    nft_ids.push_back(nft_id);
+    store_nft_record(env, &(HOLDER_NFTS_PREFIX, holder), &nft_ids);
+    Ok(())
+}
+
+// Drop `nft_id` from `holder`'s list
+fn remove_holder_nft(env: &Env, holder: &Address, nft_id: u64) {
+    let mut nft_ids = VaultNFTContract::get_holder_nfts(env.clone(), holder.clone());
+    if let Some(index) = nft_ids.first_index_of(nft_id) {
+        nft_ids.remove(index);
+        if nft_ids.is_empty() {
+            remove_nft_record(env, &(HOLDER_NFTS_PREFIX, holder));
+        } else {
+            store_nft_record(env, &(HOLDER_NFTS_PREFIX, holder), &nft_ids);
+        }
+    }
+}
+
+// Ownership minted against a vault so far, in basis points
+// Kept as a running total by mint_nft; vaults minted before it was tracked
+// are summed from their NFTs once.
+fn ownership_total(env: &Env, vault_address: &Address) -> Result<i128, VaultNFTError> {
+    if let Some(total) = load_nft_record(env, &(OWNERSHIP_TOTAL_PREFIX, vault_address)) {
+        return Ok(total);
+    }
+    
+    let nft_ids = VaultNFTContract::get_vault_nfts(env.clone(), vault_address.clone());
+    let mut total: i128 = 0;
+    for nft_id in bounded_iter(&nft_ids, MAX_VAULT_NFTS, VaultNFTError::TooManyItems)? {
+        let nft: VaultNFT = load_nft(env, nft_id)
+            .ok_or(VaultNFTError::NFTNotFound)?;
+        total = total.checked_add(nft.ownership_percentage)
+            .ok_or(VaultNFTError::InvalidAmount)?;
+    }
+    Ok(total)
+}
+
+// Split `total_profit` across a vault's NFT holders by ownership percentage
+// Returns the per-holder amounts and the total handed out
+fn compute_distribution(
+    env: &Env,
+    vault_address: &Address,
+    total_profit: i128,
+) -> Result<(Map<Address, i128>, i128), VaultNFTError> {
+    // Get all NFTs for this vault
+    let nft_ids: Vec<u64> = VaultNFTContract::get_vault_nfts(env.clone(), vault_address.clone());
+    
+    let mut distributions = Map::new(env);
+    let mut total_distributed: i128 = 0;
+    
+    // Calculate distribution for each NFT holder
+    for nft_id in bounded_iter(&nft_ids, MAX_VAULT_NFTS, VaultNFTError::TooManyItems)? {
+        let nft: VaultNFT = VaultNFTContract::get_nft(env.clone(), nft_id)?;
+        
+        // Calculate holder's share based on ownership percentage
+        // ownership_percentage is in basis points (10000 = 100%)
+        let holder_share = total_profit
+            .checked_mul(nft.ownership_percentage)
+            .ok_or(VaultNFTError::InvalidAmount)?
+            / MAX_OWNERSHIP_PCT;
+        
+        if holder_share > 0 {
+            // Add to or update holder's distribution
+            let current: i128 = distributions.get(nft.holder.clone()).unwrap_or(0);
+            let updated = current.checked_add(holder_share).ok_or(VaultNFTError::InvalidAmount)?;
+            distributions.set(nft.holder.clone(), updated);
+            total_distributed = total_distributed.checked_add(holder_share)
+                .ok_or(VaultNFTError::InvalidAmount)?;
+        }
+    }
+    
+    Ok((distributions, total_distributed))
+}
+
+// `caller` must be the vault itself or the distributor registered for it
+fn require_distributor(env: &Env, caller: &Address, vault_address: &Address) -> Result<(), VaultNFTError> {
+    caller.require_auth();
+    if caller != vault_address
+        && VaultNFTContract::get_distributor(env.clone(), vault_address.clone()) != Some(caller.clone())
+    {
+        return Err(VaultNFTError::Unauthorized);
+    }
+    Ok(())
+}
+
+// Everything owed in `token` once `added` more is, failing with Underfunded
+// if this contract doesn't hold that much
+fn funded_total_owed(env: &Env, token: &Address, added: i128) -> Result<i128, VaultNFTError> {
+    let total_owed = VaultNFTContract::total_owed(env.clone(), token.clone())
+        .checked_add(added)
+        .ok_or(VaultNFTError::InvalidAmount)?;
+    let funded = token::TokenClient::new(env, token)
+        .balance(&env.current_contract_address());
+    if funded < total_owed {
+        return Err(VaultNFTError::Underfunded);
+    }
+    Ok(total_owed)
+}
+
+// Accept `admin` only if initialize made it the contract admin
+fn require_admin(env: &Env, admin: &Address) -> Result<(), VaultNFTError> {
+    let stored_admin: Address = env.storage()
+        .instance()
+        .get(&ADMIN)
+        .ok_or(VaultNFTError::Unauthorized)?;
+    if stored_admin != *admin {
+        return Err(VaultNFTError::Unauthorized);
+    }
+    Ok(())
+}
+
+// Reject basis points outside 0..=MAX_OWNERSHIP_PCT
+fn validate_bps(value: i128) -> Result<(), VaultNFTError> {
+    if !(0..=MAX_OWNERSHIP_PCT).contains(&value) {
+        return Err(VaultNFTError::InvalidBps);
+    }
+    Ok(())
+}
+
+// An NFT's ownership is 1 to 10000 basis points (0.01% - 100%)
+fn validate_ownership(ownership_percentage: i128) -> Result<(), VaultNFTError> {
+    validate_bps(ownership_percentage)?;
+    if ownership_percentage == 0 {
+        return Err(VaultNFTError::InvalidOwnership);
+    }
+    Ok(())
+}
+
+// Reject an amount that isn't strictly positive
+fn validate_positive_amount(amount: i128) -> Result<(), VaultNFTError> {
+    if amount <= 0 {
+        return Err(VaultNFTError::InvalidAmount);
+    }
+    Ok(())
+}
+
+// Pause or unpause once `admin` is confirmed as the stored admin
+fn set_paused(env: &Env, admin: &Address, paused: bool) -> Result<(), VaultNFTError> {
+    admin.require_auth();
+    
+    if VaultNFTContract::get_admin(env.clone()).as_ref() != Some(admin) {
+        return Err(VaultNFTError::Unauthorized);
+    }
+    
+    if paused {
+        env.storage().instance().set(&PAUSED, &true);
+    } else {
+        env.storage().instance().remove(&PAUSED);
+    }
+    let topic = if paused { symbol_short!("PAUSED") } else { symbol_short!("UNPAUSED") };
+    env.events().publish((topic,), admin.clone());
+    
+    Ok(())
+}
+
+// Fail with Paused while the contract is paused
+fn when_not_paused(env: &Env) -> Result<(), VaultNFTError> {
+    if VaultNFTContract::paused(env.clone()) {
+        return Err(VaultNFTError::Paused);
+    }
+    Ok(())
+}
+
+// When the holder's current claim window ends, if a window is configured
+fn claim_deadline(env: &Env, holder: &Address, token: &Address) -> Option<u64> {
+    let window: u64 = env.storage().instance().get(&CLAIM_WINDOW)?;
+    let owed_at: u64 = load_nft_record(env, &(OWED_AT_PREFIX, holder.clone(), token.clone())).unwrap_or(0);
+    Some(owed_at.saturating_add(window))
+}
+
+fn claim_expired(env: &Env, holder: &Address, token: &Address) -> bool {
+    match claim_deadline(env, holder, token) {
+        Some(deadline) => env.ledger().timestamp() > deadline,
+        None => false,
+    }
+}
+
+// Clear `owed` of `token` for `holder` before it is paid out
+fn settle_owed(env: &Env, holder: &Address, token: &Address, owed: i128) -> Result<(), VaultNFTError> {
+    remove_nft_record(env, &(OWED_PREFIX, holder.clone(), token.clone()));
+    remove_nft_record(env, &(OWED_AT_PREFIX, holder.clone(), token.clone()));
+    let total_owed = VaultNFTContract::total_owed(env.clone(), token.clone())
+        .checked_sub(owed)
+        .ok_or(VaultNFTError::InvalidAmount)?;
+    store_nft_record(env, &(TOTAL_OWED_PREFIX, token.clone()), &total_owed);
+    Ok(())
+}
+
+// If a factory is configured, only vaults it deployed are accepted
+fn require_known_vault(env: &Env, vault_address: &Address) -> Result<(), VaultNFTError> {
+    if let Some(factory) = env.storage().instance().get::<_, Address>(&FACTORY) {
+        if !FactoryClient::new(env, &factory).is_vault(vault_address) {
+            return Err(VaultNFTError::UnknownVault);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,1585 @@
+#![cfg(test)]
+
+extern crate std;
+
+use soroban_sdk::{
+    contract, contractimpl,
+    testutils::{Address as _, Events, Ledger},
+    token::{StellarAssetClient, TokenClient},
+    map, symbol_short, vec, Address, Env, IntoVal, String,
+};
+
+use crate::{
+    NFTMetadata, TransferRecord, VaultNFTContract, VaultNFTContractClient, VaultNFTError,
+    WithdrawReceipt,
+};
+use syft_errors::VaultError;
+
+// Vault stand-in that distributes through the NFT contract as itself,
+// takes deposit_for deposits 1:1 in shares, unless it has been told to refuse,
+// and pays withdrawals out of its token balance pro rata, unless illiquid
+#[contract]
+pub struct MockVault;
+
+#[contractimpl]
+impl MockVault {
+    pub fn distribute(env: Env, nft: Address, total_profit: i128, token: Address) {
+        let vault = env.current_contract_address();
+        VaultNFTContractClient::new(&env, &nft).distribute_profits(&vault, &vault, &total_profit, &token);
+    }
+
+    pub fn set_token(env: Env, token: Address) {
+        env.storage().instance().set(&symbol_short!("token"), &token);
+    }
+
+    pub fn set_refusing(env: Env, refusing: bool) {
+        env.storage().instance().set(&symbol_short!("refusing"), &refusing);
+    }
+
+    pub fn deposit_for(env: Env, payer: Address, beneficiary: Address, amount: i128) -> i128 {
+        payer.require_auth();
+        if env.storage().instance().get(&symbol_short!("refusing")).unwrap_or(false) {
+            panic!("deposits paused");
+        }
+        let vault = env.current_contract_address();
+        let token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+        TokenClient::new(&env, &token).transfer_from(&vault, &payer, &vault, &amount);
+        let shares = Self::shares(env.clone(), beneficiary.clone()) + amount;
+        env.storage().instance().set(&beneficiary, &shares);
+        let total = Self::total_shares(env.clone()) + amount;
+        env.storage().instance().set(&symbol_short!("total"), &total);
+        amount
+    }
+
+    pub fn set_illiquid(env: Env, illiquid: bool) {
+        env.storage().instance().set(&symbol_short!("illiquid"), &illiquid);
+    }
+
+    pub fn total_shares(env: Env) -> i128 {
+        env.storage().instance().get(&symbol_short!("total")).unwrap_or(0)
+    }
+
+    pub fn withdraw(env: Env, user: Address, shares: i128) -> Result<WithdrawReceipt, VaultError> {
+        user.require_auth();
+        if env.storage().instance().get(&symbol_short!("illiquid")).unwrap_or(false) {
+            return Err(VaultError::InsufficientLiquidity);
+        }
+        let held = Self::shares(env.clone(), user.clone());
+        if shares > held {
+            return Err(VaultError::InsufficientShares);
+        }
+        let vault = env.current_contract_address();
+        let token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+        let total = Self::total_shares(env.clone());
+        let amount = shares * TokenClient::new(&env, &token).balance(&vault) / total;
+        env.storage().instance().set(&user, &(held - shares));
+        env.storage().instance().set(&symbol_short!("total"), &(total - shares));
+        TokenClient::new(&env, &token).transfer(&vault, &user, &amount);
+        Ok(WithdrawReceipt {
+            shares_burned: shares,
+            gross_amount: amount,
+            fees: vec![&env],
+            net_amount: amount,
+            payout_token: token,
+        })
+    }
+
+    pub fn shares(env: Env, holder: Address) -> i128 {
+        env.storage().instance().get(&holder).unwrap_or(0)
+    }
+}
+
+// Factory stand-in that knows a single registered vault
+#[contract]
+pub struct MockFactory;
+
+#[contractimpl]
+impl MockFactory {
+    pub fn register(env: Env, vault: Address) {
+        env.storage().instance().set(&vault, &true);
+    }
+
+    pub fn is_vault(env: Env, vault: Address) -> bool {
+        env.storage().instance().get(&vault).unwrap_or(false)
+    }
+}
+
+fn create_nft_contract<'a>(env: &Env) -> VaultNFTContractClient<'a> {
+    let contract_id = env.register_contract(None, VaultNFTContract);
+    let nft = VaultNFTContractClient::new(env, &contract_id);
+    nft.initialize(&Address::generate(env));
+    nft
+}
+
+/// Mint an NFT for `holder` the way a vault's minter would: mint, then transfer
+/// The first holder minted for a vault is registered as its minter.
+fn mint(nft: &VaultNFTContractClient, holder: &Address, vault: &Address, ownership_percentage: i128) -> u64 {
+    let minter = match nft.get_vault_minter(vault) {
+        Some(minter) => minter,
+        None => {
+            nft.set_vault_minter(&nft.get_admin().unwrap(), vault, holder);
+            holder.clone()
+        }
+    };
+    let nft_id = nft.mint_nft(&minter, vault, &ownership_percentage, &metadata(&nft.env));
+    if minter != *holder {
+        nft.transfer(&nft_id, &minter, holder);
+    }
+    nft_id
+}
+
+fn create_token<'a>(env: &Env, admin: &Address) -> (TokenClient<'a>, StellarAssetClient<'a>) {
+    let sac = env.register_stellar_asset_contract_v2(admin.clone());
+    (
+        TokenClient::new(env, &sac.address()),
+        StellarAssetClient::new(env, &sac.address()),
+    )
+}
+
+fn metadata(env: &Env) -> NFTMetadata {
+    NFTMetadata {
+        name: String::from_str(env, "Vault Share"),
+        description: String::from_str(env, "Fractional vault ownership"),
+        image_url: String::from_str(env, "https://example.com/nft.png"),
+        vault_performance: 0,
+    }
+}
+
+#[test]
+fn mint_checks_vault_against_configured_factory() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let minter = Address::generate(&env);
+    let registered = Address::generate(&env);
+    let unregistered = Address::generate(&env);
+
+    // Without a factory any address is accepted
+    assert_eq!(mint(&nft, &minter, &unregistered, 1000), 1);
+
+    let factory_id = env.register_contract(None, MockFactory);
+    MockFactoryClient::new(&env, &factory_id).register(&registered);
+    nft.set_factory(&admin, &Some(factory_id.clone()));
+    assert_eq!(nft.get_factory(), Some(factory_id));
+
+    assert_eq!(
+        nft.try_mint_nft(&minter, &unregistered, &1000, &metadata(&env)),
+        Err(Ok(VaultNFTError::UnknownVault))
+    );
+    assert_eq!(mint(&nft, &minter, &registered, 1000), 2);
+    assert_eq!(nft.get_vault_nfts(&unregistered).len(), 1);
+}
+
+#[test]
+fn factory_setting_is_admin_only() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let factory = Address::generate(&env);
+    nft.set_factory(&admin, &Some(factory));
+
+    let stranger = Address::generate(&env);
+    assert_eq!(
+        nft.try_set_factory(&stranger, &None),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    nft.set_factory(&admin, &None);
+    assert_eq!(nft.get_factory(), None);
+}
+
+#[test]
+fn admin_setters_need_an_initialized_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, VaultNFTContract);
+    let nft = VaultNFTContractClient::new(&env, &contract_id);
+    let caller = Address::generate(&env);
+    let vault = Address::generate(&env);
+
+    // Nobody becomes admin by calling a setter first
+    assert_eq!(nft.try_set_factory(&caller, &None), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(nft.try_set_claim_window(&caller, &Some(60)), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(
+        nft.try_set_vault_minter(&caller, &vault, &caller),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(nft.get_admin(), None);
+
+    nft.initialize(&caller);
+    nft.set_claim_window(&caller, &Some(60));
+    assert_eq!(nft.get_claim_window(), Some(60));
+}
+
+#[test]
+fn preview_matches_distribution() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    token_admin.mint(&nft.address, &4000);
+
+    mint(&nft, &alice, &vault, 2500);
+    mint(&nft, &bob, &vault, 1000);
+    mint(&nft, &alice, &vault, 500);
+
+    let preview = nft.preview_distribution(&vault, &10_000);
+    assert_eq!(preview.get(alice.clone()), Some(3000));
+    assert_eq!(preview.get(bob.clone()), Some(1000));
+
+    let distributed = nft.distribute_profits(&vault, &vault, &10_000, &token.address);
+    assert_eq!(preview, distributed);
+
+    assert_eq!(
+        nft.try_preview_distribution(&vault, &0),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+}
+
+#[test]
+fn distribution_must_be_funded() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+
+    mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 2500);
+
+    // 750 would be owed but only 500 is held
+    token_admin.mint(&nft.address, &500);
+    assert_eq!(
+        nft.try_distribute_profits(&vault, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Underfunded))
+    );
+    assert_eq!(nft.total_owed(&token.address), 0);
+
+    token_admin.mint(&nft.address, &250);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+    assert_eq!(nft.total_owed(&token.address), 750);
+    assert_eq!(nft.get_owed(&alice, &token.address), 500);
+
+    // A second distribution has to be backed on top of what is still owed
+    assert_eq!(
+        nft.try_distribute_profits(&vault, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Underfunded))
+    );
+
+    assert_eq!(nft.claim_profit(&alice, &token.address), 500);
+    assert_eq!(token.balance(&alice), 500);
+    assert_eq!(nft.total_owed(&token.address), 250);
+    assert_eq!(
+        nft.try_claim_profit(&alice, &token.address),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+
+    assert_eq!(nft.claim_profit(&bob, &token.address), 250);
+    assert_eq!(nft.total_owed(&token.address), 0);
+    assert_eq!(token.balance(&nft.address), 0);
+}
+
+#[test]
+fn recorded_distributions_are_claimed_one_by_one() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+
+    mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 2500);
+
+    token_admin.mint(&nft.address, &1000);
+    assert_eq!(
+        nft.try_record_distribution(&vault, &vault, &2000, &token.address),
+        Err(Ok(VaultNFTError::Underfunded))
+    );
+    assert_eq!(nft.try_record_distribution(&carol, &vault, &400, &token.address), Err(Ok(VaultNFTError::Unauthorized)));
+
+    let first = nft.record_distribution(&vault, &vault, &400, &token.address);
+    let second = nft.record_distribution(&vault, &vault, &800, &token.address);
+    assert_eq!((first, second), (1, 2));
+    assert_eq!(nft.total_owed(&token.address), 900);
+    assert_eq!(nft.get_claimable(&alice), map![&env, (token.address.clone(), 600)]);
+    assert_eq!(nft.get_claimable(&bob), map![&env, (token.address.clone(), 300)]);
+    assert_eq!(token.balance(&alice), 0);
+
+    // Distributions in another token are totalled separately
+    let (other, other_admin) = create_token(&env, &vault);
+    other_admin.mint(&nft.address, &100);
+    let third = nft.record_distribution(&vault, &vault, &100, &other.address);
+    assert_eq!(
+        nft.get_claimable(&alice),
+        map![&env, (token.address.clone(), 600), (other.address.clone(), 50)]
+    );
+
+    assert_eq!(nft.claim(&alice, &second), 400);
+    assert_eq!(token.balance(&alice), 400);
+    assert_eq!(nft.claim(&alice, &third), 50);
+    assert_eq!(nft.get_claimable(&alice), map![&env, (token.address.clone(), 200)]);
+    assert_eq!(nft.try_claim(&alice, &second), Err(Ok(VaultNFTError::AlreadyClaimed)));
+
+    // Nothing recorded for carol, nor for anyone under an unknown id
+    assert_eq!(nft.try_claim(&carol, &first), Err(Ok(VaultNFTError::InvalidAmount)));
+    assert_eq!(nft.try_claim(&bob, &4), Err(Ok(VaultNFTError::InvalidAmount)));
+
+    assert_eq!(nft.claim(&alice, &first), 200);
+    assert_eq!(nft.claim(&bob, &first), 100);
+    assert_eq!(nft.claim(&bob, &second), 200);
+    assert_eq!(nft.get_claimable(&alice), map![&env]);
+    assert_eq!(nft.total_owed(&token.address), 0);
+    assert_eq!(token.balance(&nft.address), 100);
+}
+
+#[test]
+fn claims_within_the_window_pay_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+
+    nft.set_claim_window(&admin, &Some(100));
+    mint(&nft, &alice, &vault, 5000);
+    token_admin.mint(&nft.address, &500);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+    assert_eq!(nft.get_claim_deadline(&alice, &token.address), Some(1_100));
+
+    // The deadline itself is still inside the window
+    env.ledger().with_mut(|l| l.timestamp = 1_100);
+    assert_eq!(nft.claim_profit(&alice, &token.address), 500);
+    assert_eq!(token.balance(&alice), 500);
+    assert_eq!(nft.get_claim_deadline(&alice, &token.address), None);
+}
+
+#[test]
+fn expired_claims_are_reclaimable_by_the_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().with_mut(|l| l.timestamp = 1_000);
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let treasury = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+
+    nft.set_claim_window(&admin, &Some(100));
+    assert_eq!(
+        nft.try_set_claim_window(&bob, &None),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    mint(&nft, &alice, &vault, 5000);
+    token_admin.mint(&nft.address, &500);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+
+    // Too early to reclaim
+    assert_eq!(
+        nft.try_reclaim_expired(&admin, &alice, &token.address, &treasury),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    env.ledger().with_mut(|l| l.timestamp = 1_101);
+    assert_eq!(
+        nft.try_claim_profit(&alice, &token.address),
+        Err(Ok(VaultNFTError::ClaimExpired))
+    );
+    assert_eq!(
+        nft.try_reclaim_expired(&bob, &alice, &token.address, &bob),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    assert_eq!(nft.reclaim_expired(&admin, &alice, &token.address, &treasury), 500);
+    assert_eq!(token.balance(&treasury), 500);
+    assert_eq!(nft.get_owed(&alice, &token.address), 0);
+    assert_eq!(nft.total_owed(&token.address), 0);
+
+    // Without a window claims never expire
+    nft.set_claim_window(&admin, &None);
+    token_admin.mint(&nft.address, &500);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+    env.ledger().with_mut(|l| l.timestamp = 1_000_000);
+    assert_eq!(nft.claim_profit(&alice, &token.address), 500);
+}
+
+#[test]
+fn available_ownership_shrinks_as_fractions_are_minted() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    assert_eq!(nft.available_ownership(&vault), 10000);
+
+    mint(&nft, &buyer, &vault, 2500);
+    assert_eq!(nft.available_ownership(&vault), 7500);
+
+    mint(&nft, &buyer, &vault, 7500);
+    assert_eq!(nft.available_ownership(&vault), 0);
+
+    // Other vaults are unaffected
+    assert_eq!(nft.available_ownership(&Address::generate(&env)), 10000);
+}
+
+#[test]
+fn mint_rejects_ownership_past_the_vault_total() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    // Exactly 100% fits
+    mint(&nft, &buyer, &vault, 5000);
+    mint(&nft, &buyer, &vault, 4999);
+    mint(&nft, &buyer, &vault, 1);
+    assert_eq!(nft.get_total_ownership(&vault), 10000);
+
+    // The next basis point doesn't, and the failed mint leaves nothing behind
+    assert_eq!(
+        nft.try_mint_nft(&buyer, &vault, &1, &metadata(&env)),
+        Err(Ok(VaultNFTError::OwnershipExceeded))
+    );
+    assert_eq!(nft.get_total_ownership(&vault), 10000);
+    assert_eq!(nft.get_vault_nfts(&vault).len(), 3);
+
+    // A mint that would overshoot from below is rejected too
+    let other = Address::generate(&env);
+    mint(&nft, &buyer, &other, 6000);
+    assert_eq!(
+        nft.try_mint_nft(&buyer, &other, &5000, &metadata(&env)),
+        Err(Ok(VaultNFTError::OwnershipExceeded))
+    );
+    assert_eq!(nft.available_ownership(&other), 4000);
+}
+
+#[test]
+fn batch_mint_mints_all_or_nothing() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let minter = Address::generate(&env);
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &minter);
+    mint(&nft, &minter, &vault, 3000);
+
+    let investors: std::vec::Vec<Address> = (0..7).map(|_| Address::generate(&env)).collect();
+    let mut recipients = soroban_sdk::Vec::new(&env);
+    for investor in &investors {
+        recipients.push_back((investor.clone(), 1000));
+    }
+
+    // Seven 10% NFTs fill the vault exactly, so one more basis point on the
+    // last of them fails the whole batch
+    recipients.set(6, (investors[6].clone(), 1001));
+    let events_before = env.events().all().len();
+    assert_eq!(
+        nft.try_batch_mint(&minter, &vault, &recipients, &metadata(&env)),
+        Err(Ok(VaultNFTError::OwnershipExceeded))
+    );
+    assert_eq!(env.events().all().len(), events_before);
+    assert_eq!(nft.get_vault_nfts(&vault).len(), 1);
+    assert_eq!(nft.get_total_ownership(&vault), 3000);
+
+    assert_eq!(
+        nft.try_batch_mint(&minter, &vault, &soroban_sdk::Vec::new(&env), &metadata(&env)),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+
+    recipients.set(6, (investors[6].clone(), 1000));
+    assert_eq!(
+        nft.try_batch_mint(&investors[0], &vault, &recipients, &metadata(&env)),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    let ids = nft.batch_mint(&minter, &vault, &recipients, &metadata(&env));
+    assert_eq!(ids, vec![&env, 2, 3, 4, 5, 6, 7, 8]);
+    for (investor, id) in investors.iter().zip(ids.iter()) {
+        assert_eq!(nft.get_nft(&id).holder, *investor);
+        assert_eq!(nft.get_holder_nfts(investor), vec![&env, id]);
+    }
+    assert_eq!(nft.get_total_ownership(&vault), 10000);
+
+    // One NFT_MINT per NFT, then the summary
+    let events = env.events().all();
+    let (_, topics, data) = events.last().unwrap();
+    assert_eq!(topics, (symbol_short!("NFT_BATCH"), vault.clone()).into_val(&env));
+    let summary: (u32, i128) = data.into_val(&env);
+    assert_eq!(summary, (7, 7000));
+    let mints = events.iter()
+        .skip(events_before as usize)
+        .filter(|(_, topics, _)| *topics == (symbol_short!("NFT_MINT"), vault.clone()).into_val(&env))
+        .count();
+    assert_eq!(mints, 7);
+}
+
+#[test]
+fn burning_releases_ownership_for_new_mints() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let kept = mint(&nft, &alice, &vault, 6000);
+    let burned = mint(&nft, &bob, &vault, 4000);
+    assert_eq!(nft.try_burn_nft(&alice, &burned), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(nft.try_burn_nft(&bob, &99), Err(Ok(VaultNFTError::NFTNotFound)));
+
+    nft.burn_nft(&bob, &burned);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("NFT_BURN"), vault.clone()).into_val(&env));
+    let burn: (u64, i128) = data.into_val(&env);
+    assert_eq!(burn, (burned, 4000));
+
+    assert_eq!(nft.get_total_ownership(&vault), 6000);
+    assert_eq!(nft.get_vault_nfts(&vault), soroban_sdk::vec![&env, kept]);
+    assert_eq!(nft.try_get_nft(&burned), Err(Ok(VaultNFTError::NFTNotFound)));
+    assert_eq!(nft.try_burn_nft(&bob, &burned), Err(Ok(VaultNFTError::NFTNotFound)));
+
+    // The released 40% can be minted again, but no more
+    mint(&nft, &bob, &vault, 4000);
+    assert_eq!(
+        nft.try_mint_nft(&alice, &vault, &1, &metadata(&env)),
+        Err(Ok(VaultNFTError::OwnershipExceeded))
+    );
+}
+
+#[test]
+fn only_the_registered_minter_mints_for_a_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let vault = Address::generate(&env);
+    let attacker = Address::generate(&env);
+
+    // A vault without a minter rejects every mint
+    assert_eq!(
+        nft.try_mint_nft(&attacker, &vault, &1000, &metadata(&env)),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    // Only the admin names the minter, and then only the minter mints
+    assert_eq!(nft.try_initialize(&attacker), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(
+        nft.try_set_vault_minter(&attacker, &vault, &attacker),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    nft.set_vault_minter(&admin, &vault, &vault);
+    assert_eq!(nft.get_vault_minter(&vault), Some(vault.clone()));
+    assert_eq!(
+        nft.try_mint_nft(&attacker, &vault, &1000, &metadata(&env)),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(nft.mint_nft(&vault, &vault, &1000, &metadata(&env)), 1);
+    assert_eq!(nft.get_total_ownership(&vault), 1000);
+}
+
+#[test]
+fn limits_match_what_mint_enforces() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let limits = nft.get_limits();
+    assert_eq!(limits.version, 2);
+    assert_eq!((limits.min_ownership_bps, limits.max_ownership_bps, limits.bps_scale), (1, 10000, 10000));
+    assert_eq!(limits.max_nfts_per_vault, 100);
+
+    let minter = Address::generate(&env);
+    let vault = Address::generate(&env);
+    assert_eq!(
+        nft.try_mint_nft(&minter, &vault, &(limits.min_ownership_bps - 1), &metadata(&env)),
+        Err(Ok(VaultNFTError::InvalidOwnership))
+    );
+    assert_eq!(
+        nft.try_mint_nft(&minter, &vault, &(limits.max_ownership_bps + 1), &metadata(&env)),
+        Err(Ok(VaultNFTError::InvalidBps))
+    );
+    mint(&nft, &minter, &vault, limits.max_ownership_bps);
+}
+
+#[test]
+fn bps_inputs_are_validated_at_every_entry_point() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let minter = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let nft_id = mint(&nft, &minter, &vault, 5000);
+
+    // Ownership: negative or past the bps scale is InvalidBps, zero is InvalidOwnership
+    for (pct, err) in [
+        (-1, VaultNFTError::InvalidBps),
+        (10001, VaultNFTError::InvalidBps),
+        (0, VaultNFTError::InvalidOwnership),
+    ] {
+        assert_eq!(nft.try_mint_nft(&minter, &vault, &pct, &metadata(&env)), Err(Ok(err)));
+        assert_eq!(
+            nft.try_batch_mint(&minter, &vault, &vec![&env, (alice.clone(), pct)], &metadata(&env)),
+            Err(Ok(err))
+        );
+    }
+    assert_eq!(nft.try_split_nft(&minter, &nft_id, &-1), Err(Ok(VaultNFTError::InvalidBps)));
+
+    // Profit share and royalty, up to and past their caps
+    assert_eq!(nft.try_register_profit_share(&minter, &vault, &-1), Err(Ok(VaultNFTError::InvalidBps)));
+    assert_eq!(nft.try_register_profit_share(&minter, &vault, &10001), Err(Ok(VaultNFTError::InvalidBps)));
+    nft.register_profit_share(&minter, &vault, &10000);
+    assert_eq!(nft.try_set_royalty(&minter, &vault, &-1, &alice), Err(Ok(VaultNFTError::InvalidBps)));
+    assert_eq!(nft.try_set_royalty(&minter, &vault, &1001, &alice), Err(Ok(VaultNFTError::InvalidBps)));
+    nft.set_royalty(&minter, &vault, &1000, &alice);
+
+    // Prices and profits must be positive
+    let (token, _) = create_token(&env, &alice);
+    for amount in [0, -1] {
+        assert_eq!(
+            nft.try_buy_nft(&alice, &minter, &nft_id, &token.address, &amount),
+            Err(Ok(VaultNFTError::InvalidPrice))
+        );
+        assert_eq!(
+            nft.try_distribute_profits(&vault, &vault, &amount, &Address::generate(&env)),
+            Err(Ok(VaultNFTError::InvalidAmount))
+        );
+        assert_eq!(
+            nft.try_preview_distribution(&vault, &amount),
+            Err(Ok(VaultNFTError::InvalidAmount))
+        );
+    }
+}
+
+#[test]
+fn profit_share_is_set_by_one_minter_and_only_rises() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let minter = Address::generate(&env);
+    let other = Address::generate(&env);
+    let vault = Address::generate(&env);
+
+    // Nobody may register before the admin names the vault's minter
+    assert_eq!(nft.get_profit_share(&vault), 0);
+    assert_eq!(
+        nft.try_register_profit_share(&minter, &vault, &8000),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &minter);
+    nft.register_profit_share(&minter, &vault, &8000);
+    assert_eq!(nft.get_profit_share(&vault), 8000);
+    assert_eq!(nft.get_vault_minter(&vault), Some(minter.clone()));
+
+    // Another address can neither change the agreement nor mint against it
+    assert_eq!(
+        nft.try_register_profit_share(&other, &vault, &9000),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(
+        nft.try_mint_nft(&other, &vault, &1000, &metadata(&env)),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    mint(&nft, &minter, &vault, 1000);
+
+    // The share can be raised but never lowered
+    assert_eq!(
+        nft.try_register_profit_share(&minter, &vault, &7999),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+    assert_eq!(
+        nft.try_register_profit_share(&minter, &vault, &10001),
+        Err(Ok(VaultNFTError::InvalidBps))
+    );
+    nft.register_profit_share(&minter, &vault, &9000);
+    assert_eq!(nft.get_profit_share(&vault), 9000);
+}
+
+#[test]
+fn vault_contract_distributes_as_itself() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let alice = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    mint(&nft, &alice, &vault, 5000);
+    token_admin.mint(&nft.address, &500);
+
+    // No mocked signatures: the vault's own invocation is its authorization
+    env.set_auths(&[]);
+    assert!(nft.try_distribute_profits(&vault, &vault, &1000, &token.address).is_err());
+    MockVaultClient::new(&env, &vault).distribute(&nft.address, &1000, &token.address);
+    assert_eq!(nft.get_owed(&alice, &token.address), 500);
+}
+
+#[test]
+fn registered_distributor_distributes_for_the_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let minter = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let stranger = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &minter);
+    nft.register_profit_share(&minter, &vault, &5000);
+    mint(&nft, &minter, &vault, 5000);
+    nft.transfer(&1, &minter, &alice);
+    token_admin.mint(&nft.address, &1000);
+
+    assert_eq!(
+        nft.try_distribute_profits(&operator, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(
+        nft.try_set_distributor(&stranger, &vault, &Some(stranger.clone())),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    nft.set_distributor(&minter, &vault, &Some(operator.clone()));
+    assert_eq!(nft.get_distributor(&vault), Some(operator.clone()));
+    nft.distribute_profits(&operator, &vault, &1000, &token.address);
+    assert_eq!(env.auths()[0].0, operator);
+    assert_eq!(nft.get_owed(&alice, &token.address), 500);
+    assert_eq!(
+        nft.try_distribute_profits(&stranger, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+
+    nft.set_distributor(&minter, &vault, &None);
+    assert_eq!(
+        nft.try_distribute_profits(&operator, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+}
+
+#[test]
+fn opted_in_profits_are_reinvested_as_vault_shares() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let vault_client = MockVaultClient::new(&env, &vault);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    vault_client.set_token(&token.address);
+    mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 5000);
+    token_admin.mint(&nft.address, &1000);
+
+    nft.set_reinvest(&alice, &vault, &true);
+    assert!(nft.get_reinvest(&alice, &vault));
+    vault_client.distribute(&nft.address, &1000, &token.address);
+
+    // Alice's cut waits to be reinvested; Bob's is claimable as usual
+    assert_eq!(nft.get_owed(&alice, &token.address), 0);
+    assert_eq!(nft.get_pending_reinvest(&alice, &vault, &token.address), 500);
+    assert_eq!(nft.get_owed(&bob, &token.address), 500);
+    assert_eq!(nft.total_owed(&token.address), 1000);
+
+    assert_eq!(nft.process_reinvest(&alice, &vault, &token.address), 500);
+    assert_eq!(vault_client.shares(&alice), 500);
+    assert_eq!(token.balance(&vault), 500);
+    assert_eq!(token.allowance(&nft.address, &vault), 0);
+    assert_eq!(nft.get_pending_reinvest(&alice, &vault, &token.address), 0);
+    assert_eq!(nft.total_owed(&token.address), 500);
+    assert_eq!(
+        nft.try_process_reinvest(&alice, &vault, &token.address),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+}
+
+#[test]
+fn failed_reinvestment_falls_back_to_a_claimable_balance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let vault_client = MockVaultClient::new(&env, &vault);
+    let alice = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    vault_client.set_token(&token.address);
+    mint(&nft, &alice, &vault, 10000);
+    token_admin.mint(&nft.address, &1000);
+    nft.set_reinvest(&alice, &vault, &true);
+    vault_client.distribute(&nft.address, &400, &token.address);
+
+    vault_client.set_refusing(&true);
+    assert_eq!(nft.process_reinvest(&alice, &vault, &token.address), 0);
+    assert_eq!(vault_client.shares(&alice), 0);
+    assert_eq!(token.allowance(&nft.address, &vault), 0);
+    assert_eq!(nft.get_pending_reinvest(&alice, &vault, &token.address), 0);
+    assert_eq!(nft.get_owed(&alice, &token.address), 400);
+    assert_eq!(nft.total_owed(&token.address), 400);
+
+    // Opting out later also turns what is held into a claim
+    vault_client.distribute(&nft.address, &600, &token.address);
+    nft.set_reinvest(&alice, &vault, &false);
+    vault_client.set_refusing(&false);
+    assert_eq!(nft.process_reinvest(&alice, &vault, &token.address), 0);
+    assert_eq!(nft.claim_profit(&alice, &token.address), 1000);
+    assert_eq!(token.balance(&alice), 1000);
+}
+
+#[test]
+fn idle_holders_dont_hold_up_recorded_distributions() {
+    use crate::{ENTITLEMENT_PREFIX, HOLDER_DISTS_PREFIX};
+    use soroban_sdk::testutils::storage::Persistent as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+    mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 5000);
+    token_admin.mint(&nft.address, &1000);
+
+    // Alice never claims; bob claims as he goes
+    env.budget().reset_unlimited();
+    for _ in 0..200 {
+        let id = nft.record_distribution(&vault, &vault, &2, &token.address);
+        nft.claim(&bob, &id);
+    }
+
+    // Alice's list is full, so her cut is owed to her instead
+    let id = nft.record_distribution(&vault, &vault, &2, &token.address);
+    assert_eq!(nft.get_owed(&alice, &token.address), 1);
+    assert_eq!(nft.get_claimable(&alice), map![&env, (token.address.clone(), 200)]);
+    assert_eq!(nft.get_claimable(&bob), map![&env, (token.address.clone(), 1)]);
+    assert_eq!(nft.total_owed(&token.address), 202);
+
+    let max = env.storage().max_ttl();
+    nft.extend_claim_ttl(&bob, &token.address, &max);
+    env.as_contract(&nft.address, || {
+        let persistent = env.storage().persistent();
+        assert_eq!(persistent.get_ttl(&(HOLDER_DISTS_PREFIX, bob.clone())), max);
+        assert_eq!(persistent.get_ttl(&(ENTITLEMENT_PREFIX, bob.clone(), id)), max);
+    });
+
+    assert_eq!(nft.claim(&bob, &id), 1);
+    assert_eq!(nft.claim_profit(&alice, &token.address), 1);
+    assert_eq!(nft.total_owed(&token.address), 200);
+}
+
+#[test]
+fn vault_nft_list_is_capped_and_distributes_at_the_cap() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let minter = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &minter);
+    let cap = nft.get_limits().max_nfts_per_vault;
+
+    env.budget().reset_unlimited();
+    for _ in 0..cap {
+        mint(&nft, &minter, &vault, 1);
+    }
+    assert_eq!(
+        nft.try_mint_nft(&minter, &vault, &1, &metadata(&env)),
+        Err(Ok(VaultNFTError::TooManyItems))
+    );
+    assert_eq!(nft.get_total_ownership(&vault), cap as i128);
+
+    // Other vaults have their own lists
+    mint(&nft, &minter, &Address::generate(&env), 1);
+
+    token_admin.mint(&nft.address, &10000);
+    nft.register_profit_share(&minter, &vault, &1);
+    nft.set_distributor(&minter, &vault, &Some(minter.clone()));
+
+    // A full list still distributes within a single call's budget
+    env.budget().reset_default();
+    nft.distribute_profits(&minter, &vault, &10000, &token.address);
+    assert_eq!(nft.get_owed(&minter, &token.address), cap as i128);
+}
+
+#[test]
+fn locked_nft_transfers_only_after_its_lock() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &alice);
+    let now = env.ledger().timestamp();
+    let vesting = nft.mint_nft_locked(&alice, &vault, &2500, &metadata(&env), &(now + 1000));
+    let free = mint(&nft, &alice, &vault, 2500);
+    assert_eq!(nft.get_locked_until(&vesting), Some(now + 1000));
+    assert_eq!(nft.get_locked_until(&free), None);
+
+    nft.transfer(&free, &alice, &bob);
+    assert_eq!(nft.try_transfer(&vesting, &alice, &bob), Err(Ok(VaultNFTError::Locked)));
+
+    env.ledger().with_mut(|li| li.timestamp = now + 999);
+    assert_eq!(nft.try_transfer(&vesting, &alice, &bob), Err(Ok(VaultNFTError::Locked)));
+
+    env.ledger().with_mut(|li| li.timestamp = now + 1000);
+    nft.transfer(&vesting, &alice, &bob);
+    assert_eq!(nft.get_nft(&vesting).holder, bob);
+}
+
+#[test]
+fn split_divides_an_nft_without_changing_the_vault_total() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let original = mint(&nft, &alice, &vault, 6000);
+    mint(&nft, &bob, &vault, 1000);
+
+    assert_eq!(nft.try_split_nft(&bob, &original, &1000), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(nft.try_split_nft(&alice, &99, &1000), Err(Ok(VaultNFTError::NFTNotFound)));
+    for bad in [0, 6000, 6001] {
+        assert_eq!(nft.try_split_nft(&alice, &original, &bad), Err(Ok(VaultNFTError::InvalidOwnership)));
+    }
+    assert_eq!(nft.try_split_nft(&alice, &original, &-1), Err(Ok(VaultNFTError::InvalidBps)));
+
+    let split = nft.split_nft(&alice, &original, &2500);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(topics, (symbol_short!("NFT_SPLIT"), vault.clone()).into_val(&env));
+    let event: (u64, i128, u64, i128) = data.into_val(&env);
+    assert_eq!(event, (original, 3500, split, 2500));
+
+    assert_eq!(nft.get_nft(&original).ownership_percentage, 3500);
+    let new_nft = nft.get_nft(&split);
+    assert_eq!((new_nft.ownership_percentage, new_nft.holder.clone()), (2500, alice.clone()));
+    assert_eq!(new_nft.vault_address, vault);
+    assert!(nft.get_vault_nfts(&vault).contains(split));
+    assert_eq!(nft.get_total_ownership(&vault), 7000);
+
+    // The split-off part can be sold on its own
+    nft.transfer(&split, &alice, &bob);
+    assert_eq!(nft.get_nft(&split).holder, bob);
+    assert_eq!(nft.get_nft(&original).holder, alice);
+}
+
+#[test]
+fn merge_folds_a_holders_nfts_into_one() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let other_vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let first = mint(&nft, &alice, &vault, 1000);
+    let second = mint(&nft, &alice, &vault, 500);
+    let third = nft.mint_nft_locked(&alice, &vault, &250, &metadata(&env), &5_000);
+    let bobs = mint(&nft, &bob, &vault, 2000);
+    let elsewhere = mint(&nft, &alice, &other_vault, 2000);
+
+    assert_eq!(nft.try_merge_nfts(&alice, &vec![&env]), Err(Ok(VaultNFTError::InvalidAmount)));
+    assert_eq!(nft.try_merge_nfts(&alice, &vec![&env, first]), Err(Ok(VaultNFTError::InvalidAmount)));
+    assert_eq!(nft.try_merge_nfts(&alice, &vec![&env, first, second, second]), Err(Ok(VaultNFTError::InvalidAmount)));
+    assert_eq!(nft.try_merge_nfts(&alice, &vec![&env, first, bobs]), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(nft.try_merge_nfts(&alice, &vec![&env, first, elsewhere]), Err(Ok(VaultNFTError::InvalidOwnership)));
+
+    let survivor = nft.merge_nfts(&alice, &vec![&env, first, second, third]);
+    assert_eq!(survivor, first);
+    assert_eq!(nft.get_nft(&first).ownership_percentage, 1750);
+    assert_eq!(nft.try_get_nft(&second), Err(Ok(VaultNFTError::NFTNotFound)));
+    assert_eq!(nft.try_get_nft(&third), Err(Ok(VaultNFTError::NFTNotFound)));
+    assert_eq!(nft.get_vault_nfts(&vault), vec![&env, first, bobs]);
+    assert_eq!(nft.get_total_ownership(&vault), 3750);
+
+    // The survivor keeps the merged-in lock, so merging can't skip vesting
+    assert_eq!(nft.get_locked_until(&first), Some(5_000));
+    assert_eq!(nft.get_locked_until(&third), None);
+    assert_eq!(nft.try_transfer(&first, &alice, &bob), Err(Ok(VaultNFTError::Locked)));
+}
+
+#[test]
+fn approved_spenders_and_operators_can_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    env.ledger().with_mut(|li| li.sequence_number = 100);
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let seller = Address::generate(&env);
+    let escrow = Address::generate(&env);
+    let market = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    let first = mint(&nft, &seller, &vault, 1000);
+    let second = mint(&nft, &seller, &vault, 1000);
+
+    // Nobody is approved yet, and only the holder can approve
+    assert_eq!(nft.try_transfer_from(&escrow, &seller, &buyer, &first), Err(Ok(VaultNFTError::Unauthorized)));
+    assert_eq!(nft.try_approve(&buyer, &escrow, &first, &100), Err(Ok(VaultNFTError::Unauthorized)));
+    let ledger = env.ledger().sequence();
+    assert_eq!(nft.try_approve(&seller, &escrow, &first, &(ledger - 1)), Err(Ok(VaultNFTError::InvalidAmount)));
+
+    // An approval lapses after its ledger
+    nft.approve(&seller, &escrow, &first, &(ledger + 10));
+    assert_eq!(nft.get_approved(&first), Some(escrow.clone()));
+    env.ledger().with_mut(|li| li.sequence_number += 11);
+    assert_eq!(nft.get_approved(&first), None);
+    assert_eq!(nft.try_transfer_from(&escrow, &seller, &buyer, &first), Err(Ok(VaultNFTError::Unauthorized)));
+
+    // A live approval moves the NFT once and is then cleared
+    let ledger = env.ledger().sequence();
+    nft.approve(&seller, &escrow, &first, &(ledger + 10));
+    assert_eq!(nft.try_transfer_from(&market, &seller, &buyer, &first), Err(Ok(VaultNFTError::Unauthorized)));
+    nft.transfer_from(&escrow, &seller, &buyer, &first);
+    assert_eq!(nft.get_nft(&first).holder, buyer);
+    assert_eq!(nft.get_approved(&first), None);
+    assert_eq!(nft.try_transfer_from(&escrow, &buyer, &seller, &first), Err(Ok(VaultNFTError::Unauthorized)));
+
+    // A plain transfer clears an approval too
+    nft.approve(&seller, &escrow, &second, &(ledger + 10));
+    nft.transfer(&second, &seller, &buyer);
+    assert_eq!(nft.get_approved(&second), None);
+    nft.transfer(&second, &buyer, &seller);
+
+    // An operator can move any of the holder's NFTs until revoked
+    nft.set_approval_for_all(&seller, &market, &true);
+    assert!(nft.is_approved_for_all(&seller, &market));
+    nft.transfer_from(&market, &seller, &buyer, &second);
+    assert_eq!(nft.get_nft(&second).holder, buyer);
+    assert_eq!(nft.try_transfer_from(&market, &buyer, &seller, &second), Err(Ok(VaultNFTError::Unauthorized)));
+
+    nft.set_approval_for_all(&seller, &market, &false);
+    let third = mint(&nft, &seller, &vault, 1000);
+    assert_eq!(nft.try_transfer_from(&market, &seller, &buyer, &third), Err(Ok(VaultNFTError::Unauthorized)));
+
+    // Burning clears the approval
+    nft.approve(&seller, &escrow, &third, &(ledger + 10));
+    nft.burn_nft(&seller, &third);
+    assert_eq!(nft.get_approved(&third), None);
+}
+
+#[test]
+fn holder_index_follows_every_ownership_change() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let other_vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let first = mint(&nft, &alice, &vault, 1000);
+    let second = mint(&nft, &alice, &vault, 500);
+    let elsewhere = mint(&nft, &alice, &other_vault, 2000);
+    assert_eq!(nft.get_holder_nfts(&alice), vec![&env, first, second, elsewhere]);
+    assert_eq!(nft.get_holder_ownership(&alice, &vault), 1500);
+    assert_eq!(nft.get_holder_nfts(&bob), vec![&env]);
+
+    nft.transfer(&second, &alice, &bob);
+    assert_eq!(nft.get_holder_nfts(&alice), vec![&env, first, elsewhere]);
+    assert_eq!(nft.get_holder_nfts(&bob), vec![&env, second]);
+    assert_eq!(nft.get_holder_ownership(&alice, &vault), 1000);
+    assert_eq!(nft.get_holder_ownership(&bob, &vault), 500);
+
+    let split = nft.split_nft(&alice, &first, &400);
+    assert_eq!(nft.get_holder_nfts(&alice), vec![&env, first, elsewhere, split]);
+    assert_eq!(nft.get_holder_ownership(&alice, &vault), 1000);
+
+    nft.merge_nfts(&alice, &vec![&env, split, first]);
+    assert_eq!(nft.get_holder_nfts(&alice), vec![&env, elsewhere, split]);
+
+    nft.burn_nft(&bob, &second);
+    assert_eq!(nft.get_holder_nfts(&bob), vec![&env]);
+    assert_eq!(nft.get_holder_ownership(&bob, &vault), 0);
+    assert_eq!(nft.get_holder_ownership(&alice, &vault), 1000);
+    assert_eq!(nft.get_holder_ownership(&alice, &other_vault), 2000);
+}
+
+#[test]
+fn transfer_history_starts_at_mint_and_keeps_the_latest_entries() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000);
+    let nft_id = mint(&nft, &alice, &vault, 1000);
+    env.ledger().with_mut(|li| li.timestamp = 2_000);
+    nft.transfer(&nft_id, &alice, &bob);
+    nft.set_approval_for_all(&bob, &carol, &true);
+    nft.transfer_from(&carol, &bob, &alice, &nft_id);
+
+    let history = nft.get_transfer_history(&nft_id);
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get(0).unwrap(), TransferRecord { from: None, to: alice.clone(), timestamp: 1_000 });
+    assert_eq!(history.get(1).unwrap(), TransferRecord { from: Some(alice.clone()), to: bob.clone(), timestamp: 2_000 });
+    assert_eq!(history.get(2).unwrap(), TransferRecord { from: Some(bob.clone()), to: alice.clone(), timestamp: 2_000 });
+
+    // A split-off NFT carries its parent's provenance
+    let split = nft.split_nft(&alice, &nft_id, &400);
+    assert_eq!(nft.get_transfer_history(&split), history);
+
+    // Past 20 entries the oldest go first, the mint among them
+    for _ in 0..10 {
+        nft.transfer(&nft_id, &alice, &bob);
+        nft.transfer(&nft_id, &bob, &alice);
+    }
+    let history = nft.get_transfer_history(&nft_id);
+    assert_eq!(history.len(), 20);
+    assert_eq!(history.get(0).unwrap().from, Some(alice.clone()));
+    assert_eq!(history.last().unwrap(), TransferRecord { from: Some(bob.clone()), to: alice.clone(), timestamp: 2_000 });
+
+    // Gone with the NFT
+    nft.burn_nft(&alice, &nft_id);
+    assert_eq!(nft.get_transfer_history(&nft_id).len(), 0);
+    assert_eq!(nft.get_transfer_history(&99).len(), 0);
+}
+
+#[test]
+fn full_metadata_round_trips() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+
+    let meta = NFTMetadata {
+        name: String::from_str(&env, "Growth Vault #1"),
+        description: String::from_str(&env, "10% of the growth vault"),
+        image_url: String::from_str(&env, "ipfs://growth/1.png"),
+        vault_performance: 1250,
+    };
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &alice);
+    let nft_id = nft.mint_nft(&alice, &vault, &1000, &meta);
+    assert_eq!(nft.get_nft_metadata(&nft_id), meta);
+    assert_eq!(nft.get_nft(&nft_id).metadata, meta);
+
+    // A split-off NFT describes the same holding
+    let split = nft.split_nft(&alice, &nft_id, &400);
+    assert_eq!(nft.get_nft_metadata(&split), meta);
+
+    nft.burn_nft(&alice, &split);
+    assert_eq!(nft.try_get_nft_metadata(&split), Err(Ok(VaultNFTError::NFTNotFound)));
+}
+
+#[test]
+fn instance_stored_nfts_move_to_persistent_storage_on_read() {
+    use crate::{LegacyVaultNFT, NFT_METADATA_PREFIX, NFT_PREFIX, VAULT_NFTS_PREFIX};
+    use soroban_sdk::testutils::storage::Persistent as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    // NFTs written the way earlier versions did, with only the name inline
+    let legacy = |nft_id: u64| LegacyVaultNFT {
+        nft_id,
+        vault_address: vault.clone(),
+        ownership_percentage: 2500,
+        holder: alice.clone(),
+        metadata: String::from_str(&env, "Vault Share"),
+    };
+    let full = metadata(&env);
+    env.as_contract(&nft.address, || {
+        env.storage().instance().set(&(NFT_PREFIX, 7u64), &legacy(7));
+        env.storage().instance().set(&(NFT_METADATA_PREFIX, 7u64), &full);
+        env.storage().persistent().set(&(NFT_PREFIX, 8u64), &legacy(8));
+        env.storage().instance().set(&(VAULT_NFTS_PREFIX, vault.clone()), &vec![&env, 7u64, 8]);
+    });
+
+    let upgraded = nft.get_nft(&7);
+    assert_eq!((upgraded.nft_id, upgraded.holder.clone(), upgraded.ownership_percentage), (7, alice.clone(), 2500));
+    assert_eq!(upgraded.metadata, full);
+    assert_eq!(nft.get_nft_metadata(&8).name, String::from_str(&env, "Vault Share"));
+    assert_eq!(nft.get_nft_metadata(&8).image_url, String::from_str(&env, ""));
+    assert_eq!(nft.get_vault_nfts(&vault), vec![&env, 7, 8]);
+    env.as_contract(&nft.address, || {
+        assert!(!env.storage().instance().has(&(NFT_PREFIX, 7u64)));
+        assert!(!env.storage().instance().has(&(NFT_METADATA_PREFIX, 7u64)));
+        assert!(!env.storage().instance().has(&(VAULT_NFTS_PREFIX, vault.clone())));
+        assert!(env.storage().persistent().has(&(NFT_PREFIX, 7u64)));
+    });
+
+    // Migrated NFTs behave like any other
+    nft.transfer(&7, &alice, &bob);
+    assert_eq!(nft.get_nft(&7).holder, bob);
+
+    // New mints go straight to persistent storage, and anyone can extend them
+    let minted = mint(&nft, &alice, &vault, 1000);
+    let ttl = |nft_id: u64| env.as_contract(&nft.address, || env.storage().persistent().get_ttl(&(NFT_PREFIX, nft_id)));
+    let before = ttl(minted);
+    nft.extend_nft_ttl(&minted, &(before + 1000));
+    assert_eq!(ttl(minted), before + 1000);
+
+    assert_eq!(nft.try_extend_nft_ttl(&99, &1000), Err(Ok(VaultNFTError::NFTNotFound)));
+    assert_eq!(
+        nft.try_extend_nft_ttl(&minted, &(env.storage().max_ttl() + 1)),
+        Err(Ok(VaultNFTError::InvalidAmount))
+    );
+}
+
+#[test]
+fn holder_and_vault_state_is_persistent_and_extendable() {
+    use crate::{
+        APPROVAL_PREFIX, HOLDER_NFTS_PREFIX, OPERATOR_PREFIX, OWED_AT_PREFIX, OWED_PREFIX,
+        OWNERSHIP_TOTAL_PREFIX, REINVEST_OWED_PREFIX, REINVEST_PREFIX, TOTAL_OWED_PREFIX,
+    };
+    use soroban_sdk::testutils::storage::Persistent as _;
+
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &vault);
+
+    let nft_id = mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 5000);
+    nft.approve(&alice, &operator, &nft_id, &1000);
+    nft.set_approval_for_all(&alice, &operator, &true);
+    nft.set_reinvest(&bob, &vault, &true);
+    token_admin.mint(&nft.address, &1000);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+
+    let max = env.storage().max_ttl();
+    nft.extend_nft_ttl(&nft_id, &max);
+    nft.extend_claim_ttl(&alice, &token.address, &max);
+    nft.extend_claim_ttl(&bob, &token.address, &max);
+    assert_eq!(nft.try_extend_claim_ttl(&alice, &token.address, &0), Err(Ok(VaultNFTError::InvalidAmount)));
+
+    env.as_contract(&nft.address, || {
+        let persistent = env.storage().persistent();
+        assert_eq!(persistent.get_ttl(&(APPROVAL_PREFIX, nft_id)), max);
+        assert_eq!(persistent.get_ttl(&(HOLDER_NFTS_PREFIX, alice.clone())), max);
+        assert_eq!(persistent.get_ttl(&(OWNERSHIP_TOTAL_PREFIX, vault.clone())), max);
+        assert_eq!(persistent.get_ttl(&(OWED_PREFIX, alice.clone(), token.address.clone())), max);
+        assert_eq!(persistent.get_ttl(&(OWED_AT_PREFIX, alice.clone(), token.address.clone())), max);
+        assert_eq!(persistent.get_ttl(&(TOTAL_OWED_PREFIX, token.address.clone())), max);
+        assert_eq!(
+            persistent.get_ttl(&(REINVEST_OWED_PREFIX, bob.clone(), vault.clone(), token.address.clone())),
+            max
+        );
+        assert!(persistent.has(&(REINVEST_PREFIX, bob.clone(), vault.clone())));
+        assert!(persistent.has(&(OPERATOR_PREFIX, alice.clone(), operator.clone())));
+        assert!(!env.storage().instance().has(&(OWED_PREFIX, alice.clone(), token.address.clone())));
+    });
+    assert!(nft.is_approved_for_all(&alice, &operator));
+    assert_eq!(nft.get_owed(&alice, &token.address), 500);
+    assert_eq!(nft.get_pending_reinvest(&bob, &vault, &token.address), 500);
+}
+
+#[test]
+fn redeem_pays_the_nft_share_of_the_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let vault_client = MockVaultClient::new(&env, &vault);
+    let depositor = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &depositor);
+    vault_client.set_token(&token.address);
+
+    // 1000 shares held by the NFT contract, worth 1200 after yield
+    token_admin.mint(&depositor, &1000);
+    token.approve(&depositor, &vault, &1000, &1000);
+    nft.fund_nfts(&depositor, &vault, &1000);
+    token_admin.mint(&vault, &200);
+
+    let alice_nft = mint(&nft, &alice, &vault, 2500);
+    let bob_nft = mint(&nft, &bob, &vault, 2500);
+
+    assert_eq!(
+        nft.try_redeem(&bob, &alice_nft),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(nft.redeem(&alice, &alice_nft), 300);
+    assert_eq!(token.balance(&alice), 300);
+    assert_eq!(vault_client.shares(&nft.address), 750);
+    assert_eq!(nft.get_nft_shares(&vault), 750);
+    assert_eq!(nft.try_get_nft(&alice_nft), Err(Ok(VaultNFTError::NFTNotFound)));
+    assert_eq!(nft.get_total_ownership(&vault), 2500);
+
+    // An illiquid vault fails the redemption and leaves the NFT in place
+    vault_client.set_illiquid(&true);
+    assert_eq!(
+        nft.try_redeem(&bob, &bob_nft),
+        Err(Ok(VaultNFTError::InsufficientLiquidity))
+    );
+    assert_eq!(nft.get_nft(&bob_nft).holder, bob);
+    assert_eq!(token.balance(&bob), 0);
+}
+
+#[test]
+fn redemptions_add_up_to_the_whole_position() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let vault_client = MockVaultClient::new(&env, &vault);
+    let depositor = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &depositor);
+    vault_client.set_token(&token.address);
+
+    token_admin.mint(&depositor, &1000);
+    token.approve(&depositor, &vault, &1000, &1000);
+    nft.fund_nfts(&depositor, &vault, &1000);
+    token_admin.mint(&vault, &200);
+
+    let alice_nft = mint(&nft, &alice, &vault, 3000);
+    let bob_nft = mint(&nft, &bob, &vault, 7000);
+
+    // Bob's 70% is of the 1000 shares there were, not of the 700 left after alice
+    assert_eq!(nft.redeem(&alice, &alice_nft), 360);
+    assert_eq!(nft.redeem(&bob, &bob_nft), 840);
+    assert_eq!(token.balance(&alice) + token.balance(&bob), 1200);
+    assert_eq!(vault_client.shares(&nft.address), 0);
+    assert_eq!(nft.get_vault_nfts(&vault), vec![&env]);
+}
+
+#[test]
+fn redeem_leaves_other_depositors_shares_alone() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = env.register_contract(None, MockVault);
+    let vault_client = MockVaultClient::new(&env, &vault);
+    let depositor = Address::generate(&env);
+    let carol = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &depositor);
+    vault_client.set_token(&token.address);
+
+    // 1000 shares back the NFTs; carol holds 3000 of her own in the same vault
+    token_admin.mint(&depositor, &1000);
+    token.approve(&depositor, &vault, &1000, &1000);
+    assert_eq!(nft.fund_nfts(&depositor, &vault, &1000), 1000);
+    token_admin.mint(&carol, &3000);
+    token.approve(&carol, &vault, &3000, &1000);
+    vault_client.deposit_for(&carol, &carol, &3000);
+    assert_eq!(nft.get_nft_shares(&vault), 1000);
+
+    let alice_nft = mint(&nft, &alice, &vault, 4000);
+    let bob_nft = mint(&nft, &bob, &vault, 6000);
+
+    // 100% of the NFTs redeems the 1000 NFT shares, not 100% of the 4000 in the vault
+    assert_eq!(nft.redeem(&alice, &alice_nft), 400);
+    assert_eq!(nft.redeem(&bob, &bob_nft), 600);
+    assert_eq!(vault_client.shares(&nft.address), 0);
+    assert_eq!(nft.get_nft_shares(&vault), 0);
+    assert_eq!(vault_client.shares(&carol), 3000);
+    assert_eq!(vault_client.total_shares(), 3000);
+}
+
+#[test]
+fn oversized_profits_fail_cleanly() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    mint(&nft, &alice, &vault, 5000);
+    mint(&nft, &bob, &vault, 2500);
+
+    // Multiplying by the ownership percentage would overflow i128
+    for profit in [i128::MAX, i128::MAX / 5000 + 1] {
+        assert_eq!(
+            nft.try_distribute_profits(&vault, &vault, &profit, &token.address),
+            Err(Ok(VaultNFTError::InvalidAmount))
+        );
+    }
+    assert_eq!(nft.get_owed(&alice, &token.address), 0);
+
+    // The largest profit that still fits is split as usual
+    let profit = i128::MAX / 5000;
+    token_admin.mint(&nft.address, &i128::MAX);
+    let split = nft.distribute_profits(&vault, &vault, &profit, &token.address);
+    assert_eq!(split.get(alice.clone()), Some(profit / 2));
+    assert_eq!(split.get(bob.clone()), Some(profit / 4));
+    assert_eq!(nft.get_total_ownership(&vault), 7500);
+}
+
+#[test]
+fn sales_pay_the_vault_royalty_but_transfers_do_not() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let minter = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    nft.set_vault_minter(&nft.get_admin().unwrap(), &vault, &minter);
+    let nft_id = mint(&nft, &minter, &vault, 2500);
+    nft.transfer(&nft_id, &minter, &alice);
+
+    assert_eq!(
+        nft.try_set_royalty(&alice, &vault, &500, &creator),
+        Err(Ok(VaultNFTError::Unauthorized))
+    );
+    assert_eq!(
+        nft.try_set_royalty(&minter, &vault, &1001, &creator),
+        Err(Ok(VaultNFTError::InvalidBps))
+    );
+    assert_eq!(
+        nft.try_set_royalty(&minter, &vault, &500, &nft.address),
+        Err(Ok(VaultNFTError::InvalidRecipient))
+    );
+    nft.set_royalty(&minter, &vault, &500, &creator);
+    assert_eq!(nft.get_royalty(&vault), Some((500, creator.clone())));
+
+    // 5% of 1000 to the creator, the rest to the seller
+    token_admin.mint(&bob, &1000);
+    assert_eq!(nft.buy_nft(&bob, &alice, &nft_id, &token.address, &1000), 50);
+    assert_eq!(token.balance(&creator), 50);
+    assert_eq!(token.balance(&alice), 950);
+    assert_eq!(token.balance(&bob), 0);
+    assert_eq!(nft.get_nft(&nft_id).holder, bob);
+
+    nft.transfer(&nft_id, &bob, &carol);
+    assert_eq!(token.balance(&creator), 50);
+
+    // Without a royalty the seller gets the full price
+    nft.set_royalty(&minter, &vault, &0, &creator);
+    assert_eq!(nft.get_royalty(&vault), None);
+    token_admin.mint(&alice, &300);
+    assert_eq!(nft.buy_nft(&alice, &carol, &nft_id, &token.address, &300), 0);
+    assert_eq!(token.balance(&carol), 300);
+    assert_eq!(token.balance(&creator), 50);
+}
+
+#[test]
+fn holder_count_counts_each_holder_once() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let vault = Address::generate(&env);
+    let other_vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+    assert_eq!(nft.holder_count(&vault), 0);
+
+    mint(&nft, &alice, &vault, 1000);
+    mint(&nft, &alice, &vault, 1000);
+    let bobs = mint(&nft, &bob, &vault, 1000);
+    mint(&nft, &bob, &vault, 1000);
+    mint(&nft, &carol, &other_vault, 1000);
+    assert_eq!(nft.get_vault_nfts(&vault).len(), 4);
+    assert_eq!(nft.holder_count(&vault), 2);
+
+    // Giving one of bob's NFTs to carol adds a holder
+    nft.transfer(&bobs, &bob, &carol);
+    assert_eq!(nft.holder_count(&vault), 3);
+    assert_eq!(nft.holder_count(&other_vault), 1);
+}
+
+#[test]
+fn pause_halts_minting_transfers_and_distributions() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let nft = create_nft_contract(&env);
+    let admin = nft.get_admin().unwrap();
+    let vault = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let (token, token_admin) = create_token(&env, &alice);
+    let nft_id = mint(&nft, &alice, &vault, 5000);
+    token_admin.mint(&nft.address, &1000);
+
+    assert_eq!(nft.try_pause(&alice), Err(Ok(VaultNFTError::Unauthorized)));
+    nft.pause(&admin);
+    assert!(nft.paused());
+
+    assert_eq!(
+        nft.try_mint_nft(&alice, &vault, &1000, &metadata(&env)),
+        Err(Ok(VaultNFTError::Paused))
+    );
+    assert_eq!(nft.try_transfer(&nft_id, &alice, &bob), Err(Ok(VaultNFTError::Paused)));
+    assert_eq!(
+        nft.try_distribute_profits(&vault, &vault, &1000, &token.address),
+        Err(Ok(VaultNFTError::Paused))
+    );
+    // Reads keep working
+    assert_eq!(nft.get_nft(&nft_id).holder, alice);
+
+    assert_eq!(nft.try_unpause(&bob), Err(Ok(VaultNFTError::Unauthorized)));
+    nft.unpause(&admin);
+    assert!(!nft.paused());
+    nft.mint_nft(&alice, &vault, &1000, &metadata(&env));
+    nft.transfer(&nft_id, &alice, &bob);
+    nft.distribute_profits(&vault, &vault, &1000, &token.address);
+    assert_eq!(nft.get_owed(&bob, &token.address), 500);
+}
@@ -119,6 +119,8 @@ if ($EXISTING_FACTORY) {
     Write-Host "✅ Factory deployed: $FACTORY_ADDRESS" -ForegroundColor Green
     
     # Initialize factory
+    # Vaults are stamped with the factory's network; the CLI's "public" is mainnet
+    $FACTORY_NETWORK = if ($NETWORK -eq "public") { "mainnet" } else { $NETWORK }
     Write-Host "   Initializing factory..." -ForegroundColor Gray
     $initResult = stellar contract invoke `
         --id $FACTORY_ADDRESS `
@@ -126,7 +128,8 @@ if ($EXISTING_FACTORY) {
         --source $DEPLOYER_SECRET `
         -- initialize `
         --admin $DEPLOYER_ADDRESS `
-        --wasm_hash $vaultWasmHash 2>&1
+        --wasm_hash $vaultWasmHash `
+        --network $FACTORY_NETWORK 2>&1
     
     if ($LASTEXITCODE -ne 0) {
         Write-Host "❌ Failed to initialize factory:" -ForegroundColor Red
@@ -137,6 +140,26 @@ if ($EXISTING_FACTORY) {
     Write-Host "✅ Factory initialized`n" -ForegroundColor Green
 }
 
+# User wallets deploy their own vaults through the backend, which passes the
+# wallet as create_vault's caller; the factory refuses anyone but its admin
+# until it is opened to them
+Write-Host "🔓 Opening factory to user deployments..." -ForegroundColor Gray
+$openResult = stellar contract invoke `
+    --id $FACTORY_ADDRESS `
+    --network $NETWORK `
+    --source $DEPLOYER_SECRET `
+    -- set_permissionless `
+    --admin $DEPLOYER_ADDRESS `
+    --permissionless true 2>&1
+
+if ($LASTEXITCODE -ne 0) {
+    Write-Host "❌ Failed to open factory:" -ForegroundColor Red
+    Write-Host $openResult -ForegroundColor Red
+    exit 1
+}
+
+Write-Host "✅ Factory open to user deployments`n" -ForegroundColor Green
+
 # Step 3: Update .env files
 Write-Host "💾 Step 3: Updating .env files..." -ForegroundColor Yellow
 